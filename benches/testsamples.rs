@@ -97,3 +97,147 @@ fn bench_p3_stereo_16bit(bencher: &mut Bencher) {
 fn bench_p4_stereo_16bit(bencher: &mut Bencher) {
     bench_decode("testsamples/p4.flac", bencher);
 }
+
+/// Decode the entire file at once, either with `decode_all_into()`, or by
+/// collecting the `samples()` iterator, to compare the two.
+fn bench_decode_all<P: AsRef<Path>>(path: P, use_iterator: bool, bencher: &mut Bencher) {
+    let mut file = File::open(path).unwrap();
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).unwrap();
+
+    let mut samples = Vec::new();
+    bencher.iter(|| {
+        let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+        samples.clear();
+        if use_iterator {
+            samples.extend(reader.samples().map(|s| s.unwrap()));
+        } else {
+            reader.decode_all_into(&mut samples).unwrap();
+        }
+        test::black_box(&samples);
+    });
+}
+
+#[bench]
+fn bench_p2_decode_all_into(bencher: &mut Bencher) {
+    bench_decode_all("testsamples/p2.flac", false, bencher);
+}
+
+#[bench]
+fn bench_p2_samples_collect(bencher: &mut Bencher) {
+    bench_decode_all("testsamples/p2.flac", true, bencher);
+}
+
+/// Every frame of this file uses a different stereo decorrelation.
+///
+/// Compare with and without `--features simd` for the vectorized version.
+#[bench]
+fn bench_stereo_24bit_decorrelated(bencher: &mut Bencher) {
+    bench_decode_all("testsamples/stereo_24bit_decorrelated.flac", false, bencher);
+}
+
+/// Decode many files by collecting the `samples()` iterator, with or without
+/// a shared buffer pool, to measure the cost of allocating a buffer per file.
+fn bench_decode_many(use_pool: bool, bencher: &mut Bencher) {
+    let mut files = Vec::new();
+    for fname in &["testsamples/p0.flac", "testsamples/p1.flac", "testsamples/p2.flac",
+                   "testsamples/p3.flac", "testsamples/p4.flac"] {
+        let mut data = Vec::new();
+        File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+        files.push(data);
+    }
+
+    let pool = claxon::BufferPool::new();
+    let mut samples = Vec::new();
+    bencher.iter(|| {
+        for data in &files {
+            let cursor = Cursor::new(&data[..]);
+            let mut reader = if use_pool {
+                claxon::FlacReader::new_with_pool(cursor, pool.clone()).unwrap()
+            } else {
+                claxon::FlacReader::new(cursor).unwrap()
+            };
+            samples.clear();
+            samples.extend(reader.samples().map(|s| s.unwrap()));
+            test::black_box(&samples);
+        }
+    });
+}
+
+#[bench]
+fn bench_decode_many_without_pool(bencher: &mut Bencher) {
+    bench_decode_many(false, bencher);
+}
+
+#[bench]
+fn bench_decode_many_with_pool(bencher: &mut Bencher) {
+    bench_decode_many(true, bencher);
+}
+
+/// Compute the MD5 signature while decoding, on the decoding thread or on a
+/// separate hashing thread, to measure the benefit of the pipeline.
+fn bench_compute_digest(pipelined: bool, bencher: &mut Bencher) {
+    let mut file = File::open("testsamples/p2.flac").unwrap();
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).unwrap();
+
+    bencher.iter(|| {
+        let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+        let digest = if pipelined {
+            reader.compute_digest_pipelined().unwrap()
+        } else {
+            reader.compute_digest().unwrap()
+        };
+        test::black_box(digest);
+    });
+    bencher.bytes = data.len() as u64;
+}
+
+#[bench]
+fn bench_compute_digest_serial(bencher: &mut Bencher) {
+    bench_compute_digest(false, bencher);
+}
+
+#[bench]
+fn bench_compute_digest_pipelined(bencher: &mut Bencher) {
+    bench_compute_digest(true, bencher);
+}
+
+/// Look up tags in the Vorbis comment block, with an owned `VorbisComment`
+/// read by a metadata-only `FlacReader`, or with a borrowed view into a buffer
+/// that is reused.
+fn bench_scan_tags(borrowed: bool, bencher: &mut Bencher) {
+    let mut file = File::open("testsamples/encoder_hints.flac").unwrap();
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).unwrap();
+    let mut buffer = Vec::new();
+
+    bencher.iter(|| {
+        if borrowed {
+            claxon::read_vorbis_comment_into(Cursor::new(&data[..]), &mut buffer).unwrap();
+            let comment = claxon::metadata::VorbisCommentRef::parse(&buffer).unwrap();
+            test::black_box(comment.vendor());
+            test::black_box(comment.get_tag("encoder").next());
+            test::black_box(comment.get_tag("encoder_settings").next());
+        } else {
+            let opts = claxon::FlacReaderOptions {
+                metadata_only: true,
+                .. claxon::FlacReaderOptions::default()
+            };
+            let reader = claxon::FlacReader::new_ext(Cursor::new(&data[..]), opts).unwrap();
+            test::black_box(reader.vendor());
+            test::black_box(reader.get_tag("encoder").next());
+            test::black_box(reader.get_tag("encoder_settings").next());
+        }
+    });
+}
+
+#[bench]
+fn bench_scan_tags_owned(bencher: &mut Bencher) {
+    bench_scan_tags(false, bencher);
+}
+
+#[bench]
+fn bench_scan_tags_borrowed(bencher: &mut Bencher) {
+    bench_scan_tags(true, bencher);
+}
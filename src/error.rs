@@ -29,17 +29,119 @@ pub enum Error {
     /// `Unsupported` is used for features that are in the specification, but
     /// which are not implemented by Claxon.
     Unsupported(&'static str),
+
+    /// A decoded sample did not fit in the requested sample type.
+    ///
+    /// This is returned by `FlacReader::samples_as()`; a type of at least
+    /// `needed_bits` bits can hold all samples of the stream.
+    SampleTooWide {
+        /// The number of bits per sample of the stream.
+        needed_bits: u32,
+        /// The name of the requested type, such as `"i16"`.
+        requested_type: &'static str,
+    },
+
+    /// Decoding did not finish before the deadline in the reader options.
+    ///
+    /// See `FlacReaderOptions::deadline`. The frame reader that hit the
+    /// deadline decoded `decoded_samples` inter-channel samples before that.
+    DeadlineExceeded {
+        /// The number of inter-channel samples decoded before the deadline.
+        decoded_samples: u64,
+    },
+
+    /// Decoding a frame would take more memory than the reader options allow.
+    ///
+    /// See `FlacReaderOptions::max_memory`. Decoding needs `needed_bytes`
+    /// for the read buffer and the samples of the frame, but the budget is
+    /// only `budget_bytes`.
+    MemoryBudgetExceeded {
+        /// The number of bytes that decoding would need.
+        needed_bytes: usize,
+        /// The memory budget in bytes.
+        budget_bytes: usize,
+    },
+
+    /// The stream contains more metadata blocks than the reader options allow.
+    ///
+    /// See `FlacReaderOptions::max_metadata_blocks`.
+    TooManyMetadataBlocks,
+
+    /// The lead-out of the CUE sheet differs from the number of samples in the streaminfo.
+    ///
+    /// See `FlacReader::check_cuesheet_lead_out()`. This is returned when
+    /// constructing a reader in strict mode as well.
+    CueSheetLeadOutMismatch {
+        /// The offset in samples of the lead-out track.
+        lead_out: u64,
+        /// The number of inter-channel samples in the streaminfo.
+        samples: u64,
+    },
+
+    /// An error that occurred while decoding a stream that has a label.
+    ///
+    /// See `FlacReader::set_label()`. The label, such as a file name, is
+    /// included in the `Display` of the error, to tell streams apart in logs.
+    Labeled {
+        /// The label of the stream.
+        label: String,
+        /// The error that occurred.
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Attaches a label, such as a file name, that identifies the stream.
+    ///
+    /// `FlacReader::set_label()` does this for errors that occur while
+    /// decoding. This can be used for other errors, for instance when
+    /// constructing the reader fails. An error that has a label keeps it.
+    pub fn with_label(self, label: &str) -> Error {
+        match self {
+            Error::Labeled { .. } => self,
+            error => Error::Labeled {
+                label: label.to_string(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Returns the error without its label, if it has one.
+    pub fn unlabeled(&self) -> &Error {
+        match *self {
+            Error::Labeled { ref error, .. } => error,
+            _ => self,
+        }
+    }
 }
 
 impl PartialEq for Error {
     fn eq(&self, other: &Error) -> bool {
-        use error::Error::{IoError, FormatError, Unsupported};
+        use error::Error::{IoError, FormatError, Unsupported, SampleTooWide, DeadlineExceeded,
+                           MemoryBudgetExceeded, TooManyMetadataBlocks, CueSheetLeadOutMismatch, Labeled};
         match (self, other) {
             (&FormatError(r1), &FormatError(r2)) => r1 == r2,
             (&Unsupported(f1), &Unsupported(f2)) => f1 == f2,
+            (&SampleTooWide { needed_bits: b1, requested_type: t1 },
+             &SampleTooWide { needed_bits: b2, requested_type: t2 }) => b1 == b2 && t1 == t2,
+            (&DeadlineExceeded { decoded_samples: n1 },
+             &DeadlineExceeded { decoded_samples: n2 }) => n1 == n2,
+            (&MemoryBudgetExceeded { needed_bytes: n1, budget_bytes: b1 },
+             &MemoryBudgetExceeded { needed_bytes: n2, budget_bytes: b2 }) => n1 == n2 && b1 == b2,
+            (&TooManyMetadataBlocks, &TooManyMetadataBlocks) => true,
+            (&CueSheetLeadOutMismatch { lead_out: l1, samples: n1 },
+             &CueSheetLeadOutMismatch { lead_out: l2, samples: n2 }) => l1 == l2 && n1 == n2,
+            (&Labeled { label: ref l1, error: ref e1 },
+             &Labeled { label: ref l2, error: ref e2 }) => l1 == l2 && e1 == e2,
             (&IoError(_), _) => false,
             (&FormatError(_), _) => false,
             (&Unsupported(_), _) => false,
+            (&SampleTooWide { .. }, _) => false,
+            (&DeadlineExceeded { .. }, _) => false,
+            (&MemoryBudgetExceeded { .. }, _) => false,
+            (&TooManyMetadataBlocks, _) => false,
+            (&CueSheetLeadOutMismatch { .. }, _) => false,
+            (&Labeled { .. }, _) => false,
         }
     }
 }
@@ -57,6 +159,27 @@ impl fmt::Display for Error {
                                           was encountered: "));
                 formatter.write_str(feature)
             }
+            Error::SampleTooWide { needed_bits, requested_type } => {
+                write!(formatter, "A sample does not fit in {}, the stream has {} bits per sample.",
+                       requested_type, needed_bits)
+            }
+            Error::DeadlineExceeded { decoded_samples } => {
+                write!(formatter, "The decoding deadline passed after {} samples.", decoded_samples)
+            }
+            Error::MemoryBudgetExceeded { needed_bytes, budget_bytes } => {
+                write!(formatter, "Decoding needs {} bytes, but the memory budget is {} bytes.",
+                       needed_bytes, budget_bytes)
+            }
+            Error::TooManyMetadataBlocks => {
+                formatter.write_str("The stream contains too many metadata blocks.")
+            }
+            Error::CueSheetLeadOutMismatch { lead_out, samples } => {
+                write!(formatter, "The CUE sheet lead-out is at sample {}, but the stream has {} samples.",
+                       lead_out, samples)
+            }
+            Error::Labeled { ref label, ref error } => {
+                write!(formatter, "error in '{}': {}", label, error)
+            }
         }
     }
 }
@@ -67,6 +190,12 @@ impl error::Error for Error {
             Error::IoError(ref err) => err.description(),
             Error::FormatError(reason) => reason,
             Error::Unsupported(_) => "unsupported feature",
+            Error::SampleTooWide { .. } => "sample does not fit in requested type",
+            Error::DeadlineExceeded { .. } => "decoding deadline exceeded",
+            Error::MemoryBudgetExceeded { .. } => "memory budget exceeded",
+            Error::TooManyMetadataBlocks => "too many metadata blocks",
+            Error::CueSheetLeadOutMismatch { .. } => "CUE sheet lead-out does not match the number of samples",
+            Error::Labeled { ref error, .. } => error.description(),
         }
     }
 
@@ -75,6 +204,12 @@ impl error::Error for Error {
             Error::IoError(ref err) => Some(err),
             Error::FormatError(_) => None,
             Error::Unsupported(_) => None,
+            Error::SampleTooWide { .. } => None,
+            Error::DeadlineExceeded { .. } => None,
+            Error::MemoryBudgetExceeded { .. } => None,
+            Error::TooManyMetadataBlocks => None,
+            Error::CueSheetLeadOutMismatch { .. } => None,
+            Error::Labeled { ref error, .. } => Some(&**error),
         }
     }
 }
@@ -7,12 +7,23 @@
 
 //! The `frame` module deals with the frames that make up a FLAC stream.
 
+use std::cmp;
 use std::i32;
+use std::io;
+use std::mem;
+use std::slice;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
+use crc;
 use crc::{Crc8Reader, Crc16Reader};
 use error::{Error, Result, fmt_err};
 use input::{Bitstream, ReadBytes};
+use metadata::StreamInfo;
+use simd;
 use subframe;
+use subframe::SubframeType;
+use FLAC_MAGIC;
 
 #[derive(Clone, Copy)]
 enum BlockingStrategy {
@@ -38,6 +49,34 @@ enum ChannelAssignment {
     MidSideStereo,
 }
 
+impl ChannelAssignment {
+    fn decorrelation(&self) -> Decorrelation {
+        match *self {
+            ChannelAssignment::Independent(..) => Decorrelation::Independent,
+            ChannelAssignment::LeftSideStereo => Decorrelation::LeftSide,
+            ChannelAssignment::RightSideStereo => Decorrelation::RightSide,
+            ChannelAssignment::MidSideStereo => Decorrelation::MidSide,
+        }
+    }
+}
+
+/// How the subframes of a frame relate to the channels of the decoded block.
+///
+/// For stereo, FLAC can code the difference between the channels (the side
+/// channel) rather than one of the channels themselves. There are always as
+/// many subframes as channels; decoding reconstructs left and right.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Decorrelation {
+    /// Every subframe holds one channel as-is.
+    Independent,
+    /// The subframes hold the left and side channel.
+    LeftSide,
+    /// The subframes hold the side and right channel.
+    RightSide,
+    /// The subframes hold the mid and side channel.
+    MidSide,
+}
+
 #[derive(Clone, Copy)]
 struct FrameHeader {
     pub block_time: BlockTime,
@@ -65,6 +104,13 @@ fn read_var_length_int<R: ReadBytes>(input: &mut R) -> Result<u64> {
     // The number of consecutive 1s followed by a 0 is the number of additional
     // bytes to read.
     let first = try!(input.read_u8());
+
+    // The longest form is 7 bytes, starting with 0xfe, for a 36-bit integer.
+    // A first byte with eight leading 1s cannot start any form.
+    if first == 0xff {
+        return fmt_err("invalid variable-length integer");
+    }
+
     let mut read_additional = 0u8;
     let mut mask_data = 0b0111_1111u8;
     let mut mask_mark = 0b1000_0000u8;
@@ -126,6 +172,210 @@ fn verify_read_var_length_int() {
     // Continuation byte can never be the first byte.
     assert_eq!(read_var_length_int(&mut reader).err().unwrap(),
                Error::FormatError("invalid variable-length integer"));
+
+    // The 7-byte form holds a 36-bit integer, there is no 8-byte form.
+    let mut reader = BufferedReader::new(
+        io::Cursor::new(vec![0xfe, 0x88, 0x80, 0x80, 0x80, 0x80, 0x85,
+                             0xfe, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf,
+                             0xff, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80,
+                             0xfe, 0x80, 0x80, 0x80, 0x80, 0x80]));
+    assert_eq!(read_var_length_int(&mut reader).unwrap(), (1 << 33) + 5);
+    assert_eq!(read_var_length_int(&mut reader).unwrap(), (1 << 36) - 1);
+    assert_eq!(read_var_length_int(&mut reader).err().unwrap(),
+               Error::FormatError("invalid variable-length integer"));
+
+    // Skip over the remainder of the invalid integer, then hit a truncated one.
+    for _ in 0..7 {
+        reader.read_u8().unwrap();
+    }
+    assert!(read_var_length_int(&mut reader).is_err());
+}
+
+/// Appends an integer of at most 36 bits in the variable-length "UTF-8" coding.
+///
+/// This is the inverse of `read_var_length_int()`, it uses the shortest form.
+fn write_var_length_int(x: u64, out: &mut Vec<u8>) {
+    debug_assert!(x < 1 << 36);
+    if x < 0x80 {
+        out.push(x as u8);
+        return
+    }
+
+    // Every additional byte holds 6 bits, the first byte holds 6 - n bits for
+    // a total of n + 1 bytes, except in the 7-byte form, where it holds none.
+    let mut n_additional = 1;
+    while n_additional < 6 && x >= 1 << (6 * n_additional + 6 - n_additional) {
+        n_additional += 1;
+    }
+
+    let mark = !(0xffu32 >> (n_additional + 1)) as u8;
+    out.push(mark | (x >> (6 * n_additional)) as u8);
+    for i in (0..n_additional).rev() {
+        out.push(0b1000_0000 | ((x >> (6 * i)) & 0b0011_1111) as u8);
+    }
+}
+
+#[test]
+fn verify_write_var_length_int() {
+    let mut out = Vec::new();
+    write_var_length_int(0x24, &mut out);
+    write_var_length_int(0xa2, &mut out);
+    write_var_length_int(0x20ac, &mut out);
+    write_var_length_int(0x010348, &mut out);
+    assert_eq!(out, vec![0x24, 0xc2, 0xa2, 0xe2, 0x82, 0xac, 0xf0, 0x90, 0x8d, 0x88]);
+
+    // Every form round-trips, including the boundaries between forms.
+    for &x in &[0, 0x7f, 0x80, 0x7ff, 0x800, 0xffff, 0x10000, 0x1f_ffff, 0x20_0000,
+                0x3ff_ffff, 0x400_0000, 0x7fff_ffff, 0x8000_0000, (1 << 36) - 1] {
+        let mut out = Vec::new();
+        write_var_length_int(x, &mut out);
+        let mut cursor = ::std::io::Cursor::new(&out);
+        assert_eq!(read_var_length_int(&mut cursor).unwrap(), x);
+        assert_eq!(cursor.position() as usize, out.len());
+    }
+}
+
+/// Reads the number in the header of an encoded frame, and the offset where the number ends.
+fn read_frame_number(frame: &[u8]) -> Result<(u64, usize)> {
+    // The header consists of 4 fixed bytes, the number, optional block size
+    // and sample rate bytes, and the CRC-8. A CRC-16 ends the frame.
+    if frame.len() < 8 {
+        return fmt_err("frame is too short")
+    }
+
+    let mut cursor = io::Cursor::new(&frame[4..]);
+    let number = try!(read_var_length_int(&mut cursor));
+    Ok((number, 4 + cursor.position() as usize))
+}
+
+/// Returns the number in the header of an encoded frame.
+///
+/// A frame of a fixed-blocksize stream carries its frame number, a frame of a
+/// variable-blocksize stream carries the number of its first sample.
+pub fn frame_number(frame: &[u8]) -> Result<u64> {
+    read_frame_number(frame).map(|(number, _)| number)
+}
+
+/// Copies an encoded frame, lowering the number in its header by `base`.
+///
+/// See `frame_number()` for what the number is. Subtracting the number of the
+/// first frame in a range of frames from every frame in the range makes it a
+/// stream of its own. The header CRC-8 and the frame CRC-16 are recomputed,
+/// the audio data is copied verbatim. The frame itself is not validated
+/// beyond its header layout.
+pub fn renumber_frame(frame: &[u8], base: u64, out: &mut Vec<u8>) -> Result<()> {
+    let (number, number_end) = try!(read_frame_number(frame));
+    if number < base {
+        return fmt_err("frame number is lower than the amount to subtract")
+    }
+
+    let block_size_extra = match frame[2] >> 4 {
+        0b0110 => 1,
+        0b0111 => 2,
+        _ => 0,
+    };
+    let sample_rate_extra = match frame[2] & 0b1111 {
+        0b1100 => 1,
+        0b1101 | 0b1110 => 2,
+        _ => 0,
+    };
+    let crc8_pos = number_end + block_size_extra + sample_rate_extra;
+    if crc8_pos + 3 > frame.len() {
+        return fmt_err("frame is too short")
+    }
+
+    let start = out.len();
+    out.extend_from_slice(&frame[..4]);
+    write_var_length_int(number - base, out);
+    out.extend_from_slice(&frame[number_end..crc8_pos]);
+    let header_crc = crc::crc8(&out[start..]);
+    out.push(header_crc);
+    out.extend_from_slice(&frame[crc8_pos + 1..frame.len() - 2]);
+    let frame_crc = crc::crc16(&out[start..]);
+    out.push((frame_crc >> 8) as u8);
+    out.push(frame_crc as u8);
+    Ok(())
+}
+
+/// Converts the 4-bit sample rate code of a frame header to a sample rate in Hz.
+///
+/// Code 0 means that the sample rate is the one in the streaminfo. Codes 12
+/// through 14 mean that the sample rate is stored at the end of the frame
+/// header, as an 8-bit value in Hz, a 16-bit value in Hz, or a 16-bit value in
+/// tens of Hz respectively. For these codes, `extra` must contain that stored
+/// value. Code 15 is invalid, to prevent fooling the frame sync.
+pub fn sample_rate_from_code(code: u8,
+                             stream_info: &StreamInfo,
+                             extra: Option<u32>)
+                             -> Result<u32> {
+    let max_extra = match code {
+        12 => 0xff,
+        13 | 14 => 0xffff,
+        _ => 0,
+    };
+    let extra_value = match extra {
+        Some(value) if value <= max_extra => value,
+        Some(_) if max_extra == 0 => {
+            return fmt_err("invalid sample rate, code does not take a value from the header")
+        }
+        Some(_) => return fmt_err("invalid sample rate, value too large for code"),
+        None if max_extra == 0 => 0,
+        None => return fmt_err("invalid sample rate, code requires a value from the header"),
+    };
+
+    match code {
+        0 => Ok(stream_info.sample_rate),
+        1 => Ok(88_200),
+        2 => Ok(176_400),
+        3 => Ok(192_000),
+        4 => Ok(8_000),
+        5 => Ok(16_000),
+        6 => Ok(22_050),
+        7 => Ok(24_000),
+        8 => Ok(32_000),
+        9 => Ok(44_100),
+        10 => Ok(48_000),
+        11 => Ok(96_000),
+        12 | 13 => Ok(extra_value),
+        14 => Ok(extra_value * 10),
+        _ => fmt_err("invalid sample rate code"),
+    }
+}
+
+#[test]
+fn verify_sample_rate_from_code() {
+    let stream_info = StreamInfo {
+        min_block_size: 4096,
+        max_block_size: 4096,
+        min_frame_size: None,
+        max_frame_size: None,
+        sample_rate: 12_345,
+        channels: 2,
+        bits_per_sample: 16,
+        samples: None,
+        md5sum: [0u8; 16],
+    };
+    let table = [12_345, 88_200, 176_400, 192_000, 8_000, 16_000, 22_050, 24_000,
+                 32_000, 44_100, 48_000, 96_000];
+    for (code, &rate) in table.iter().enumerate() {
+        assert_eq!(sample_rate_from_code(code as u8, &stream_info, None).ok(), Some(rate));
+        assert!(sample_rate_from_code(code as u8, &stream_info, Some(1)).is_err());
+    }
+
+    assert_eq!(sample_rate_from_code(12, &stream_info, Some(255)).ok(), Some(255));
+    assert_eq!(sample_rate_from_code(13, &stream_info, Some(65_535)).ok(), Some(65_535));
+    assert_eq!(sample_rate_from_code(14, &stream_info, Some(65_535)).ok(), Some(655_350));
+    assert!(sample_rate_from_code(12, &stream_info, Some(256)).is_err());
+    assert!(sample_rate_from_code(13, &stream_info, Some(65_536)).is_err());
+    assert!(sample_rate_from_code(14, &stream_info, Some(65_536)).is_err());
+    for code in 12..15 {
+        assert!(sample_rate_from_code(code, &stream_info, None).is_err());
+    }
+
+    // Code 15 is invalid, and there are only 4 bits.
+    assert_eq!(sample_rate_from_code(15, &stream_info, None).err().unwrap(),
+               Error::FormatError("invalid sample rate code"));
+    assert!(sample_rate_from_code(16, &stream_info, None).is_err());
 }
 
 fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameHeader>> {
@@ -206,6 +456,7 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
         0b1101 => read_16bit_sr = true, // Read Hz from end of header.
         0b1110 => read_16bit_sr_ten = true, // Read tens of Hz from end of header.
         // 1111 is invalid to prevent sync-fooling.
+        0b1111 => return fmt_err("invalid frame header, invalid sample rate code"),
         // Other values are impossible at this point.
         _ => return fmt_err("invalid frame header"),
     }
@@ -258,6 +509,19 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
         }
     };
 
+    // The block size and sample rate codes can refer to fields at the end of
+    // the header. If the input is known to end before those, report where they
+    // start, rather than failing on the first missing byte.
+    let n_trailing = read_8bit_bs as u64 + 2 * read_16bit_bs as u64 +
+                     read_8bit_sr as u64 + 2 * (read_16bit_sr || read_16bit_sr_ten) as u64;
+    if n_trailing > 0 && !try!(crc_input.may_have_bytes(n_trailing)) {
+        let msg = match crc_input.byte_position() {
+            Some(pos) => format!("frame header ends before its block size or sample rate at byte {}", pos),
+            None => "frame header ends before its block size or sample rate".to_string(),
+        };
+        return Err(Error::IoError(io::Error::new(io::ErrorKind::UnexpectedEof, msg)));
+    }
+
     if read_8bit_bs {
         // 8 bit block size - 1 is stored.
         let bs = try!(crc_input.read_u8());
@@ -275,6 +539,13 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
         block_size = bs + 1;
     }
 
+    // None of the above can produce a block size of zero, because the code
+    // 0000 is reserved, and the stored sizes are off by one. The decoder relies
+    // on a nonempty block, so state that explicitly.
+    if block_size == 0 {
+        return fmt_err("invalid block size, must be at least 1");
+    }
+
     if read_8bit_sr {
         let sr = try!(crc_input.read_u8());
         sample_rate = Some(sr as u32);
@@ -310,8 +581,125 @@ fn read_frame_header_or_eof<R: ReadBytes>(input: &mut R) -> Result<Option<FrameH
     Ok(Some(frame_header))
 }
 
+/// Reads the frame header at the start of `input`, and returns the number of its first sample.
+///
+/// This is used for seeking, to recognize a frame at an arbitrary position in
+/// the stream, without decoding it. For a frame that is numbered by frame
+/// rather than by sample, `fixed_block_size` is the size of the blocks in the
+/// stream; a frame with a bigger block is rejected.
+pub fn read_frame_time<R: ReadBytes>(input: &mut R, fixed_block_size: u16) -> Result<u64> {
+    let header = match try!(read_frame_header_or_eof(input)) {
+        Some(header) => header,
+        None => return fmt_err("expected frame header, found end of stream"),
+    };
+
+    match header.block_time {
+        BlockTime::FrameNumber(_) if header.block_size > fixed_block_size => {
+            fmt_err("invalid frame header, block size exceeds fixed block size")
+        }
+        BlockTime::FrameNumber(fnr) => Ok(fixed_block_size as u64 * fnr as u64),
+        BlockTime::SampleNumber(snr) => Ok(snr),
+    }
+}
+
+#[test]
+fn verify_read_frame_time() {
+    use std::io;
+
+    // Frame number 2 with a block size of 4096, and a valid header CRC.
+    let header = vec![0xff, 0xf8, 0xc9, 0x08, 0x02, 0x9b];
+    assert_eq!(read_frame_time(&mut io::Cursor::new(&header), 4096).unwrap(), 8192);
+    assert!(read_frame_time(&mut io::Cursor::new(&header), 1024).is_err());
+}
+
+/// Derives the stream parameters from the frame header at the start of `input`.
+///
+/// This is a fallback for damaged streams that lack a streaminfo block. The
+/// header must specify the sample rate and bits per sample, which it usually
+/// does. The streaminfo fields that cannot be known from a single header, such
+/// as the number of samples and the MD5 signature, are left unknown; the block
+/// size bounds are set to the widest range that the format allows.
+pub fn read_streaminfo_from_frame_header<R: ReadBytes>(input: &mut R) -> Result<StreamInfo> {
+    let header = match try!(read_frame_header_or_eof(input)) {
+        Some(header) => header,
+        None => return fmt_err("streaminfo block missing, and stream contains no frames"),
+    };
+
+    let (sample_rate, bits_per_sample) = match (header.sample_rate, header.bits_per_sample) {
+        (Some(sample_rate), Some(bps)) => (sample_rate, bps),
+        _ => return fmt_err("streaminfo block missing, and frame header refers to it"),
+    };
+
+    // A sample rate stored in the header can be zero, which is as invalid here
+    // as it is in a streaminfo block, and the stream duration divides by it.
+    if sample_rate == 0 {
+        return fmt_err("invalid sample rate");
+    }
+
+    let streaminfo = StreamInfo {
+        min_block_size: 16,
+        max_block_size: 0xffff,
+        min_frame_size: None,
+        max_frame_size: None,
+        sample_rate: sample_rate,
+        channels: header.channels() as u32,
+        bits_per_sample: bits_per_sample,
+        samples: None,
+        md5sum: [0; 16],
+    };
+    Ok(streaminfo)
+}
+
+#[test]
+fn verify_read_frame_header_reports_truncated_trailing_fields() {
+    // A frame header with block size code 1100 and sample rate code 1101, a
+    // 16-bit rate in Hz at the end of the header, then stereo, 16 bits, and
+    // frame number 0. The rate and the CRC-8 are cut off entirely or halfway.
+    for &len in &[5, 6] {
+        let header = [0xff, 0xf8, 0xcd, 0x18, 0x00, 0xac, 0x44];
+        match read_frame_header_or_eof(&mut io::Cursor::new(&header[..len])) {
+            Err(Error::IoError(ref err)) => {
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+                assert!(err.to_string().contains("at byte 5"));
+            }
+            _ => panic!("expected an unexpected end of input"),
+        }
+    }
+
+    // With block size code 0111, a 16-bit block size precedes the rate.
+    let header = [0xff, 0xf8, 0x7d, 0x18, 0x00, 0x0f, 0xff, 0xac];
+    match read_frame_header_or_eof(&mut io::Cursor::new(&header[..])) {
+        Err(Error::IoError(ref err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+        _ => panic!("expected an unexpected end of input"),
+    }
+}
+
+#[test]
+fn verify_read_streaminfo_from_frame_header_rejects_zero_sample_rate() {
+    // A stereo 16-bit frame header of 1024 samples, with an 8-bit sample rate
+    // in Hz at the end, and the CRC-8.
+    let header = [0xff, 0xf8, 0xac, 0x18, 0x00, 0x08, 0x63];
+    let streaminfo = read_streaminfo_from_frame_header(&mut io::Cursor::new(&header[..])).unwrap();
+    assert_eq!(streaminfo.sample_rate, 8);
+    assert_eq!(streaminfo.channels, 2);
+    assert_eq!(streaminfo.bits_per_sample, 16);
+
+    let header = [0xff, 0xf8, 0xac, 0x18, 0x00, 0x00, 0x5b];
+    assert_eq!(read_streaminfo_from_frame_header(&mut io::Cursor::new(&header[..])).err(),
+               Some(Error::FormatError("invalid sample rate")));
+}
+
 /// Converts a buffer with left samples and a side channel in-place to left ++ right.
+///
+/// With the `simd` feature, this is vectorized if the CPU supports it.
 fn decode_left_side(buffer: &mut [i32]) {
+    if !simd::decode_left_side(buffer) {
+        decode_left_side_scalar(buffer);
+    }
+}
+
+/// Converts a buffer with left samples and a side channel in-place to left ++ right, one sample at a time.
+fn decode_left_side_scalar(buffer: &mut [i32]) {
     let block_size = buffer.len() / 2;
     let (mids, sides) = buffer.split_at_mut(block_size);
     for (fst, snd) in mids.iter_mut().zip(sides) {
@@ -319,8 +707,9 @@ fn decode_left_side(buffer: &mut [i32]) {
         let side = *snd;
 
         // Left is correct already, only the right channel needs to be decoded.
-        // side = left - right => right = left - side. A valid FLAC file will
-        // never overflow here. If we do have an overflow then we decode
+        // side = left - right => right = left - side. Frame headers allow at
+        // most 24 bits per sample, so the side channel has at most 25 bits,
+        // and a valid FLAC file will never overflow here. If we do have an overflow then we decode
         // garbage, but at least Rust does not panic in debug mode due to
         // overflow.
         let right = left.wrapping_sub(side);
@@ -337,7 +726,16 @@ fn verify_decode_left_side() {
 }
 
 /// Converts a buffer with right samples and a side channel in-place to left ++ right.
+///
+/// With the `simd` feature, this is vectorized if the CPU supports it.
 fn decode_right_side(buffer: &mut [i32]) {
+    if !simd::decode_right_side(buffer) {
+        decode_right_side_scalar(buffer);
+    }
+}
+
+/// Converts a buffer with right samples and a side channel in-place to left ++ right, one sample at a time.
+fn decode_right_side_scalar(buffer: &mut [i32]) {
     let block_size = buffer.len() / 2;
     let (mids, sides) = buffer.split_at_mut(block_size);
     for (fst, snd) in mids.iter_mut().zip(sides) {
@@ -345,8 +743,9 @@ fn decode_right_side(buffer: &mut [i32]) {
         let right = *snd;
 
         // Right is correct already, only the left channel needs to be decoded.
-        // side = left - right => left = side + right. A valid FLAC file will
-        // never overflow here. If we do have an overflow then we decode
+        // side = left - right => left = side + right. As for left-side, the
+        // side channel has at most 25 bits, so a valid FLAC file will never
+        // overflow here. If we do have an overflow then we decode
         // garbage, but at least Rust does not panic in debug mode due to
         // overflow.
         let left = side.wrapping_add(right);
@@ -363,7 +762,16 @@ fn verify_decode_right_side() {
 }
 
 /// Converts a buffer with mid samples and a side channel in-place to left ++ right.
+///
+/// With the `simd` feature, this is vectorized if the CPU supports it.
 fn decode_mid_side(buffer: &mut [i32]) {
+    if !simd::decode_mid_side(buffer) {
+        decode_mid_side_scalar(buffer);
+    }
+}
+
+/// Converts a buffer with mid samples and a side channel in-place to left ++ right, one sample at a time.
+fn decode_mid_side_scalar(buffer: &mut [i32]) {
     let block_size = buffer.len() / 2;
     let (mids, sides) = buffer.split_at_mut(block_size);
     for (fst, snd) in mids.iter_mut().zip(sides) {
@@ -371,9 +779,10 @@ fn decode_mid_side(buffer: &mut [i32]) {
         let side = *snd;
 
         // Double mid first, and then correct for truncated rounding that
-        // will have occured if side is odd. Note that samples are never
-        // expected to exceed 25 bits, so the wrapping multiplication does not
-        // actually wrap for valid files.
+        // will have occured if side is odd. Mid has at most bps bits and side
+        // at most bps + 1, so the doubled mid has bps + 1 bits, and the sum
+        // and difference have bps + 2: for up to 30 bits per sample, the
+        // wrapping operations do not actually wrap for valid files.
         let mid = mid.wrapping_mul(2) | (side & 1);
         let left = mid.wrapping_add(side) / 2;
         let right = mid.wrapping_sub(side) / 2;
@@ -393,6 +802,118 @@ fn verify_decode_mid_side() {
     assert_eq!(buffer, result);
 }
 
+#[test]
+fn verify_decode_stereo_with_full_range_side_channel() {
+    // The extremes of 24-bit samples, for which the side channel needs all
+    // of its 25 bits.
+    let left = [-0x80_0000, 0x7f_ffff, -0x80_0000, 0x7f_ffff];
+    let right = [0x7f_ffff, -0x80_0000, -0x80_0000, 0x7f_ffff];
+    let side: Vec<i32> = left.iter().zip(right.iter()).map(|(l, r)| l - r).collect();
+    let mid: Vec<i32> = left.iter().zip(right.iter()).map(|(l, r)| (l + r) >> 1).collect();
+    assert_eq!(side[0], -0xff_ffff);
+    assert_eq!(side[1], 0xff_ffff);
+
+    let mut expected = left.to_vec();
+    expected.extend_from_slice(&right);
+
+    let mut buffer = left.to_vec();
+    buffer.extend_from_slice(&side);
+    decode_left_side(&mut buffer);
+    assert_eq!(buffer, expected);
+
+    let mut buffer = side.clone();
+    buffer.extend_from_slice(&right);
+    decode_right_side(&mut buffer);
+    assert_eq!(buffer, expected);
+
+    let mut buffer = mid;
+    buffer.extend_from_slice(&side);
+    decode_mid_side(&mut buffer);
+    assert_eq!(buffer, expected);
+
+    assert!(check_sample_range(&expected, 24).is_ok());
+    assert!(check_sample_range(&expected, 23).is_err());
+    assert!(check_sample_range(&side, 24).is_err());
+    assert!(check_sample_range(&side, 25).is_ok());
+}
+
+#[test]
+fn verify_decode_stereo_matches_scalar() {
+    // A xorshift generator, for inputs that cover the full range of an i32,
+    // so the wrapping behavior of invalid streams is compared too.
+    let mut state = 0x2545_f491_u32;
+    let mut random = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as i32
+    };
+
+    // Block sizes that are and are not a multiple of the vector width.
+    for &block_size in &[1, 7, 8, 9, 64, 1000, 4096] {
+        for &small in &[false, true] {
+            let input: Vec<i32> = (0..block_size * 2).map(|_| {
+                let x = random();
+                // Valid streams have at most 25 bits in the side channel.
+                if small { x >> 7 } else { x }
+            }).collect();
+
+            let fns: [(fn(&mut [i32]), fn(&mut [i32])); 3] = [
+                (decode_left_side, decode_left_side_scalar),
+                (decode_right_side, decode_right_side_scalar),
+                (decode_mid_side, decode_mid_side_scalar),
+            ];
+            for &(decode, scalar) in &fns {
+                let mut expected = input.clone();
+                scalar(&mut expected);
+                let mut buffer = input.clone();
+                decode(&mut buffer);
+                assert_eq!(buffer, expected);
+            }
+        }
+    }
+}
+
+/// Returns an error if a sample in `buffer` does not fit in `bps` bits.
+///
+/// Samples reconstructed from a stereo pair depend on the sum or difference of
+/// two subframes. In a valid stream they fit in the bits per sample. In a
+/// corrupt stream they might not, and this detects that.
+fn check_sample_range(buffer: &[i32], bps: u32) -> Result<()> {
+    debug_assert!(bps >= 1 && bps <= 32);
+    let max = (1i64 << (bps - 1)) - 1;
+    let min = -max - 1;
+    let (lo, hi) = buffer.iter().fold((0, 0), |(lo, hi), &s| (cmp::min(lo, s), cmp::max(hi, s)));
+    if (lo as i64) < min || (hi as i64) > max {
+        return fmt_err("reconstructed sample exceeds bits per sample");
+    }
+    Ok(())
+}
+
+/// Returns the number of bits read from `bitstream` since it was at bit position `start`.
+///
+/// Returns zero if the reader does not report its position.
+fn bits_since<R: ReadBytes>(bitstream: &Bitstream<R>, start: Option<u64>) -> u32 {
+    match (start, bitstream.bit_position()) {
+        (Some(from), Some(to)) => (to - from) as u32,
+        _ => 0,
+    }
+}
+
+/// Returns the constant channel mask for a decorrelated stereo pair.
+///
+/// The `verbatim_mask` has the bit set for the channel that is stored as-is
+/// (the left channel for left-side, the right channel for right-side, none for
+/// mid-side). That channel is constant if its own subframe is. If both
+/// subframes are constant, both reconstructed channels are constant.
+fn stereo_constant_channels(channel: SubframeType, side: SubframeType, verbatim_mask: u8) -> u8 {
+    match (channel, side) {
+        (SubframeType::Constant, SubframeType::Constant) => 0b11,
+        (SubframeType::Constant, _) => verbatim_mask,
+        _ => 0,
+    }
+}
+
 /// A block of raw audio samples.
 pub struct Block {
     /// The sample number of the first sample in the this block.
@@ -401,17 +922,40 @@ pub struct Block {
     block_size: u32,
     /// The number of channels in the block.
     channels: u32,
+    /// The number of bits per sample, from the frame header or the streaminfo.
+    bits_per_sample: u32,
     /// The decoded samples, the channels stored consecutively.
     buffer: Vec<i32>,
+    /// Bit `ch` is set if all samples in channel `ch` have the same value.
+    constant_channels: u8,
+    /// How the channels were coded in the subframes.
+    decorrelation: Decorrelation,
+    /// The type of every subframe, as read from its header.
+    subframe_types: [SubframeType; 8],
+    /// The number of bits that every subframe occupied in the frame.
+    subframe_bits: [u32; 8],
 }
 
 impl Block {
-    fn new(time: u64, bs: u32, buffer: Vec<i32>) -> Block {
+    fn new(time: u64,
+           bs: u32,
+           bits_per_sample: u32,
+           buffer: Vec<i32>,
+           constant_channels: u8,
+           decorrelation: Decorrelation,
+           subframe_types: [SubframeType; 8],
+           subframe_bits: [u32; 8])
+           -> Block {
         Block {
             first_sample_number: time,
             block_size: bs,
             channels: buffer.len() as u32 / bs,
+            bits_per_sample: bits_per_sample,
             buffer: buffer,
+            constant_channels: constant_channels,
+            decorrelation: decorrelation,
+            subframe_types: subframe_types,
+            subframe_bits: subframe_bits,
         }
     }
 
@@ -421,7 +965,12 @@ impl Block {
             first_sample_number: 0,
             block_size: 0,
             channels: 0,
+            bits_per_sample: 0,
             buffer: Vec::with_capacity(0),
+            constant_channels: 0,
+            decorrelation: Decorrelation::Independent,
+            subframe_types: [SubframeType::Verbatim; 8],
+            subframe_bits: [0; 8],
         }
     }
 
@@ -434,6 +983,16 @@ impl Block {
         self.first_sample_number
     }
 
+    /// Returns the inter-channel sample number of the first sample in the block.
+    ///
+    /// This is the same as `time()`. The frame header codes it in one of two
+    /// ways, depending on the blocking strategy bit: a fixed-blocksize stream
+    /// codes the frame number, which is multiplied by the block size of the
+    /// stream, and a variable-blocksize stream codes the sample number itself.
+    pub fn first_sample_number(&self) -> u64 {
+        self.first_sample_number
+    }
+
     /// Returns the total number of samples in this block.
     ///
     /// Samples in different channels are counted as distinct samples.
@@ -463,6 +1022,69 @@ impl Block {
         self.channels
     }
 
+    /// Returns the number of bits per sample of the block.
+    ///
+    /// The frame header codes the common bit depths. For other bit depths,
+    /// it refers to the streaminfo, and this returns the value from there,
+    /// so it is always the bit depth that the samples were decoded with.
+    /// Returns zero for an empty block.
+    #[inline]
+    pub fn bits_per_sample(&self) -> u32 {
+        self.bits_per_sample
+    }
+
+    /// Returns the number of subframes that the block was decoded from.
+    ///
+    /// This always equals `channels()`: in a stereo pair, the side channel is
+    /// coded in a subframe of its own, in place of the left or right channel.
+    /// See `decorrelation()` for how the subframes map onto the channels.
+    #[inline]
+    pub fn subframe_count(&self) -> u32 {
+        self.channels
+    }
+
+    /// Returns how the channels were coded in the subframes of the frame.
+    ///
+    /// The samples in the block are always the reconstructed channels, so
+    /// this is for diagnostics only.
+    #[inline]
+    pub fn decorrelation(&self) -> Decorrelation {
+        self.decorrelation
+    }
+
+    /// Returns the type of the (zero-based) `i`-th subframe, such as the order of its predictor.
+    ///
+    /// This is the choice that the encoder made for the subframe, as read from
+    /// its header, which is useful to analyze encoder settings. For stereo
+    /// decorrelation, the subframes store the channels returned by
+    /// `decorrelation()`, such as mid and side, rather than left and right.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i >= channels()`.
+    pub fn subframe_type(&self, i: u32) -> SubframeType {
+        assert!(i < self.channels, "subframe index out of range");
+        self.subframe_types[i as usize]
+    }
+
+    /// Returns the number of bits that the (zero-based) `i`-th subframe occupied in the frame.
+    ///
+    /// This includes the subframe header, and for stereo decorrelation the
+    /// subframes are those of `subframe_type()`, so comparing the mid and side
+    /// subframes shows how much decorrelation helped. Together with the frame
+    /// header, the padding to a byte boundary, and the CRC-16, the subframes
+    /// make up the frame. The bits are counted from the position of the
+    /// underlying reader, so this is zero if the reader does not report its
+    /// position, see `ReadBytes::byte_position()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i >= channels()`.
+    pub fn subframe_bits(&self, i: u32) -> u64 {
+        assert!(i < self.channels, "subframe index out of range");
+        self.subframe_bits[i as usize] as u64
+    }
+
     /// Returns the (zero-based) `ch`-th channel as a slice.
     ///
     /// # Panics
@@ -475,6 +1097,64 @@ impl Block {
         &self.buffer[ch_usz * bsz..(ch_usz + 1) * bsz]
     }
 
+    /// Returns the (zero-based) `ch`-th channel as a mutable slice.
+    ///
+    /// This allows processing samples in place, such as applying a gain. As
+    /// the samples may change, the channel is no longer known to be constant
+    /// afterwards, see `is_channel_constant()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ch >= channels()`.
+    #[inline]
+    pub fn channel_mut(&mut self, ch: u32) -> &mut [i32] {
+        assert!(ch < self.channels, "channel index out of range");
+        self.constant_channels &= !(1 << ch);
+        let bsz = self.block_size as usize;
+        let ch_usz = ch as usize;
+        &mut self.buffer[ch_usz * bsz..(ch_usz + 1) * bsz]
+    }
+
+    /// Returns an iterator over all channels as mutable slices, in channel order.
+    ///
+    /// See `channel_mut()`; no channel is known to be constant afterwards.
+    #[inline]
+    pub fn channels_mut<'a>(&'a mut self) -> slice::ChunksMut<'a, i32> {
+        self.constant_channels = 0;
+        let len = self.len() as usize;
+        // An empty block has no channels, but the chunk size must be nonzero.
+        let bsz = cmp::max(1, self.block_size as usize);
+        self.buffer[..len].chunks_mut(bsz)
+    }
+
+    /// Returns the value of every sample in channel `ch`, if they are all equal.
+    ///
+    /// A channel is known to be constant when it was coded as a constant
+    /// subframe, which is common for digital silence. This allows consumers to
+    /// skip processing such channels sample by sample. For channels that are
+    /// reconstructed from a stereo pair, the channel is constant only if both
+    /// subframes of the pair are constant. Returns `None` if the channel is not
+    /// known to be constant.
+    ///
+    /// This is only a hint, it does not make decoding faster. The samples of
+    /// a constant channel are still written to the block, so `channel()` and
+    /// `sample()` work as for any other channel. Filling the buffer lazily
+    /// would avoid that pass, but `channel()` returns a plain slice, and stereo
+    /// decorrelation reads the decoded samples from the buffer directly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ch >= channels()`.
+    #[inline]
+    pub fn is_channel_constant(&self, ch: u32) -> Option<i32> {
+        assert!(ch < self.channels, "channel index out of range");
+        if self.constant_channels & (1 << ch) != 0 {
+            Some(self.buffer[ch as usize * self.block_size as usize])
+        } else {
+            None
+        }
+    }
+
     /// Returns a sample in this block.
     ///
     /// The value returned is for the zero-based `ch`-th channel of the
@@ -499,6 +1179,26 @@ impl Block {
         return self.buffer;
     }
 
+    /// Appends the samples in this block to `out` as big-endian bytes.
+    ///
+    /// The samples are interleaved, and every sample takes the smallest whole
+    /// number of bytes that fits `bits_per_sample` bits. As AIFF requires,
+    /// samples are left-justified: when `bits_per_sample` is not a multiple of
+    /// eight, the unused low bits of the last byte are zero.
+    pub fn write_canonical_bytes_be(&self, bits_per_sample: u32, out: &mut Vec<u8>) {
+        let bytes_per_sample = (bits_per_sample + 7) / 8;
+        let shift = bytes_per_sample * 8 - bits_per_sample;
+        out.reserve((self.duration() * self.channels() * bytes_per_sample) as usize);
+        for i in 0..self.duration() {
+            for ch in 0..self.channels() {
+                let sample = self.sample(ch, i) << shift;
+                for k in (0..bytes_per_sample).rev() {
+                    out.push((sample >> (8 * k)) as u8);
+                }
+            }
+        }
+    }
+
     /// Returns an iterator that produces left and right channel samples.
     ///
     /// This iterator can be more efficient than requesting a sample directly,
@@ -529,7 +1229,12 @@ fn verify_block_sample() {
         first_sample_number: 0,
         block_size: 5,
         channels: 3,
+        bits_per_sample: 16,
         buffer: vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47],
+        constant_channels: 0,
+        decorrelation: Decorrelation::Independent,
+        subframe_types: [SubframeType::Verbatim; 8],
+        subframe_bits: [0; 8],
     };
 
     assert_eq!(block.sample(0, 2), 5);
@@ -537,6 +1242,86 @@ fn verify_block_sample() {
     assert_eq!(block.sample(2, 4), 47);
 }
 
+#[test]
+fn verify_block_write_canonical_bytes_be() {
+    let block = Block {
+        first_sample_number: 0,
+        block_size: 2,
+        channels: 2,
+        bits_per_sample: 16,
+        buffer: vec![-2, 0x1234, 1, -0x8000],
+        constant_channels: 0,
+        decorrelation: Decorrelation::Independent,
+        subframe_types: [SubframeType::Verbatim; 8],
+        subframe_bits: [0; 8],
+    };
+
+    let mut out = Vec::new();
+    block.write_canonical_bytes_be(16, &mut out);
+    assert_eq!(out, vec![0xff, 0xfe, 0x00, 0x01, 0x12, 0x34, 0x80, 0x00]);
+
+    // 20-bit samples are stored left-justified in three bytes.
+    out.clear();
+    block.write_canonical_bytes_be(20, &mut out);
+    assert_eq!(&out[..6], &[0xff, 0xff, 0xe0, 0x00, 0x00, 0x10]);
+}
+
+#[test]
+fn verify_block_is_channel_constant() {
+    let block = Block {
+        first_sample_number: 0,
+        block_size: 3,
+        channels: 3,
+        bits_per_sample: 16,
+        buffer: vec![2, 3, 5, -7, -7, -7, 0, 0, 0],
+        constant_channels: 0b110,
+        decorrelation: Decorrelation::Independent,
+        subframe_types: [SubframeType::Verbatim; 8],
+        subframe_bits: [0; 8],
+    };
+
+    assert_eq!(block.is_channel_constant(0), None);
+    assert_eq!(block.is_channel_constant(1), Some(-7));
+    assert_eq!(block.is_channel_constant(2), Some(0));
+}
+
+#[test]
+fn verify_block_channel_mut() {
+    let mut block = Block {
+        first_sample_number: 0,
+        block_size: 3,
+        channels: 2,
+        bits_per_sample: 16,
+        buffer: vec![2, 3, 5, -7, -7, -7],
+        constant_channels: 0b10,
+        decorrelation: Decorrelation::Independent,
+        subframe_types: [SubframeType::Verbatim; 8],
+        subframe_bits: [0; 8],
+    };
+
+    block.channel_mut(1)[2] = 11;
+    assert_eq!(block.channel(0), &[2, 3, 5]);
+    assert_eq!(block.channel(1), &[-7, -7, 11]);
+    assert_eq!(block.sample(1, 2), 11);
+    assert_eq!(block.is_channel_constant(1), None);
+
+    for (ch, channel) in block.channels_mut().enumerate() {
+        assert_eq!(channel.len(), 3);
+        channel[0] = -(ch as i32);
+    }
+    assert_eq!(block.sample(0, 0), 0);
+    assert_eq!(block.sample(1, 0), -1);
+    assert_eq!(block.channels_mut().count(), 2);
+    assert_eq!(Block::empty().channels_mut().count(), 0);
+}
+
+#[test]
+#[should_panic]
+fn verify_block_channel_mut_out_of_range() {
+    let mut block = Block::empty();
+    block.channel_mut(0);
+}
+
 /// An iterator over the stereo sample pairs in a block.
 ///
 /// This iterator is produced by `Block::stereo_samples()`.
@@ -580,7 +1365,12 @@ fn verify_block_stereo_samples_iterator() {
         first_sample_number: 0,
         block_size: 3,
         channels: 2,
+        bits_per_sample: 16,
         buffer: vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47],
+        constant_channels: 0,
+        decorrelation: Decorrelation::Independent,
+        subframe_types: [SubframeType::Verbatim; 8],
+        subframe_bits: [0; 8],
     };
 
     let mut iter = block.stereo_samples();
@@ -591,12 +1381,218 @@ fn verify_block_stereo_samples_iterator() {
     assert_eq!(iter.next(), None);
 }
 
+/// The location of an encoded frame in the underlying stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FrameRange {
+    /// The offset of the first byte of the frame, counted from the start of the stream.
+    pub offset: u64,
+    /// The size of the frame in bytes, including header and footer.
+    pub len: u64,
+}
+
 /// Reads frames from a stream and exposes decoded blocks as an iterator.
 ///
+/// As an iterator, `None` means that the stream ended cleanly after a complete
+/// frame. A stream that ends in the middle of a frame, even in the middle of
+/// its header, yields an `Error::IoError` of kind `UnexpectedEof` instead.
+/// After an error, the iterator yields `None`. Every call to `next()` allocates
+/// a new buffer; use `read_next_or_eof()` to reuse buffers.
+///
 /// TODO: for now, it is assumes that the reader starts at a frame header;
 /// no searching for a sync code is performed at the moment.
 pub struct FrameReader<R: ReadBytes> {
     input: R,
+
+    /// Set when reading failed, after which the iterator yields no more blocks.
+    has_failed: bool,
+
+    /// Whether to end the stream at a metadata block that follows a frame.
+    stop_at_metadata: bool,
+
+    /// The largest block size seen in a frame that carries a frame number.
+    fixed_block_size: u16,
+
+    /// Frames with a larger block size are rejected before allocating a buffer.
+    max_block_size: u16,
+
+    /// The bits per sample for frames whose header refers to the streaminfo.
+    streaminfo_bits_per_sample: Option<u32>,
+
+    /// Called after every frame, with the total number of samples to report.
+    progress: Option<(ProgressCallback, Option<u64>)>,
+
+    /// Updated after every frame, if statistics are collected.
+    statistics: Option<SharedStatistics>,
+
+    /// Whether to verify that samples reconstructed from a stereo pair fit the bits per sample.
+    check_sample_range: bool,
+
+    /// After this instant, no further frames are decoded.
+    deadline: Option<Instant>,
+
+    /// The number of inter-channel samples decoded by this reader.
+    decoded_samples: u64,
+
+    /// Whether to pad a block shorter than `fixed_block_size` with zeros.
+    pad_final_block: bool,
+
+    /// The number of inter-channel padding samples added by this reader.
+    padding_samples: u64,
+
+    /// The buffer passed to the call of `read_next_or_eof()` that found the end.
+    unused_buffer: Vec<i32>,
+
+    /// The memory budget in bytes, and the part of it taken by other buffers, if bounded.
+    memory_budget: Option<(usize, usize)>,
+
+    /// Whether a stream that ends in the middle of a frame ends cleanly before that frame.
+    partial_frame_is_eof: bool,
+
+    /// Set when a partial frame ended the stream, after which no frames are read.
+    is_cut_off: bool,
+
+    /// A label that identifies the stream in errors, if set.
+    label: Option<Arc<String>>,
+}
+
+/// The number of decoded subframes of every subframe type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SubframeTypeCounts {
+    /// The number of constant subframes.
+    pub constant: u64,
+    /// The number of verbatim subframes.
+    pub verbatim: u64,
+    /// The number of subframes with a fixed predictor.
+    pub fixed: u64,
+    /// The number of subframes with a linear predictor.
+    pub lpc: u64,
+}
+
+impl SubframeTypeCounts {
+    /// Counts one subframe of the given type.
+    pub fn add(&mut self, sf_type: SubframeType) {
+        match sf_type {
+            SubframeType::Constant => self.constant += 1,
+            SubframeType::Verbatim => self.verbatim += 1,
+            SubframeType::Fixed(..) => self.fixed += 1,
+            SubframeType::Lpc(..) => self.lpc += 1,
+        }
+    }
+
+    /// Returns the total number of subframes.
+    pub fn total(&self) -> u64 {
+        self.constant + self.verbatim + self.fixed + self.lpc
+    }
+}
+
+/// Statistics about decoded frames, for profiling.
+///
+/// Only frames that were decoded successfully are counted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DecodeStatistics {
+    /// The number of decoded frames.
+    pub frames: u64,
+    /// The time spent decoding frames, in nanoseconds.
+    pub total_decode_nanos: u64,
+    /// The number of bytes that the decoded frames occupy in the stream.
+    ///
+    /// This is zero if the underlying reader does not report its position.
+    pub bytes_read: u64,
+    /// The number of decoded subframes of every type.
+    pub subframe_type_counts: SubframeTypeCounts,
+}
+
+/// Statistics that every frame reader created by a `FlacReader` adds to.
+pub type SharedStatistics = Arc<Mutex<DecodeStatistics>>;
+
+/// A pool of sample buffers, shared between readers that decode many streams.
+///
+/// Every iterator that decodes blocks needs a buffer the size of a block, and
+/// allocates it when it decodes its first frame. A batch decoder that handles
+/// many files, possibly on several threads, can share a pool instead, so the
+/// buffers of finished iterators are reused by new ones. Cloning a pool
+/// returns a handle to the same pool.
+///
+/// See `FlacReader::new_with_pool()`. Buffers can also be taken from and
+/// given to the pool directly, for use with `FrameReader::read_next_or_eof()`.
+#[derive(Clone, Debug, Default)]
+pub struct BufferPool {
+    buffers: Arc<Mutex<Vec<Vec<i32>>>>,
+}
+
+impl BufferPool {
+    /// Creates an empty pool.
+    pub fn new() -> BufferPool {
+        BufferPool::default()
+    }
+
+    /// Takes a buffer from the pool, or returns an empty one if the pool is empty.
+    ///
+    /// The contents of the buffer are unspecified.
+    pub fn take(&self) -> Vec<i32> {
+        match self.buffers.lock() {
+            Ok(mut buffers) => buffers.pop().unwrap_or_else(Vec::new),
+            Err(..) => Vec::new(),
+        }
+    }
+
+    /// Returns a buffer to the pool, so it can be taken again.
+    ///
+    /// Buffers without capacity are dropped, as reusing them saves nothing.
+    pub fn give(&self, buffer: Vec<i32>) {
+        if buffer.capacity() == 0 {
+            return
+        }
+        if let Ok(mut buffers) = self.buffers.lock() {
+            buffers.push(buffer);
+        }
+    }
+
+    /// Returns the number of buffers in the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.lock().map(|buffers| buffers.len()).unwrap_or(0)
+    }
+
+    /// Returns whether the pool holds no buffers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[test]
+fn verify_buffer_pool() {
+    let pool = BufferPool::new();
+    assert!(pool.is_empty());
+    assert_eq!(pool.take().capacity(), 0);
+
+    pool.give(Vec::new());
+    assert!(pool.is_empty());
+
+    let handle = pool.clone();
+    handle.give(Vec::with_capacity(4096));
+    assert_eq!(pool.len(), 1);
+    assert!(pool.take().capacity() >= 4096);
+    assert!(handle.is_empty());
+}
+
+/// A callback that reports decoding progress.
+///
+/// It is called with the number of inter-channel samples decoded so far (the
+/// end of the last decoded block), and the total number of inter-channel
+/// samples in the stream, if known. It is shared, so that every frame reader
+/// created by a `FlacReader` can report to it.
+pub type ProgressCallback = Arc<Mutex<FnMut(u64, Option<u64>) + Send>>;
+
+/// Returns `Error::DeadlineExceeded` if `deadline` has passed and decoding has started.
+///
+/// Before the first frame, the deadline is not checked, so that every reader
+/// decodes at least one frame.
+pub fn check_deadline(deadline: Instant, decoded_samples: u64) -> Result<()> {
+    if decoded_samples > 0 && Instant::now() >= deadline {
+        Err(Error::DeadlineExceeded { decoded_samples: decoded_samples })
+    } else {
+        Ok(())
+    }
 }
 
 /// Either a `Block` or an `Error`.
@@ -629,11 +1625,174 @@ fn ensure_buffer_len(mut buffer: Vec<i32>, new_len: usize) -> Vec<i32> {
 impl<R: ReadBytes> FrameReader<R> {
     /// Creates a new frame reader that will yield at least one element.
     pub fn new(input: R) -> FrameReader<R> {
+        FrameReader::new_ext(input, 0xffff)
+    }
+
+    /// Creates a new frame reader that rejects blocks larger than `max_block_size`.
+    ///
+    /// The block size determines how big the sample buffer must be, so this
+    /// bounds the memory allocated per frame. A frame with a larger block size
+    /// results in a format error. The block size can be at most 65535 anyway.
+    pub fn new_ext(input: R, max_block_size: u16) -> FrameReader<R> {
         FrameReader {
             input: input,
+            has_failed: false,
+            stop_at_metadata: false,
+            fixed_block_size: 0,
+            max_block_size: max_block_size,
+            streaminfo_bits_per_sample: None,
+            progress: None,
+            statistics: None,
+            check_sample_range: false,
+            deadline: None,
+            decoded_samples: 0,
+            pad_final_block: false,
+            padding_samples: 0,
+            unused_buffer: Vec::new(),
+            memory_budget: None,
+            partial_frame_is_eof: false,
+            is_cut_off: false,
+            label: None,
         }
     }
 
+    /// Sets the block size of a fixed-blocksize stream.
+    ///
+    /// The time of a block in such a stream is its frame number times the
+    /// block size. The final block may be shorter, so its own size cannot be
+    /// used. Without this, the block size is learned from the largest block
+    /// that has been read, which fails when the final block is the first block
+    /// that this reader reads, for instance after seeking.
+    pub fn set_fixed_block_size(&mut self, block_size: u16) {
+        self.fixed_block_size = block_size;
+    }
+
+    /// Sets the bits per sample from the streaminfo.
+    ///
+    /// The frame header has codes for the common bit depths only. For other
+    /// bit depths, such as 28, the header refers to the streaminfo instead.
+    /// Without this, such a frame results in `Error::Unsupported`.
+    pub fn set_streaminfo_bits_per_sample(&mut self, bits_per_sample: u32) {
+        self.streaminfo_bits_per_sample = Some(bits_per_sample);
+    }
+
+    /// Sets a callback that is invoked after every decoded frame.
+    ///
+    /// See `ProgressCallback` for the arguments; `total_samples` is passed
+    /// as the total.
+    pub fn set_progress_callback(&mut self, callback: ProgressCallback, total_samples: Option<u64>) {
+        self.progress = Some((callback, total_samples));
+    }
+
+    /// Sets statistics that are updated after every decoded frame.
+    ///
+    /// Frame decoding is timed only when this is set.
+    pub fn set_statistics(&mut self, statistics: SharedStatistics) {
+        self.statistics = Some(statistics);
+    }
+
+    /// Sets whether to verify that samples reconstructed from a stereo pair fit the bits per sample.
+    ///
+    /// In left-side, right-side, and mid-side stereo, a channel is computed
+    /// from two subframes, and in a corrupt frame it could exceed the bits
+    /// per sample of the frame. When this is set, such a frame results in a
+    /// format error. This costs a pass over the samples of the pair. Defaults
+    /// to false.
+    pub fn set_check_sample_range(&mut self, check_sample_range: bool) {
+        self.check_sample_range = check_sample_range;
+    }
+
+    /// Sets an instant after which decoding stops with `Error::DeadlineExceeded`.
+    ///
+    /// The deadline is checked before every frame header except the first one
+    /// that this reader reads, so the reader always makes progress, and the
+    /// cost is one clock read per frame. Once the deadline has passed, the
+    /// check fails even if the stream would have ended.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Bounds the size of the sample buffer, so that decoding takes at most `budget_bytes` bytes.
+    ///
+    /// Of the budget, `reserved_bytes` are taken by other buffers, such as
+    /// the read buffer of the input, and the rest is available for samples.
+    /// The size of the sample buffer follows from the block size and the
+    /// number of channels in the frame header, including padding of the final
+    /// block. A frame that would not fit in the budget results in
+    /// `Error::MemoryBudgetExceeded`, before the buffer is allocated. Buffers
+    /// passed to `read_next_or_eof()` are reused as they are, so their
+    /// capacity is not counted.
+    pub fn set_memory_budget(&mut self, budget_bytes: usize, reserved_bytes: usize) {
+        self.memory_budget = Some((budget_bytes, reserved_bytes));
+    }
+
+    /// Sets whether a metadata block after a frame ends the stream.
+    ///
+    /// Metadata blocks belong before the first frame, but some non-conformant
+    /// taggers append them after the last frame. A frame starts with the byte
+    /// 0xff, and a metadata block header never does, so when this is set, the
+    /// reader stops cleanly at any other byte, without consuming it. When not
+    /// set (the default), such a byte results in a format error.
+    pub fn set_stop_at_metadata(&mut self, stop_at_metadata: bool) {
+        self.stop_at_metadata = stop_at_metadata;
+    }
+
+    /// Sets whether to pad a short final block to the fixed block size.
+    ///
+    /// In a fixed-blocksize stream, the final block may be shorter than the
+    /// others. When this is set, and the fixed block size was set with
+    /// `set_fixed_block_size()`, such a block is padded with zeros at the end
+    /// of every channel, so all blocks have the same duration. The padding is
+    /// counted in `padding_samples()`, but not in progress reports. Padding
+    /// that would exceed the maximum block size is not added. Defaults to
+    /// false.
+    pub fn set_pad_final_block(&mut self, pad_final_block: bool) {
+        self.pad_final_block = pad_final_block;
+    }
+
+    /// Sets whether a stream that ends in the middle of a frame ends cleanly before it.
+    ///
+    /// This is for input that is known to be a prefix of a stream, such as a
+    /// partial download. When this is set, a frame that is cut off by the end
+    /// of the input is discarded, and `read_next_or_eof()` returns `Ok(None)`
+    /// instead of an error of kind `UnexpectedEof`, then and on every later
+    /// call. Defaults to false.
+    pub fn set_partial_frame_is_eof(&mut self, partial_frame_is_eof: bool) {
+        self.partial_frame_is_eof = partial_frame_is_eof;
+    }
+
+    /// Sets a label, such as a file name, that identifies the stream in errors.
+    ///
+    /// Errors returned by `read_next_or_eof()` are wrapped in
+    /// `Error::Labeled` with this label. See `FlacReader::set_label()`.
+    pub fn set_label(&mut self, label: Arc<String>) {
+        self.label = Some(label);
+    }
+
+    /// Attaches the label set with `set_label()` to an error, if there is one.
+    pub fn label_error(&self, error: Error) -> Error {
+        match self.label {
+            Some(ref label) => error.with_label(label),
+            None => error,
+        }
+    }
+
+    /// Returns the number of inter-channel padding samples added so far.
+    ///
+    /// This is nonzero only if padding was enabled with `set_pad_final_block()`.
+    pub fn padding_samples(&self) -> u64 {
+        self.padding_samples
+    }
+
+    /// Returns the buffer that was passed to `read_next_or_eof()` when it found the end of the stream.
+    ///
+    /// At the end of the stream, there is no block to move the buffer into,
+    /// so the reader keeps it, for instance to return it to a `BufferPool`.
+    /// Returns an empty buffer if the end has not been found.
+    pub fn take_unused_buffer(&mut self) -> Vec<i32> {
+        mem::replace(&mut self.unused_buffer, Vec::new())
+    }
+
     /// Decodes the next frame or returns an error if the data was invalid.
     ///
     /// The buffer is moved into the returned block, so that the same buffer may
@@ -642,78 +1801,213 @@ impl<R: ReadBytes> FrameReader<R> {
     /// buffer is not large enough to hold all samples, a larger buffer is
     /// allocated automatically.
     ///
+    /// Returns `Ok(None)` only if the stream ends right after the previous
+    /// frame, in which case the buffer can be retrieved with
+    /// `take_unused_buffer()`. If it ends in the middle of a frame, that is an
+    /// error of kind `UnexpectedEof`, unless `set_partial_frame_is_eof()` was
+    /// set.
+    ///
     /// TODO: I should really be consistent with 'read' and 'decode'.
-    pub fn read_next_or_eof(&mut self, mut buffer: Vec<i32>) -> FrameResult {
+    pub fn read_next_or_eof(&mut self, buffer: Vec<i32>) -> FrameResult {
+        if self.is_cut_off {
+            self.unused_buffer = buffer;
+            return Ok(None)
+        }
+
+        match self.read_next_frame(buffer) {
+            Err(Error::IoError(ref err)) if self.partial_frame_is_eof &&
+                                            err.kind() == io::ErrorKind::UnexpectedEof => {
+                self.is_cut_off = true;
+                Ok(None)
+            }
+            Err(err) => Err(self.label_error(err)),
+            result => result,
+        }
+    }
+
+    /// Implements `read_next_or_eof()`, returning an error for a partial frame.
+    fn read_next_frame(&mut self, mut buffer: Vec<i32>) -> FrameResult {
         // The frame includes a CRC-16 at the end. It can be computed
         // automatically while reading, by wrapping the input reader in a reader
         // that computes the CRC. If the stream ended before the the frame
         // header (so not in the middle of the frame header), return `None`,
         // indicating EOF.
+        // If another stream is chained after this one, its `fLaC` header
+        // follows the last frame. This stream has ended then.
+        if try!(self.input.peek_equals(&FLAC_MAGIC)) {
+            self.unused_buffer = buffer;
+            return Ok(None)
+        }
+
+        if self.stop_at_metadata {
+            match try!(self.input.peek_u8()) {
+                Some(byte) if byte != 0xff => {
+                    self.unused_buffer = buffer;
+                    return Ok(None)
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(deadline) = self.deadline {
+            try!(check_deadline(deadline, self.decoded_samples));
+        }
+
+        let start = match self.statistics {
+            Some(..) => Some((Instant::now(), self.input.byte_position())),
+            None => None,
+        };
+        let mut sf_counts = SubframeTypeCounts::default();
+
         let mut crc_input = Crc16Reader::new(&mut self.input);
         let header = match try!(read_frame_header_or_eof(&mut crc_input)) {
-            None => return Ok(None),
+            None => {
+                self.unused_buffer = buffer;
+                return Ok(None)
+            }
             Some(h) => h,
         };
 
+        // Check the block size before allocating anything; it comes straight
+        // from the stream, so it cannot be trusted.
+        if header.block_size > self.max_block_size {
+            return fmt_err("invalid frame header, block size exceeds maximum block size");
+        }
+        if let Some((budget_bytes, reserved_bytes)) = self.memory_budget {
+            // A final block that is padded below takes the fixed block size.
+            let is_padded = self.pad_final_block && header.block_size < self.fixed_block_size &&
+                            self.fixed_block_size <= self.max_block_size;
+            let buffer_block_size = if is_padded { self.fixed_block_size } else { header.block_size };
+            let buffer_len = header.channels() as usize * buffer_block_size as usize;
+            let needed_bytes = reserved_bytes + buffer_len * mem::size_of::<i32>();
+            if needed_bytes > budget_bytes {
+                return Err(Error::MemoryBudgetExceeded {
+                    needed_bytes: needed_bytes,
+                    budget_bytes: budget_bytes,
+                })
+            }
+        }
+
         // We must allocate enough space for all channels in the block to be
         // decoded.
         let total_samples = header.channels() as usize * header.block_size as usize;
         buffer = ensure_buffer_len(buffer, total_samples);
 
-        let bps = match header.bits_per_sample {
-            Some(x) => x,
-            // TODO: if the bps is missing from the header, we must get it from
-            // the streaminfo block.
-            None => return Err(Error::Unsupported("header without bits per sample info")),
+        let bps = match (header.bits_per_sample, self.streaminfo_bits_per_sample) {
+            (Some(x), _) => x,
+            (None, Some(x)) => x,
+            (None, None) => return Err(Error::Unsupported("header without bits per sample info")),
         };
 
-        // The number of bits per sample must not exceed 32, for we decode into
-        // an i32. TODO: Turn this into an error instead of panic? Or is it
-        // enforced elsewhere?
-        debug_assert!(bps as usize <= 32);
+        // The samples are decoded into an i32, so they can have at most 32
+        // bits. The side channel of decorrelated stereo has one bit more than
+        // the other channels, so there the limit is 31 bits. A frame can take
+        // its bits per sample from the streaminfo, which allows up to 32.
+        let is_decorrelated = match header.channel_assignment {
+            ChannelAssignment::Independent(..) => false,
+            _ => true,
+        };
+        if bps > 32 || (is_decorrelated && bps > 31) {
+            return Err(Error::Unsupported("decorrelated stereo with more than 31 bits per sample"));
+        }
 
         // In the next part of the stream, nothing is byte-aligned any more,
         // we need a bitstream. Then we can decode subframes from the bitstream.
+        // Keep track of which channels are constant, so consumers can skip
+        // processing them. For decorrelated stereo, a reconstructed channel is
+        // constant only if both of the subframes it is derived from are.
+        let constant_channels;
+        let mut subframe_types = [SubframeType::Verbatim; 8];
+        let mut subframe_bits = [0u32; 8];
+
         {
             let mut bitstream = Bitstream::new(&mut crc_input);
             let bs = header.block_size as usize;
 
             match header.channel_assignment {
                 ChannelAssignment::Independent(n_ch) => {
+                    let mut mask = 0u8;
                     for ch in 0..n_ch as usize {
-                        try!(subframe::decode(&mut bitstream,
-                                              bps,
-                                              &mut buffer[ch * bs..(ch + 1) * bs]));
+                        let start = bitstream.bit_position();
+                        let sf_type = try!(subframe::decode(&mut bitstream,
+                                                            bps,
+                                                            &mut buffer[ch * bs..(ch + 1) * bs]));
+                        subframe_bits[ch] = bits_since(&bitstream, start);
+                        sf_counts.add(sf_type);
+                        subframe_types[ch] = sf_type;
+                        if sf_type == SubframeType::Constant {
+                            mask = mask | (1 << ch);
+                        }
                     }
+                    constant_channels = mask;
                 }
                 ChannelAssignment::LeftSideStereo => {
                     // The side channel has one extra bit per sample.
-                    try!(subframe::decode(&mut bitstream, bps, &mut buffer[..bs]));
-                    try!(subframe::decode(&mut bitstream,
-                                          bps + 1,
-                                          &mut buffer[bs..bs * 2]));
+                    let start = bitstream.bit_position();
+                    let left = try!(subframe::decode(&mut bitstream, bps, &mut buffer[..bs]));
+                    subframe_bits[0] = bits_since(&bitstream, start);
+                    let start = bitstream.bit_position();
+                    let side = try!(subframe::decode(&mut bitstream,
+                                                     bps + 1,
+                                                     &mut buffer[bs..bs * 2]));
+                    subframe_bits[1] = bits_since(&bitstream, start);
+
+                    sf_counts.add(left);
+                    sf_counts.add(side);
+                    subframe_types[0] = left;
+                    subframe_types[1] = side;
 
                     // Then decode the side channel into the right channel.
                     decode_left_side(&mut buffer[..bs * 2]);
+                    if self.check_sample_range {
+                        try!(check_sample_range(&buffer[..bs * 2], bps));
+                    }
+                    constant_channels = stereo_constant_channels(left, side, 0b01);
                 }
                 ChannelAssignment::RightSideStereo => {
                     // The side channel has one extra bit per sample.
-                    try!(subframe::decode(&mut bitstream, bps + 1, &mut buffer[..bs]));
-                    try!(subframe::decode(&mut bitstream, bps, &mut buffer[bs..bs * 2]));
+                    let start = bitstream.bit_position();
+                    let side = try!(subframe::decode(&mut bitstream, bps + 1, &mut buffer[..bs]));
+                    subframe_bits[0] = bits_since(&bitstream, start);
+                    let start = bitstream.bit_position();
+                    let right = try!(subframe::decode(&mut bitstream, bps, &mut buffer[bs..bs * 2]));
+                    subframe_bits[1] = bits_since(&bitstream, start);
+
+                    sf_counts.add(side);
+                    sf_counts.add(right);
+                    subframe_types[0] = side;
+                    subframe_types[1] = right;
 
                     // Then decode the side channel into the left channel.
                     decode_right_side(&mut buffer[..bs * 2]);
+                    if self.check_sample_range {
+                        try!(check_sample_range(&buffer[..bs * 2], bps));
+                    }
+                    constant_channels = stereo_constant_channels(right, side, 0b10);
                 }
                 ChannelAssignment::MidSideStereo => {
                     // Decode mid as the first channel, then side with one
                     // extra bitp per sample.
-                    try!(subframe::decode(&mut bitstream, bps, &mut buffer[..bs]));
-                    try!(subframe::decode(&mut bitstream,
-                                          bps + 1,
-                                          &mut buffer[bs..bs * 2]));
+                    let start = bitstream.bit_position();
+                    let mid = try!(subframe::decode(&mut bitstream, bps, &mut buffer[..bs]));
+                    subframe_bits[0] = bits_since(&bitstream, start);
+                    let start = bitstream.bit_position();
+                    let side = try!(subframe::decode(&mut bitstream,
+                                                     bps + 1,
+                                                     &mut buffer[bs..bs * 2]));
+                    subframe_bits[1] = bits_since(&bitstream, start);
+
+                    sf_counts.add(mid);
+                    sf_counts.add(side);
+                    subframe_types[0] = mid;
+                    subframe_types[1] = side;
 
                     // Then decode mid-side channel into left-right.
                     decode_mid_side(&mut buffer[..bs * 2]);
+                    if self.check_sample_range {
+                        try!(check_sample_range(&buffer[..bs * 2], bps));
+                    }
+                    constant_channels = stereo_constant_channels(mid, side, 0b00);
                 }
             }
 
@@ -740,11 +2034,81 @@ impl<R: ReadBytes> FrameReader<R> {
         // TODO: constant block size should be verified if a frame number is
         // encountered.
         let time = match header.block_time {
-            BlockTime::FrameNumber(fnr) => header.block_size as u64 * fnr as u64,
+            BlockTime::FrameNumber(fnr) => {
+                // In a fixed-blocksize stream, all blocks have the same size,
+                // except for the last one, which may be shorter. Its time must
+                // be computed with the size of the other blocks. This cannot be
+                // done if it is the first frame read by this reader.
+                self.fixed_block_size = cmp::max(self.fixed_block_size, header.block_size);
+                self.fixed_block_size as u64 * fnr as u64
+            }
             BlockTime::SampleNumber(snr) => snr,
         };
 
-        let block = Block::new(time, header.block_size as u32, buffer);
+        let mut block_size = header.block_size;
+        let mut constant_channels = constant_channels;
+        if self.pad_final_block && block_size < self.fixed_block_size &&
+           self.fixed_block_size <= self.max_block_size {
+            let channels = header.channels() as usize;
+            let bs = block_size as usize;
+            let padded_bs = self.fixed_block_size as usize;
+            buffer.resize(channels * padded_bs, 0);
+
+            // Move every channel to its padded position, starting with the
+            // last one, so no channel is overwritten before it is moved.
+            for ch in (0..channels).rev() {
+                for i in (0..bs).rev() {
+                    buffer[ch * padded_bs + i] = buffer[ch * bs + i];
+                }
+                for x in &mut buffer[ch * padded_bs + bs..(ch + 1) * padded_bs] {
+                    *x = 0;
+                }
+                // A constant channel stays constant only if it is zero.
+                if constant_channels & (1 << ch) != 0 && buffer[ch * padded_bs] != 0 {
+                    constant_channels &= !(1 << ch);
+                }
+            }
+
+            self.padding_samples += (padded_bs - bs) as u64;
+            block_size = self.fixed_block_size;
+        }
+
+        let block = Block::new(time,
+                               block_size as u32,
+                               bps,
+                               buffer,
+                               constant_channels,
+                               header.channel_assignment.decorrelation(),
+                               subframe_types,
+                               subframe_bits);
+
+        if let (Some(statistics), Some((start_time, start_pos))) = (self.statistics.as_ref(), start) {
+            let elapsed = start_time.elapsed();
+            let nanos = elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64;
+            let bytes = match (start_pos, self.input.byte_position()) {
+                (Some(from), Some(to)) => to - from,
+                _ => 0,
+            };
+            if let Ok(mut stats) = statistics.lock() {
+                stats.frames += 1;
+                stats.total_decode_nanos += nanos;
+                stats.bytes_read += bytes;
+                let counts = &mut stats.subframe_type_counts;
+                counts.constant += sf_counts.constant;
+                counts.verbatim += sf_counts.verbatim;
+                counts.fixed += sf_counts.fixed;
+                counts.lpc += sf_counts.lpc;
+            }
+        }
+
+        self.decoded_samples += header.block_size as u64;
+
+        if let Some((ref callback, total_samples)) = self.progress {
+            // If the callback panicked before, it is not called any more.
+            if let Ok(mut callback) = callback.lock() {
+                (&mut *callback)(time + header.block_size as u64, total_samples);
+            }
+        }
 
         Ok(Some(block))
     }
@@ -755,5 +2119,131 @@ impl<R: ReadBytes> FrameReader<R> {
     }
 }
 
-// TODO: implement Iterator<Item = FrameResult> for FrameReader, with an
-// accurate size hint.
+#[test]
+fn verify_frame_reader_rejects_large_block_size() {
+    use std::io;
+
+    // A frame header with block size 4096, followed by garbage.
+    let header = vec![0xff, 0xf8, 0xc9, 0x08, 0x00, 0x95, 0x00, 0x00];
+    let mut reader = FrameReader::new_ext(io::Cursor::new(header), 1024);
+    assert_eq!(reader.read_next_or_eof(Vec::new()).err().unwrap(),
+               Error::FormatError("invalid frame header, block size exceeds maximum block size"));
+
+    // The block size code 0000, which would encode an empty block, is reserved.
+    let header = vec![0xff, 0xf8, 0x09, 0x08, 0x00, 0x00, 0x00, 0x00];
+    let mut reader = FrameReader::new(io::Cursor::new(header));
+    assert_eq!(reader.read_next_or_eof(Vec::new()).err().unwrap(),
+               Error::FormatError("invalid frame header, encountered reserved value"));
+
+    // A frame header with a 16-bit block size of 65536.
+    let header = vec![0xff, 0xf8, 0x79, 0x08, 0x00, 0xff, 0xff, 0x45, 0x00, 0x00];
+    let mut reader = FrameReader::new(io::Cursor::new(header));
+    assert_eq!(reader.read_next_or_eof(Vec::new()).err().unwrap(),
+               Error::FormatError("invalid block size, exceeds 65535"));
+}
+
+#[test]
+fn verify_frame_reader_rejects_32_bit_decorrelated_stereo() {
+    use std::io;
+
+    // A left-side stereo frame header with block size 192, that takes its bits
+    // per sample from the streaminfo, followed by garbage. With 32 bits per
+    // sample, the side channel would need 33 bits.
+    let header = vec![0xff, 0xf8, 0x19, 0x80, 0x00, 0xa4, 0x00, 0x00];
+    let mut reader = FrameReader::new(io::Cursor::new(header));
+    reader.set_streaminfo_bits_per_sample(32);
+    assert_eq!(reader.read_next_or_eof(Vec::new()).err().unwrap(),
+               Error::Unsupported("decorrelated stereo with more than 31 bits per sample"));
+
+    // Independent channels of 32 bits fit, so decoding proceeds to the
+    // subframes, and fails there on the garbage instead.
+    let header = vec![0xff, 0xf8, 0x19, 0x10, 0x00, 0x45, 0x00, 0x00];
+    let mut reader = FrameReader::new(io::Cursor::new(header));
+    reader.set_streaminfo_bits_per_sample(32);
+    match reader.read_next_or_eof(Vec::new()) {
+        Err(Error::Unsupported(..)) => panic!("32-bit independent channels must be supported"),
+        Err(..) => {}
+        Ok(..) => panic!("expected an error for the garbage subframes"),
+    }
+}
+
+#[test]
+fn verify_frame_reader_rejects_invalid_sample_rate_code() {
+    use std::io;
+
+    // A frame header with block size 4096 and sample rate code 1111, followed
+    // by garbage. The header is rejected before its CRC is checked.
+    let header = vec![0xff, 0xf8, 0xcf, 0x08, 0x00, 0x00, 0x00, 0x00];
+    let mut reader = FrameReader::new(io::Cursor::new(header));
+    assert_eq!(reader.read_next_or_eof(Vec::new()).err().unwrap(),
+               Error::FormatError("invalid frame header, invalid sample rate code"));
+}
+
+impl<R: ReadBytes> Iterator for FrameReader<R> {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Result<Block>> {
+        if self.has_failed {
+            return None
+        }
+
+        match self.read_next_or_eof(Vec::new()) {
+            Ok(None) => None,
+            Ok(Some(block)) => Some(Ok(block)),
+            Err(error) => {
+                self.has_failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+// TODO: Provide an accurate size hint for the FrameReader iterator.
+
+/// An iterator over blocks that also yields the sample position of each block.
+///
+/// The position of the first block is taken from its frame header, after that
+/// positions are the running sum of the block durations. This is correct for
+/// variable block sizes and for a short final block alike.
+pub struct BlocksWithPosition<R: ReadBytes> {
+    frame_reader: FrameReader<R>,
+    position: Option<u64>,
+
+    /// If reading ever failed, this flag is set, so that the iterator knows not
+    /// to return any new values.
+    has_failed: bool,
+}
+
+impl<R: ReadBytes> BlocksWithPosition<R> {
+    /// Wraps a frame reader to track the position of the blocks it yields.
+    pub fn new(frame_reader: FrameReader<R>) -> BlocksWithPosition<R> {
+        BlocksWithPosition {
+            frame_reader: frame_reader,
+            position: None,
+            has_failed: false,
+        }
+    }
+}
+
+impl<R: ReadBytes> Iterator for BlocksWithPosition<R> {
+    type Item = Result<(u64, Block)>;
+
+    fn next(&mut self) -> Option<Result<(u64, Block)>> {
+        if self.has_failed {
+            return None
+        }
+
+        match self.frame_reader.read_next_or_eof(Vec::new()) {
+            Ok(None) => None,
+            Ok(Some(block)) => {
+                let position = self.position.unwrap_or(block.time());
+                self.position = Some(position + block.duration() as u64);
+                Some(Ok((position, block)))
+            }
+            Err(error) => {
+                self.has_failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
@@ -15,6 +15,9 @@
 use std::cmp;
 use std::io;
 
+/// The size of the buffer of a `BufferedReader`, in bytes.
+pub const BUFFER_SIZE: usize = 2048;
+
 /// Similar to `std::io::BufRead`, but more performant.
 ///
 /// There is no simple way to wrap a standard `BufRead` such that it can compute
@@ -33,19 +36,101 @@ pub struct BufferedReader<R: io::Read> {
 
     /// The number of bytes of the buffer which have meaningful content.
     num_valid: u32,
+
+    /// The number of bytes read from the inner reader before the buffer.
+    buf_offset: u64,
+
+    /// The position beyond which nothing is read from the inner reader.
+    limit: u64,
 }
 
 impl<R: io::Read> BufferedReader<R> {
 
     /// Wrap the reader in a new buffered reader.
     pub fn new(inner: R) -> BufferedReader<R> {
-        let buf = vec![0; 2048].into_boxed_slice();
+        let buf = vec![0; BUFFER_SIZE].into_boxed_slice();
         BufferedReader {
             inner: inner,
             buf: buf,
             pos: 0,
             num_valid: 0,
+            buf_offset: 0,
+            limit: u64::max_value(),
+        }
+    }
+
+    /// Stops reading from the inner reader at `limit`, as returned by `position()`.
+    ///
+    /// Beyond the limit, the reader behaves as if the stream ended there, also
+    /// for `end_position()`. Bytes that were buffered before the limit was set
+    /// remain available.
+    pub fn set_limit(&mut self, limit: u64) {
+        self.limit = limit;
+    }
+
+    /// Returns the number of bytes consumed since the reader was wrapped.
+    ///
+    /// This is the offset in the inner reader of the next byte to be read,
+    /// relative to the position of the inner reader when it was wrapped. Bytes
+    /// that have been buffered but not yet consumed are not counted.
+    pub fn position(&self) -> u64 {
+        self.buf_offset + self.pos as u64
+    }
+
+    /// Returns the next `n` bytes without consuming them.
+    ///
+    /// Fewer bytes are returned only if the end of the stream is reached first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` exceeds the size of the buffer, which is 2048 bytes.
+    pub fn peek(&mut self, n: u32) -> io::Result<&[u8]> {
+        assert!(n as usize <= self.buf.len(), "cannot peek beyond the buffer size");
+
+        if self.num_valid - self.pos < n {
+            // Move the unconsumed bytes to the front of the buffer, and fill
+            // the remainder of the buffer after them.
+            let num_left = self.num_valid - self.pos;
+            for i in 0..num_left as usize {
+                self.buf[i] = self.buf[self.pos as usize + i];
+            }
+            self.buf_offset += self.pos as u64;
+            self.pos = 0;
+            self.num_valid = num_left;
+
+            while self.num_valid < n {
+                let from = self.num_valid;
+                let num_read = try!(self.read_inner(from));
+                if num_read == 0 {
+                    break
+                }
+                self.num_valid += num_read as u32;
+            }
         }
+
+        let end = cmp::min(self.num_valid, self.pos + n);
+        Ok(&self.buf[self.pos as usize..end as usize])
+    }
+
+    /// Replaces the contents of the buffer with new data from the inner reader.
+    #[inline(always)]
+    fn refill(&mut self) -> io::Result<()> {
+        self.buf_offset += self.num_valid as u64;
+        self.pos = 0;
+        self.num_valid = 0;
+        self.num_valid = try!(self.read_inner(0)) as u32;
+        Ok(())
+    }
+
+    /// Reads from the inner reader into the buffer, starting at index `from`, up to the limit.
+    #[inline(always)]
+    fn read_inner(&mut self, from: u32) -> io::Result<usize> {
+        let remaining = self.limit.saturating_sub(self.buf_offset + from as u64);
+        let end = cmp::min(self.buf.len() as u64, from as u64 + remaining) as usize;
+        if end == from as usize {
+            return Ok(0)
+        }
+        self.inner.read(&mut self.buf[from as usize..end])
     }
 
     /// Destroys the buffered reader, returning the wrapped reader.
@@ -54,8 +139,50 @@ impl<R: io::Read> BufferedReader<R> {
     pub fn into_inner(self) -> R {
         self.inner
     }
+
+    /// Destroys the buffered reader, returning the wrapped reader and the unconsumed buffered bytes.
+    ///
+    /// The bytes have been read from the wrapped reader already, but they were
+    /// not consumed yet. They precede whatever the wrapped reader reads next.
+    pub fn into_inner_with_buffer(self) -> (R, Vec<u8>) {
+        let buffered = self.buf[self.pos as usize..self.num_valid as usize].to_vec();
+        (self.inner, buffered)
+    }
 }
 
+impl<R: io::Read + io::Seek> BufferedReader<R> {
+    /// Moves to the given position, as returned by `position()`.
+    ///
+    /// If the position is inside the current buffer, no seek on the inner
+    /// reader is performed.
+    pub fn seek_to(&mut self, position: u64) -> io::Result<()> {
+        let buf_end = self.buf_offset + self.num_valid as u64;
+        if position >= self.buf_offset && position <= buf_end {
+            self.pos = (position - self.buf_offset) as u32;
+            return Ok(())
+        }
+
+        // The inner reader is positioned at the end of the buffer.
+        let delta = position as i64 - buf_end as i64;
+        try!(self.inner.seek(io::SeekFrom::Current(delta)));
+        self.buf_offset = position;
+        self.pos = 0;
+        self.num_valid = 0;
+        Ok(())
+    }
+
+    /// Returns the position of the end of the stream, as it would be returned by `position()`.
+    ///
+    /// This does not change the current position.
+    pub fn end_position(&mut self) -> io::Result<u64> {
+        // The inner reader is positioned at the end of the buffer.
+        let buf_end = self.buf_offset + self.num_valid as u64;
+        let current = try!(self.inner.seek(io::SeekFrom::Current(0)));
+        let end = try!(self.inner.seek(io::SeekFrom::End(0)));
+        try!(self.inner.seek(io::SeekFrom::Start(current)));
+        Ok(cmp::min(buf_end + (end - current), self.limit))
+    }
+}
 
 /// Provides convenience methods to make input less cumbersome.
 pub trait ReadBytes {
@@ -73,6 +200,29 @@ pub trait ReadBytes {
     /// For a buffered reader, this can help a lot by just bumping a pointer.
     fn skip(&mut self, amount: u32) -> io::Result<()>;
 
+    /// Returns the next byte without consuming it, or `None` at the end of the stream.
+    fn peek_u8(&mut self) -> io::Result<Option<u8>>;
+
+    /// Returns whether the next bytes are equal to `bytes`, without consuming them.
+    ///
+    /// Readers that cannot look ahead return false.
+    fn peek_equals(&mut self, _bytes: &[u8]) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Returns false if the stream is known to end within the next `n` bytes.
+    ///
+    /// This is a hint to fail early on truncated input, it does not consume
+    /// anything. Readers that cannot look ahead far enough return true.
+    fn may_have_bytes(&mut self, _n: u64) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Returns the number of bytes consumed so far, if the reader keeps track.
+    fn byte_position(&self) -> Option<u64> {
+        None
+    }
+
     /// Reads two bytes and interprets them as a big-endian 16-bit unsigned integer.
     fn read_be_u16(&mut self) -> io::Result<u16> {
         let b0 = try!(self.read_u8()) as u16;
@@ -81,13 +231,17 @@ pub trait ReadBytes {
     }
 
     /// Reads two bytes and interprets them as a big-endian 16-bit unsigned integer.
+    ///
+    /// Returns `None` if the stream ends before the first byte. If the stream
+    /// ends after the first byte, that is an `UnexpectedEof` error.
     fn read_be_u16_or_eof(&mut self) -> io::Result<Option<u16>> {
-        if let Some(b0) = try!(self.read_u8_or_eof()) {
-            if let Some(b1) = try!(self.read_u8_or_eof()) {
-                return Ok(Some((b0 as u16) << 8 | (b1 as u16)));
+        match try!(self.read_u8_or_eof()) {
+            Some(b0) => {
+                let b1 = try!(self.read_u8());
+                Ok(Some((b0 as u16) << 8 | (b1 as u16)))
             }
+            None => Ok(None),
         }
-        Ok(None)
     }
 
     /// Reads three bytes and interprets them as a big-endian 24-bit unsigned integer.
@@ -107,6 +261,13 @@ pub trait ReadBytes {
         Ok(b0 << 24 | b1 << 16 | b2 << 8 | b3)
     }
 
+    /// Reads eight bytes and interprets them as a big-endian 64-bit unsigned integer.
+    fn read_be_u64(&mut self) -> io::Result<u64> {
+        let msb = try!(self.read_be_u32()) as u64;
+        let lsb = try!(self.read_be_u32()) as u64;
+        Ok(msb << 32 | lsb)
+    }
+
     /// Reads four bytes and interprets them as a little-endian 32-bit unsigned integer.
     fn read_le_u32(&mut self) -> io::Result<u32> {
         let b0 = try!(self.read_u8()) as u32;
@@ -123,8 +284,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
     fn read_u8(&mut self) -> io::Result<u8> {
         if self.pos == self.num_valid {
             // The buffer was depleted, replenish it first.
-            self.pos = 0;
-            self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+            try!(self.refill());
 
             if self.num_valid == 0 {
                 return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
@@ -143,8 +303,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
     fn read_u8_or_eof(&mut self) -> io::Result<Option<u8>> {
         if self.pos == self.num_valid {
             // The buffer was depleted, try to replenish it first.
-            self.pos = 0;
-            self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+            try!(self.refill());
 
             if self.num_valid == 0 {
                 return Ok(None);
@@ -167,8 +326,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
 
             if bytes_left > 0 {
                 // Replenish the buffer if there is more to be read.
-                self.pos = 0;
-                self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+                try!(self.refill());
                 if self.num_valid == 0 {
                     return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
                                               "Expected more bytes."))
@@ -188,8 +346,7 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
 
             if amount > 0 {
                 // If there is more to skip, refill the buffer first.
-                self.pos = 0;
-                self.num_valid = try!(self.inner.read(&mut self.buf)) as u32;
+                try!(self.refill());
 
                 if self.num_valid == 0 {
                     return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
@@ -199,6 +356,35 @@ impl<R: io::Read> ReadBytes for BufferedReader<R>
         }
         Ok(())
     }
+
+    fn peek_u8(&mut self) -> io::Result<Option<u8>> {
+        if self.pos < self.num_valid {
+            return Ok(Some(self.buf[self.pos as usize]))
+        }
+        let next = try!(self.peek(1));
+        Ok(next.first().cloned())
+    }
+
+    fn peek_equals(&mut self, bytes: &[u8]) -> io::Result<bool> {
+        let next = try!(self.peek(bytes.len() as u32));
+        Ok(next == bytes)
+    }
+
+    fn may_have_bytes(&mut self, n: u64) -> io::Result<bool> {
+        if n <= (self.num_valid - self.pos) as u64 {
+            return Ok(true)
+        }
+
+        // We can only look as far ahead as the buffer is long. If the stream
+        // ends before that, we know for sure.
+        let lookahead = cmp::min(n, self.buf.len() as u64) as u32;
+        let num_available = try!(self.peek(lookahead)).len() as u32;
+        Ok(num_available == lookahead)
+    }
+
+    fn byte_position(&self) -> Option<u64> {
+        Some(self.position())
+    }
 }
 
 impl<'r, R: ReadBytes> ReadBytes for &'r mut R {
@@ -219,6 +405,22 @@ impl<'r, R: ReadBytes> ReadBytes for &'r mut R {
     fn skip(&mut self, amount: u32) -> io::Result<()> {
         (*self).skip(amount)
     }
+
+    fn peek_u8(&mut self) -> io::Result<Option<u8>> {
+        (*self).peek_u8()
+    }
+
+    fn peek_equals(&mut self, bytes: &[u8]) -> io::Result<bool> {
+        (*self).peek_equals(bytes)
+    }
+
+    fn may_have_bytes(&mut self, n: u64) -> io::Result<bool> {
+        (*self).may_have_bytes(n)
+    }
+
+    fn byte_position(&self) -> Option<u64> {
+        (**self).byte_position()
+    }
 }
 
 impl<T: AsRef<[u8]>> ReadBytes for io::Cursor<T> {
@@ -265,6 +467,25 @@ impl<T: AsRef<[u8]>> ReadBytes for io::Cursor<T> {
             Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected eof"))
         }
     }
+    fn peek_u8(&mut self) -> io::Result<Option<u8>> {
+        let pos = self.position();
+        Ok(self.get_ref().as_ref().get(pos as usize).cloned())
+    }
+
+    fn peek_equals(&mut self, bytes: &[u8]) -> io::Result<bool> {
+        let data = self.get_ref().as_ref();
+        let pos = cmp::min(self.position(), data.len() as u64) as usize;
+        Ok(data[pos..].starts_with(bytes))
+    }
+
+    fn may_have_bytes(&mut self, n: u64) -> io::Result<bool> {
+        let len = self.get_ref().as_ref().len() as u64;
+        Ok(self.position().saturating_add(n) <= len)
+    }
+
+    fn byte_position(&self) -> Option<u64> {
+        Some(self.position())
+    }
 }
 
 #[test]
@@ -333,6 +554,20 @@ fn verify_read_be_u16_cursor() {
     assert!(cursor.read_be_u16().is_err());
 }
 
+#[test]
+fn verify_read_be_u16_or_eof_buffered_reader() {
+    let mut reader = BufferedReader::new(io::Cursor::new(vec![0u8, 2, 0x80, 0x01]));
+    assert_eq!(reader.read_be_u16_or_eof().ok(), Some(Some(2)));
+    assert_eq!(reader.read_be_u16_or_eof().ok(), Some(Some(32769)));
+    assert_eq!(reader.read_be_u16_or_eof().ok(), Some(None));
+
+    // A single trailing byte is not a clean end of the stream.
+    let mut reader = BufferedReader::new(io::Cursor::new(vec![0u8, 2, 0xff]));
+    assert_eq!(reader.read_be_u16_or_eof().ok(), Some(Some(2)));
+    let err = reader.read_be_u16_or_eof().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
 #[test]
 fn verify_read_be_u24_buffered_reader() {
     let mut reader = BufferedReader::new(io::Cursor::new(vec![0u8, 0, 2, 0x8f, 0xff, 0xf3, 122]));
@@ -365,6 +600,24 @@ fn verify_read_be_u32_cursor() {
     assert!(cursor.read_be_u32().is_err());
 }
 
+#[test]
+fn verify_read_be_u64_buffered_reader() {
+    let mut reader = BufferedReader::new(io::Cursor::new(vec![0u8, 0, 0, 0, 0, 0, 0, 2,
+                                                              0x80, 0x01, 0xff, 0xe9, 0, 0, 0, 1, 0]));
+    assert_eq!(reader.read_be_u64().ok(), Some(2));
+    assert_eq!(reader.read_be_u64().ok(), Some(0x8001_ffe9_0000_0001));
+    assert!(reader.read_be_u64().is_err());
+}
+
+#[test]
+fn verify_read_be_u64_cursor() {
+    let mut cursor = io::Cursor::new(vec![0u8, 0, 0, 0, 0, 0, 0, 2,
+                                          0x80, 0x01, 0xff, 0xe9, 0, 0, 0, 1, 0]);
+    assert_eq!(cursor.read_be_u64().ok(), Some(2));
+    assert_eq!(cursor.read_be_u64().ok(), Some(0x8001_ffe9_0000_0001));
+    assert!(cursor.read_be_u64().is_err());
+}
+
 #[test]
 fn verify_read_le_u32_buffered_reader() {
     let mut reader = BufferedReader::new(io::Cursor::new(vec![2u8, 0, 0, 0, 0xe9, 0xff, 0x01, 0x80, 0]));
@@ -381,6 +634,184 @@ fn verify_read_le_u32_cursor() {
     assert!(reader.read_le_u32().is_err());
 }
 
+#[test]
+fn verify_position_buffered_reader() {
+    // Use more data than fits in the buffer, to cover refills.
+    let data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+    let mut reader = BufferedReader::new(io::Cursor::new(data));
+    assert_eq!(reader.position(), 0);
+    reader.read_u8().unwrap();
+    assert_eq!(reader.position(), 1);
+    reader.skip(2100).unwrap();
+    assert_eq!(reader.position(), 2101);
+    let mut buf = [0u8; 2000];
+    reader.read_into(&mut buf).unwrap();
+    assert_eq!(reader.position(), 4101);
+    assert_eq!(reader.read_u8().unwrap(), (4101 % 256) as u8);
+    reader.skip(898).unwrap();
+    assert_eq!(reader.position(), 5000);
+    assert_eq!(reader.read_u8_or_eof().unwrap(), None);
+    assert_eq!(reader.position(), 5000);
+}
+
+#[test]
+fn verify_peek_buffered_reader() {
+    let data: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+    let mut reader = BufferedReader::new(io::Cursor::new(data));
+    assert_eq!(reader.peek(3).unwrap(), &[0, 1, 2]);
+    assert_eq!(reader.position(), 0);
+
+    // Peeking across the end of the buffer must not lose or reorder bytes.
+    reader.skip(2040).unwrap();
+    assert_eq!(reader.peek(16).unwrap(), &[248, 249, 250, 251, 252, 253, 254, 255,
+                                           0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(reader.position(), 2040);
+    assert_eq!(reader.read_u8().unwrap(), 248);
+
+    // Near the end of the stream, fewer bytes are returned.
+    reader.skip(955).unwrap();
+    assert_eq!(reader.peek(8).unwrap(), &[(2996 % 256) as u8, (2997 % 256) as u8,
+                                          (2998 % 256) as u8, (2999 % 256) as u8]);
+    assert_eq!(reader.position(), 2996);
+}
+
+#[test]
+fn verify_peek_equals() {
+    let data = vec![b'f', b'L', b'a', b'C', 0];
+    let mut reader = BufferedReader::new(io::Cursor::new(data.clone()));
+    let mut cursor = io::Cursor::new(data);
+    assert!(reader.peek_equals(b"fLaC").unwrap());
+    assert!(cursor.peek_equals(b"fLaC").unwrap());
+    assert_eq!(reader.read_u8().unwrap(), b'f');
+    assert_eq!(cursor.read_u8().unwrap(), b'f');
+    assert!(!reader.peek_equals(b"fLaC").unwrap());
+    assert!(!cursor.peek_equals(b"fLaC").unwrap());
+
+    assert_eq!(reader.peek_u8().unwrap(), Some(b'L'));
+    assert_eq!(cursor.peek_u8().unwrap(), Some(b'L'));
+
+    // Near the end, the remaining bytes are too short to match.
+    reader.skip(3).unwrap();
+    cursor.skip(3).unwrap();
+    assert!(reader.peek_equals(b"").unwrap());
+    assert!(!reader.peek_equals(b"\0\0").unwrap());
+    assert!(!cursor.peek_equals(b"\0\0").unwrap());
+    reader.skip(1).unwrap();
+    cursor.skip(1).unwrap();
+    assert_eq!(reader.peek_u8().unwrap(), None);
+    assert_eq!(cursor.peek_u8().unwrap(), None);
+}
+
+#[test]
+fn verify_into_inner_with_buffer() {
+    let data: Vec<u8> = (0..100).collect();
+    let mut reader = BufferedReader::new(io::Cursor::new(data));
+    reader.skip(10).unwrap();
+    let (cursor, buffered) = reader.into_inner_with_buffer();
+    let buffered_end = 10 + buffered.len();
+    assert_eq!(&buffered[..], &(10..buffered_end as u8).collect::<Vec<u8>>()[..]);
+    assert_eq!(cursor.position(), buffered_end as u64);
+}
+
+#[test]
+fn verify_seek_to_buffered_reader() {
+    let data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+    let mut reader = BufferedReader::new(io::Cursor::new(data));
+    reader.skip(3000).unwrap();
+
+    // Backwards out of the buffer, within the buffer, and forward past it.
+    for &target in &[10, 12, 4321, 4000, 2047, 2048] {
+        reader.seek_to(target).unwrap();
+        assert_eq!(reader.position(), target);
+        assert_eq!(reader.read_u8().unwrap(), target as u8);
+    }
+}
+
+#[test]
+fn verify_end_position_buffered_reader() {
+    let data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+    let mut cursor = io::Cursor::new(data);
+    cursor.set_position(100);
+    let mut reader = BufferedReader::new(cursor);
+    reader.skip(3000).unwrap();
+
+    // Positions are relative to where the buffered reader started.
+    assert_eq!(reader.end_position().unwrap(), 4900);
+    assert_eq!(reader.position(), 3000);
+    assert_eq!(reader.read_u8().unwrap(), 3100u32 as u8);
+}
+
+/// A reader that reads a sequence of readers one after another.
+///
+/// This presents a stream that is split over several parts, such as the
+/// `.001`, `.002`, etc. files of a split archive, as a single stream. When a
+/// part is exhausted, reading continues with the next one, so parts may be
+/// split at any byte. A `ChainReader` can be passed to `FlacReader::new()`
+/// like any other reader. To chain readers of different types, use boxed
+/// readers.
+pub struct ChainReader<R: io::Read> {
+    /// The parts, in order.
+    readers: Vec<R>,
+
+    /// The index of the part that is currently being read.
+    current: usize,
+}
+
+impl<R: io::Read> ChainReader<R> {
+    /// Creates a reader that reads `readers` in order.
+    pub fn new(readers: Vec<R>) -> ChainReader<R> {
+        ChainReader {
+            readers: readers,
+            current: 0,
+        }
+    }
+
+    /// Destroys the chain reader and returns the parts.
+    pub fn into_inner(self) -> Vec<R> {
+        self.readers
+    }
+}
+
+impl<R: io::Read> io::Read for ChainReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0)
+        }
+        while self.current < self.readers.len() {
+            let n = try!(self.readers[self.current].read(buf));
+            if n > 0 {
+                return Ok(n)
+            }
+            self.current += 1;
+        }
+        Ok(0)
+    }
+}
+
+#[test]
+fn verify_chain_reader() {
+    use std::io::Read;
+    let parts = vec![io::Cursor::new(vec![1u8, 2, 3]),
+                     io::Cursor::new(vec![]),
+                     io::Cursor::new(vec![4u8]),
+                     io::Cursor::new(vec![5u8, 6])];
+    let mut reader = ChainReader::new(parts);
+    let mut buf = [0u8; 2];
+    assert_eq!(reader.read(&mut buf).unwrap(), 2);
+    assert_eq!(buf, [1, 2]);
+
+    // A read does not span parts, but all bytes arrive in order.
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, [3, 4, 5, 6]);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+    // Across a buffered reader, multi-byte reads span parts.
+    let parts = vec![io::Cursor::new(vec![0x12u8]), io::Cursor::new(vec![0x34u8, 0x56, 0x78])];
+    let mut reader = BufferedReader::new(ChainReader::new(parts));
+    assert_eq!(reader.read_be_u32().unwrap(), 0x12345678);
+}
+
 /// Left shift that does not panic when shifting by the integer width.
 #[inline(always)]
 fn shift_left(x: u8, shift: u32) -> u8 {
@@ -630,6 +1061,83 @@ impl<R: ReadBytes> Bitstream<R> {
             Ok((msb << (bits - 16)) | lsb)
         }
     }
+
+    /// Returns false if the input is known to end within the next `bits` bits.
+    ///
+    /// See `ReadBytes::may_have_bytes()`. Nothing is consumed.
+    pub fn may_have_bits(&mut self, bits: u64) -> io::Result<bool> {
+        if bits <= self.bits_left as u64 {
+            return Ok(true)
+        }
+        let bits_needed = bits - self.bits_left as u64;
+        self.reader.may_have_bytes((bits_needed + 7) / 8)
+    }
+
+    /// Returns the number of bits consumed so far, if the reader keeps track.
+    ///
+    /// This counts from the position where the underlying reader started
+    /// counting, see `ReadBytes::byte_position()`. The bits of a partially
+    /// consumed byte count individually.
+    pub fn bit_position(&self) -> Option<u64> {
+        self.reader.byte_position().map(|bytes| bytes * 8 - self.bits_left as u64)
+    }
+
+    /// Returns the number of whole bytes consumed so far, if the reader keeps track.
+    ///
+    /// A partially consumed byte is not counted. At a byte boundary, such as
+    /// between frames, this equals the byte position of the underlying reader.
+    pub fn byte_position(&self) -> Option<u64> {
+        self.bit_position().map(|bits| bits / 8)
+    }
+}
+
+#[test]
+fn verify_bitstream_position() {
+    let data = io::Cursor::new(vec![0b1010_0100, 0b1110_0001, 0xff]);
+    let mut bits = Bitstream::new(BufferedReader::new(data));
+    assert_eq!(bits.bit_position(), Some(0));
+
+    bits.read_leq_u8(3).unwrap();
+    assert_eq!(bits.bit_position(), Some(3));
+    assert_eq!(bits.byte_position(), Some(0));
+
+    bits.read_leq_u16(9).unwrap();
+    assert_eq!(bits.bit_position(), Some(12));
+    assert_eq!(bits.byte_position(), Some(1));
+
+    bits.read_leq_u8(4).unwrap();
+    assert_eq!(bits.bit_position(), Some(16));
+    assert_eq!(bits.byte_position(), Some(2));
+
+    // The position does not depend on how far the reader buffered.
+    let mut bits = Bitstream::new(io::Cursor::new(vec![0u8, 0]));
+    bits.read_bit().unwrap();
+    assert_eq!(bits.bit_position(), Some(1));
+}
+
+#[test]
+fn verify_may_have_bytes() {
+    let data = vec![2u8, 3, 5, 7, 11, 13, 17, 19, 23];
+
+    let mut cursor = io::Cursor::new(data.clone());
+    cursor.read_u8().unwrap();
+    assert!(cursor.may_have_bytes(8).unwrap());
+    assert!(!cursor.may_have_bytes(9).unwrap());
+
+    let mut reader = BufferedReader::new(io::Cursor::new(data.clone()));
+    reader.read_u8().unwrap();
+    assert!(reader.may_have_bytes(8).unwrap());
+    assert!(!reader.may_have_bytes(9).unwrap());
+    assert!(!reader.may_have_bytes(1 << 20).unwrap());
+
+    // Beyond the buffer size, a longer stream cannot be ruled out.
+    let mut reader = BufferedReader::new(io::Cursor::new(vec![0u8; 4096]));
+    assert!(reader.may_have_bytes(4000).unwrap());
+
+    let mut bits = Bitstream::new(BufferedReader::new(io::Cursor::new(data)));
+    bits.read_leq_u8(3).unwrap();
+    assert!(bits.may_have_bits(69).unwrap());
+    assert!(!bits.may_have_bits(70).unwrap());
 }
 
 #[test]
@@ -68,32 +68,143 @@
 
 #![warn(missing_docs)]
 
+#[cfg(feature = "dasp")]
+extern crate dasp;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "rodio")]
+extern crate rodio;
+
+use std::cmp;
 use std::fs;
 use std::io;
+use std::marker::PhantomData;
 use std::mem;
+use std::ops;
+use std::panic;
 use std::path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 use error::fmt_err;
-use frame::FrameReader;
+use frame::{BlocksWithPosition, DecodeStatistics, FrameRange, FrameReader, ProgressCallback, SharedStatistics,
+            SubframeTypeCounts};
 use input::{BufferedReader, ReadBytes};
-use metadata::{MetadataBlock, MetadataBlockReader, StreamInfo, VorbisComment};
+use md5::Md5;
+use metadata::{BroadcastInfo, ChannelMask, CueSheet, CueSheetTrack, EncoderHints, ForeignMetadata, Gain, Identifiers, Lyrics, MetadataBlock, MetadataBlockHeader, MetadataBlockReader, Picture, SeekTable, StreamInfo,
+               VorbisComment};
 
 mod crc;
 mod error;
 pub mod frame;
 pub mod input;
+mod md5;
 pub mod metadata;
+pub mod raw;
+#[cfg(feature = "resample")]
+pub mod resample;
+pub mod sample;
+#[cfg(feature = "dasp")]
+pub mod signal;
+mod simd;
+#[cfg(feature = "rodio")]
+pub mod source;
 pub mod subframe;
 
 pub use error::{Error, Result};
-pub use frame::Block;
+pub use frame::{Block, BufferPool};
+pub use sample::{Sample, SampleArray};
+
+/// The four bytes that every FLAC stream starts with.
+pub const FLAC_MAGIC: [u8; 4] = [b'f', b'L', b'a', b'C'];
+
+/// The optional features that Claxon was compiled with.
+///
+/// See `features()`. There is a field for every Cargo feature of the crate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Features {
+    /// Whether `FlacReader::resampled()` and the `resample` module are available.
+    pub resample: bool,
+    /// Whether stereo decorrelation is vectorized on CPUs that support it.
+    pub simd: bool,
+    /// Whether `FlacReader::signal()` and the `signal` module are available.
+    pub dasp: bool,
+    /// Whether `FlacReader::into_source()` and the `source` module are available.
+    pub rodio: bool,
+    /// Whether `metadata::Picture::decode_image()` is available.
+    pub image: bool,
+}
+
+/// Returns the optional features that Claxon was compiled with.
+///
+/// This is useful for diagnostics, such as a version banner or a bug report.
+pub fn features() -> Features {
+    Features {
+        resample: cfg!(feature = "resample"),
+        simd: cfg!(feature = "simd"),
+        dasp: cfg!(feature = "dasp"),
+        rodio: cfg!(feature = "rodio"),
+        image: cfg!(feature = "image"),
+    }
+}
+
+#[test]
+fn verify_features() {
+    // Run the tests with `--features resample` to check the enabled case.
+    assert_eq!(features().resample, cfg!(feature = "resample"));
+    assert_eq!(features().simd, cfg!(feature = "simd"));
+    assert_eq!(features().dasp, cfg!(feature = "dasp"));
+    assert_eq!(features().rodio, cfg!(feature = "rodio"));
+    assert_eq!(features().image, cfg!(feature = "image"));
+}
 
 /// A FLAC decoder that can decode the stream from the underlying reader.
 ///
 /// TODO: Add an example.
 pub struct FlacReader<R: io::Read> {
     streaminfo: StreamInfo,
+    streaminfo_bytes: Option<[u8; 34]>,
     vorbis_comment: Option<VorbisComment>,
+    cuesheet: Option<CueSheet>,
+    seektable: Option<SeekTable>,
+    foreign_metadata: Option<ForeignMetadata>,
+    pictures: Vec<Picture>,
+    metadata_blocks: Vec<MetadataBlockHeader>,
+    skipped_metadata: Vec<(MetadataBlockHeader, Error)>,
     input: FlacReaderState<BufferedReader<R>>,
+
+    /// A partially consumed block, for reads that resume in the middle of a block.
+    pending_block: Block,
+
+    /// The index of the next interleaved sample to return from `pending_block`.
+    pending_pos: u32,
+
+    /// The position of the first frame, relative to the start of the stream.
+    audio_start: u64,
+
+    /// The options that the reader was constructed with.
+    options: FlacReaderOptions,
+
+    /// Called after every decoded frame, if set.
+    progress: Option<ProgressCallback>,
+
+    /// The end of the last decoded block, for `progress()`.
+    samples_done: Arc<Mutex<u64>>,
+
+    /// Updates `samples_done` and calls `progress`, passed to frame readers.
+    progress_tracker: ProgressCallback,
+
+    /// Updated after every decoded frame, if `collect_stats` is set.
+    statistics: Option<SharedStatistics>,
+
+    /// Whether the tags differ from those of the previous stream in a chain.
+    tags_changed: bool,
+
+    /// The pool that sample iterators take their buffers from, if any.
+    pool: Option<BufferPool>,
+
+    /// A label that identifies the stream in errors, if set.
+    label: Option<Arc<String>>,
 }
 
 enum FlacReaderState<T> {
@@ -139,13 +250,175 @@ pub struct FlacReaderOptions {
     ///
     /// Defaults to true.
     pub read_vorbis_comment: bool,
+
+    /// When true, keep the picture blocks, such as cover art, for `FlacReader::pictures()`.
+    ///
+    /// Pictures can be large, so they are discarded unless they are desired.
+    /// A stream can contain any number of pictures, so when `metadata_only` is
+    /// set as well, all metadata blocks are read.
+    ///
+    /// Defaults to false.
+    pub read_picture: bool,
+
+    /// When true, treat inconsistencies between the audio data and the metadata as errors.
+    ///
+    /// Currently this covers:
+    ///
+    /// * A stream that ends before the number of samples in the streaminfo has
+    ///   been decoded. This can happen when a file has been truncated at a
+    ///   frame boundary, in which case every remaining frame is valid by
+    ///   itself. When false, the samples iterator ends normally, and
+    ///   `FlacSamples::is_truncated()` reports the shortfall.
+    /// * A stream that contains more samples than the streaminfo declares.
+    ///   When false, the extra samples are decoded, and
+    ///   `FlacSamples::has_extra_samples()` reports the excess.
+    /// * A frame with a block size larger than the maximum block size in the
+    ///   streaminfo.
+    /// * An application block, seek table, CUE sheet, or picture with invalid
+    ///   contents. When false, such a block is skipped, and
+    ///   `FlacReader::skipped_metadata_blocks()` reports it.
+    /// * A stereo frame of which a reconstructed channel does not fit in the
+    ///   bits per sample of the frame.
+    /// * A last-metadata-block flag that is set on the wrong block. When false,
+    ///   the flag is ignored, and the metadata ends where the first frame
+    ///   starts, as determined by `MetadataBlockReader::set_infer_last_block()`.
+    /// * A CUE sheet of which the lead-out offset differs from the number of
+    ///   samples in the streaminfo. When false, the CUE sheet is kept, and
+    ///   `FlacReader::check_cuesheet_lead_out()` reports the mismatch.
+    ///
+    /// Players want to decode whatever audio is intact, while validators want
+    /// to know about every inconsistency, so validators should set this.
+    ///
+    /// Defaults to false.
+    pub strict: bool,
+
+    /// The largest block size (in inter-channel samples) to accept.
+    ///
+    /// Frames with a larger block size are rejected with a format error before
+    /// a buffer is allocated for them, which bounds the memory used per frame.
+    /// In strict mode, the maximum block size in the streaminfo is enforced as
+    /// well.
+    ///
+    /// Defaults to 65535, the largest block size that the format allows.
+    pub max_block_size: u16,
+
+    /// When true, accept streams without a streaminfo block.
+    ///
+    /// The streaminfo block is mandatory, but damaged files may lack it while
+    /// still containing valid frames. When this is set and the streaminfo is
+    /// missing, the sample rate, number of channels, and bits per sample are
+    /// derived from the first frame header instead. The number of samples and
+    /// the MD5 signature are unknown in that case. When false, a missing
+    /// streaminfo block is a format error.
+    ///
+    /// Defaults to false.
+    pub allow_missing_streaminfo: bool,
+
+    /// When true, read all metadata blocks, and tolerate metadata blocks after the audio.
+    ///
+    /// Some non-conformant taggers append a Vorbis comment or picture block
+    /// after the last frame, instead of placing it before the first frame.
+    /// When this is set, decoding ends cleanly at such a block, and
+    /// `FlacReader::read_trailing_metadata()` can be used after decoding to
+    /// read it. Also, all metadata blocks before the audio are read, even if
+    /// `metadata_only` is set. When false, a block after the audio results in
+    /// a format error, as the specification prescribes.
+    ///
+    /// Defaults to false.
+    pub scan_all_metadata: bool,
+
+    /// When true, collect statistics about decoded frames.
+    ///
+    /// The statistics can be retrieved with `FlacReader::statistics()`. This
+    /// includes the time spent decoding, so every frame is timed. When false,
+    /// no timing or counting takes place.
+    ///
+    /// Defaults to false.
+    pub collect_stats: bool,
+
+    /// An instant after which decoding stops with `Error::DeadlineExceeded`.
+    ///
+    /// This bounds the time spent decoding a stream, regardless of its size.
+    /// The deadline is checked between frames: every iterator or frame reader
+    /// created by the `FlacReader` decodes at least one frame, and before
+    /// every next frame it reads the clock. The error reports how many
+    /// inter-channel samples it decoded before the deadline.
+    ///
+    /// Defaults to `None`, no deadline.
+    pub deadline: Option<Instant>,
+
+    /// The largest number of metadata blocks to accept before the audio.
+    ///
+    /// Real files contain a handful of metadata blocks, but a small malicious
+    /// file could chain a huge number of empty ones. If a stream contains more
+    /// blocks than this, `Error::TooManyMetadataBlocks` is returned, which
+    /// bounds the work done before the first frame. Skipped damaged blocks
+    /// count as well.
+    ///
+    /// Defaults to 1024.
+    pub max_metadata_blocks: u32,
+
+    /// When true, pad a short final block with zeros to the fixed block size.
+    ///
+    /// In a fixed-blocksize stream, every block has the same size except for
+    /// the final one, which may be shorter. Consumers that process audio in
+    /// fixed-size chunks can set this to receive uniform blocks instead. The
+    /// padding is appended to every channel, and
+    /// `FlacSamples::padding_samples()` or `FrameReader::padding_samples()`
+    /// report how many inter-channel samples were added. It is not counted
+    /// against the number of samples in the streaminfo. Streams with a
+    /// variable block size are not padded.
+    ///
+    /// Defaults to false.
+    pub pad_final_block: bool,
+
+    /// The largest number of bytes to use for the read buffer and the samples of a frame.
+    ///
+    /// This bounds the memory used for decoding, for constrained targets. The
+    /// read buffer takes `input::BUFFER_SIZE` bytes, and the rest of the
+    /// budget caps the sample buffer of a frame, which takes four bytes per
+    /// sample in every channel. A frame whose block size needs more results
+    /// in `Error::MemoryBudgetExceeded` before its buffer is allocated, and so
+    /// does constructing a reader with a budget smaller than the read buffer.
+    /// Metadata blocks are not covered; they are bounded by their own size.
+    /// Buffers made by the caller, such as the one passed to
+    /// `FlacReader::decode_all_into()`, are not covered either.
+    ///
+    /// Defaults to `None`, no budget.
+    pub max_memory: Option<usize>,
+
+    /// The largest number of bytes to read from the input, counted from the start of the stream.
+    ///
+    /// This is for input of which only the first bytes are available, such as
+    /// a partially downloaded file. Nothing beyond the limit is read, and
+    /// decoding ends cleanly after the last complete frame: a frame that is
+    /// cut off, by the limit or by the end of the input, is discarded rather
+    /// than resulting in an error. Ending before the number of samples in the
+    /// streaminfo is then expected, so it is not an error in strict mode
+    /// either, but `FlacSamples::is_truncated()` does report it. The metadata
+    /// must fit within the limit. The start of the stream is where offsets
+    /// such as those of `FlacReader::frames()` are counted from.
+    ///
+    /// Defaults to `None`, no limit.
+    pub max_input_bytes: Option<u64>,
 }
 
 impl Default for FlacReaderOptions {
     fn default() -> FlacReaderOptions {
         FlacReaderOptions {
             read_vorbis_comment: true,
+            read_picture: false,
             metadata_only: false,
+            strict: false,
+            max_block_size: 0xffff,
+            allow_missing_streaminfo: false,
+            scan_all_metadata: false,
+            collect_stats: false,
+            deadline: None,
+            max_metadata_blocks: 1024,
+            pad_final_block: false,
+            max_memory: None,
+            max_input_bytes: None,
         }
     }
 }
@@ -159,9 +432,8 @@ impl FlacReaderOptions {
             return true
         }
 
-        // Should be the or of all read_* fields, of which vorbis_comment is the
-        // only one at the moment.
-        self.read_vorbis_comment
+        // Should be the or of all read_* fields.
+        self.read_vorbis_comment || self.read_picture || self.scan_all_metadata
     }
 }
 
@@ -172,6 +444,30 @@ pub struct FlacSamples<R: ReadBytes> {
     sample: u32,
     channel: u32,
 
+    /// The number of inter-channel samples in the stream, if known.
+    expected_samples: Option<u64>,
+
+    /// Whether ending before `expected_samples`, or continuing after it, is an error.
+    strict: bool,
+
+    /// Whether ending before `expected_samples` is expected, because the input is limited.
+    allow_truncation: bool,
+
+    /// Set if the stream ended before `expected_samples` were decoded.
+    is_truncated: bool,
+
+    /// The number of the first sample decoded by this iterator.
+    first_time: Option<u64>,
+
+    /// Set if the stream contains more than `expected_samples` samples.
+    has_extra_samples: bool,
+
+    /// The number of padding samples at the end of the current block.
+    block_padding: u64,
+
+    /// The pool to take the first buffer from, and to return it to on drop.
+    pool: Option<BufferPool>,
+
     /// If reading ever failed, this flag is set, so that the iterator knows not
     /// to return any new values.
     has_failed: bool,
@@ -179,199 +475,1635 @@ pub struct FlacSamples<R: ReadBytes> {
 
 // TODO: Add a `FlacIntoSamples`.
 
-fn read_stream_header<R: ReadBytes>(input: &mut R) -> Result<()> {
-    // A FLAC stream starts with a 32-bit header 'fLaC' (big endian).
-    const FLAC_HEADER: u32 = 0x66_4c_61_43;
+/// The producing end of a single-producer single-consumer sample queue.
+///
+/// This is the interface that `FlacReader::fill_producer()` and
+/// `FlacReader::decode_into_producer()` push interleaved samples into, for
+/// instance a ring buffer shared with an audio thread. The names follow those
+/// of common ring buffer crates, so an implementation is usually a thin
+/// wrapper.
+pub trait SampleProducer {
+    /// Returns the number of samples that can be pushed without blocking.
+    fn slots(&self) -> usize;
 
-    // Some files start with ID3 tag data. The reference decoder supports this
-    // for convenience. Claxon does not, but we can at least generate a helpful
-    // error message if a file starts like this.
-    const ID3_HEADER: u32 = 0x49_44_33_00;
+    /// Pushes a sample. This is only called when `slots()` is nonzero.
+    fn push(&mut self, sample: i32);
 
-    let header = try!(input.read_be_u32());
-    if header != FLAC_HEADER {
-        if (header & 0xff_ff_ff_00) == ID3_HEADER {
-            fmt_err("stream starts with ID3 header rather than FLAC header")
-        } else {
-            fmt_err("invalid stream header")
-        }
-    } else {
-        Ok(())
+    /// Returns whether the consumer is gone, after which nothing is pushed.
+    ///
+    /// The default implementation returns false.
+    fn is_abandoned(&self) -> bool {
+        false
     }
 }
 
-impl<R: io::Read> FlacReader<R> {
-    /// Create a reader that reads the FLAC format.
+/// The block sizes of a stream, as declared by the streaminfo.
+///
+/// This helps to choose buffer sizes that line up with the blocks that the
+/// decoder produces. See `FlacReader::block_size_info()`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BlockSizeInfo {
+    /// The smallest block size in the stream, in inter-channel samples, excluding the final block.
+    pub min_block_size: u16,
+    /// The largest block size in the stream, in inter-channel samples.
+    pub max_block_size: u16,
+    /// Whether all blocks have the same size, except possibly a shorter final block.
+    pub is_uniform: bool,
+    /// A chunk size in inter-channel samples that is efficient to consume the stream in.
     ///
-    /// The header and metadata blocks are read immediately. Audio frames
-    /// will be read on demand.
+    /// For a uniform stream this is the block size, so every chunk is exactly
+    /// one block. Otherwise it is the largest block size, so a chunk never
+    /// spans more than two blocks.
+    pub recommended_chunk_size: u32,
+}
+
+/// The sample count and MD5 signature of decoded audio data.
+///
+/// These are the values that the streaminfo block should contain for the audio
+/// data, and they can be compared against it to detect corruption.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AudioDigest {
+    /// The total number of inter-channel samples.
+    pub samples: u64,
+    /// The MD5 signature of the unencoded audio data.
+    pub md5sum: [u8; 16],
+}
+
+/// How far decoding has progressed, as returned by `FlacReader::progress()`.
+///
+/// The sample counts allow computing progress through the audio, and the
+/// byte counts allow computing progress through the input, which is the
+/// better basis for an estimate of the remaining time on a slow source.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// The number of inter-channel samples decoded, the end of the last decoded block.
+    pub samples_done: u64,
+    /// The total number of inter-channel samples, if the streaminfo specifies it.
+    pub samples_total: Option<u64>,
+    /// The number of bytes of the stream consumed, see `FlacReader::byte_position()`.
+    pub bytes_read: u64,
+    /// The size of the stream in bytes, if known.
+    pub bytes_total: Option<u64>,
+}
+
+/// How well the frames of a stream are compressed, as found by `FlacReader::compression_profile()`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CompressionProfile {
+    /// The number of subframes of every type.
+    pub subframe_type_counts: SubframeTypeCounts,
+    /// The size of the decoded audio, with every sample in whole bytes, as in a WAV file.
+    pub decoded_bytes: u64,
+    /// The number of bytes that the frames occupy in the stream.
+    pub frame_bytes: u64,
+}
+
+impl CompressionProfile {
+    /// Returns the fraction of subframes that are verbatim or constant.
     ///
-    /// Claxon rejects files that claim to contain excessively large metadata
-    /// blocks, to protect against denial of service attacks where a
-    /// small damaged or malicous file could cause gigabytes of memory
-    /// to be allocated. `Error::Unsupported` is returned in that case.
-    pub fn new(reader: R) -> Result<FlacReader<R>> {
-        FlacReader::new_ext(reader, FlacReaderOptions::default())
+    /// An encoder stores a subframe verbatim when no predictor beats the plain
+    /// samples, so a high fraction of verbatim subframes suggests noise-like
+    /// content, or an encoder that did not try. Constant subframes are small,
+    /// but they are counted as well, as they take up the same space in a
+    /// decoded file. Returns `None` if there are no subframes.
+    pub fn verbatim_or_constant_fraction(&self) -> Option<f64> {
+        let counts = self.subframe_type_counts;
+        match counts.total() {
+            0 => None,
+            total => Some((counts.verbatim + counts.constant) as f64 / total as f64),
+        }
     }
 
-    /// Create a reader that reads the FLAC format, with reader options.
+    /// Returns the size of the frames relative to the size of the decoded audio.
     ///
-    /// The header and metadata blocks are read immediately, but only as much as
-    /// specified in the options. See `FlacReaderOptions` for more details.
+    /// This is the ratio that the reference encoder reports: lower is better,
+    /// and a ratio near or above 1 means that the audio was barely compressed.
+    /// Metadata is not counted. Returns `None` if no audio was decoded.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.decoded_bytes {
+            0 => None,
+            n => Some(self.frame_bytes as f64 / n as f64),
+        }
+    }
+}
+
+/// The largest jump in one channel across a frame boundary, see `FlacReader::boundary_discontinuities()`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BoundaryDiscontinuity {
+    /// The absolute difference between the last sample of a frame and the first sample of the next.
+    pub magnitude: u32,
+    /// The position of the first sample after the boundary, in inter-channel samples.
     ///
-    /// Claxon rejects files that claim to contain excessively large metadata
-    /// blocks, to protect against denial of service attacks where a
-    /// small damaged or malicous file could cause gigabytes of memory
-    /// to be allocated. `Error::Unsupported` is returned in that case.
-    pub fn new_ext(reader: R, options: FlacReaderOptions) -> Result<FlacReader<R>> {
-        let mut buf_reader = BufferedReader::new(reader);
-        let mut opts_current = options;
+    /// Like `HealthReport::samples`, this counts from where decoding started.
+    pub position: u64,
+}
 
-        // A flac stream first of all starts with a stream header.
-        try!(read_stream_header(&mut buf_reader));
+/// The full-scale samples of one channel, as found by `FlacReader::full_check()`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct FullScaleSamples {
+    /// The number of samples at the largest or smallest value of the bit depth.
+    pub count: u64,
+    /// The position of the first full-scale sample, in inter-channel samples.
+    ///
+    /// Like `HealthReport::samples`, this counts from where decoding started.
+    pub first: Option<u64>,
+}
 
-        // Start a new scope, because the input reader must be available again
-        // for the frame reader next.
-        let (streaminfo, vorbis_comment) = {
-            // Next are one or more metadata blocks. The flac specification
-            // dictates that the streaminfo block is the first block. The metadata
-            // block reader will yield at least one element, so the unwrap is safe.
-            let mut metadata_iter = MetadataBlockReader::new(&mut buf_reader);
-            let streaminfo_block = try!(metadata_iter.next().unwrap());
-            let streaminfo = match streaminfo_block {
-                MetadataBlock::StreamInfo(info) => info,
-                _ => return fmt_err("streaminfo block missing"),
-            };
+/// The result of `FlacReader::full_check()`.
+#[derive(Debug)]
+pub struct HealthReport {
+    /// The headers of the metadata blocks, in stream order.
+    pub metadata_blocks: Vec<MetadataBlockHeader>,
+    /// The number of frames that decoded successfully, including their CRCs.
+    pub frames: u64,
+    /// The number of inter-channel samples in the frames that decoded successfully.
+    pub samples: u64,
+    /// Whether the MD5 signature of the decoded audio matches the streaminfo.
+    ///
+    /// This is `None` if the streaminfo signature is all zeros (the encoder did
+    /// not compute it), or if decoding stopped at an error.
+    pub md5_matches: Option<bool>,
+    /// The error that stopped decoding, if any.
+    ///
+    /// A frame with a CRC mismatch is reported here as a format error, and a
+    /// stream that ends in the middle of a frame as an I/O error of kind
+    /// `UnexpectedEof`.
+    pub error: Option<Error>,
+    /// Problems that did not prevent decoding.
+    pub warnings: Vec<&'static str>,
+    /// The full-scale samples of every channel, in the frames that decoded successfully.
+    ///
+    /// Samples at the extremes of the bit depth of the stream indicate that
+    /// the source was clipped before it was encoded, so a master with many
+    /// of them may be damaged. These are not counted as warnings, because
+    /// a few full-scale samples can occur legitimately.
+    pub full_scale: Vec<FullScaleSamples>,
+}
 
-            let mut vorbis_comment = None;
+impl HealthReport {
+    /// Returns whether the stream decoded without errors or warnings, and the MD5 signature did not mismatch.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none() && self.warnings.is_empty() && self.md5_matches != Some(false)
+    }
+}
 
-            // There might be more metadata blocks, read and store them.
-            for block_result in metadata_iter {
-                match try!(block_result) {
-                    MetadataBlock::VorbisComment(vc) => {
-                        // The Vorbis comment block need not be present, but
-                        // when it is, it must be unique.
-                        if vorbis_comment.is_some() {
-                            return fmt_err("encountered second Vorbis comment block")
-                        } else {
-                            vorbis_comment = Some(vc);
-                        }
+/// Feeds the samples of a block into the hasher, as the streaminfo prescribes.
+///
+/// The signature is computed over the interleaved samples in little-endian
+/// two's complement, using the smallest whole number of bytes per sample.
+fn update_md5(hasher: &mut Md5, block: &Block, bits_per_sample: u32, bytes: &mut Vec<u8>) {
+    let bytes_per_sample = (bits_per_sample as usize + 7) / 8;
+    bytes.clear();
+    for i in 0..block.duration() {
+        for ch in 0..block.channels() {
+            let sample = block.sample(ch, i);
+            for k in 0..bytes_per_sample {
+                bytes.push((sample >> (8 * k)) as u8);
+            }
+        }
+    }
+    hasher.update(bytes);
+}
 
-                        // We have one, no new one is desired.
-                        opts_current.read_vorbis_comment = false;
-                    }
-                    MetadataBlock::StreamInfo(..) => {
-                        return fmt_err("encountered second streaminfo block")
-                    }
-                    // Other blocks are currently not handled.
-                    _block => {}
-                }
+/// Appends a 32-bit big-endian integer to the buffer.
+fn push_be_u32(buffer: &mut Vec<u8>, x: u32) {
+    buffer.push((x >> 24) as u8);
+    buffer.push((x >> 16) as u8);
+    buffer.push((x >> 8) as u8);
+    buffer.push(x as u8);
+}
 
-                // Early-out reading metadata once all desired blocks have been
-                // collected.
-                if !opts_current.has_desired_blocks() {
-                    break
-                }
-            }
+/// Appends a 16-bit little-endian integer to the buffer.
+fn push_le_u16(buffer: &mut Vec<u8>, x: u16) {
+    buffer.push(x as u8);
+    buffer.push((x >> 8) as u8);
+}
 
-            // TODO: Rather than discarding afterwards, never parse it in the
-            // first place; treat it like padding in the MetadataBlockReader.
-            if !options.read_vorbis_comment {
-                vorbis_comment = None;
-            }
+/// Appends a 32-bit little-endian integer to the buffer.
+fn push_le_u32(buffer: &mut Vec<u8>, x: u32) {
+    push_le_u16(buffer, x as u16);
+    push_le_u16(buffer, (x >> 16) as u16);
+}
 
-            (streaminfo, vorbis_comment)
-        };
+/// Overwrites four bytes of the buffer at `pos` with a 32-bit little-endian integer.
+fn patch_le_u32(buffer: &mut [u8], pos: usize, x: u32) {
+    for i in 0..4 {
+        buffer[pos + i] = (x >> (8 * i)) as u8;
+    }
+}
 
-        // Even if we might have read all metadata blocks, only set the state to
-        // "full" if `metadata_only` was false: this results in more predictable
-        // behavior.
-        let state = if options.metadata_only {
-            FlacReaderState::MetadataOnly(buf_reader)
+/// The GUID of integer PCM audio, the subformat of a WAVEFORMATEXTENSIBLE.
+const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+                                            0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+/// The GUID of IEEE float audio, the subformat of a WAVEFORMATEXTENSIBLE.
+const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: [u8; 16] = [0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+                                                   0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+/// Returns the number of bytes that a WAV file uses per sample of `bits_per_sample` bits.
+fn wav_bytes_per_sample(bits_per_sample: u32, float: bool) -> u32 {
+    if float { 4 } else { (bits_per_sample + 7) / 8 }
+}
+
+/// Returns the header of a WAV file with the format of the stream, up to the data chunk.
+///
+/// The RIFF and data chunk sizes are zero, to be filled in afterwards. If
+/// `float` is set, the samples are 32-bit floats, and otherwise integers of
+/// the bit depth of the stream. For more than two channels, or for integers
+/// of more than 16 bits per sample or a bit depth that is not a multiple of
+/// 8, the fmt chunk is a WAVEFORMATEXTENSIBLE.
+fn wav_header(streaminfo: &StreamInfo, channel_mask: Option<ChannelMask>, float: bool) -> Vec<u8> {
+    let channels = streaminfo.channels;
+    let bits_per_sample = if float { 32 } else { streaminfo.bits_per_sample };
+    let bytes_per_sample = wav_bytes_per_sample(bits_per_sample, float);
+    let is_extensible = channels > 2 ||
+                        !float && (bits_per_sample > 16 || bits_per_sample % 8 != 0);
+    let fmt_len = if is_extensible { 40 } else { 16 };
+    let format_tag = match (is_extensible, float) {
+        (true, _) => 0xfffe,
+        (false, true) => 3,
+        (false, false) => 1,
+    };
+
+    let mut wav = Vec::with_capacity(12 + 8 + fmt_len as usize + 8);
+    wav.extend_from_slice(b"RIFF\0\0\0\0WAVEfmt ");
+    push_le_u32(&mut wav, fmt_len);
+    push_le_u16(&mut wav, format_tag);
+    push_le_u16(&mut wav, channels as u16);
+    push_le_u32(&mut wav, streaminfo.sample_rate);
+    push_le_u32(&mut wav, streaminfo.sample_rate * channels * bytes_per_sample);
+    push_le_u16(&mut wav, (channels * bytes_per_sample) as u16);
+    push_le_u16(&mut wav, (bytes_per_sample * 8) as u16);
+    if is_extensible {
+        push_le_u16(&mut wav, 22);
+        push_le_u16(&mut wav, bits_per_sample as u16);
+        push_le_u32(&mut wav, channel_mask.map_or(0, |mask| mask.0));
+        if float {
+            wav.extend_from_slice(&KSDATAFORMAT_SUBTYPE_IEEE_FLOAT);
         } else {
-            FlacReaderState::Full(buf_reader)
-        };
+            wav.extend_from_slice(&KSDATAFORMAT_SUBTYPE_PCM);
+        }
+    }
+    wav.extend_from_slice(b"data\0\0\0\0");
+    wav
+}
 
-        // The flac reader will contain the reader that will read frames.
-        let flac_reader = FlacReader {
-            streaminfo: streaminfo,
-            vorbis_comment: vorbis_comment,
-            input: state,
-        };
+/// Appends the samples of `block`, of `bits_per_sample` bits, to `out` as WAV data.
+///
+/// Samples are stored little-endian in the smallest whole number of bytes
+/// that fits the bit depth, left-justified, and offset to unsigned for 8 bits.
+fn push_wav_samples(out: &mut Vec<u8>, block: &Block, bits_per_sample: u32) {
+    let bytes_per_sample = (bits_per_sample + 7) / 8;
+    let shift = bytes_per_sample * 8 - bits_per_sample;
+    for i in 0..block.duration() {
+        for ch in 0..block.channels() {
+            let mut sample = block.sample(ch, i) << shift;
+            // 8-bit WAV samples are unsigned, with silence at 128.
+            if bytes_per_sample == 1 {
+                sample ^= 0x80;
+            }
+            for k in 0..bytes_per_sample {
+                out.push((sample >> (8 * k)) as u8);
+            }
+        }
+    }
+}
 
-        Ok(flac_reader)
+/// Appends the samples of `block`, of `bits_per_sample` bits, to `out` as 32-bit float WAV data.
+///
+/// Samples are converted with `sample::to_f32()`, and stored little-endian.
+fn push_wav_f32_samples(out: &mut Vec<u8>, block: &Block, bits_per_sample: u32) {
+    for i in 0..block.duration() {
+        for ch in 0..block.channels() {
+            let x = sample::to_f32(block.sample(ch, i), bits_per_sample);
+            // Reinterpret the float as its bits; `f32::to_bits()` requires Rust 1.20.
+            let bits = unsafe { *(&x as *const f32 as *const u32) };
+            push_le_u32(out, bits);
+        }
     }
+}
 
-    /// Returns the streaminfo metadata.
-    ///
-    /// This contains information like the sample rate and number of channels.
-    pub fn streaminfo(&self) -> StreamInfo {
-        self.streaminfo
+/// Appends the samples of `block` to `out` as WAV data, as floats or as integers.
+fn push_wav_block(out: &mut Vec<u8>, block: &Block, bits_per_sample: u32, float: bool) {
+    if float {
+        push_wav_f32_samples(out, block, bits_per_sample);
+    } else {
+        push_wav_samples(out, block, bits_per_sample);
     }
+}
 
-    /// Returns the vendor string of the Vorbis comment block, if present.
-    ///
-    /// This string usually contains the name and version of the program that
-    /// encoded the FLAC stream, such as `reference libFLAC 1.3.2 20170101`
-    /// or `Lavf57.25.100`.
-    pub fn vendor(&self) -> Option<&str> {
-        self.vorbis_comment.as_ref().map(|vc| &vc.vendor[..])
+/// Splits the stream header and metadata blocks into block types and contents.
+///
+/// The input starts with the `fLaC` magic, and it has been parsed before, so
+/// a block that is cut short at the end is simply truncated.
+fn split_metadata_blocks(header: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut blocks = Vec::new();
+    let mut pos = 4;
+    while pos + 4 <= header.len() {
+        let block_type = header[pos] & 0x7f;
+        let length = (header[pos + 1] as usize) << 16 |
+                     (header[pos + 2] as usize) << 8 |
+                     header[pos + 3] as usize;
+        blocks.push((block_type, &header[pos + 4..cmp::min(pos + 4 + length, header.len())]));
+        pos += 4 + length;
     }
+    blocks
+}
 
-    /// Returns name-value pairs of Vorbis comments, such as `("ARTIST", "Queen")`.
-    ///
-    /// The name is supposed to be interpreted case-insensitively, and is
-    /// guaranteed to consist of ASCII characters. Claxon does not normalize
-    /// the casing of the name. Use `get_tag()` to do a case-insensitive lookup.
-    ///
-    /// Names need not be unique. For instance, multiple `ARTIST` comments might
-    /// be present on a collaboration track.
-    ///
-    /// See <https://www.xiph.org/vorbis/doc/v-comment.html> for more details.
-    pub fn tags<'a>(&'a self) -> metadata::Tags<'a> {
-        match self.vorbis_comment.as_ref() {
-            Some(vc) => metadata::Tags::new(&vc.comments[..]),
-            None => metadata::Tags::new(&[]),
-        }
+/// Serializes the stream header and metadata blocks, setting the last-block flag on the last one.
+fn join_metadata_blocks(blocks: &[(u8, &[u8])]) -> Vec<u8> {
+    let len = blocks.iter().fold(4, |acc, &(_, body)| acc + 4 + body.len());
+    let mut metadata = Vec::with_capacity(len);
+    metadata.extend_from_slice(&FLAC_MAGIC);
+    for (i, &(block_type, body)) in blocks.iter().enumerate() {
+        let is_last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+        metadata.push(is_last | block_type);
+        metadata.push((body.len() >> 16) as u8);
+        metadata.push((body.len() >> 8) as u8);
+        metadata.push(body.len() as u8);
+        metadata.extend_from_slice(body);
     }
+    metadata
+}
 
-    /// Look up a Vorbis comment such as `ARTIST` in a case-insensitive way.
-    ///
-    /// Returns an iterator,  because tags may occur more than once. There could
-    /// be multiple `ARTIST` tags on a collaboration track, for instance.
-    ///
-    /// Note that tag names are ASCII and never contain `'='`; trying to look up
-    /// a non-ASCII tag will return no results. Furthermore, the Vorbis comment
-    /// spec dictates that tag names should be handled case-insensitively, so
-    /// this method performs a case-insensitive lookup.
-    ///
-    /// See also `tags()` for access to the raw tags.
-    /// See <https://www.xiph.org/vorbis/doc/v-comment.html> for more details.
-    pub fn get_tag<'a>(&'a self, tag_name: &'a str) -> metadata::GetTag<'a> {
-        match self.vorbis_comment.as_ref() {
-            Some(vc) => metadata::GetTag::new(&vc.comments[..], tag_name),
-            None => metadata::GetTag::new(&[], tag_name),
-        }
+/// Encodes a sample rate as 80-bit IEEE 754 extended precision float.
+///
+/// This is the format that AIFF uses for the sample rate.
+fn sample_rate_to_extended(sample_rate: u32) -> [u8; 10] {
+    let mut bytes = [0u8; 10];
+    if sample_rate == 0 {
+        return bytes
     }
 
-    /// Returns an iterator that decodes a single frame on every iteration.
-    /// TODO: It is not an iterator.
-    ///
-    /// This is a low-level primitive that gives you control over when decoding
-    /// happens. The representation of the decoded audio is somewhat specific to
-    /// the FLAC format. For a higher-level interface, see `samples()`.
-    pub fn blocks<'r>(&'r mut self) -> FrameReader<&'r mut BufferedReader<R>> {
-        match self.input {
-            FlacReaderState::Full(ref mut inp) => FrameReader::new(inp),
-            FlacReaderState::MetadataOnly(..) =>
-                panic!("FlacReaderOptions::metadata_only must be false \
-                       to be able to use FlacReader::blocks()"),
-        }
+    // The mantissa has an explicit leading one bit, so normalize the integer
+    // such that its most significant bit is the most significant mantissa bit.
+    let msb = 31 - sample_rate.leading_zeros();
+    let exponent = 16383 + msb;
+    let mantissa = (sample_rate as u64) << (63 - msb);
+    bytes[0] = (exponent >> 8) as u8;
+    bytes[1] = exponent as u8;
+    for i in 0..8 {
+        bytes[2 + i] = (mantissa >> (56 - 8 * i)) as u8;
     }
+    bytes
+}
 
-    /// Returns an iterator over all samples.
-    ///
-    /// The channel data is is interleaved. The iterator is streaming. That is,
+#[test]
+fn verify_sample_rate_to_extended() {
+    assert_eq!(sample_rate_to_extended(44100), [0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(sample_rate_to_extended(48000), [0x40, 0x0e, 0xbb, 0x80, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(sample_rate_to_extended(1), [0x3f, 0xff, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(sample_rate_to_extended(0), [0; 10]);
+}
+
+/// Returns the bucket of sample `t`, when `total` samples are divided into `buckets` buckets.
+///
+/// This is `t * buckets / total`, clamped to the last bucket. The product does
+/// not fit in a `u64` for long streams with many buckets, so the division is
+/// split up instead. This relies on `total` being less than 2<sup>36</sup>, as
+/// the streaminfo guarantees.
+fn bucket_index(t: u64, buckets: u64, total: u64) -> u64 {
+    debug_assert!(total > 0 && total < 1 << 36 && buckets > 0);
+    if t >= total {
+        return buckets - 1
+    }
+    if let Some(product) = t.checked_mul(buckets) {
+        return product / total
+    }
+
+    // With buckets = q * total + r, the index is t * q + t * r / total. For
+    // the second term, t and r are less than 2^36, so split t at bit 18.
+    let (q, r) = (buckets / total, buckets % total);
+    let (t_hi, t_lo) = (t >> 18, t & 0x3_ffff);
+    let x = t_hi * r;
+    let low = ((x % total) << 18) + t_lo * r;
+    t * q + ((x / total) << 18) + low / total
+}
+
+#[test]
+fn verify_bucket_index() {
+    assert_eq!(bucket_index(0, 10, 100), 0);
+    assert_eq!(bucket_index(99, 10, 100), 9);
+    assert_eq!(bucket_index(100, 10, 100), 9);
+    assert_eq!(bucket_index(1 << 40, 10, 100), 9);
+
+    // Cases where t * buckets overflows, for a stream of more than 2^32 samples.
+    let total = (1 << 36) - 1;
+    let buckets = 1 << 30;
+    assert_eq!(bucket_index(total - 1, buckets, total), buckets - 1);
+    assert_eq!(bucket_index(total / 2, buckets, total), buckets / 2 - 1);
+    assert_eq!(bucket_index(1 << 35, buckets, total), 1 << 29);
+    assert_eq!(bucket_index(12_345_678_901, 3 << 40, total), 592_592_587_256);
+}
+
+/// Searches for a frame header that starts in the range `from..to`.
+///
+/// Returns the position of the header and the number of the first sample in
+/// the frame, or `None` if no valid frame header starts in the range.
+fn find_frame<R: io::Read + io::Seek>(input: &mut BufferedReader<R>,
+                                      from: u64,
+                                      to: u64,
+                                      fixed_block_size: u16)
+                                      -> Result<Option<(u64, u64)>> {
+    try!(input.seek_to(from));
+    for pos in from..to {
+        let byte = match try!(input.peek_u8()) {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+
+        // A frame header starts with the byte 0xff of the sync code.
+        if byte == 0xff {
+            match frame::read_frame_time(input, fixed_block_size) {
+                Ok(time) => return Ok(Some((pos, time))),
+                Err(Error::IoError(ref err)) if err.kind() != io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::IoError(io::Error::new(err.kind(), err.to_string())))
+                }
+                Err(..) => try!(input.seek_to(pos)),
+            }
+        }
+
+        try!(input.skip(1));
+    }
+
+    Ok(None)
+}
+
+/// A reader that copies everything it reads into a writer.
+struct TeeReader<R: io::Read, W: io::Write> {
+    inner: R,
+    output: W,
+}
+
+impl<R: io::Read, W: io::Write> io::Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        try!(self.output.write_all(&buf[..n]));
+        Ok(n)
+    }
+}
+
+/// Reads the raw contents of the Vorbis comment block of a FLAC stream into a buffer.
+///
+/// This reads only the stream header and the metadata blocks up to the Vorbis
+/// comment block, skipping the others, and it does not decode anything. The
+/// buffer can be reused, so scanning the tags of many files does not need to
+/// allocate once it is large enough. Use `metadata::VorbisCommentRef::parse()`
+/// to look up the tags in the buffer without copying them. Returns whether the
+/// stream has a Vorbis comment block; if not, the buffer is left empty.
+pub fn read_vorbis_comment_into<R: io::Read>(input: R, buffer: &mut Vec<u8>) -> Result<bool> {
+    let mut buf_reader = BufferedReader::new(input);
+    try!(read_stream_header(&mut buf_reader));
+    metadata::read_vorbis_comment_block_into(&mut buf_reader, buffer)
+}
+
+/// Copies a FLAC stream, correcting the sample count and MD5 signature.
+///
+/// Some encoders write a streaminfo block with an unknown or wrong number of
+/// samples, or with an all-zero MD5 signature. This decodes the entire stream
+/// while copying it verbatim from `input` to `output`, and then patches the
+/// streaminfo block in `output` with the values computed from the decoded
+/// audio data. The streaminfo block is always the first metadata block and it
+/// has a fixed size, so everything else is left untouched. The computed values
+/// are returned.
+///
+/// The stream is assumed to start at the current position of `output`.
+pub fn repair_streaminfo<R, W>(input: R, mut output: W) -> Result<AudioDigest>
+    where R: io::Read, W: io::Write + io::Seek {
+    let start = try!(output.seek(io::SeekFrom::Current(0)));
+
+    let tee = TeeReader {
+        inner: input,
+        output: &mut output,
+    };
+    let mut reader = try!(FlacReader::new(tee));
+    let digest = try!(reader.compute_digest());
+    let streaminfo = reader.streaminfo();
+
+    // Copy anything that follows the last frame, which the decoder did not
+    // need to read.
+    let mut tee = reader.into_inner();
+    try!(io::copy(&mut tee.inner, tee.output));
+
+    // The sample count is a 36-bit field.
+    if digest.samples >= 1 << 36 {
+        return fmt_err("stream has too many samples for the streaminfo block");
+    }
+
+    // The streaminfo starts after the 4-byte stream header and the 4-byte
+    // metadata block header. At offset 10 into the block is a 64-bit field with
+    // the sample rate (20 bits), channels minus one (3 bits), bits per sample
+    // minus one (5 bits), and sample count (36 bits). The MD5 signature follows.
+    let fields = (streaminfo.sample_rate as u64) << 44 |
+                 (streaminfo.channels as u64 - 1) << 41 |
+                 (streaminfo.bits_per_sample as u64 - 1) << 36 |
+                 digest.samples;
+    let mut patch = [0u8; 24];
+    for (i, byte) in patch[..8].iter_mut().enumerate() {
+        *byte = (fields >> (56 - 8 * i)) as u8;
+    }
+    patch[8..].copy_from_slice(&digest.md5sum);
+
+    let end = try!(output.seek(io::SeekFrom::Current(0)));
+    try!(output.seek(io::SeekFrom::Start(start + 18)));
+    try!(output.write_all(&patch));
+    try!(output.seek(io::SeekFrom::Start(end)));
+
+    Ok(digest)
+}
+
+/// The first difference between two streams, as found by `flac_difference()`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Difference {
+    /// The sample rate, number of channels, or bits per sample differ.
+    Format,
+
+    /// One stream ends at inter-channel sample `index`, the other continues.
+    Length {
+        /// The number of inter-channel samples in the shorter stream.
+        index: u64,
+    },
+
+    /// The samples at inter-channel sample `index` of `channel` differ.
+    Sample {
+        /// The index of the inter-channel sample, counted from the start of the stream.
+        index: u64,
+        /// The channel in which the samples differ.
+        channel: u32,
+    },
+}
+
+/// Decodes two streams and returns whether they contain the same audio.
+///
+/// See `flac_difference()`, this returns whether it found no difference.
+pub fn flac_equal<R1: io::Read, R2: io::Read>(a: R1, b: R2) -> Result<bool> {
+    flac_difference(a, b).map(|difference| difference.is_none())
+}
+
+/// Decodes two streams in lockstep, and returns the first difference in their audio.
+///
+/// Two streams are equal if they have the same sample rate, number of
+/// channels, and bits per sample, and they decode to the same samples. The
+/// metadata and the way the audio is encoded, such as the block size, may
+/// differ. The streams are decoded one block at a time, so memory use does not
+/// depend on their length, and decoding stops at the first difference. Returns
+/// `None` if the streams are equal. A decoding error in either stream is
+/// returned as error.
+pub fn flac_difference<R1: io::Read, R2: io::Read>(a: R1, b: R2) -> Result<Option<Difference>> {
+    let mut reader_a = try!(FlacReader::new(a));
+    let mut reader_b = try!(FlacReader::new(b));
+    let info_a = reader_a.streaminfo();
+    let info_b = reader_b.streaminfo();
+    if info_a.sample_rate != info_b.sample_rate ||
+       info_a.channels != info_b.channels ||
+       info_a.bits_per_sample != info_b.bits_per_sample {
+        return Ok(Some(Difference::Format))
+    }
+
+    let channels = info_a.channels as u64;
+    let mut samples_a = reader_a.samples();
+    let mut samples_b = reader_b.samples();
+    let mut n = 0;
+    loop {
+        match (samples_a.next(), samples_b.next()) {
+            (None, None) => return Ok(None),
+            (Some(Err(err)), _) | (_, Some(Err(err))) => return Err(err),
+            (Some(Ok(x)), Some(Ok(y))) => {
+                if x != y {
+                    return Ok(Some(Difference::Sample {
+                        index: n / channels,
+                        channel: (n % channels) as u32,
+                    }))
+                }
+            }
+            // A stream always ends at a block boundary, so here n is a
+            // multiple of the number of channels.
+            (Some(Ok(..)), None) | (None, Some(Ok(..))) => {
+                return Ok(Some(Difference::Length { index: n / channels }))
+            }
+        }
+        n += 1;
+    }
+}
+
+/// An iterator that yields blocks together with the bytes they were decoded from.
+pub struct FlacFrames<'r, R: 'r + io::Read> {
+    input: &'r mut BufferedReader<R>,
+    max_block_size: u16,
+    options: FlacReaderOptions,
+    progress: Option<ProgressCallback>,
+    statistics: Option<SharedStatistics>,
+    label: Option<Arc<String>>,
+    streaminfo: StreamInfo,
+
+    /// The number of inter-channel samples decoded so far, to report when the deadline passes.
+    decoded_samples: u64,
+
+    /// If reading ever failed, this flag is set, so that the iterator knows not
+    /// to return any new values.
+    has_failed: bool,
+}
+
+/// Returns whether an error is a failure to read from the underlying reader.
+///
+/// An unexpected end of the input is not, as it is a sign of a damaged or
+/// truncated stream. The error can have the label of the reader.
+fn is_read_failure(error: &Error) -> bool {
+    match *error.unlabeled() {
+        Error::IoError(ref err) => err.kind() != io::ErrorKind::UnexpectedEof,
+        _ => false,
+    }
+}
+
+/// Returns the capacity to reserve for `per_sample` elements per inter-channel sample of the stream.
+///
+/// This is the number of samples in the streaminfo times `per_sample`, or
+/// zero if the streaminfo does not specify the number of samples. The
+/// streaminfo is not to be trusted with a huge allocation up front, so the
+/// capacity is capped at 2<sup>24</sup> elements. A longer stream grows the
+/// buffer as it is decoded.
+fn bounded_capacity(streaminfo: &StreamInfo, per_sample: u64) -> usize {
+    let len = streaminfo.samples.unwrap_or(0).saturating_mul(per_sample);
+    cmp::min(len, 1 << 24) as usize
+}
+
+/// Returns an error if the lead-out of the CUE sheet differs from the number of samples.
+///
+/// The lead-out track marks the end of the audio, so in a CD image that was
+/// not edited, it is at the last sample.
+fn check_lead_out(cuesheet: &CueSheet, streaminfo: &StreamInfo) -> Result<()> {
+    match (cuesheet.lead_out(), streaminfo.samples) {
+        (Some(lead_out), Some(samples)) if lead_out != samples => {
+            Err(Error::CueSheetLeadOutMismatch {
+                lead_out: lead_out,
+                samples: samples,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns an error if a block has a different number of channels than the streaminfo.
+///
+/// Methods that select a channel by an index checked against the streaminfo
+/// call this for every block, so a stream whose frames disagree with the
+/// streaminfo results in an error rather than a panic.
+fn check_block_channels(block: &Block, streaminfo: &StreamInfo) -> Result<()> {
+    if block.channels() != streaminfo.channels {
+        return fmt_err("frame has a different number of channels than the streaminfo")
+    }
+    Ok(())
+}
+
+/// Returns a progress callback that records the samples done, and calls `progress` if set.
+fn new_progress_tracker(samples_done: &Arc<Mutex<u64>>,
+                        progress: Option<ProgressCallback>)
+                        -> ProgressCallback {
+    let samples_done = samples_done.clone();
+    Arc::new(Mutex::new(move |done: u64, total: Option<u64>| {
+        if let Ok(mut samples_done) = samples_done.lock() {
+            *samples_done = done;
+        }
+        if let Some(ref callback) = progress {
+            // If the callback panicked before, it is not called any more.
+            if let Ok(mut callback) = callback.lock() {
+                (&mut *callback)(done, total);
+            }
+        }
+    }))
+}
+
+/// Creates a frame reader with the block size limit, the behavior set in the options, progress callback, statistics, and label.
+fn new_frame_reader<T: ReadBytes>(input: T,
+                                  max_block_size: u16,
+                                  options: &FlacReaderOptions,
+                                  progress: Option<ProgressCallback>,
+                                  statistics: Option<SharedStatistics>,
+                                  label: Option<Arc<String>>,
+                                  streaminfo: &StreamInfo)
+                                  -> FrameReader<T> {
+    let mut frame_reader = FrameReader::new_ext(input, max_block_size);
+    frame_reader.set_stop_at_metadata(options.scan_all_metadata);
+    frame_reader.set_check_sample_range(options.strict);
+    if let Some(deadline) = options.deadline {
+        frame_reader.set_deadline(deadline);
+    }
+    if let Some(max_memory) = options.max_memory {
+        frame_reader.set_memory_budget(max_memory, input::BUFFER_SIZE);
+    }
+    frame_reader.set_partial_frame_is_eof(options.max_input_bytes.is_some());
+    if streaminfo.is_fixed_blocksize() {
+        frame_reader.set_fixed_block_size(streaminfo.max_block_size);
+        frame_reader.set_pad_final_block(options.pad_final_block);
+    }
+    frame_reader.set_streaminfo_bits_per_sample(streaminfo.bits_per_sample);
+    if let Some(callback) = progress {
+        frame_reader.set_progress_callback(callback, streaminfo.samples);
+    }
+    if let Some(statistics) = statistics {
+        frame_reader.set_statistics(statistics);
+    }
+    if let Some(label) = label {
+        frame_reader.set_label(label);
+    }
+    frame_reader
+}
+
+fn read_stream_header<R: ReadBytes>(input: &mut R) -> Result<()> {
+    // Some files start with ID3 tag data. The reference decoder supports this
+    // for convenience. Only `FlacReader::open_any()` skips it, but here we can
+    // at least generate a helpful error message if a file starts like this.
+    const ID3_HEADER: u32 = 0x49_44_33_00;
+
+    // A FLAC stream starts with a 32-bit header 'fLaC'.
+    let mut magic = [0u8; 4];
+    try!(input.read_into(&mut magic));
+    let header = (magic[0] as u32) << 24 | (magic[1] as u32) << 16 |
+                 (magic[2] as u32) << 8 | magic[3] as u32;
+    if magic != FLAC_MAGIC {
+        if (header & 0xff_ff_ff_00) == ID3_HEADER {
+            fmt_err("stream starts with ID3 header rather than FLAC header")
+        } else {
+            fmt_err("invalid stream header")
+        }
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns whether the next bytes are a frame sync code, without consuming them.
+fn is_at_frame_sync<R: io::Read>(input: &mut BufferedReader<R>) -> Result<bool> {
+    let bytes = try!(input.peek(2));
+    Ok(bytes.len() == 2 && bytes[0] == 0xff && bytes[1] & 0b1111_1110 == 0b1111_1000)
+}
+
+impl<R: io::Read> FlacReader<R> {
+    /// Create a reader that reads the FLAC format.
+    ///
+    /// The header and metadata blocks are read immediately. Audio frames
+    /// will be read on demand.
+    ///
+    /// Claxon rejects files that claim to contain excessively large metadata
+    /// blocks, to protect against denial of service attacks where a
+    /// small damaged or malicous file could cause gigabytes of memory
+    /// to be allocated. `Error::Unsupported` is returned in that case.
+    pub fn new(reader: R) -> Result<FlacReader<R>> {
+        FlacReader::new_ext(reader, FlacReaderOptions::default())
+    }
+
+    /// Create a reader that reads the FLAC format, with reader options.
+    ///
+    /// The header and metadata blocks are read immediately, but only as much as
+    /// specified in the options. See `FlacReaderOptions` for more details.
+    ///
+    /// Claxon rejects files that claim to contain excessively large metadata
+    /// blocks, to protect against denial of service attacks where a
+    /// small damaged or malicous file could cause gigabytes of memory
+    /// to be allocated. `Error::Unsupported` is returned in that case.
+    pub fn new_ext(reader: R, options: FlacReaderOptions) -> Result<FlacReader<R>> {
+        FlacReader::new_buffered(BufferedReader::new(reader), options)
+    }
+
+    /// Create a reader that draws the buffers of its sample iterators from `pool`.
+    ///
+    /// The iterators returned by `samples()` and the methods built on it take
+    /// a buffer from the pool when they decode their first block, and give it
+    /// back when they are dropped. Sharing one pool between the readers of a
+    /// batch of files avoids allocating a new buffer for every file. The pool
+    /// is kept by the reader that `next_stream()` returns. Decoding itself is
+    /// not affected.
+    pub fn new_with_pool(reader: R, pool: BufferPool) -> Result<FlacReader<R>> {
+        let mut flac_reader = try!(FlacReader::new(reader));
+        flac_reader.pool = Some(pool);
+        Ok(flac_reader)
+    }
+
+    /// Confirms that `reader` contains a decodable FLAC stream, and returns its streaminfo.
+    ///
+    /// This reads the metadata, and then decodes the first frame, including
+    /// its CRC-16 check. Reading stops after that frame. This is a stronger
+    /// test than checking the `fLaC` magic: a stream without audio frames, or
+    /// one that is damaged at the start of the audio, results in an error.
+    pub fn sniff(reader: R) -> Result<StreamInfo> {
+        let mut flac_reader = try!(FlacReader::new(reader));
+        match try!(flac_reader.blocks().read_next_or_eof(Vec::new())) {
+            Some(..) => Ok(flac_reader.streaminfo),
+            None => fmt_err("stream contains no audio frames"),
+        }
+    }
+
+    /// Creates a reader for the stream that starts at the current position of `buf_reader`.
+    fn new_buffered(mut buf_reader: BufferedReader<R>,
+                    options: FlacReaderOptions)
+                    -> Result<FlacReader<R>> {
+        let mut opts_current = options;
+
+        if let Some(max_memory) = options.max_memory {
+            if max_memory < input::BUFFER_SIZE {
+                return Err(Error::MemoryBudgetExceeded {
+                    needed_bytes: input::BUFFER_SIZE,
+                    budget_bytes: max_memory,
+                })
+            }
+        }
+
+        if let Some(max_input_bytes) = options.max_input_bytes {
+            buf_reader.set_limit(max_input_bytes);
+        }
+
+        // A flac stream first of all starts with a stream header.
+        try!(read_stream_header(&mut buf_reader));
+
+        // Start a new scope, because the input reader must be available again
+        // for the frame reader next.
+        let (streaminfo, streaminfo_bytes, vorbis_comment, cuesheet, seektable, foreign_metadata, pictures, metadata_blocks, skipped_metadata) = {
+            let mut metadata_blocks = Vec::new();
+            let mut skipped_metadata = Vec::new();
+            let mut streaminfo = None;
+            let mut streaminfo_bytes = None;
+            let mut vorbis_comment = None;
+            let mut cuesheet = None;
+            let mut seektable = None;
+            let mut foreign_metadata: Option<ForeignMetadata> = None;
+            let mut pictures = Vec::new();
+
+            // Next are one or more metadata blocks. When a missing streaminfo
+            // is allowed, the stream might continue with a frame straight away.
+            let has_metadata = !options.allow_missing_streaminfo ||
+                               !try!(is_at_frame_sync(&mut buf_reader));
+
+            // The flac specification dictates that the streaminfo block is the
+            // first block. The metadata block reader will yield at least one
+            // element.
+            if has_metadata {
+                let mut metadata_iter = MetadataBlockReader::new(&mut buf_reader);
+                metadata_iter.set_skip_damaged_blocks(!options.strict);
+                metadata_iter.set_infer_last_block(!options.strict);
+                let mut i = 0;
+                loop {
+                    let block_result = metadata_iter.next();
+
+                    // Blocks that were skipped precede the block just read.
+                    for (header, err) in metadata_iter.take_skipped_blocks() {
+                        metadata_blocks.push(header);
+                        skipped_metadata.push((header, err));
+                    }
+
+                    let block = match block_result {
+                        Some(result) => try!(result),
+                        None => break,
+                    };
+                    metadata_blocks.extend(metadata_iter.last_header());
+                    if metadata_blocks.len() > options.max_metadata_blocks as usize {
+                        return Err(Error::TooManyMetadataBlocks)
+                    }
+
+                    match block {
+                        MetadataBlock::StreamInfo(info) => {
+                            if streaminfo.is_some() {
+                                return fmt_err("encountered second streaminfo block")
+                            }
+                            streaminfo = Some(info);
+                            streaminfo_bytes = metadata_iter.streaminfo_bytes();
+                        }
+                        _ if i == 0 && !options.allow_missing_streaminfo => {
+                            return fmt_err("streaminfo block missing")
+                        }
+                        MetadataBlock::VorbisComment(vc) => {
+                            // The Vorbis comment block need not be present, but
+                            // when it is, it must be unique.
+                            if vorbis_comment.is_some() {
+                                return fmt_err("encountered second Vorbis comment block")
+                            } else {
+                                vorbis_comment = Some(vc);
+                            }
+
+                            // We have one, no new one is desired.
+                            opts_current.read_vorbis_comment = false;
+                        }
+                        MetadataBlock::CueSheet(cs) => {
+                            // Like the Vorbis comment block, the CUE sheet is
+                            // optional, but there can be at most one.
+                            if cuesheet.is_some() {
+                                return fmt_err("encountered second CUE sheet block")
+                            }
+                            cuesheet = Some(cs);
+                        }
+                        MetadataBlock::SeekTable(st) => {
+                            // There can be at most one seek table as well.
+                            if seektable.is_some() {
+                                return fmt_err("encountered second seek table block")
+                            }
+                            seektable = Some(st);
+                        }
+                        MetadataBlock::Application { id, data }
+                            if id == metadata::APPLICATION_ID_RIFF || id == metadata::APPLICATION_ID_AIFF => {
+                            // The chunks of a file are spread over consecutive
+                            // blocks. Blocks of another container type are not
+                            // part of the same file, so they are ignored.
+                            match foreign_metadata {
+                                None => foreign_metadata = Some(ForeignMetadata::new(id, data)),
+                                Some(ref mut fm) if fm.application_id() == id => fm.push(&data),
+                                Some(..) => {}
+                            }
+                        }
+                        MetadataBlock::Picture(picture) => {
+                            if options.read_picture {
+                                pictures.push(picture);
+                            }
+                        }
+                        // Other blocks are currently not handled.
+                        _block => {}
+                    }
+                    i += 1;
+
+                    // Early-out reading metadata once all desired blocks have
+                    // been collected. The streaminfo is always desired.
+                    if streaminfo.is_some() && !opts_current.has_desired_blocks() {
+                        break
+                    }
+                }
+            }
+
+            // TODO: Rather than discarding afterwards, never parse it in the
+            // first place; treat it like padding in the MetadataBlockReader.
+            if !options.read_vorbis_comment {
+                vorbis_comment = None;
+            }
+
+            // If the streaminfo is still missing, it was allowed to be missing,
+            // and all metadata has been read. Fall back to the frame header.
+            let streaminfo = match streaminfo {
+                Some(info) => info,
+                None => {
+                    // A frame header is at most 16 bytes.
+                    let header_bytes = try!(buf_reader.peek(16));
+                    let mut header = io::Cursor::new(header_bytes);
+                    try!(frame::read_streaminfo_from_frame_header(&mut header))
+                }
+            };
+
+            if options.strict {
+                if let Some(ref cs) = cuesheet {
+                    try!(check_lead_out(cs, &streaminfo));
+                }
+            }
+
+            (streaminfo, streaminfo_bytes, vorbis_comment, cuesheet, seektable, foreign_metadata, pictures, metadata_blocks, skipped_metadata)
+        };
+
+        // If all metadata has been read, the reader is now at the first frame.
+        let audio_start = buf_reader.position();
+
+        // Even if we might have read all metadata blocks, only set the state to
+        // "full" if `metadata_only` was false: this results in more predictable
+        // behavior.
+        let state = if options.metadata_only {
+            FlacReaderState::MetadataOnly(buf_reader)
+        } else {
+            FlacReaderState::Full(buf_reader)
+        };
+
+        let samples_done = Arc::new(Mutex::new(0));
+        let progress_tracker = new_progress_tracker(&samples_done, None);
+
+        // The flac reader will contain the reader that will read frames.
+        let flac_reader = FlacReader {
+            streaminfo: streaminfo,
+            streaminfo_bytes: streaminfo_bytes,
+            vorbis_comment: vorbis_comment,
+            cuesheet: cuesheet,
+            seektable: seektable,
+            foreign_metadata: foreign_metadata,
+            pictures: pictures,
+            metadata_blocks: metadata_blocks,
+            skipped_metadata: skipped_metadata,
+            input: state,
+            pending_block: Block::empty(),
+            pending_pos: 0,
+            audio_start: audio_start,
+            options: options,
+            progress: None,
+            samples_done: samples_done,
+            progress_tracker: progress_tracker,
+            statistics: if options.collect_stats {
+                Some(Arc::new(Mutex::new(DecodeStatistics::default())))
+            } else {
+                None
+            },
+            tags_changed: false,
+            pool: None,
+            label: None,
+        };
+
+        Ok(flac_reader)
+    }
+
+    /// Returns the number of bytes of the stream consumed so far.
+    ///
+    /// This is the offset of the next byte to be read, counted from the
+    /// position of the reader when the `FlacReader` was constructed. Bytes
+    /// that were buffered but not yet decoded are not counted, so after
+    /// construction it is the size of the header and metadata, and between
+    /// frames it is the offset of the next frame.
+    pub fn byte_position(&self) -> u64 {
+        match self.input {
+            FlacReaderState::Full(ref inp) => inp.position(),
+            FlacReaderState::MetadataOnly(ref inp) => inp.position(),
+        }
+    }
+
+    /// Returns how far decoding has progressed, for a progress indicator.
+    ///
+    /// The samples done are the end of the last decoded block, as reported to
+    /// the callback of `set_progress_callback()`, so they include frames
+    /// decoded by any means, and after seeking they are measured from the
+    /// start of the stream. The totals come from the streaminfo; the size of
+    /// the stream is not known to a reader that cannot seek, so
+    /// `bytes_total` is `None`. Use `progress_with_length()` to include it.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            samples_done: self.samples_done.lock().map(|s| *s).unwrap_or(0),
+            samples_total: self.streaminfo.samples,
+            bytes_read: self.byte_position(),
+            bytes_total: None,
+        }
+    }
+
+    /// Returns the byte offset of the first frame, where the metadata ends.
+    ///
+    /// This is the size of the `fLaC` magic and all metadata blocks with
+    /// their headers, counted from the start of the stream, like the offsets
+    /// of `frames()`. Unlike `byte_position()`, it does not change while
+    /// decoding, so it remains valid for patching metadata in place. If the
+    /// reader was constructed with `metadata_only` set, reading may have
+    /// stopped before the last metadata block, and it is then the end of the
+    /// last block that was read.
+    pub fn audio_start_offset(&self) -> u64 {
+        self.audio_start
+    }
+
+    /// Returns the streaminfo metadata.
+    ///
+    /// This contains information like the sample rate and number of channels.
+    pub fn streaminfo(&self) -> StreamInfo {
+        self.streaminfo
+    }
+
+    /// Returns the contents of the streaminfo block, exactly as stored.
+    ///
+    /// These are the 34 bytes after the block header, from which `streaminfo()`
+    /// was parsed. This is for copying or hashing the block verbatim, without
+    /// re-encoding the parsed fields. Returns `None` if the stream has no
+    /// streaminfo block, which is only accepted with `allow_missing_streaminfo`.
+    pub fn streaminfo_bytes(&self) -> Option<[u8; 34]> {
+        self.streaminfo_bytes
+    }
+
+    /// Returns the metadata blocks that were skipped because of invalid contents, with the errors.
+    ///
+    /// When the `strict` option is false, an application block, seek table,
+    /// CUE sheet, or picture that fails to parse does not make construction
+    /// fail, because the audio can be decoded without it. Such a block is
+    /// skipped instead, and it is reported here. It is still included in
+    /// `metadata_blocks()`. In strict mode, this is always empty.
+    pub fn skipped_metadata_blocks(&self) -> &[(MetadataBlockHeader, Error)] {
+        &self.skipped_metadata
+    }
+
+    /// Returns the block sizes of the stream, as declared by the streaminfo.
+    ///
+    /// This requires no decoding. For a stream that is not uniform, the
+    /// streaminfo only bounds the block sizes; the actual sizes can be learned
+    /// by decoding, for instance with `blocks_with_position()`. If the
+    /// encoder did not record the block sizes, the recommended chunk size is
+    /// 4096, the most common block size.
+    pub fn block_size_info(&self) -> BlockSizeInfo {
+        let max_block_size = self.streaminfo.max_block_size;
+        BlockSizeInfo {
+            min_block_size: self.streaminfo.min_block_size,
+            max_block_size: max_block_size,
+            is_uniform: self.streaminfo.is_fixed_blocksize(),
+            recommended_chunk_size: if max_block_size == 0 { 4096 } else { max_block_size as u32 },
+        }
+    }
+
+    /// Returns the headers of the metadata blocks that were read, in stream order.
+    ///
+    /// When `metadata_only` is set, reading stops as soon as all desired
+    /// blocks have been found, so later blocks are not included.
+    pub fn metadata_blocks(&self) -> &[MetadataBlockHeader] {
+        &self.metadata_blocks
+    }
+
+    /// Returns the total size in bytes of the padding blocks.
+    ///
+    /// Padding is space reserved for metadata to grow into, so tags can be
+    /// edited in place without rewriting the entire file. Only the contents of
+    /// the padding blocks are counted, not their 4-byte headers. Like
+    /// `metadata_blocks()`, this only considers blocks that were read.
+    pub fn total_padding(&self) -> u32 {
+        self.metadata_blocks
+            .iter()
+            .filter(|header| header.block_type == metadata::BLOCK_TYPE_PADDING)
+            .fold(0u32, |total, header| total.saturating_add(header.length))
+    }
+
+    /// Returns the CUE sheet, if present.
+    ///
+    /// When `metadata_only` is set, metadata blocks after the desired ones are
+    /// not read, so a CUE sheet that occurs after them is not returned.
+    pub fn cuesheet(&self) -> Option<&CueSheet> {
+        self.cuesheet.as_ref()
+    }
+
+    /// Returns the pictures, such as cover art, in stream order.
+    ///
+    /// Pictures are only kept when `read_picture` is set in the options, so
+    /// this is empty otherwise. Use `metadata::Picture::picture_type` to find
+    /// the front cover.
+    pub fn pictures(&self) -> &[Picture] {
+        &self.pictures
+    }
+
+    /// Checks that the lead-out of the CUE sheet is at the last sample of the stream.
+    ///
+    /// In an unedited CD image, the lead-out track starts right after the
+    /// audio, so its offset equals the number of samples in the streaminfo. A
+    /// mismatch suggests a corrupt or edited file, and results in
+    /// `Error::CueSheetLeadOutMismatch`. If there is no CUE sheet, or the
+    /// streaminfo does not specify the number of samples, there is nothing to
+    /// compare, and this returns `Ok`. In strict mode, constructing the reader
+    /// performs this check already.
+    pub fn check_cuesheet_lead_out(&self) -> Result<()> {
+        match self.cuesheet {
+            Some(ref cuesheet) => check_lead_out(cuesheet, &self.streaminfo),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the seek table, if present.
+    ///
+    /// `seek_to_sample()` uses the seek table to find a frame near the target
+    /// in a variable-blocksize stream. Like for the CUE sheet, a seek table
+    /// after the desired blocks is not read when `metadata_only` is set.
+    pub fn seek_table(&self) -> Option<&SeekTable> {
+        self.seektable.as_ref()
+    }
+
+    /// Returns the WAV or AIFF chunks stored by `flac --keep-foreign-metadata`, if present.
+    ///
+    /// Like the CUE sheet, this is not returned when the application blocks
+    /// occur after the desired blocks and `metadata_only` is set.
+    pub fn foreign_metadata(&self) -> Option<&ForeignMetadata> {
+        self.foreign_metadata.as_ref()
+    }
+
+    /// Returns the part of the original WAV or AIFF file that preceded the audio.
+    ///
+    /// This reassembles the chunks stored in the foreign metadata, up to and
+    /// including the header of the `data` or `SSND` chunk. Together with the
+    /// decoded audio and `original_container_trailer()`, it reconstructs the
+    /// original file exactly. The audio is stored little-endian in WAV and
+    /// big-endian in AIFF, at the bytes per sample of the stream, so it can be
+    /// produced with `into_raw_reader()`, unless the header declares a
+    /// different sample format. Returns `None` if there is no foreign metadata,
+    /// or if its chunks cannot be reassembled.
+    pub fn original_container_header(&self) -> Option<Vec<u8>> {
+        self.foreign_metadata.as_ref().and_then(|fm| fm.header()).map(|h| h.to_vec())
+    }
+
+    /// Returns the part of the original WAV or AIFF file that followed the audio.
+    ///
+    /// See `original_container_header()`. This includes the padding byte of
+    /// an audio chunk of odd size.
+    pub fn original_container_trailer(&self) -> Option<Vec<u8>> {
+        self.foreign_metadata.as_ref().and_then(|fm| fm.trailer())
+    }
+
+    /// Returns the origination info of a Broadcast Wave file, if present.
+    ///
+    /// Broadcast Wave files carry a `bext` chunk, which `flac
+    /// --keep-foreign-metadata` stores with the other WAV chunks. This parses
+    /// that chunk from the foreign metadata. Returns `None` if there is no
+    /// foreign metadata, if it contains no `bext` chunk, or if the chunk is
+    /// too short. See `metadata::BroadcastInfo` for the fields.
+    pub fn broadcast_info(&self) -> Option<BroadcastInfo> {
+        match self.foreign_metadata {
+            Some(ref fm) if fm.application_id() == metadata::APPLICATION_ID_RIFF => {
+                fm.chunk(b"bext").and_then(metadata::parse_bext_chunk)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether the audio needs de-emphasis to be played back correctly.
+    ///
+    /// Audio recorded with pre-emphasis (this happens mostly on older CDs) has
+    /// its high frequencies boosted, and a player must apply a de-emphasis
+    /// filter to undo this. Claxon does not apply de-emphasis itself.
+    ///
+    /// This returns true if any audio track in the CUE sheet has the
+    /// pre-emphasis flag set, or if there is a `PRE_EMPHASIS` or `EMPHASIS`
+    /// tag with value `1`, `yes`, `true`, or `on` (case-insensitive). Note
+    /// that with a CUE sheet, pre-emphasis might apply to some tracks only.
+    pub fn has_pre_emphasis(&self) -> bool {
+        // This import is actually required on Rust 1.13.
+        #[allow(unused_imports)]
+        use std::ascii::AsciiExt;
+
+        if let Some(ref cuesheet) = self.cuesheet {
+            if cuesheet.tracks.iter().any(|t| t.is_audio && t.pre_emphasis) {
+                return true
+            }
+        }
+
+        let is_set = |value: &str| {
+            ["1", "yes", "true", "on"].iter().any(|v| value.trim().eq_ignore_ascii_case(v))
+        };
+        self.get_tag("PRE_EMPHASIS").chain(self.get_tag("EMPHASIS")).any(is_set)
+    }
+
+    /// Returns the vendor string of the Vorbis comment block, if present.
+    ///
+    /// This string usually contains the name and version of the program that
+    /// encoded the FLAC stream, such as `reference libFLAC 1.3.2 20170101`
+    /// or `Lavf57.25.100`.
+    pub fn vendor(&self) -> Option<&str> {
+        self.vorbis_comment.as_ref().map(|vc| &vc.vendor[..])
+    }
+
+    /// Returns name-value pairs of Vorbis comments, such as `("ARTIST", "Queen")`.
+    ///
+    /// The name is supposed to be interpreted case-insensitively, and is
+    /// guaranteed to consist of ASCII characters. Claxon does not normalize
+    /// the casing of the name. Use `get_tag()` to do a case-insensitive lookup.
+    ///
+    /// Names need not be unique. For instance, multiple `ARTIST` comments might
+    /// be present on a collaboration track.
+    ///
+    /// See <https://www.xiph.org/vorbis/doc/v-comment.html> for more details.
+    pub fn tags<'a>(&'a self) -> metadata::Tags<'a> {
+        match self.vorbis_comment.as_ref() {
+            Some(vc) => metadata::Tags::new(&vc.comments[..]),
+            None => metadata::Tags::new(&[]),
+        }
+    }
+
+    /// Look up a Vorbis comment such as `ARTIST` in a case-insensitive way.
+    ///
+    /// Returns an iterator,  because tags may occur more than once. There could
+    /// be multiple `ARTIST` tags on a collaboration track, for instance.
+    ///
+    /// Note that tag names are ASCII and never contain `'='`; trying to look up
+    /// a non-ASCII tag will return no results. Furthermore, the Vorbis comment
+    /// spec dictates that tag names should be handled case-insensitively, so
+    /// this method performs a case-insensitive lookup.
+    ///
+    /// See also `tags()` for access to the raw tags.
+    /// See <https://www.xiph.org/vorbis/doc/v-comment.html> for more details.
+    pub fn get_tag<'a>(&'a self, tag_name: &'a str) -> metadata::GetTag<'a> {
+        match self.vorbis_comment.as_ref() {
+            Some(vc) => metadata::GetTag::new(&vc.comments[..], tag_name),
+            None => metadata::GetTag::new(&[], tag_name),
+        }
+    }
+
+    /// Returns the speaker positions of the channels.
+    ///
+    /// Multichannel files can specify the layout in a
+    /// `WAVEFORMATEXTENSIBLE_CHANNEL_MASK` tag. If there is no such tag, the
+    /// layout that the FLAC format prescribes for the number of channels is
+    /// returned. If the tag cannot be parsed, or if its number of speakers
+    /// differs from the number of channels, the layout is ambiguous, and
+    /// `None` is returned.
+    pub fn channel_mask(&self) -> Option<ChannelMask> {
+        let channels = self.streaminfo.channels;
+        match self.get_tag("WAVEFORMATEXTENSIBLE_CHANNEL_MASK").next() {
+            Some(value) => ChannelMask::from_tag_value(value).and_then(|mask| {
+                if mask.channels() == channels { Some(mask) } else { None }
+            }),
+            None => ChannelMask::default_for_channels(channels),
+        }
+    }
+
+    /// Returns whether the tags differ from those of the previous stream in a chain.
+    ///
+    /// A stream that follows another, as returned by `next_stream()`, carries
+    /// its own Vorbis comments. Internet radio uses this to change the tags
+    /// with every song, so a "now playing" display should be updated when this
+    /// returns true. Only the comments are compared, the vendor string is not.
+    /// Gaining or losing the Vorbis comment block counts as a change. For the
+    /// first stream, this returns false.
+    pub fn tags_changed(&self) -> bool {
+        self.tags_changed
+    }
+
+    /// Returns all identifying metadata of the stream at once.
+    ///
+    /// This collects the ISRC and MusicBrainz IDs from the Vorbis comments, and
+    /// the MD5 signature from the streaminfo. See `metadata::Identifiers` for
+    /// the tags that are used.
+    pub fn identifiers<'a>(&'a self) -> Identifiers<'a> {
+        let md5sum = if self.streaminfo.md5sum == [0; 16] {
+            None
+        } else {
+            Some(self.streaminfo.md5sum)
+        };
+
+        Identifiers {
+            isrc: self.get_tag("ISRC").next(),
+            musicbrainz_track_id: self.get_tag("MUSICBRAINZ_TRACKID").next(),
+            musicbrainz_release_track_id: self.get_tag("MUSICBRAINZ_RELEASETRACKID").next(),
+            musicbrainz_album_id: self.get_tag("MUSICBRAINZ_ALBUMID").next(),
+            musicbrainz_artist_id: self.get_tag("MUSICBRAINZ_ARTISTID").next(),
+            musicbrainz_release_group_id: self.get_tag("MUSICBRAINZ_RELEASEGROUPID").next(),
+            md5sum: md5sum,
+        }
+    }
+
+    /// Returns the loudness normalization gains of the stream.
+    ///
+    /// This parses the classic ReplayGain tags, and the EBU R128 gain tags,
+    /// whose fixed-point values are converted to dB. Players should prefer
+    /// whichever matches their reference level. See `metadata::Gain` for the
+    /// tags that are used.
+    pub fn gain(&self) -> Gain {
+        let replay_gain = |name| self.get_tag(name).next().and_then(metadata::parse_replay_gain);
+        let r128_gain = |name| self.get_tag(name).next().and_then(metadata::parse_r128_gain);
+        Gain {
+            track_gain: replay_gain("REPLAYGAIN_TRACK_GAIN"),
+            track_peak: replay_gain("REPLAYGAIN_TRACK_PEAK"),
+            album_gain: replay_gain("REPLAYGAIN_ALBUM_GAIN"),
+            album_peak: replay_gain("REPLAYGAIN_ALBUM_PEAK"),
+            r128_track_gain: r128_gain("R128_TRACK_GAIN"),
+            r128_album_gain: r128_gain("R128_ALBUM_GAIN"),
+        }
+    }
+
+    /// Returns the track number, and the total number of tracks if known.
+    ///
+    /// This parses the first `TRACKNUMBER` tag, in the form `N` or `N/M`.
+    /// Returns `None` if the tag is missing or not numeric. See
+    /// `metadata::parse_position()` for the details.
+    pub fn track_number(&self) -> Option<(u32, Option<u32>)> {
+        self.get_tag("TRACKNUMBER").next().and_then(metadata::parse_position)
+    }
+
+    /// Returns the disc number, and the total number of discs if known.
+    ///
+    /// This parses the first `DISCNUMBER` tag, in the same way as
+    /// `track_number()` parses the track number.
+    pub fn disc_number(&self) -> Option<(u32, Option<u32>)> {
+        self.get_tag("DISCNUMBER").next().and_then(metadata::parse_position)
+    }
+
+    /// Returns what the stream records about how it was encoded.
+    ///
+    /// This extracts the libFLAC version from the vendor string, and collects
+    /// the `ENCODER`, and `ENCODER_SETTINGS` or `ENCODER_OPTIONS` tags. See
+    /// `metadata::EncoderHints` for the details.
+    pub fn encoder_hints<'a>(&'a self) -> EncoderHints<'a> {
+        EncoderHints {
+            libflac_version: self.vendor().and_then(metadata::parse_libflac_version),
+            encoder: self.get_tag("ENCODER").next(),
+            settings: self.get_tag("ENCODER_SETTINGS").chain(self.get_tag("ENCODER_OPTIONS")).next(),
+        }
+    }
+
+    /// Returns the lyrics of the stream, if present.
+    ///
+    /// The lyrics are taken from the first `LYRICS` tag, or if there is none,
+    /// from the first `UNSYNCEDLYRICS` tag. Lyrics in LRC format, with lines
+    /// that start with a time tag like `[00:12.34]`, are parsed into lines
+    /// with their start time. See `metadata::Lyrics` for the details.
+    pub fn lyrics<'a>(&'a self) -> Option<Lyrics<'a>> {
+        self.get_tag("LYRICS")
+            .chain(self.get_tag("UNSYNCEDLYRICS"))
+            .next()
+            .map(Lyrics::from_tag_value)
+    }
+
+    /// Returns an iterator that yields the samples converted to `target_hz` Hz.
+    ///
+    /// The samples are interleaved, like `samples()` yields them. Blocks of
+    /// any size are fed into the conversion, and at the end of the stream the
+    /// last samples are flushed, so a stream of `n` inter-channel samples
+    /// produces `n * target_hz / sample_rate` of them, rounded up. See
+    /// `resample::Resampled` for how the conversion works.
+    ///
+    /// This method is only available with the `resample` feature enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target_hz` is zero, or if the `FlacReader` was constructed
+    /// with `metadata_only` set (when the iterator is advanced).
+    #[cfg(feature = "resample")]
+    pub fn resampled(self, target_hz: u32) -> resample::Resampled<R> {
+        resample::Resampled::new(self, target_hz)
+    }
+
+    /// Returns a rodio `Source` that plays the decoded samples.
+    ///
+    /// The samples are interleaved, like `samples()` yields them, and
+    /// converted to `f32`, the sample type of rodio. The source reports the
+    /// number of channels, the sample rate, and the duration of the stream
+    /// from the streaminfo. See `source::FlacSource` for details.
+    ///
+    /// This method is only available with the `rodio` feature enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    #[cfg(feature = "rodio")]
+    pub fn into_source(self) -> source::FlacSource<R> {
+        source::FlacSource::new(self)
+    }
+
+    /// Returns a reader that yields the decoded samples as raw PCM bytes in the given format.
+    ///
+    /// Samples are interleaved and scaled to the bits of the format, as with
+    /// `samples_at_bit_depth()`. For example, a format of 8 unsigned bits
+    /// produces the samples of an 8-bit WAV file, and 16 signed little-endian
+    /// bits those of a CD-quality one. See `raw::RawFormat` for the options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of bits of `format` is not 8, 16, 24, or 32, or if
+    /// the `FlacReader` was constructed with `metadata_only` set (when the
+    /// reader is read from).
+    pub fn into_raw_reader(self, format: raw::RawFormat) -> raw::RawReader<R> {
+        raw::RawReader::new(self, format)
+    }
+
+    /// Sets a label, such as a file name, that identifies the stream in errors.
+    ///
+    /// When decoding many files in a batch, an error by itself does not say
+    /// which file it came from. With a label set, errors that occur while
+    /// decoding frames, through `samples()`, `blocks()`, `frames()`, or any
+    /// method built on them, are wrapped in `Error::Labeled`, whose `Display`
+    /// includes the label. Use `Error::unlabeled()` to inspect the underlying
+    /// error. Errors from other operations, such as constructing the reader or
+    /// seeking, do not have the label; `Error::with_label()` can attach it.
+    /// The label is kept by the reader that `next_stream()` returns.
+    pub fn set_label(&mut self, label: String) {
+        self.label = Some(Arc::new(label));
+    }
+
+    /// Returns the label set with `set_label()`, if any.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_ref().map(|label| &label[..])
+    }
+
+    /// Sets a callback that is invoked after every decoded frame, to report progress.
+    ///
+    /// The callback is called with the number of inter-channel samples decoded
+    /// so far, and the total number of inter-channel samples in the stream as
+    /// the streaminfo specifies it, or `None` if that is unknown. It is called
+    /// once per frame rather than once per sample, so the overhead is
+    /// negligible. It applies to every way of decoding, including `samples()`,
+    /// `blocks()`, and the frames that seeking decodes. The callback is kept
+    /// by the reader that `next_stream()` returns.
+    pub fn set_progress_callback<F>(&mut self, callback: F)
+        where F: FnMut(u64, Option<u64>) + Send + 'static
+    {
+        let callback: ProgressCallback = Arc::new(Mutex::new(callback));
+        self.set_shared_progress_callback(Some(callback));
+    }
+
+    /// Sets the callback that `set_progress_callback()` wraps, or removes it.
+    fn set_shared_progress_callback(&mut self, callback: Option<ProgressCallback>) {
+        self.progress_tracker = new_progress_tracker(&self.samples_done, callback.clone());
+        self.progress = callback;
+    }
+
+    /// Returns statistics about the frames decoded so far, if they are collected.
+    ///
+    /// This returns `None` unless the reader was constructed with the
+    /// `collect_stats` option. The statistics cover frames decoded by any
+    /// means, including `samples()`, `blocks()`, and seeking. A reader
+    /// returned by `next_stream()` starts counting afresh.
+    pub fn statistics(&self) -> Option<DecodeStatistics> {
+        self.statistics.as_ref().and_then(|stats| stats.lock().ok().map(|s| *s))
+    }
+
+    /// Returns an iterator that decodes a single frame on every iteration.
+    /// TODO: It is not an iterator.
+    ///
+    /// This is a low-level primitive that gives you control over when decoding
+    /// happens. The representation of the decoded audio is somewhat specific to
+    /// the FLAC format. For a higher-level interface, see `samples()`.
+    pub fn blocks<'r>(&'r mut self) -> FrameReader<&'r mut BufferedReader<R>> {
+        let max_block_size = self.max_block_size();
+        let progress = Some(self.progress_tracker.clone());
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => {
+                new_frame_reader(inp,
+                                 max_block_size,
+                                 &self.options,
+                                 progress,
+                                 self.statistics.clone(),
+                                 self.label.clone(),
+                                 &self.streaminfo)
+            }
+            FlacReaderState::MetadataOnly(..) =>
+                panic!("FlacReaderOptions::metadata_only must be false \
+                       to be able to use FlacReader::blocks()"),
+        }
+    }
+
+    /// Returns an iterator over blocks, together with their sample position.
+    ///
+    /// Every item is the index of the first inter-channel sample of the block,
+    /// and the block itself. The positions form the running sum of the block
+    /// durations, so they need not be accumulated manually. Like `blocks()`,
+    /// the iterator is streaming, and it starts at the current position.
+    ///
+    /// Unlike `blocks()`, this allocates a new buffer for every block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn blocks_with_position<'r>(&'r mut self)
+                                    -> BlocksWithPosition<&'r mut BufferedReader<R>> {
+        BlocksWithPosition::new(self.blocks())
+    }
+
+    /// Returns the largest block size that frames may have, given the options.
+    fn max_block_size(&self) -> u16 {
+        if self.options.strict {
+            cmp::min(self.options.max_block_size, self.streaminfo.max_block_size)
+        } else {
+            self.options.max_block_size
+        }
+    }
+
+    /// Returns an iterator over blocks, together with their location in the stream.
+    ///
+    /// Every item is the byte range of the encoded frame, and the block decoded
+    /// from it. Offsets are counted from the start of the stream, where the
+    /// stream started when the `FlacReader` was constructed. The frames are
+    /// contiguous, so the ranges can be used to copy frames verbatim. Like
+    /// `blocks()`, the iterator is streaming.
+    ///
+    /// Unlike `blocks()`, this allocates a new buffer for every block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn frames<'r>(&'r mut self) -> FlacFrames<'r, R> {
+        let max_block_size = self.max_block_size();
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => {
+                FlacFrames {
+                    input: inp,
+                    max_block_size: max_block_size,
+                    options: self.options,
+                    progress: Some(self.progress_tracker.clone()),
+                    statistics: self.statistics.clone(),
+                    label: self.label.clone(),
+                    streaminfo: self.streaminfo,
+                    decoded_samples: 0,
+                    has_failed: false,
+                }
+            }
+            FlacReaderState::MetadataOnly(..) =>
+                panic!("FlacReaderOptions::metadata_only must be false \
+                       to be able to use FlacReader::frames()"),
+        }
+    }
+
+    /// Returns the number of the first sample and the byte offset of every frame.
+    ///
+    /// This is a dense index that can serve as seek table for a stream that
+    /// lacks one. Every entry is the index of the first inter-channel sample of
+    /// a frame, and the offset of its frame header, counted from the start of
+    /// the stream like `frames()` does. Subframes are not prefixed with their
+    /// size, so finding the next frame requires decoding the current one, but
+    /// the samples are not retained. Sample numbers are the running sum of the
+    /// block durations, starting at the first sample of the first frame. Like
+    /// `frames()`, this starts at the current position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn build_index(&mut self) -> Result<Vec<(u64, u64)>> {
+        let mut index = Vec::new();
+        let mut time = None;
+        for frame in self.frames() {
+            let (range, block) = try!(frame);
+            let first_sample = time.unwrap_or(block.time());
+            index.push((first_sample, range.offset));
+            time = Some(first_sample + block.duration() as u64);
+        }
+        Ok(index)
+    }
+
+    /// Returns an iterator over all samples.
+    ///
+    /// The channel data is is interleaved, so `for sample in reader.samples()`
+    /// visits every sample of the stream in order. Frames are decoded lazily,
+    /// one at a time, when the iterator reaches them, so partial iteration
+    /// only decodes the frames that it consumes. The iterator is streaming. That is,
     /// if you call this method once, read a few samples, and call this method
     /// again, the second iterator will not start again from the beginning of
     /// the file. It will continue somewhere after where the first iterator
@@ -379,43 +2111,2453 @@ impl<R: io::Read> FlacReader<R> {
     /// a stream into blocks, which have to be decoded entirely. If you drop the
     /// iterator, you lose the unread samples in that block.)
     ///
-    /// This is a user-friendly interface that trades performance for ease of
-    /// use. If performance is an issue, consider using `blocks()` instead.
+    /// This is a user-friendly interface that trades performance for ease of
+    /// use. If performance is an issue, consider using `blocks()` instead.
+    ///
+    /// This is a high-level interface to the decoder. The cost of retrieving
+    /// the next sample can vary significantly, as sometimes a new block has to
+    /// be decoded. Additionally, there is a cost to every iteration returning a
+    /// `Result`. When a block has been decoded, iterating the samples in that
+    /// block can never fail, but a match on every sample is required
+    /// nonetheless. For more control over when decoding happens, and less error
+    /// handling overhead, use `blocks()`.
+    pub fn samples<'r>(&'r mut self) -> FlacSamples<&'r mut BufferedReader<R>> {
+        let max_block_size = self.max_block_size();
+        let progress = Some(self.progress_tracker.clone());
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => {
+                FlacSamples {
+                    frame_reader: new_frame_reader(inp,
+                                                   max_block_size,
+                                                   &self.options,
+                                                   progress,
+                                                   self.statistics.clone(),
+                                                   self.label.clone(),
+                                                   &self.streaminfo),
+                    block: Block::empty(),
+                    sample: 0,
+                    channel: 0,
+                    expected_samples: self.streaminfo.samples,
+                    strict: self.options.strict,
+                    allow_truncation: self.options.max_input_bytes.is_some(),
+                    is_truncated: false,
+                    first_time: None,
+                    has_extra_samples: false,
+                    block_padding: 0,
+                    pool: self.pool.clone(),
+                    has_failed: false,
+                }
+            }
+            FlacReaderState::MetadataOnly(..) => {
+                panic!("FlacReaderOptions::metadata_only must be false \
+                       to be able to use FlacReader::samples()")
+            }
+        }
+    }
+
+    /// Returns an iterator over all samples, converted to the sample type `S`.
+    ///
+    /// This behaves like `samples()`, but every sample is converted to `S`. If
+    /// a sample does not fit, the iterator yields `Error::SampleTooWide`, which
+    /// names the bits per sample of the stream, and then stops. Note that a
+    /// stream with a high bit depth but a low volume might not trigger this:
+    /// the check is on the samples, not on the streaminfo.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn samples_as<'r, S: Sample>(&'r mut self) -> SamplesAs<&'r mut BufferedReader<R>, S> {
+        SamplesAs {
+            bits_per_sample: self.streaminfo.bits_per_sample,
+            samples: self.samples(),
+            has_failed: false,
+            sample_type: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over arrays that hold the samples of all channels at one time.
+    ///
+    /// Where `samples()` yields the samples of every channel one by one, this
+    /// yields a `[i32; N]` with one sample per channel for every inter-channel
+    /// sample. This is the frame representation of DSP libraries such as dasp.
+    /// With the `dasp` feature enabled, `signal()` provides these arrays as a
+    /// `dasp::Signal`. Like `samples()`, this decodes from the current position
+    /// in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the array type `F` differs from the number of
+    /// channels, or if the `FlacReader` was constructed with `metadata_only`
+    /// set. A frame with a different number of channels than the streaminfo
+    /// is a format error.
+    pub fn sample_arrays<'r, F: SampleArray>(&'r mut self) -> SampleArrays<&'r mut BufferedReader<R>, F> {
+        assert_eq!(F::channels(), self.streaminfo.channels,
+                   "array length must equal the number of channels");
+        let streaminfo = self.streaminfo;
+        SampleArrays {
+            frame_reader: self.blocks(),
+            streaminfo: streaminfo,
+            block: Block::empty(),
+            sample: 0,
+            has_failed: false,
+            array_type: PhantomData,
+        }
+    }
+
+    /// Returns a `dasp::Signal` that yields the samples of all channels at a time.
+    ///
+    /// This is `sample_arrays()` as a signal, so the decoded stream can be
+    /// used in a dasp processing graph directly. The frame type `F` is an
+    /// array such as `[i32; 2]`, with one sample per channel. Because a signal
+    /// cannot fail, an error ends the signal, and it can be retrieved with
+    /// `signal::FlacSignal::error()`. See `signal::FlacSignal` for details.
+    ///
+    /// This method is only available with the `dasp` feature enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the array type `F` differs from the number of
+    /// channels, or if the `FlacReader` was constructed with `metadata_only`
+    /// set.
+    #[cfg(feature = "dasp")]
+    pub fn signal<'r, F: SampleArray>(&'r mut self) -> signal::FlacSignal<&'r mut BufferedReader<R>, F> {
+        signal::FlacSignal::new(self.sample_arrays())
+    }
+
+    /// Returns an iterator over all samples, converted to a different bit depth.
+    ///
+    /// Samples are converted from the bit depth in the streaminfo to
+    /// `target_bits`. When the target is wider, samples are shifted left, which
+    /// is lossless. When the target is narrower, samples are shifted right,
+    /// which discards the least significant bits (rounding towards negative
+    /// infinity). See `sample::convert_bit_depth()` for how this affects the
+    /// extremes of the range. Apart from that, this behaves like `samples()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target_bits` is not between 1 and 32 inclusive, or if the
+    /// `FlacReader` was constructed with `metadata_only` set.
+    pub fn samples_at_bit_depth<'r>(&'r mut self,
+                                    target_bits: u32)
+                                    -> BitDepthSamples<&'r mut BufferedReader<R>> {
+        assert!(target_bits >= 1 && target_bits <= 32, "target bit depth must be between 1 and 32");
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        BitDepthSamples {
+            samples: self.samples(),
+            shift_left: target_bits.saturating_sub(bits_per_sample),
+            shift_right: bits_per_sample.saturating_sub(target_bits),
+            target_bits: target_bits,
+            ditherer: None,
+        }
+    }
+
+    /// Returns an iterator over all samples, converted to a different bit depth with dither.
+    ///
+    /// This is like `samples_at_bit_depth()`, but when the target is narrower,
+    /// noise of the given kind is added before the least significant bits are
+    /// discarded, which avoids the distortion that plain truncation causes.
+    /// The noise is pseudorandom, seeded from `seed`, so the output is
+    /// reproducible. See `sample::Ditherer` for the details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target_bits` is not between 1 and 32 inclusive, or if the
+    /// `FlacReader` was constructed with `metadata_only` set.
+    pub fn samples_at_bit_depth_dithered<'r>(&'r mut self,
+                                             target_bits: u32,
+                                             dither: sample::Dither,
+                                             seed: u64)
+                                             -> BitDepthSamples<&'r mut BufferedReader<R>> {
+        let mut samples = self.samples_at_bit_depth(target_bits);
+        if samples.shift_right > 0 {
+            samples.ditherer = Some(sample::Ditherer::new(dither, seed));
+        }
+        samples
+    }
+
+    /// Returns an iterator over all samples, together with their channel and index.
+    ///
+    /// Every item is a tuple `(channel, index, value)`, where `index` is the
+    /// absolute index of the inter-channel sample in the stream. The index is
+    /// computed from the sizes of the blocks decoded so far, so it starts at
+    /// zero at the current position, like `samples()` does. Samples are
+    /// yielded in the same interleaved order as `samples()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn indexed_samples<'r>(&'r mut self) -> IndexedSamples<&'r mut BufferedReader<R>> {
+        IndexedSamples {
+            samples: self.samples(),
+            block_start: 0,
+            next_block_start: 0,
+        }
+    }
+
+    /// Fills `out` with interleaved samples, normalized to floats in [-1.0, 1.0).
+    ///
+    /// Samples are divided by 2 to the power of the bit depth minus one, so
+    /// the full range of the stream maps onto [-1.0, 1.0), as with
+    /// `sample::to_f32()`. Returns the number of samples written, which is
+    /// less than the length of `out` only when the end of the stream was
+    /// reached. Reads resume where the previous call stopped, also in the
+    /// middle of a block, so this can be called from an audio callback with a
+    /// buffer of any size. Apart from buffer growth when a larger block is
+    /// encountered, this does not allocate.
+    ///
+    /// Samples that were decoded into the internal block but not yet returned
+    /// are not seen by `blocks()` or `samples()`. If an error occurs, the
+    /// number of samples written before the error is not reported.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn read_interleaved_f32(&mut self, out: &mut [f32]) -> Result<usize> {
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let len = out.len();
+        let mut samples = out.iter_mut();
+        self.read_pending(len, |value| {
+            *samples.next().unwrap() = sample::to_f32(value, bits_per_sample);
+        })
+    }
+
+    /// Pushes as many interleaved samples into `producer` as it has room for.
+    ///
+    /// Returns the number of samples pushed, which is less than the number of
+    /// free slots only when the end of the stream was reached. Like
+    /// `read_interleaved_f32()`, this resumes in the middle of a block, so it
+    /// can be called whenever the consumer has freed up space, to top up the
+    /// queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn fill_producer<P: SampleProducer>(&mut self, producer: &mut P) -> Result<usize> {
+        let slots = producer.slots();
+        self.read_pending(slots, |sample| producer.push(sample))
+    }
+
+    /// Decodes the rest of the stream into `producer`, waiting for space when it is full.
+    ///
+    /// When the queue is full, this yields the thread until the consumer has
+    /// freed up space. It returns at the end of the stream, or as soon as the
+    /// consumer is gone. For more control over waiting, call `fill_producer()`
+    /// in a loop instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_into_producer<P: SampleProducer>(&mut self, producer: &mut P) -> Result<()> {
+        while !producer.is_abandoned() {
+            let slots = producer.slots();
+            if slots == 0 {
+                thread::yield_now();
+                continue
+            }
+
+            if try!(self.fill_producer(producer)) < slots {
+                break
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes up to `max` interleaved samples to `emit`, resuming in the pending block.
+    ///
+    /// Returns the number of samples emitted, which is less than `max` only at
+    /// the end of the stream.
+    fn read_pending<F: FnMut(i32)>(&mut self, max: usize, mut emit: F) -> Result<usize> {
+        let mut emitted = 0;
+
+        while emitted < max {
+            let n_channels = self.pending_block.channels();
+            let len = self.pending_block.len();
+
+            if self.pending_pos == len {
+                // Reuse the buffer of the depleted block to decode the next one.
+                let block = mem::replace(&mut self.pending_block, Block::empty());
+                self.pending_pos = 0;
+                match try!(self.blocks().read_next_or_eof(block.into_buffer())) {
+                    Some(next_block) => self.pending_block = next_block,
+                    None => break,
+                }
+                continue
+            }
+
+            let count = cmp::min(max - emitted, (len - self.pending_pos) as usize);
+            for _ in 0..count {
+                let ch = self.pending_pos % n_channels;
+                let i = self.pending_pos / n_channels;
+                emit(self.pending_block.sample(ch, i));
+                self.pending_pos += 1;
+            }
+            emitted += count;
+        }
+
+        Ok(emitted)
+    }
+
+    /// Decodes the stream and appends all samples to `out`, interleaved.
+    ///
+    /// This is the fastest way to decode an entire stream: a single sample
+    /// buffer is reused for all frames, and samples are copied into `out` one
+    /// block at a time. Note that `out` is appended to, not cleared. Like
+    /// `samples()`, this decodes from the current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_all_into(&mut self, out: &mut Vec<i32>) -> Result<()> {
+        self.decode_all_into_with(out, |_| {})
+    }
+
+    /// Decodes the stream, passing every block to `f` before its samples are collected.
+    ///
+    /// This allows processing the audio in place while it is decoded, without
+    /// a second pass over the samples: `f` can modify the block through
+    /// `Block::channel_mut()`, for instance to apply a gain or to invert the
+    /// polarity. The modified samples are returned interleaved, as collected
+    /// by `decode_all_into()`. Like `samples()`, this decodes from the current
+    /// position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_with<F: FnMut(&mut Block)>(&mut self, f: F) -> Result<Vec<i32>> {
+        let mut out = Vec::new();
+        try!(self.decode_all_into_with(&mut out, f));
+        Ok(out)
+    }
+
+    /// Implements `decode_all_into()`, calling `f` on every block before collecting it.
+    fn decode_all_into_with<F: FnMut(&mut Block)>(&mut self, out: &mut Vec<i32>, mut f: F) -> Result<()> {
+        out.reserve(bounded_capacity(&self.streaminfo, self.streaminfo.channels as u64));
+
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(mut block) = try!(blocks.read_next_or_eof(buffer)) {
+            f(&mut block);
+
+            let start = out.len();
+            out.resize(start + block.len() as usize, 0);
+
+            // There are at most 8 channels.
+            let n_channels = block.channels() as usize;
+            let mut channels: [&[i32]; 8] = [&[]; 8];
+            for (ch, channel) in channels[..n_channels].iter_mut().enumerate() {
+                *channel = block.channel(ch as u32);
+            }
+
+            for (i, frame) in out[start..].chunks_mut(n_channels).enumerate() {
+                for (sample, channel) in frame.iter_mut().zip(channels.iter()) {
+                    *sample = channel[i];
+                }
+            }
+
+            buffer = block.into_buffer();
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the stream into a shared buffer of interleaved samples, and returns it with the streaminfo.
+    ///
+    /// This is `decode_all_into()` into a fresh vector, which is then moved
+    /// into an `Arc` without copying. Cloning the `Arc` shares the samples,
+    /// so audio that is decoded once can be handed to many consumers cheaply.
+    /// The samples are an `Arc<Vec<i32>>` rather than an `Arc<[i32]>`,
+    /// because the conversion from `Vec` to the latter is not available on
+    /// all versions of Rust that Claxon supports; it derefs to a slice just
+    /// the same. Like `samples()`, this decodes from the current position in
+    /// the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn into_arc_samples(mut self) -> Result<(StreamInfo, Arc<Vec<i32>>)> {
+        let mut samples = Vec::new();
+        try!(self.decode_all_into(&mut samples));
+        Ok((self.streaminfo, Arc::new(samples)))
+    }
+
+    /// Decodes the stream, and scales the samples so that the peak reaches full scale.
+    ///
+    /// This is peak normalization, not loudness normalization: it finds the
+    /// largest magnitude of any sample, as `peak()` does, and multiplies all
+    /// samples by the gain that brings it to 2<sup>bits per sample - 1</sup>
+    /// minus one, rounding to the nearest integer and saturating at the range
+    /// of the bit depth. Two streams with the same peak can differ greatly in
+    /// perceived loudness; for that, see `gain()`. Silence stays silent. The
+    /// samples are decoded once, into memory, and then scaled in place, so
+    /// the stream need not be seekable. Returns the streaminfo with the
+    /// interleaved samples. Like `samples()`, this decodes from the current
+    /// position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_normalized(mut self) -> Result<(StreamInfo, Vec<i32>)> {
+        let mut samples = Vec::new();
+        try!(self.decode_all_into(&mut samples));
+
+        // The wrapping absolute value of `i32::MIN` is itself, which is 2^31 as `u32`.
+        let peak = samples.iter().fold(0u32, |m, &s| cmp::max(m, s.wrapping_abs() as u32));
+        if peak == 0 {
+            return Ok((self.streaminfo, samples))
+        }
+
+        let max = ((1u64 << (self.streaminfo.bits_per_sample - 1)) - 1) as f64;
+        let gain = max / peak as f64;
+        for s in &mut samples {
+            let scaled = (*s as f64 * gain).round();
+            *s = if scaled > max {
+                max as i32
+            } else if scaled < -max - 1.0 {
+                (-max - 1.0) as i32
+            } else {
+                scaled as i32
+            };
+        }
+
+        Ok((self.streaminfo, samples))
+    }
+
+    /// Decodes the stream, and returns the streaminfo, the samples, and their MD5 signature.
+    ///
+    /// The signature is computed during the same pass that collects the
+    /// interleaved samples, in the same way as `compute_digest()` computes
+    /// it, so for an intact stream it equals the `md5sum` of the streaminfo.
+    /// It depends only on the audio, not on the metadata, which makes it
+    /// suitable as a key for the decoded samples. Like `samples()`, this
+    /// decodes from the current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_with_md5(mut self) -> Result<(StreamInfo, Vec<i32>, [u8; 16])> {
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let mut hasher = Md5::new();
+        let mut bytes = Vec::new();
+        let mut samples = Vec::new();
+        try!(self.decode_all_into_with(&mut samples, |block| {
+            update_md5(&mut hasher, block, bits_per_sample, &mut bytes);
+        }));
+        Ok((self.streaminfo, samples, hasher.finalize()))
+    }
+
+    /// Decodes the stream and writes it to `output` as an AIFF file.
+    ///
+    /// The AIFF header is constructed from the streaminfo, and samples are
+    /// written big-endian at the bit depth of the stream. Because the header
+    /// precedes the audio data, this requires the total number of samples from
+    /// the streaminfo; if the streaminfo does not specify it, or if the stream
+    /// is too long to be represented in AIFF, `Error::Unsupported` is returned.
+    /// If the stream turns out to contain a different number of samples, a
+    /// `FormatError` is returned after writing. Like `samples()`, this decodes
+    /// from the current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn write_aiff<W: io::Write>(&mut self, mut output: W) -> Result<()> {
+        let streaminfo = self.streaminfo;
+        let samples = match streaminfo.samples {
+            Some(n) => n,
+            None => return Err(Error::Unsupported("AIFF output requires the total number of samples")),
+        };
+
+        let bytes_per_sample = (streaminfo.bits_per_sample as u64 + 7) / 8;
+        let data_len = samples * streaminfo.channels as u64 * bytes_per_sample;
+
+        // The FORM chunk contains the form type, the COMM chunk, and the SSND
+        // chunk, of which the data is padded to an even length.
+        let form_len = 4 + 26 + 16 + data_len + (data_len & 1);
+        if samples > 0xffff_ffff || form_len > 0xffff_ffff {
+            return Err(Error::Unsupported("stream is too long for AIFF"))
+        }
+
+        let mut header = Vec::with_capacity(54);
+        header.extend_from_slice(b"FORM");
+        push_be_u32(&mut header, form_len as u32);
+        header.extend_from_slice(b"AIFF");
+        header.extend_from_slice(b"COMM");
+        push_be_u32(&mut header, 18);
+        header.push(0);
+        header.push(streaminfo.channels as u8);
+        push_be_u32(&mut header, samples as u32);
+        header.push(0);
+        header.push(streaminfo.bits_per_sample as u8);
+        header.extend_from_slice(&sample_rate_to_extended(streaminfo.sample_rate));
+        header.extend_from_slice(b"SSND");
+        push_be_u32(&mut header, data_len as u32 + 8);
+        // The offset and block size, which are not used.
+        push_be_u32(&mut header, 0);
+        push_be_u32(&mut header, 0);
+        try!(output.write_all(&header));
+
+        let mut decoded = 0u64;
+        let mut bytes = Vec::new();
+        {
+            let mut blocks = self.blocks();
+            let mut buffer = Vec::new();
+            while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+                bytes.clear();
+                block.write_canonical_bytes_be(streaminfo.bits_per_sample, &mut bytes);
+                try!(output.write_all(&bytes));
+                decoded += block.duration() as u64;
+                buffer = block.into_buffer();
+            }
+        }
+
+        if data_len & 1 == 1 {
+            try!(output.write_all(&[0]));
+        }
+
+        if decoded != samples {
+            return fmt_err("stream contains a different number of samples than the streaminfo")
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the stream into a complete WAV file in memory.
+    ///
+    /// Samples are stored little-endian in the smallest whole number of bytes
+    /// that fits the bit depth of the stream, left-justified as WAV requires,
+    /// and offset to unsigned for 8 bits. For more than two channels, more
+    /// than 16 bits per sample, or a bit depth that is not a multiple of 8,
+    /// the fmt chunk is a WAVEFORMATEXTENSIBLE, which carries the actual bits
+    /// per sample and the `channel_mask()`. When the streaminfo specifies the
+    /// number of samples, the buffer is allocated up front; either way, the
+    /// chunk sizes are filled in after decoding, from the samples that were
+    /// decoded. If the audio is too long for the 32-bit
+    /// sizes of WAV, `Error::Unsupported` is returned. Like `samples()`, this
+    /// decodes from the current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn to_wav_bytes(self) -> Result<Vec<u8>> {
+        self.to_wav_bytes_with(false)
+    }
+
+    /// Decodes the stream into a complete 32-bit float WAV file in memory.
+    ///
+    /// This is `to_wav_bytes()`, but the samples are converted to floats in
+    /// [-1.0, 1.0) with `sample::to_f32()`, and the fmt chunk has format tag 3,
+    /// `WAVE_FORMAT_IEEE_FLOAT`, and 32 bits per sample. For more than two
+    /// channels, it is a WAVEFORMATEXTENSIBLE with the float subformat.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn to_wav_f32_bytes(self) -> Result<Vec<u8>> {
+        self.to_wav_bytes_with(true)
+    }
+
+    /// Implements `to_wav_bytes()` and `to_wav_f32_bytes()`.
+    fn to_wav_bytes_with(mut self, float: bool) -> Result<Vec<u8>> {
+        let streaminfo = self.streaminfo;
+        let bits_per_sample = streaminfo.bits_per_sample;
+        let mut wav = wav_header(&streaminfo, self.channel_mask(), float);
+        let header_len = wav.len();
+
+        let bytes_per_sample = wav_bytes_per_sample(bits_per_sample, float);
+        wav.reserve(bounded_capacity(&streaminfo, (streaminfo.channels * bytes_per_sample) as u64) + 1);
+
+        {
+            let mut blocks = self.blocks();
+            let mut buffer = Vec::new();
+            while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+                push_wav_block(&mut wav, &block, bits_per_sample, float);
+                buffer = block.into_buffer();
+            }
+        }
+
+        // The data chunk is padded to an even length.
+        let data_len = (wav.len() - header_len) as u64;
+        if data_len & 1 == 1 {
+            wav.push(0);
+        }
+        if wav.len() as u64 - 8 > 0xffff_ffff {
+            return Err(Error::Unsupported("stream is too long for WAV"))
+        }
+        let riff_len = wav.len() as u32 - 8;
+        patch_le_u32(&mut wav, 4, riff_len);
+        patch_le_u32(&mut wav, header_len - 4, data_len as u32);
+
+        Ok(wav)
+    }
+
+    /// Decodes the stream into a WAV file at `path`.
+    ///
+    /// The file is created, or truncated if it exists. Its contents are those
+    /// of `to_wav_bytes()`, but the samples are written block by block, so the
+    /// stream is never held in memory. When the streaminfo specifies the
+    /// number of samples, the file is sized up front. The chunk sizes are
+    /// written afterwards, by seeking back to the header, and the file is
+    /// trimmed to the samples that were decoded. If the audio is too long for
+    /// the 32-bit sizes of WAV, `Error::Unsupported` is returned, and the file
+    /// is left incomplete. Like `samples()`, this decodes from the current
+    /// position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_to_wav_file<P: AsRef<path::Path>>(self, path: P) -> Result<()> {
+        self.decode_to_wav_file_with(path.as_ref(), false)
+    }
+
+    /// Decodes the stream into a 32-bit float WAV file at `path`.
+    ///
+    /// The file is written like `decode_to_wav_file()` writes it, and its
+    /// contents are those of `to_wav_f32_bytes()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_to_wav_f32_file<P: AsRef<path::Path>>(self, path: P) -> Result<()> {
+        self.decode_to_wav_file_with(path.as_ref(), true)
+    }
+
+    /// Implements `decode_to_wav_file()` and `decode_to_wav_f32_file()`.
+    fn decode_to_wav_file_with(mut self, path: &path::Path, float: bool) -> Result<()> {
+        use std::io::{Seek, Write};
+
+        let streaminfo = self.streaminfo;
+        let bits_per_sample = streaminfo.bits_per_sample;
+        let header = wav_header(&streaminfo, self.channel_mask(), float);
+        let header_len = header.len() as u64;
+
+        let mut file = try!(fs::File::create(path));
+        if let Some(n) = streaminfo.samples {
+            let bytes_per_sample = wav_bytes_per_sample(bits_per_sample, float);
+            let data_len = n.saturating_mul((streaminfo.channels * bytes_per_sample) as u64);
+            try!(file.set_len(header_len + cmp::min(data_len, 0xffff_ffff)));
+        }
+        try!(file.write_all(&header));
+
+        let mut data_len = 0u64;
+        {
+            let mut bytes = Vec::new();
+            let mut blocks = self.blocks();
+            let mut buffer = Vec::new();
+            while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+                bytes.clear();
+                push_wav_block(&mut bytes, &block, bits_per_sample, float);
+                data_len += bytes.len() as u64;
+                // Count the padding byte and the RIFF header as well.
+                if header_len + data_len + 1 - 8 > 0xffff_ffff {
+                    return Err(Error::Unsupported("stream is too long for WAV"))
+                }
+                try!(file.write_all(&bytes));
+                buffer = block.into_buffer();
+            }
+        }
+
+        // The data chunk is padded to an even length.
+        if data_len & 1 == 1 {
+            try!(file.write_all(&[0]));
+        }
+        let file_len = header_len + data_len + (data_len & 1);
+        try!(file.set_len(file_len));
+
+        let mut size = Vec::with_capacity(4);
+        push_le_u32(&mut size, (file_len - 8) as u32);
+        try!(file.seek(io::SeekFrom::Start(4)));
+        try!(file.write_all(&size));
+        size.clear();
+        push_le_u32(&mut size, data_len as u32);
+        try!(file.seek(io::SeekFrom::Start(header_len - 4)));
+        try!(file.write_all(&size));
+        Ok(())
+    }
+
+    /// Decodes the stream, and splits the samples at the track boundaries of the CUE sheet.
+    ///
+    /// This turns a single-file CD image into its tracks. Every track except
+    /// the lead-out track is returned with its interleaved samples, which run
+    /// from the offset of the track up to the offset of the next one, so the
+    /// last track ends at the lead-out. Samples before the first track or
+    /// after the lead-out belong to no track, and they are dropped. The buffers
+    /// are filled block by block, so the stream is never held in memory twice.
+    ///
+    /// A CUE sheet is required; if there is none, `Error::Unsupported` is
+    /// returned. A CUE sheet of which the track offsets decrease is a format
+    /// error. Like `samples()`, this decodes from the current position in the
+    /// stream, and the sample numbers of the blocks determine where they go.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn split_by_cuesheet(mut self) -> Result<Vec<(CueSheetTrack, Vec<i32>)>> {
+        let mut tracks = match self.cuesheet.take() {
+            Some(cuesheet) => cuesheet.tracks,
+            None => return Err(Error::Unsupported("splitting by CUE sheet requires a CUE sheet")),
+        };
+        if tracks.windows(2).any(|pair| pair[1].offset < pair[0].offset) {
+            return fmt_err("CUE sheet track offsets are not in increasing order")
+        }
+
+        // Every track ends where the next one starts, the lead-out ends nothing.
+        let lead_out = match tracks.pop() {
+            Some(track) => track.offset,
+            None => return Ok(Vec::new()),
+        };
+        let mut ends: Vec<u64> = tracks.iter().skip(1).map(|track| track.offset).collect();
+        ends.push(lead_out);
+        let start = tracks.first().map_or(lead_out, |track| track.offset);
+        let buffers = try!(self.split_at_offsets(start, &ends));
+
+        Ok(tracks.into_iter().zip(buffers.into_iter()).collect())
+    }
+
+    /// Decodes the stream, and splits the samples at every index point of the CUE sheet.
+    ///
+    /// This is a finer split than `split_by_cuesheet()`: a track with a
+    /// pregap, index point 0, and the start of its audio proper, index point
+    /// 1, is returned as two segments, and so on for further index points.
+    /// Every segment is returned with the number of its track, the number of
+    /// its index point, and its interleaved samples, which run from the index
+    /// point up to the next one in the CUE sheet, so the last segment ends at
+    /// the lead-out. A track without index points produces no segment.
+    /// Samples before the first index point or after the lead-out are
+    /// dropped.
+    ///
+    /// A CUE sheet is required; if there is none, `Error::Unsupported` is
+    /// returned. A CUE sheet of which the index points, taken relative to the
+    /// start of the stream, decrease is a format error. Like `samples()`, this
+    /// decodes from the current position in the stream, and the sample
+    /// numbers of the blocks determine where they go.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn split_by_cuesheet_indices(mut self) -> Result<Vec<(u8, u8, Vec<i32>)>> {
+        let mut tracks = match self.cuesheet.take() {
+            Some(cuesheet) => cuesheet.tracks,
+            None => return Err(Error::Unsupported("splitting by CUE sheet requires a CUE sheet")),
+        };
+        let lead_out = match tracks.pop() {
+            Some(track) => track.offset,
+            None => return Ok(Vec::new()),
+        };
+
+        // Index point offsets are relative to the offset of their track.
+        let mut points = Vec::new();
+        for track in &tracks {
+            for index in &track.indices {
+                match track.offset.checked_add(index.offset) {
+                    Some(offset) => points.push((track.number, index.number, offset)),
+                    None => return fmt_err("CUE sheet index point offset is too large"),
+                }
+            }
+        }
+        if points.windows(2).any(|pair| pair[1].2 < pair[0].2) ||
+           points.last().map_or(false, |point| point.2 > lead_out) {
+            return fmt_err("CUE sheet index points are not in increasing order")
+        }
+
+        let mut ends: Vec<u64> = points.iter().skip(1).map(|point| point.2).collect();
+        ends.push(lead_out);
+        let start = points.first().map_or(lead_out, |point| point.2);
+        let buffers = try!(self.split_at_offsets(start, &ends));
+
+        let segments = points.into_iter()
+                             .zip(buffers.into_iter())
+                             .map(|((track, index, _), samples)| (track, index, samples))
+                             .collect();
+        Ok(segments)
+    }
+
+    /// Decodes the stream into consecutive parts that start at `start` and end at every offset in `ends`.
+    ///
+    /// The offsets are inter-channel sample numbers, in increasing order, and
+    /// every part holds the interleaved samples up to its end. Samples before
+    /// `start` or after the last end go into no part.
+    fn split_at_offsets(&mut self, start: u64, ends: &[u64]) -> Result<Vec<Vec<i32>>> {
+        let mut buffers: Vec<Vec<i32>> = ends.iter().map(|_| Vec::new()).collect();
+        let mut current = 0;
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            for i in 0..block.duration() {
+                let t = block.time() + i as u64;
+                while current < ends.len() && t >= ends[current] {
+                    current += 1;
+                }
+                if t < start || current == ends.len() {
+                    continue
+                }
+                for ch in 0..block.channels() {
+                    buffers[current].push(block.sample(ch, i));
+                }
+            }
+            buffer = block.into_buffer();
+        }
+        Ok(buffers)
+    }
+
+    /// Decodes the stream and returns the samples of a single channel.
+    ///
+    /// All other channels are discarded as soon as a block has been decoded,
+    /// so memory usage is that of the requested channel plus a single block.
+    /// Note that frames must still be decoded entirely: subframes are not
+    /// prefixed with their size, so they cannot be skipped, and for stereo
+    /// frames with inter-channel decorrelation, reconstructing either channel
+    /// requires both subframes. Like `samples()`, this decodes from the current
+    /// position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is not less than the number of channels, or if the
+    /// `FlacReader` was constructed with `metadata_only` set. A frame with a
+    /// different number of channels than the streaminfo is a format error.
+    pub fn channel_samples(&mut self, channel: u32) -> Result<Vec<i32>> {
+        assert!(channel < self.streaminfo.channels, "channel index out of range");
+
+        let mut samples = Vec::with_capacity(bounded_capacity(&self.streaminfo, 1));
+
+        let streaminfo = self.streaminfo;
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            try!(check_block_channels(&block, &streaminfo));
+            samples.extend_from_slice(block.channel(channel));
+            buffer = block.into_buffer();
+        }
+
+        Ok(samples)
+    }
+
+    /// Decodes the stream and returns the samples of the channels in `range`, one vector per channel.
+    ///
+    /// This is like `channel_samples()` for several consecutive channels at
+    /// once, for example `0..2` to take the front left and right channels of
+    /// a surround stream. All other channels are discarded as soon as a block
+    /// has been decoded. Like `samples()`, this decodes from the current
+    /// position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is decreasing or extends beyond the number of
+    /// channels, or if the `FlacReader` was constructed with `metadata_only`
+    /// set. A frame with a different number of channels than the streaminfo
+    /// is a format error.
+    pub fn decode_channel_range(&mut self, range: ops::Range<u32>) -> Result<Vec<Vec<i32>>> {
+        assert!(range.start <= range.end && range.end <= self.streaminfo.channels,
+                "channel range out of range");
+
+        let capacity = bounded_capacity(&self.streaminfo, 1);
+        let mut channels: Vec<Vec<i32>> = range.clone().map(|_| Vec::with_capacity(capacity)).collect();
+
+        let streaminfo = self.streaminfo;
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            try!(check_block_channels(&block, &streaminfo));
+            for (samples, ch) in channels.iter_mut().zip(range.clone()) {
+                samples.extend_from_slice(block.channel(ch));
+            }
+            buffer = block.into_buffer();
+        }
+
+        Ok(channels)
+    }
+
+    /// Returns an iterator over windows of `window` samples of a single channel.
+    ///
+    /// Consecutive windows start `hop` samples apart, so they overlap when
+    /// `hop` is less than `window`, and samples are skipped when it is
+    /// greater. This is the usual framing for spectral analysis. Samples are
+    /// buffered across block boundaries as needed; like `channel_samples()`,
+    /// all other channels are discarded after every block.
+    ///
+    /// When the stream ends in the middle of a window, that window is padded
+    /// with zeros and returned as the final window if `pad_last` is true, so
+    /// that every sample is part of a window. Otherwise it is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is not less than the number of channels, if
+    /// `window` or `hop` is zero, or if the `FlacReader` was constructed with
+    /// `metadata_only` set. A frame with a different number of channels than
+    /// the streaminfo results in a format error, which ends iteration.
+    pub fn windows<'r>(&'r mut self,
+                       channel: u32,
+                       window: usize,
+                       hop: usize,
+                       pad_last: bool)
+                       -> ChannelWindows<&'r mut BufferedReader<R>> {
+        assert!(channel < self.streaminfo.channels, "channel index out of range");
+        assert!(window > 0, "window size must be positive");
+        assert!(hop > 0, "hop size must be positive");
+        let streaminfo = self.streaminfo;
+        ChannelWindows {
+            frame_reader: self.blocks(),
+            streaminfo: streaminfo,
+            channel: channel,
+            window: window,
+            hop: hop,
+            pad_last: pad_last,
+            pending: Vec::new(),
+            skip: 0,
+            buffer: Vec::new(),
+            is_done: false,
+        }
+    }
+
+    /// Decodes the stream and computes the sample count and MD5 signature.
+    ///
+    /// Like `samples()`, this decodes from the current position in the stream,
+    /// so the digest covers the entire stream only if no audio has been read
+    /// yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn compute_digest(&mut self) -> Result<AudioDigest> {
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let mut hasher = Md5::new();
+        let mut bytes = Vec::new();
+        let mut samples = 0;
+
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            update_md5(&mut hasher, &block, bits_per_sample, &mut bytes);
+            samples += block.duration() as u64;
+            buffer = block.into_buffer();
+        }
+
+        let digest = AudioDigest {
+            samples: samples,
+            md5sum: hasher.finalize(),
+        };
+        Ok(digest)
+    }
+
+    /// Computes the same digest as `compute_digest()`, hashing on a separate thread.
+    ///
+    /// MD5 is serial, but it need not wait for the decoder: decoded blocks are
+    /// sent to a hashing thread, which converts them to bytes and feeds them
+    /// into the hasher while the next frames are decoded, and then sends the
+    /// buffers back for reuse. At most a few blocks are in flight, so memory
+    /// use stays bounded. This pays off for large streams on a machine with a
+    /// spare core; for short streams, starting the thread costs more than it
+    /// saves. Like `samples()`, this decodes from the current position in the
+    /// stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn compute_digest_pipelined(&mut self) -> Result<AudioDigest> {
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let (block_sender, block_receiver) = mpsc::sync_channel::<Block>(2);
+        let (buffer_sender, buffer_receiver) = mpsc::channel();
+
+        let hasher = thread::spawn(move || {
+            let mut hasher = Md5::new();
+            let mut bytes = Vec::new();
+            for block in block_receiver {
+                update_md5(&mut hasher, &block, bits_per_sample, &mut bytes);
+                // The decoder stops receiving buffers after the last block.
+                let _ = buffer_sender.send(block.into_buffer());
+            }
+            hasher.finalize()
+        });
+
+        let mut samples = 0;
+        let result = {
+            let mut blocks = self.blocks();
+            let mut result = Ok(());
+            loop {
+                let buffer = buffer_receiver.try_recv().unwrap_or(Vec::new());
+                match blocks.read_next_or_eof(buffer) {
+                    Ok(Some(block)) => {
+                        samples += block.duration() as u64;
+                        // Sending fails only if the hashing thread panicked,
+                        // which `join()` reports below.
+                        if block_sender.send(block).is_err() {
+                            break
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(error) => {
+                        result = Err(error);
+                        break
+                    }
+                }
+            }
+            result
+        };
+
+        // Closing the channel ends the loop of the hashing thread.
+        drop(block_sender);
+        let md5sum = match hasher.join() {
+            Ok(md5sum) => md5sum,
+            Err(payload) => panic::resume_unwind(payload),
+        };
+        try!(result);
+
+        let digest = AudioDigest {
+            samples: samples,
+            md5sum: md5sum,
+        };
+        Ok(digest)
+    }
+
+    /// Decodes the stream and checks it against the metadata, returning a report.
+    ///
+    /// This verifies every frame including its CRCs, counts frames and
+    /// samples, counts full-scale samples per channel, and checks the MD5
+    /// signature, sample count, and CUE sheet lead-out against the streaminfo, all in a single pass. Problems with the audio data are
+    /// recorded in the report rather than returned as error: decoding stops at
+    /// the first error, and the report describes everything up to that point.
+    /// An error is returned only when reading from the underlying reader fails
+    /// for a reason other than reaching the end of the stream.
+    /// Like `samples()`, this decodes from the current position in the stream,
+    /// so it should be called on a freshly constructed reader.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn full_check(&mut self) -> Result<HealthReport> {
+        let streaminfo = self.streaminfo;
+        let mut report = HealthReport {
+            metadata_blocks: self.metadata_blocks.clone(),
+            frames: 0,
+            samples: 0,
+            md5_matches: None,
+            error: None,
+            warnings: Vec::new(),
+            full_scale: vec![FullScaleSamples::default(); streaminfo.channels as usize],
+        };
+
+        // The extremes of the bit depth, the extremes of `i32` narrowed.
+        let bps = streaminfo.bits_per_sample;
+        let max = sample::convert_bit_depth(<i32 as Sample>::max(), 32, bps);
+        let min = sample::convert_bit_depth(<i32 as Sample>::min(), 32, bps);
+
+        let mut hasher = Md5::new();
+        let mut bytes = Vec::new();
+        {
+            let mut blocks = self.blocks();
+            let mut buffer = Vec::new();
+            loop {
+                match blocks.read_next_or_eof(buffer) {
+                    Ok(Some(block)) => {
+                        update_md5(&mut hasher, &block, streaminfo.bits_per_sample, &mut bytes);
+                        let channels = block.channels() as usize;
+                        for (ch, full_scale) in report.full_scale.iter_mut().take(channels).enumerate() {
+                            let channel = block.channel(ch as u32);
+                            for (i, &x) in channel.iter().enumerate() {
+                                if x == max || x == min {
+                                    full_scale.count += 1;
+                                    if full_scale.first.is_none() {
+                                        full_scale.first = Some(report.samples + i as u64);
+                                    }
+                                }
+                            }
+                        }
+                        report.frames += 1;
+                        report.samples += block.duration() as u64;
+                        buffer = block.into_buffer();
+                    }
+                    Ok(None) => break,
+                    // A failure to read from the underlying reader is not a
+                    // property of the stream, so that is not reported.
+                    Err(err) => {
+                        if is_read_failure(&err) {
+                            return Err(err)
+                        }
+                        report.error = Some(err);
+                        break
+                    }
+                }
+            }
+        }
+
+        match streaminfo.samples {
+            Some(n) if report.error.is_none() && report.samples < n => {
+                report.warnings.push("stream contains fewer samples than the streaminfo");
+            }
+            Some(n) if report.samples > n => {
+                report.warnings.push("stream contains more samples than the streaminfo");
+            }
+            Some(_) => {}
+            None => report.warnings.push("streaminfo does not specify the number of samples"),
+        }
+
+        if self.check_cuesheet_lead_out().is_err() {
+            report.warnings.push("CUE sheet lead-out does not match the number of samples");
+        }
+
+        if streaminfo.md5sum == [0u8; 16] {
+            report.warnings.push("streaminfo does not contain an MD5 signature");
+        } else if report.error.is_none() {
+            report.md5_matches = Some(hasher.finalize() == streaminfo.md5sum);
+        }
+
+        Ok(report)
+    }
+
+    /// Decodes the stream and checks it against the streaminfo MD5 signature.
+    ///
+    /// Returns whether the MD5 signature of the decoded audio data, and the
+    /// number of samples if the streaminfo specifies it, match the streaminfo.
+    /// An all-zero signature means that the encoder did not compute one, so this
+    /// returns false in that case. Like `compute_digest()`, this decodes from
+    /// the current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn verify_md5(&mut self) -> Result<bool> {
+        let digest = try!(self.compute_digest());
+        let samples_ok = self.streaminfo.samples.map_or(true, |n| n == digest.samples);
+        Ok(samples_ok && digest.md5sum == self.streaminfo.md5sum)
+    }
+
+    /// Decodes the stream and computes (min, max) pairs for drawing a waveform.
+    ///
+    /// The timeline of the stream is divided into `buckets` buckets of
+    /// (approximately) equal duration, and for every bucket the minimum and
+    /// maximum sample are returned. If `channel` is `None`, the channels are
+    /// mixed down to mono by averaging them first, otherwise only the given
+    /// zero-based channel is considered. Buckets that contain no samples
+    /// (because there are fewer samples than buckets) are reported as `(0, 0)`.
+    ///
+    /// This requires the total number of samples from the streaminfo, because
+    /// the bucket boundaries must be known up front. If the streaminfo does not
+    /// specify it, `Error::Unsupported` is returned. Like `samples()`, this
+    /// decodes from the current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel` is not less than the number of channels. When a
+    /// channel is selected, a frame with a different number of channels than
+    /// the streaminfo is a format error.
+    pub fn waveform_overview(&mut self,
+                             buckets: usize,
+                             channel: Option<u32>)
+                             -> Result<Vec<(i32, i32)>> {
+        let n_channels = self.streaminfo.channels;
+        if let Some(ch) = channel {
+            assert!(ch < n_channels, "channel index out of range");
+        }
+
+        let total = match self.streaminfo.samples {
+            Some(n) => n,
+            None => {
+                let msg = "waveform overview requires the total number of samples";
+                return Err(Error::Unsupported(msg))
+            }
+        };
+
+        let mut mins = vec![i32::max_value(); buckets];
+        let mut maxs = vec![i32::min_value(); buckets];
+        if buckets == 0 {
+            return Ok(Vec::new())
+        }
+
+        let streaminfo = self.streaminfo;
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            if channel.is_some() {
+                try!(check_block_channels(&block, &streaminfo));
+            }
+            for i in 0..block.duration() {
+                let value = match channel {
+                    Some(ch) => block.sample(ch, i),
+                    None => {
+                        let mut sum = 0i64;
+                        for ch in 0..block.channels() {
+                            sum += block.sample(ch, i) as i64;
+                        }
+                        (sum / block.channels() as i64) as i32
+                    }
+                };
+
+                // Samples beyond the declared total end up in the last bucket.
+                let t = block.time() + i as u64;
+                let bucket = bucket_index(t, buckets as u64, total) as usize;
+                mins[bucket] = cmp::min(mins[bucket], value);
+                maxs[bucket] = cmp::max(maxs[bucket], value);
+            }
+            buffer = block.into_buffer();
+        }
+
+        let overview = mins.iter().zip(maxs.iter()).map(|(&min, &max)| {
+            if min > max { (0, 0) } else { (min, max) }
+        }).collect();
+
+        Ok(overview)
+    }
+
+    /// Returns whether the stream is entirely digital silence.
+    ///
+    /// Blocks are decoded until a nonzero sample is found. For a channel coded
+    /// as a constant subframe, the value is known without inspecting every
+    /// sample; for other channels, the decoded samples are checked. Encoders
+    /// code silence as constant subframes, so for a silent file this is cheap,
+    /// and for a file that is not silent, decoding usually stops at the first
+    /// frame. Like `samples()`, this decodes from the current position in the
+    /// stream, and an empty stream counts as silent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn is_silent(&mut self) -> Result<bool> {
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            for ch in 0..block.channels() {
+                let is_silent = match block.is_channel_constant(ch) {
+                    Some(value) => value == 0,
+                    None => block.channel(ch).iter().all(|&s| s == 0),
+                };
+                if !is_silent {
+                    return Ok(false)
+                }
+            }
+            buffer = block.into_buffer();
+        }
+        Ok(true)
+    }
+
+    /// Decodes the stream and returns the peak absolute sample value of every channel.
+    ///
+    /// This is the largest magnitude that a channel reaches, compared against
+    /// 2<sup>bits per sample - 1</sup> it tells how far the stream can be
+    /// amplified without clipping. Like in `is_silent()`, for a channel coded
+    /// as a constant subframe the value is known without inspecting every
+    /// sample. The peak is a `u32`, because the magnitude of the most negative
+    /// 32-bit sample does not fit in an `i32`. Like `samples()`, this decodes
+    /// from the current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn peak(&mut self) -> Result<Vec<u32>> {
+        let mut peaks = vec![0u32; self.streaminfo.channels as usize];
+        // The wrapping absolute value of `i32::MIN` is itself, which is 2^31 as `u32`.
+        let magnitude = |s: i32| s.wrapping_abs() as u32;
+
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            let channels = block.channels() as usize;
+            for (ch, peak) in peaks.iter_mut().take(channels).enumerate() {
+                let block_peak = match block.is_channel_constant(ch as u32) {
+                    Some(value) => magnitude(value),
+                    None => block.channel(ch as u32).iter().fold(0, |m, &s| cmp::max(m, magnitude(s))),
+                };
+                *peak = cmp::max(*peak, block_peak);
+            }
+            buffer = block.into_buffer();
+        }
+
+        Ok(peaks)
+    }
+
+    /// Decodes the stream and counts how many samples fall in each of `bins` bins.
+    ///
+    /// The full range of the bit depth, from -2<sup>bits per sample - 1</sup>
+    /// up to 2<sup>bits per sample - 1</sup>, is divided into `bins` bins of
+    /// equal width, and the samples of all channels are counted in them, so the
+    /// counts add up to the number of samples times the number of channels.
+    /// Samples are counted block by block, they are never all held in memory.
+    /// Like in `peak()`, a channel coded as a constant subframe is counted
+    /// without inspecting every sample. Like `samples()`, this decodes from the
+    /// current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn sample_histogram(&mut self, bins: usize) -> Result<Vec<u64>> {
+        let mut counts = vec![0u64; bins];
+        if bins == 0 {
+            return Ok(counts)
+        }
+
+        // Offset samples by half the range, so they start at 0.
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let range = 1u64 << bits_per_sample;
+        let bin = |s: i32| {
+            let offset = s as i64 + (1i64 << (bits_per_sample - 1));
+            bucket_index(cmp::max(offset, 0) as u64, bins as u64, range) as usize
+        };
+
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            for ch in 0..block.channels() {
+                match block.is_channel_constant(ch) {
+                    Some(value) => counts[bin(value)] += block.duration() as u64,
+                    None => {
+                        for &s in block.channel(ch) {
+                            counts[bin(s)] += 1;
+                        }
+                    }
+                }
+            }
+            buffer = block.into_buffer();
+        }
+
+        Ok(counts)
+    }
+
+    /// Decodes the stream and reports how well its frames are compressed.
+    ///
+    /// This counts the subframes of every type, and compares the size of the
+    /// frames against the size of the decoded audio. See `CompressionProfile`
+    /// for how to interpret them. Like `samples()`, this decodes from the
+    /// current position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn compression_profile(&mut self) -> Result<CompressionProfile> {
+        let mut profile = CompressionProfile::default();
+        for frame in self.frames() {
+            let (range, block) = try!(frame);
+            for i in 0..block.subframe_count() {
+                profile.subframe_type_counts.add(block.subframe_type(i));
+            }
+            let bytes_per_sample = (block.bits_per_sample() as u64 + 7) / 8;
+            profile.decoded_bytes += block.duration() as u64 * block.channels() as u64 * bytes_per_sample;
+            profile.frame_bytes += range.len;
+        }
+        Ok(profile)
+    }
+
+    /// Decodes the stream and returns the largest jump across a frame boundary in every channel.
+    ///
+    /// At every boundary between two frames, this takes the absolute
+    /// difference between the last sample of the one frame and the first
+    /// sample of the next, and it keeps the largest one per channel, or the
+    /// first of those if there is a tie. Frames are coded independently, so a
+    /// jump that is large compared to the jumps within frames can point at a
+    /// damaged frame or an encoder bug. A channel has `None` if the stream
+    /// has no frame boundary. Like `samples()`, this decodes from the current
+    /// position in the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn boundary_discontinuities(&mut self) -> Result<Vec<Option<BoundaryDiscontinuity>>> {
+        let channels = self.streaminfo.channels as usize;
+        let mut maxima: Vec<Option<BoundaryDiscontinuity>> = vec![None; channels];
+        let mut last_samples: Option<Vec<i32>> = None;
+        let mut position = 0;
+
+        let mut blocks = self.blocks();
+        let mut buffer = Vec::new();
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            let len = block.duration();
+            if len > 0 {
+                if let Some(ref last) = last_samples {
+                    // A damaged stream could change the number of channels.
+                    let common = cmp::min(block.channels() as usize, last.len());
+                    for (ch, max) in maxima.iter_mut().enumerate().take(common) {
+                        let first = block.sample(ch as u32, 0);
+                        let magnitude = (first as i64 - last[ch] as i64).abs() as u32;
+                        let is_larger = match *max {
+                            Some(ref m) => magnitude > m.magnitude,
+                            None => true,
+                        };
+                        if is_larger {
+                            *max = Some(BoundaryDiscontinuity {
+                                magnitude: magnitude,
+                                position: position,
+                            });
+                        }
+                    }
+                }
+                last_samples = Some((0..block.channels()).map(|ch| block.sample(ch, len - 1)).collect());
+            }
+            position += len as u64;
+            buffer = block.into_buffer();
+        }
+
+        Ok(maxima)
+    }
+
+    /// Reads metadata blocks that follow the audio, and merges them into the reader.
+    ///
+    /// This is for files written by non-conformant taggers, that append
+    /// metadata blocks after the last frame. It must be called after decoding
+    /// all audio, with `scan_all_metadata` set; only then does decoding stop
+    /// at such blocks. A Vorbis comment block found here is used as the tags if
+    /// there were none, or its comments are appended to the existing tags
+    /// otherwise. A CUE sheet is used if there was none, and pictures are
+    /// added to `pictures()` if `read_picture` is set. The headers of all
+    /// blocks found are added to `metadata_blocks()`.
+    ///
+    /// If the stream has ended, or another stream follows, this does nothing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn read_trailing_metadata(&mut self) -> Result<()> {
+        let input = match self.input {
+            FlacReaderState::Full(ref mut inp) => inp,
+            FlacReaderState::MetadataOnly(..) => {
+                panic!("FlacReaderOptions::metadata_only must be false \
+                       to be able to use FlacReader::read_trailing_metadata()")
+            }
+        };
+
+        if try!(input.peek_equals(&FLAC_MAGIC)) {
+            return Ok(())
+        }
+
+        // Buggy taggers might not set the "last block" flag, so stop at the
+        // end of the stream as well, or when a frame follows.
+        loop {
+            match try!(input.peek_u8()) {
+                Some(byte) if byte != 0xff => {}
+                _ => return Ok(()),
+            }
+
+            let (block, header) = {
+                let mut metadata_iter = MetadataBlockReader::new(&mut *input);
+                let block = metadata_iter.next().expect("metadata block reader yields a block");
+                (try!(block), metadata_iter.last_header())
+            };
+            self.metadata_blocks.extend(header);
+
+            match block {
+                MetadataBlock::StreamInfo(..) => {
+                    return fmt_err("encountered streaminfo block after audio")
+                }
+                MetadataBlock::VorbisComment(vc) => {
+                    if !self.options.read_vorbis_comment {
+                        // Tags were not desired.
+                    } else if let Some(ref mut existing) = self.vorbis_comment {
+                        existing.comments.extend(vc.comments);
+                    } else {
+                        self.vorbis_comment = Some(vc);
+                    }
+                }
+                MetadataBlock::CueSheet(cs) => {
+                    if self.cuesheet.is_none() {
+                        self.cuesheet = Some(cs);
+                    }
+                }
+                MetadataBlock::Picture(picture) => {
+                    if self.options.read_picture {
+                        self.pictures.push(picture);
+                    }
+                }
+                _block => {}
+            }
+
+            if header.map_or(false, |h| h.is_last) {
+                return Ok(())
+            }
+        }
+    }
+
+    /// Skips the remainder of this stream, and returns a reader for a chained stream after it.
+    ///
+    /// FLAC streams can be concatenated. Each stream in such a chain starts
+    /// with its own `fLaC` header and metadata, including a streaminfo block
+    /// that describes only that stream. Decoding stops cleanly at the end of a
+    /// stream when the next one starts, so `samples()` and `blocks()` yield
+    /// only the audio of the current stream. Any remaining frames of the
+    /// current stream are decoded (and checked) to find the end.
+    ///
+    /// Returns `None` if the stream is not followed by another. The new reader
+    /// is constructed with the same options as this one. To visit all
+    /// streams, call `next_stream()` on the returned reader in turn. Use
+    /// `tags_changed()` on the new reader to learn whether the tags changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn next_stream(self) -> Result<Option<FlacReader<R>>> {
+        let options = self.options;
+        let streaminfo = self.streaminfo;
+        let progress = self.progress.clone();
+        let pool = self.pool.clone();
+        let label = self.label.clone();
+        let max_block_size = self.max_block_size();
+        let previous_tags = self.vorbis_comment.map(|vc| vc.comments);
+        let mut input = match self.input {
+            FlacReaderState::Full(inp) => inp,
+            FlacReaderState::MetadataOnly(..) => {
+                panic!("FlacReaderOptions::metadata_only must be false \
+                       to be able to use FlacReader::next_stream()")
+            }
+        };
+
+        {
+            let mut frame_reader = new_frame_reader(&mut input,
+                                                    max_block_size,
+                                                    &options,
+                                                    None,
+                                                    None,
+                                                    None,
+                                                    &streaminfo);
+            let mut buffer = Vec::new();
+            while let Some(block) = try!(frame_reader.read_next_or_eof(buffer)) {
+                buffer = block.into_buffer();
+            }
+        }
+
+        if try!(input.peek_equals(&FLAC_MAGIC)) {
+            let mut reader = try!(FlacReader::new_buffered(input, options));
+            reader.set_shared_progress_callback(progress);
+            reader.label = label;
+            reader.pool = pool;
+            reader.tags_changed = match (&previous_tags, &reader.vorbis_comment) {
+                (&Some(ref prev), &Some(ref vc)) => *prev != vc.comments,
+                (&None, &None) => false,
+                _ => true,
+            };
+            Ok(Some(reader))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Destroys the FLAC reader and returns the underlying reader.
+    ///
+    /// Because the reader employs buffering internally, anything in the buffer
+    /// will be lost. The underlying reader is positioned after the buffered
+    /// data: after decoding the entire stream, this is the end of the stream,
+    /// but after a partial decode, it can be up to 2048 bytes beyond the current
+    /// position in the FLAC stream. Use `into_inner_with_buffer()` to retain
+    /// the buffered data.
+    pub fn into_inner(self) -> R {
+        match self.input {
+            FlacReaderState::Full(inp) => inp.into_inner(),
+            FlacReaderState::MetadataOnly(inp) => inp.into_inner(),
+        }
+    }
+
+    /// Destroys the FLAC reader and returns the underlying reader, and any unconsumed buffered data.
+    ///
+    /// The underlying reader is positioned after the buffered data, so the
+    /// returned bytes, followed by what the underlying reader reads next,
+    /// form the remainder of the stream after the current position. For
+    /// instance, after decoding the entire stream, this is any trailing data.
+    pub fn into_inner_with_buffer(self) -> (R, Vec<u8>) {
+        match self.input {
+            FlacReaderState::Full(inp) => inp.into_inner_with_buffer(),
+            FlacReaderState::MetadataOnly(inp) => inp.into_inner_with_buffer(),
+        }
+    }
+}
+
+impl<R: io::Read + io::Seek> FlacReader<R> {
+    /// Create a reader for a FLAC stream, detecting the container from the first bytes.
+    ///
+    /// A native FLAC stream starts with `fLaC`. Some files have an ID3v2 tag
+    /// before that, which is not part of the FLAC format, but the reference
+    /// decoder skips it, and so does this constructor. Offsets that the reader
+    /// reports, such as those of `frames()`, are then relative to the end of
+    /// the tag. FLAC in an Ogg container starts with `OggS`; Claxon does not
+    /// support that, and `Error::Unsupported` is returned.
+    pub fn open_any(reader: R) -> Result<FlacReader<R>> {
+        FlacReader::open_any_ext(reader, FlacReaderOptions::default())
+    }
+
+    /// Create a reader for a FLAC stream, detecting the container, with reader options.
+    ///
+    /// See `open_any()` for the containers that are recognized, and
+    /// `FlacReaderOptions` for the options.
+    pub fn open_any_ext(mut reader: R, options: FlacReaderOptions) -> Result<FlacReader<R>> {
+        let start = try!(reader.seek(io::SeekFrom::Current(0)));
+        let mut header = [0u8; 10];
+        try!(reader.read_exact(&mut header[..4]));
+
+        if &header[..4] == b"OggS" {
+            return Err(Error::Unsupported("Ogg FLAC is not supported"))
+        } else if &header[..3] == b"ID3" {
+            // The ID3v2 header is followed by the size of the tag excluding
+            // the header, as a 28-bit integer of which every byte has its
+            // most significant bit unset. The flags indicate a 10-byte footer.
+            try!(reader.read_exact(&mut header[4..]));
+            if header[6..].iter().any(|&b| b & 0x80 != 0) {
+                return fmt_err("invalid ID3 tag, invalid tag size")
+            }
+            let size = header[6..].iter().fold(0u64, |acc, &b| acc << 7 | b as u64);
+            let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+            try!(reader.seek(io::SeekFrom::Start(start + 10 + size + footer)));
+        } else {
+            try!(reader.seek(io::SeekFrom::Start(start)));
+        }
+
+        FlacReader::new_ext(reader, options)
+    }
+
+    /// Seeks such that the next sample read is the given inter-channel sample.
+    ///
+    /// After seeking, `read_interleaved_f32()` and `decode_range()` continue
+    /// exactly at `sample`. Positions are determined from the actual duration
+    /// of every block, so variable block sizes and a short final block are
+    /// handled correctly. Seeking to or beyond the end of the stream positions
+    /// the reader at the end.
+    ///
+    /// For a fixed-blocksize stream, the frame that contains `sample` is
+    /// located by bisection on the frame numbers in the frame headers, so only
+    /// a few frame headers need to be read. Otherwise, if the stream has a seek
+    /// table, decoding starts at the first frame at or after the last seek
+    /// point before `sample`. The sample number of that frame is taken from
+    /// its header rather than from the seek table, so wrong offsets in a stale
+    /// seek table cannot misplace the reader. A variable-blocksize stream
+    /// without a usable seek table is bisected on the sample numbers in its
+    /// frame headers instead, so no audio before the target frame is decoded.
+    /// Failing that, because the stream is damaged, this decodes frames from
+    /// the start of the audio data until the frame that contains `sample`, so
+    /// the cost is linear in the target.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn seek_to_sample(&mut self, sample: u64) -> Result<()> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::seek_to_sample()");
+        }
+
+        match try!(self.find_frame_containing(sample)) {
+            Some((_, time, block)) => {
+                self.pending_pos = (sample - time) as u32 * block.channels();
+                self.pending_block = block;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Seeks to the start of the frame that contains the given inter-channel sample.
+    ///
+    /// Returns the number of inter-channel samples that must be discarded from
+    /// the start of the first block to arrive at `sample`: `sample` minus the
+    /// number of the first sample in its frame. FLAC frames do not depend on
+    /// each other, so no more than that needs to be discarded. After this,
+    /// `blocks()` and `samples()` start at the beginning of the frame. In
+    /// contrast, `seek_to_sample()` discards these samples itself, for
+    /// `read_interleaved_f32()` and `decode_range()`.
+    ///
+    /// Seeking to or beyond the end of the stream positions the reader at the
+    /// end, and returns 0. See `seek_to_sample()` for how the frame is found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn seek_discard_count(&mut self, sample: u64) -> Result<u64> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::seek_discard_count()");
+        }
+
+        match try!(self.find_frame_containing(sample)) {
+            Some((pos, time, _)) => {
+                match self.input {
+                    FlacReaderState::Full(ref mut inp) => try!(inp.seek_to(pos)),
+                    FlacReaderState::MetadataOnly(..) => unreachable!(),
+                }
+                Ok(sample - time)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Resumes decoding at the first frame that starts at or after byte `offset`.
+    ///
+    /// The offset is counted from the start of the stream, like the offsets
+    /// that `frames()` reports, but it need not be the start of a frame: a
+    /// bookmark kept by a player may point anywhere. This searches for the
+    /// next frame header after it, like seeking does, and decodes the frame to
+    /// verify its checksum, so a sync code in the middle of audio data is
+    /// skipped. An offset before the audio data resumes at the first frame.
+    /// Returns the number of the first inter-channel sample in the frame,
+    /// taken from its header. Afterwards, `blocks()` and `samples()` start at
+    /// the beginning of the frame.
+    ///
+    /// If no valid frame follows the offset, a format error is returned, and
+    /// the position of the reader is unspecified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn resume_at_byte(&mut self, offset: u64) -> Result<u64> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::resume_at_byte()");
+        }
+
+        self.pending_block = Block::empty();
+        self.pending_pos = 0;
+
+        let max_block_size = self.max_block_size();
+        let streaminfo = self.streaminfo;
+        let options = self.options;
+        let input = match self.input {
+            FlacReaderState::Full(ref mut inp) => inp,
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+
+        let end = try!(input.end_position());
+        let mut from = cmp::max(offset, self.audio_start);
+        loop {
+            let (pos, time) = match try!(find_frame(input, from, end, streaminfo.max_block_size)) {
+                Some(found) => found,
+                None => return fmt_err("no valid frame follows the byte offset"),
+            };
+
+            try!(input.seek_to(pos));
+            let result = new_frame_reader(&mut *input,
+                                          max_block_size,
+                                          &options,
+                                          None,
+                                          None,
+                                          None,
+                                          &streaminfo).read_next_or_eof(Vec::new());
+            match result {
+                Ok(Some(..)) => {
+                    try!(input.seek_to(pos));
+                    return Ok(time)
+                }
+                Ok(None) => return fmt_err("no valid frame follows the byte offset"),
+                Err(Error::IoError(ref err)) if err.kind() != io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::IoError(io::Error::new(err.kind(), err.to_string())))
+                }
+                Err(..) => from = pos + 1,
+            }
+        }
+    }
+
+    /// Decodes the entire stream, and returns every error found, with the position of its frame.
+    ///
+    /// Unlike `full_check()`, which stops at the first error, this continues
+    /// past damaged frames: after an error, it searches for the next frame
+    /// header after the start of the damaged frame, like seeking does, and
+    /// resumes decoding there. Every error is paired with the byte offset of
+    /// the frame where it occurred, counted from the start of the stream. An
+    /// empty result means that every frame decoded, including its CRCs. A
+    /// failure to read from the underlying reader is returned as an error.
+    ///
+    /// Decoding starts at the first frame, regardless of the current position,
+    /// and the reader is left at the end of the stream. A partially consumed
+    /// block of `read_interleaved_f32()` or `fill_producer()` is discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn validate_collect(&mut self) -> Result<Vec<(u64, Error)>> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::validate_collect()");
+        }
+
+        self.pending_block = Block::empty();
+        self.pending_pos = 0;
+
+        let max_block_size = self.max_block_size();
+        let streaminfo = self.streaminfo;
+        let options = self.options;
+        let input = match self.input {
+            FlacReaderState::Full(ref mut inp) => inp,
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+
+        let end = try!(input.end_position());
+        try!(input.seek_to(self.audio_start));
+
+        let mut errors = Vec::new();
+        let mut buffer = Vec::new();
+        loop {
+            let pos = input.position();
+            let result = new_frame_reader(&mut *input,
+                                          max_block_size,
+                                          &options,
+                                          None,
+                                          None,
+                                          None,
+                                          &streaminfo).read_next_or_eof(buffer);
+            buffer = Vec::new();
+            match result {
+                Ok(Some(block)) => buffer = block.into_buffer(),
+                Ok(None) => break,
+                Err(Error::IoError(ref err)) if err.kind() != io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::IoError(io::Error::new(err.kind(), err.to_string())))
+                }
+                Err(err) => {
+                    errors.push((pos, err));
+                    match try!(find_frame(input, pos + 1, end, streaminfo.max_block_size)) {
+                        Some((next, _)) => try!(input.seek_to(next)),
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        Ok(errors)
+    }
+
+    /// Finds and decodes the frame that contains `sample`.
+    ///
+    /// Returns the position of the frame, the number of its first sample, and
+    /// the decoded block, or `None` if the stream ends before `sample`. The
+    /// pending block is cleared, and the reader is positioned after the frame.
+    fn find_frame_containing(&mut self, sample: u64) -> Result<Option<(u64, u64, Block)>> {
+        // A fixed-blocksize stream is bisected right away. A variable-blocksize
+        // stream is bisected only if it has no usable seek table.
+        let is_fixed = self.streaminfo.is_fixed_blocksize();
+        if is_fixed {
+            match self.decode_from_bisection(sample) {
+                Ok(found) => return Ok(found),
+                // A failure to read is not a sign of a damaged stream.
+                Err(err) => {
+                    if is_read_failure(&err) {
+                        return Err(err)
+                    }
+                }
+            }
+        }
+
+        let pos = self.seek_point_before(sample);
+        if pos != self.audio_start {
+            // Seek table offsets can be stale, for example after a metadata
+            // edit that did not update them. So rather than trusting that a
+            // frame starts at the seek point, with the sample that it claims,
+            // find the first frame at or after it, and take the time from its
+            // header. If that frame lies beyond `sample`, the seek point is of
+            // no use, and decoding starts over from the start of the audio.
+            let max_block_size = self.streaminfo.max_block_size;
+            let found = match self.input {
+                FlacReaderState::Full(ref mut inp) => {
+                    let end = try!(inp.end_position());
+                    try!(find_frame(inp, pos, end, max_block_size))
+                }
+                FlacReaderState::MetadataOnly(..) => unreachable!(),
+            };
+            let result = match found {
+                Some((pos, time)) if time <= sample => self.decode_until(pos, time, sample),
+                _ => fmt_err("seek point does not precede a frame before the target"),
+            };
+            match result {
+                Ok(found) => return Ok(found),
+                Err(err) => {
+                    if is_read_failure(&err) {
+                        return Err(err)
+                    }
+                }
+            }
+        }
+
+        if !is_fixed {
+            match self.decode_from_bisection(sample) {
+                Ok(found) => return Ok(found),
+                Err(err) => {
+                    if is_read_failure(&err) {
+                        return Err(err)
+                    }
+                }
+            }
+        }
+
+        let audio_start = self.audio_start;
+        self.decode_until(audio_start, 0, sample)
+    }
+
+    /// Locates the frame that contains `sample` by bisection, and decodes it.
+    ///
+    /// Frame headers of a fixed-blocksize stream contain the frame number,
+    /// those of a variable-blocksize stream the number of the first sample, so
+    /// in both cases the time of a frame is known from its header alone, and
+    /// no audio before the frame is decoded.
+    fn decode_from_bisection(&mut self, sample: u64) -> Result<Option<(u64, u64, Block)>> {
+        let block_size = self.streaminfo.max_block_size;
+        let (pos, time) = try!(self.locate_frame(sample, block_size));
+        self.decode_until(pos, time, sample)
+    }
+
+    /// Returns the position of the last seek point at or before `sample`.
+    ///
+    /// Placeholders are skipped. Without a suitable seek point, this returns
+    /// the start of the audio data. The position is only a hint, the caller
+    /// must verify that it is at or before the frame that contains `sample`.
+    fn seek_point_before(&self, sample: u64) -> u64 {
+        let points = match self.seektable {
+            Some(ref table) => table.seekpoints(),
+            None => return self.audio_start,
+        };
+        points.iter()
+              .filter(|point| point.sample <= sample && point.sample != u64::max_value())
+              .max_by_key(|point| point.sample)
+              .and_then(|point| self.audio_start.checked_add(point.offset))
+              .unwrap_or(self.audio_start)
+    }
+
+    /// Finds the last frame that starts at or before `sample`.
+    ///
+    /// Returns the position of the frame header and the number of the first
+    /// sample in the frame. The `block_size` is used to convert frame numbers
+    /// into sample numbers. Frame headers are recognized by their sync code and
+    /// CRC-8; a false positive is unlikely, and it would be detected when the
+    /// frame fails to decode.
+    fn locate_frame(&mut self, sample: u64, block_size: u16) -> Result<(u64, u64)> {
+        let input = match self.input {
+            FlacReaderState::Full(ref mut inp) => inp,
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+
+        // The invariant is that a frame that starts at `lo` contains `sample`
+        // or an earlier one, and no frame at or after `hi` does.
+        let mut lo = self.audio_start;
+        let mut lo_time = 0;
+        let mut hi = try!(input.end_position());
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            match try!(find_frame(input, mid, hi, block_size)) {
+                Some((pos, time)) if time <= sample => {
+                    lo = pos;
+                    lo_time = time;
+                }
+                _ => hi = mid,
+            }
+        }
+
+        Ok((lo, lo_time))
+    }
+
+    /// Decodes frames from position `pos`, where sample `time` starts, up to the frame that contains `sample`.
+    ///
+    /// See `find_frame_containing()` for the return value.
+    fn decode_until(&mut self, pos: u64, time: u64, sample: u64) -> Result<Option<(u64, u64, Block)>> {
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => try!(inp.seek_to(pos)),
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        }
+
+        let block = mem::replace(&mut self.pending_block, Block::empty());
+        let mut buffer = block.into_buffer();
+        self.pending_pos = 0;
+
+        let mut time = time;
+        loop {
+            let pos = match self.input {
+                FlacReaderState::Full(ref inp) => inp.position(),
+                FlacReaderState::MetadataOnly(..) => unreachable!(),
+            };
+            let block = match try!(self.blocks().read_next_or_eof(buffer)) {
+                Some(block) => block,
+                None => return Ok(None),
+            };
+
+            let duration = block.duration() as u64;
+            if sample < time + duration {
+                return Ok(Some((pos, time, block)))
+            }
+
+            time += duration;
+            buffer = block.into_buffer();
+        }
+    }
+
+    /// Decodes the frame with zero-based index `frame_index`.
+    ///
+    /// In a fixed-blocksize stream, the frame starts at sample `frame_index`
+    /// times the block size, and it is located like `seek_to_sample()` does,
+    /// so only the frame itself and a few frame headers are read. In a
+    /// variable-blocksize stream, frames are decoded from the start of the
+    /// audio data until the requested one. Returns `None` if the stream has
+    /// fewer frames. Afterwards, the reader is positioned after the frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_frame(&mut self, frame_index: u64) -> Result<Option<Block>> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::decode_frame()");
+        }
+
+        if self.streaminfo.is_fixed_blocksize() {
+            let block_size = self.streaminfo.max_block_size as u64;
+            if let Some(sample) = frame_index.checked_mul(block_size) {
+                match try!(self.find_frame_containing(sample)) {
+                    Some((_, time, block)) if time == sample => return Ok(Some(block)),
+                    // The frame does not start where it should, so the stream
+                    // is not fixed-blocksize after all. Count frames instead.
+                    Some(..) => {}
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        let audio_start = self.audio_start;
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => try!(inp.seek_to(audio_start)),
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        }
+
+        let block = mem::replace(&mut self.pending_block, Block::empty());
+        let mut buffer = block.into_buffer();
+        self.pending_pos = 0;
+
+        let mut blocks = self.blocks();
+        let mut index = 0;
+        while let Some(block) = try!(blocks.read_next_or_eof(buffer)) {
+            if index == frame_index {
+                return Ok(Some(block))
+            }
+            index += 1;
+            buffer = block.into_buffer();
+        }
+
+        Ok(None)
+    }
+
+    /// Returns how far decoding has progressed, including the size of the stream.
+    ///
+    /// This is like `progress()`, but it also sets `bytes_total`, the size of
+    /// the stream counted from the position where the `FlacReader` was
+    /// constructed, like `bytes_read`. To determine it, the underlying reader
+    /// seeks to its end and back, which does not change the position of the
+    /// `FlacReader`.
+    pub fn progress_with_length(&mut self) -> Result<Progress> {
+        let bytes_total = match self.input {
+            FlacReaderState::Full(ref mut inp) => try!(inp.end_position()),
+            FlacReaderState::MetadataOnly(ref mut inp) => try!(inp.end_position()),
+        };
+        let mut progress = self.progress();
+        progress.bytes_total = Some(bytes_total);
+        Ok(progress)
+    }
+
+    /// Returns an iterator that yields the blocks of the stream from the last one to the first.
+    ///
+    /// This is intended for reverse playback and scrubbing backward. Frame
+    /// headers do not record where the previous frame starts, so this first
+    /// builds a dense index of the frames, like `build_index()`, which decodes
+    /// the entire stream once. The iterator then seeks to every frame in turn,
+    /// from the end toward the start, and decodes it, so the total cost is
+    /// about twice that of decoding forward. This starts at the end of the
+    /// stream regardless of the current position, and afterwards the reader
+    /// is positioned after the frame that was decoded last. A partially
+    /// consumed block of `read_interleaved_f32()` or `fill_producer()` is
+    /// discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn blocks_reverse<'r>(&'r mut self) -> Result<BlocksReverse<'r, R>> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::blocks_reverse()");
+        }
+
+        self.pending_block = Block::empty();
+        self.pending_pos = 0;
+
+        let audio_start = self.audio_start;
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => try!(inp.seek_to(audio_start)),
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        }
+
+        let offsets = try!(self.build_index()).into_iter().map(|(_, offset)| offset).collect();
+        let blocks = BlocksReverse {
+            reader: self,
+            offsets: offsets,
+            has_failed: false,
+        };
+        Ok(blocks)
+    }
+
+    /// Decodes `len` inter-channel samples starting at sample `start`.
+    ///
+    /// The samples are returned interleaved. If the stream ends before
+    /// `start + len`, fewer samples are returned. The reader is left positioned
+    /// after the last sample returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn decode_range(&mut self, start: u64, len: u64) -> Result<Vec<i32>> {
+        try!(self.seek_to_sample(start));
+        // Saturate rather than truncate, on platforms where usize is 32 bits too.
+        let n_samples = len.saturating_mul(self.streaminfo.channels as u64);
+        let n_samples = cmp::min(n_samples, usize::max_value() as u64) as usize;
+        let mut samples = Vec::with_capacity(cmp::min(n_samples, 1 << 20));
+        try!(self.read_pending(n_samples, |sample| samples.push(sample)));
+        Ok(samples)
+    }
+
+    /// Writes a stream that consists of a range of frames of this stream, without re-encoding.
+    ///
+    /// The frames with zero-based index `first_frame` up to and including
+    /// `last_frame` are copied verbatim, except for their frame or sample
+    /// number, which is lowered such that the new stream starts at zero. A
+    /// range that extends beyond the end of the stream is truncated to it.
+    /// The metadata is copied too, with a corrected streaminfo block: the
+    /// number of samples, block and frame sizes, and MD5 signature are those
+    /// of the kept frames. A seek table or CUE sheet would no longer be valid,
+    /// its offsets and lead-out refer to the original stream, so it is
+    /// replaced by padding of the same size.
+    ///
+    /// The stream is decoded once to compute the streaminfo, and the kept
+    /// frames are read twice more, so `R` must support seeking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn trim_frames<W: io::Write>(mut self,
+                                     first_frame: u64,
+                                     last_frame: u64,
+                                     mut output: W)
+                                     -> Result<()> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::trim_frames()");
+        }
+
+        let bits_per_sample = self.streaminfo.bits_per_sample;
+        let audio_start = self.audio_start;
+        let mut hasher = Md5::new();
+        let mut bytes = Vec::new();
+        let mut kept: Vec<(FrameRange, u32)> = Vec::new();
+
+        // Decode the kept frames, to compute the new streaminfo.
+        match self.input {
+            FlacReaderState::Full(ref mut inp) => try!(inp.seek_to(audio_start)),
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        }
+        for (i, item) in self.frames().enumerate() {
+            let (range, block) = try!(item);
+            if (i as u64) < first_frame {
+                continue
+            }
+            if i as u64 > last_frame {
+                break
+            }
+            update_md5(&mut hasher, &block, bits_per_sample, &mut bytes);
+            kept.push((range, block.duration()));
+        }
+
+        let input = match self.input {
+            FlacReaderState::Full(ref mut inp) => inp,
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+
+        // Renumber the frames once to learn their new sizes, the number may
+        // take fewer bytes afterwards. The first kept frame gets number zero.
+        let mut frame = Vec::new();
+        let mut renumbered = Vec::new();
+        let mut frame_sizes = Vec::with_capacity(kept.len());
+        let mut base = 0;
+        for (i, &(range, _)) in kept.iter().enumerate() {
+            try!(input.seek_to(range.offset));
+            frame.resize(range.len as usize, 0);
+            try!(input.read_into(&mut frame[..]));
+            if i == 0 {
+                base = try!(frame::frame_number(&frame[..]));
+            }
+            renumbered.clear();
+            try!(frame::renumber_frame(&frame[..], base, &mut renumbered));
+            frame_sizes.push(renumbered.len() as u32);
+        }
+
+        let samples = kept.iter().fold(0u64, |total, &(_, duration)| total + duration as u64);
+        // The sample count is a 36-bit field.
+        if samples >= 1 << 36 {
+            return fmt_err("stream has too many samples for the streaminfo block");
+        }
+        let block_sizes: Vec<u32> = kept.iter().map(|&(_, duration)| duration).collect();
+        // The minimum block size excludes the last block, unless it is the only one.
+        let min_block_candidates = if block_sizes.len() > 1 {
+            &block_sizes[..block_sizes.len() - 1]
+        } else {
+            &block_sizes[..]
+        };
+        let min_block_size = min_block_candidates.iter().cloned().min().unwrap_or(0);
+        let max_block_size = block_sizes.iter().cloned().max().unwrap_or(0);
+        let min_frame_size = frame_sizes.iter().cloned().min().unwrap_or(0);
+        let max_frame_size = frame_sizes.iter().cloned().max().unwrap_or(0);
+
+        // Copy the metadata, and patch the streaminfo block, which must be
+        // the first one. See also `repair_streaminfo()` for the layout.
+        let mut header = vec![0u8; audio_start as usize];
+        try!(input.seek_to(0));
+        try!(input.read_into(&mut header));
+        if header.len() < 42 || header[4] & 0x7f != metadata::BLOCK_TYPE_STREAMINFO {
+            return fmt_err("trimming requires a streaminfo block")
+        }
+
+        let mut pos = 4;
+        while pos + 4 <= header.len() {
+            let length = (header[pos + 1] as usize) << 16 |
+                         (header[pos + 2] as usize) << 8 |
+                         header[pos + 3] as usize;
+            let block_type = header[pos] & 0x7f;
+            if block_type == metadata::BLOCK_TYPE_SEEKTABLE || block_type == metadata::BLOCK_TYPE_CUESHEET {
+                header[pos] = (header[pos] & 0x80) | metadata::BLOCK_TYPE_PADDING;
+                for byte in &mut header[pos + 4..cmp::min(pos + 4 + length, audio_start as usize)] {
+                    *byte = 0;
+                }
+            }
+            pos += 4 + length;
+        }
+
+        let fields = (self.streaminfo.sample_rate as u64) << 44 |
+                     (self.streaminfo.channels as u64 - 1) << 41 |
+                     (bits_per_sample as u64 - 1) << 36 |
+                     samples;
+        let sizes = [(min_block_size >> 8) as u8, min_block_size as u8,
+                     (max_block_size >> 8) as u8, max_block_size as u8,
+                     (min_frame_size >> 16) as u8, (min_frame_size >> 8) as u8, min_frame_size as u8,
+                     (max_frame_size >> 16) as u8, (max_frame_size >> 8) as u8, max_frame_size as u8];
+        header[8..18].copy_from_slice(&sizes);
+        for (i, byte) in header[18..26].iter_mut().enumerate() {
+            *byte = (fields >> (56 - 8 * i)) as u8;
+        }
+        header[26..42].copy_from_slice(&hasher.finalize());
+        try!(output.write_all(&header));
+
+        for &(range, _) in &kept {
+            try!(input.seek_to(range.offset));
+            frame.resize(range.len as usize, 0);
+            try!(input.read_into(&mut frame[..]));
+            renumbered.clear();
+            try!(frame::renumber_frame(&frame[..], base, &mut renumbered));
+            try!(output.write_all(&renumbered));
+        }
+
+        Ok(())
+    }
+
+    /// Writes a copy of the stream with a seek table that has a seek point every `interval_samples`.
+    ///
+    /// For every multiple of `interval_samples`, the seek table has a point for
+    /// the frame that contains that inter-channel sample, as the reference
+    /// encoder does. An existing seek table is replaced. The new seek table
+    /// takes its space from the first padding block that is large enough, so
+    /// in the common case the metadata does not change size. Otherwise the
+    /// metadata grows. The seek table is placed after the streaminfo block,
+    /// the other metadata blocks are copied as-is, and so is everything from
+    /// the first frame onwards.
     ///
-    /// This is a high-level interface to the decoder. The cost of retrieving
-    /// the next sample can vary significantly, as sometimes a new block has to
-    /// be decoded. Additionally, there is a cost to every iteration returning a
-    /// `Result`. When a block has been decoded, iterating the samples in that
-    /// block can never fail, but a match on every sample is required
-    /// nonetheless. For more control over when decoding happens, and less error
-    /// handling overhead, use `blocks()`.
-    pub fn samples<'r>(&'r mut self) -> FlacSamples<&'r mut BufferedReader<R>> {
+    /// The stream is decoded once to locate the frames, and then copied, so
+    /// `R` must support seeking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval_samples` is zero, or if the `FlacReader` was
+    /// constructed with `metadata_only` set.
+    pub fn write_with_seek_table<W: io::Write>(mut self, mut output: W, interval_samples: u64) -> Result<()> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::write_with_seek_table()");
+        }
+        assert!(interval_samples > 0, "seek point interval must be nonzero");
+
+        let audio_start = self.audio_start;
         match self.input {
-            FlacReaderState::Full(ref mut inp) => {
-                FlacSamples {
-                    frame_reader: frame::FrameReader::new(inp),
-                    block: Block::empty(),
-                    sample: 0,
-                    channel: 0,
-                    has_failed: false,
-                }
+            FlacReaderState::Full(ref mut inp) => try!(inp.seek_to(audio_start)),
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        }
+
+        // Frames are contiguous, so the first frame that ends after the next
+        // target contains it.
+        let mut seek_table = Vec::new();
+        let mut target = 0;
+        let mut time = 0u64;
+        for item in self.frames() {
+            let (range, block) = try!(item);
+            let end = time + block.duration() as u64;
+            if target < end {
+                // A seek point is the sample number, the offset from the first
+                // frame, and the number of samples in the frame.
+                let offset = range.offset - audio_start;
+                push_be_u32(&mut seek_table, (time >> 32) as u32);
+                push_be_u32(&mut seek_table, time as u32);
+                push_be_u32(&mut seek_table, (offset >> 32) as u32);
+                push_be_u32(&mut seek_table, offset as u32);
+                seek_table.push((block.duration() >> 8) as u8);
+                seek_table.push(block.duration() as u8);
+                target = match ((end - 1) / interval_samples + 1).checked_mul(interval_samples) {
+                    Some(next) => next,
+                    None => u64::max_value(),
+                };
             }
-            FlacReaderState::MetadataOnly(..) => {
-                panic!("FlacReaderOptions::metadata_only must be false \
-                       to be able to use FlacReader::samples()")
+            time = end;
+        }
+        if seek_table.len() >= 1 << 24 {
+            return fmt_err("seek table is too large for a metadata block")
+        }
+
+        let header = try!(self.read_metadata_bytes());
+        if header.len() < 42 || header[4] & 0x7f != metadata::BLOCK_TYPE_STREAMINFO {
+            return fmt_err("writing a seek table requires a streaminfo block")
+        }
+
+        // Collect the block types and contents, without the seek table. The
+        // first padding that can hold the seek table gives up its space.
+        let mut blocks: Vec<(u8, &[u8])> = Vec::new();
+        let mut reused_padding = false;
+        for (block_type, body) in split_metadata_blocks(&header) {
+            match block_type {
+                metadata::BLOCK_TYPE_SEEKTABLE => continue,
+                metadata::BLOCK_TYPE_PADDING if !reused_padding && body.len() >= seek_table.len() => {
+                    reused_padding = true;
+                    // A block header takes four bytes, so remaining padding
+                    // of less than that is dropped.
+                    if body.len() >= seek_table.len() + 4 {
+                        blocks.push((block_type, &body[seek_table.len() + 4..]));
+                    }
+                }
+                _ => blocks.push((block_type, body)),
             }
         }
+        blocks.insert(1, (metadata::BLOCK_TYPE_SEEKTABLE, &seek_table[..]));
+
+        try!(output.write_all(&join_metadata_blocks(&blocks)));
+        self.copy_audio(output)
     }
 
-    /// Destroys the FLAC reader and returns the underlying reader.
+    /// Writes a copy of the stream with its Vorbis comments replaced by `new_tags`.
     ///
-    /// Because the reader employs buffering internally, anything in the buffer
-    /// will be lost.
-    pub fn into_inner(self) -> R {
-        match self.input {
-            FlacReaderState::Full(inp) => inp.into_inner(),
-            FlacReaderState::MetadataOnly(inp) => inp.into_inner(),
+    /// The new Vorbis comment block contains the name-value pairs in the given
+    /// order, and the vendor string of the original block, or an empty vendor
+    /// string if there was none. Even with `read_vorbis_comment` disabled, the
+    /// vendor string is preserved. It takes the place of the original block, or
+    /// follows the streaminfo if there was none. The other metadata blocks
+    /// are copied as-is, and so is everything from the first frame onwards,
+    /// so the audio is byte-identical.
+    ///
+    /// If the Vorbis comments grow, the first padding block that is large
+    /// enough gives up the space, and if they shrink, the first padding block
+    /// absorbs it, so in the common case the metadata does not change size.
+    /// Without padding, a shrink of at least four bytes, the size of a block
+    /// header, becomes a new padding block at the end of the metadata.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a tag name is empty or contains characters other than
+    /// printable ASCII excluding `'='`, as the Vorbis comment specification
+    /// requires, or if the `FlacReader` was constructed with `metadata_only`
+    /// set.
+    pub fn rewrite_tags<W: io::Write>(mut self, new_tags: &[(String, String)], mut output: W) -> Result<()> {
+        if let FlacReaderState::MetadataOnly(..) = self.input {
+            panic!("FlacReaderOptions::metadata_only must be false \
+                   to be able to use FlacReader::rewrite_tags()");
+        }
+
+        let header = try!(self.read_metadata_bytes());
+        let old_blocks = split_metadata_blocks(&header);
+
+        // The vendor string is taken from the original block rather than from
+        // `vendor()`, so it is preserved even if the block was not parsed. The
+        // block uses little-endian lengths.
+        let vendor = old_blocks.iter()
+            .find(|&&(block_type, _)| block_type == metadata::BLOCK_TYPE_VORBIS_COMMENT)
+            .and_then(|&(_, body)| {
+                if body.len() < 4 {
+                    return None
+                }
+                let len = body[0] as usize | (body[1] as usize) << 8 |
+                          (body[2] as usize) << 16 | (body[3] as usize) << 24;
+                if body.len() - 4 >= len { Some(&body[4..4 + len]) } else { None }
+            })
+            .unwrap_or(&[]);
+        let push_le_u32 = |buffer: &mut Vec<u8>, x: usize| {
+            for i in 0..4 {
+                buffer.push((x >> (8 * i)) as u8);
+            }
+        };
+        let mut comment = Vec::new();
+        push_le_u32(&mut comment, vendor.len());
+        comment.extend_from_slice(vendor);
+        push_le_u32(&mut comment, new_tags.len());
+        for &(ref name, ref value) in new_tags {
+            assert!(!name.is_empty() && name.bytes().all(|b| b >= 0x20 && b <= 0x7d && b != b'='),
+                    "invalid Vorbis comment name: {:?}", name);
+            push_le_u32(&mut comment, name.len() + 1 + value.len());
+            comment.extend_from_slice(name.as_bytes());
+            comment.push(b'=');
+            comment.extend_from_slice(value.as_bytes());
         }
+        if comment.len() >= 1 << 24 {
+            return fmt_err("Vorbis comment is too large for a metadata block")
+        }
+
+        let mut blocks: Vec<(u8, &[u8])> = Vec::new();
+        let mut old_len = 0;
+        for &(block_type, body) in &old_blocks {
+            if block_type == metadata::BLOCK_TYPE_VORBIS_COMMENT {
+                if old_len == 0 {
+                    blocks.push((block_type, &comment[..]));
+                }
+                old_len += 4 + body.len();
+            } else {
+                blocks.push((block_type, body));
+            }
+        }
+        if old_len == 0 {
+            let index = cmp::min(1, blocks.len());
+            blocks.insert(index, (metadata::BLOCK_TYPE_VORBIS_COMMENT, &comment[..]));
+        }
+
+        // Padding consists of zeros, and it never grows beyond the original
+        // size of the metadata.
+        let zeros = vec![0u8; header.len()];
+        let new_len = 4 + comment.len();
+        let padding = blocks.iter().position(|&(block_type, _)| block_type == metadata::BLOCK_TYPE_PADDING);
+        match padding {
+            Some(i) if new_len > old_len && blocks[i].1.len() >= new_len - old_len => {
+                blocks[i].1 = &zeros[..blocks[i].1.len() - (new_len - old_len)];
+            }
+            Some(i) if new_len < old_len => {
+                blocks[i].1 = &zeros[..blocks[i].1.len() + (old_len - new_len)];
+            }
+            None if old_len >= new_len + 4 => {
+                blocks.push((metadata::BLOCK_TYPE_PADDING, &zeros[..old_len - new_len - 4]));
+            }
+            _ => {}
+        }
+
+        try!(output.write_all(&join_metadata_blocks(&blocks)));
+        self.copy_audio(output)
+    }
+
+    /// Reads the stream header and the metadata blocks, from the start of the stream up to the audio.
+    fn read_metadata_bytes(&mut self) -> Result<Vec<u8>> {
+        let input = match self.input {
+            FlacReaderState::Full(ref mut inp) => inp,
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+        let mut header = vec![0u8; self.audio_start as usize];
+        try!(input.seek_to(0));
+        try!(input.read_into(&mut header));
+        Ok(header)
+    }
+
+    /// Copies everything from the first frame to the end of the stream to `output`.
+    fn copy_audio<W: io::Write>(&mut self, mut output: W) -> Result<()> {
+        let input = match self.input {
+            FlacReaderState::Full(ref mut inp) => inp,
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+        let end = try!(input.end_position());
+        try!(input.seek_to(self.audio_start));
+        let mut buffer = vec![0u8; 1 << 16];
+        let mut pos = self.audio_start;
+        while pos < end {
+            let n = cmp::min(end - pos, buffer.len() as u64) as usize;
+            try!(input.read_into(&mut buffer[..n]));
+            try!(output.write_all(&buffer[..n]));
+            pos += n as u64;
+        }
+        Ok(())
     }
 }
 
@@ -443,6 +4585,437 @@ impl FlacReader<fs::File> {
     }
 }
 
+impl<'r, R: io::Read> Iterator for FlacFrames<'r, R> {
+    type Item = Result<(FrameRange, Block)>;
+
+    fn next(&mut self) -> Option<Result<(FrameRange, Block)>> {
+        if self.has_failed {
+            return None
+        }
+
+        // Every frame gets a new frame reader, so the deadline is checked here.
+        if let Some(deadline) = self.options.deadline {
+            if let Err(error) = frame::check_deadline(deadline, self.decoded_samples) {
+                self.has_failed = true;
+                return Some(Err(match self.label {
+                    Some(ref label) => error.with_label(label),
+                    None => error,
+                }))
+            }
+        }
+
+        let offset = self.input.position();
+        let mut frame_reader = new_frame_reader(&mut *self.input,
+                                                self.max_block_size,
+                                                &self.options,
+                                                self.progress.clone(),
+                                                self.statistics.clone(),
+                                                self.label.clone(),
+                                                &self.streaminfo);
+        let result = frame_reader.read_next_or_eof(Vec::new());
+        match result {
+            Ok(None) => None,
+            Ok(Some(block)) => {
+                let range = FrameRange {
+                    offset: offset,
+                    len: self.input.position() - offset,
+                };
+                self.decoded_samples += block.duration() as u64;
+                Some(Ok((range, block)))
+            }
+            Err(error) => {
+                self.has_failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// An iterator that yields the blocks of a stream in reverse order.
+///
+/// See `FlacReader::blocks_reverse()` for more details.
+pub struct BlocksReverse<'r, R: 'r + io::Read> {
+    reader: &'r mut FlacReader<R>,
+
+    /// The offsets of the frames that remain to be decoded, the last one at the end.
+    offsets: Vec<u64>,
+
+    /// If reading ever failed, this flag is set, so that the iterator knows not
+    /// to return any new values.
+    has_failed: bool,
+}
+
+impl<'r, R: io::Read + io::Seek> Iterator for BlocksReverse<'r, R> {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Result<Block>> {
+        if self.has_failed {
+            return None
+        }
+
+        let offset = match self.offsets.pop() {
+            Some(offset) => offset,
+            None => return None,
+        };
+
+        let result = match self.reader.input {
+            FlacReaderState::Full(ref mut inp) => inp.seek_to(offset),
+            FlacReaderState::MetadataOnly(..) => unreachable!(),
+        };
+        let result = match result {
+            Ok(()) => self.reader.blocks().read_next_or_eof(Vec::new()),
+            Err(err) => Err(Error::from(err)),
+        };
+
+        match result {
+            Ok(Some(block)) => Some(Ok(block)),
+            // The index was built from the same stream, so a frame must be
+            // there, unless the underlying reader changed in between.
+            Ok(None) => {
+                self.has_failed = true;
+                Some(fmt_err("frame of the index is missing"))
+            }
+            Err(error) => {
+                self.has_failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.has_failed {
+            (0, Some(0))
+        } else {
+            (self.offsets.len(), Some(self.offsets.len()))
+        }
+    }
+}
+
+impl<R: ReadBytes> Drop for FlacSamples<R> {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool {
+            let block = mem::replace(&mut self.block, Block::empty());
+            pool.give(block.into_buffer());
+            pool.give(self.frame_reader.take_unused_buffer());
+        }
+    }
+}
+
+impl<R: ReadBytes> FlacSamples<R> {
+    /// Returns whether the stream ended before all samples in the streaminfo were decoded.
+    ///
+    /// This is only known once the iterator has reached the end of the stream.
+    /// If the streaminfo does not specify the number of samples, a stream is
+    /// never considered truncated.
+    pub fn is_truncated(&self) -> bool {
+        self.is_truncated
+    }
+
+    /// Returns whether the stream contains more samples than the streaminfo declares.
+    ///
+    /// This is set as soon as a block that extends beyond the declared number
+    /// of samples is decoded, counting from the first block that the iterator
+    /// decoded. In strict mode, decoding such a block is an error; otherwise
+    /// its samples are yielded like any other. If the streaminfo does not
+    /// specify the number of samples, this is always false.
+    pub fn has_extra_samples(&self) -> bool {
+        self.has_extra_samples
+    }
+
+    /// Returns the number of inter-channel padding samples yielded so far.
+    ///
+    /// This is nonzero only if `FlacReaderOptions::pad_final_block` is set,
+    /// and counts the zeros appended to a short final block. The padding is
+    /// counted as soon as the padded block has been decoded.
+    pub fn padding_samples(&self) -> u64 {
+        self.frame_reader.padding_samples()
+    }
+}
+
+/// An iterator that yields samples converted to the sample type `S`.
+///
+/// See `FlacReader::samples_as()` for more details.
+pub struct SamplesAs<R: ReadBytes, S: Sample> {
+    samples: FlacSamples<R>,
+    bits_per_sample: u32,
+    has_failed: bool,
+    sample_type: PhantomData<S>,
+}
+
+impl<R: ReadBytes, S: Sample> Iterator for SamplesAs<R, S> {
+    type Item = Result<S>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<S>> {
+        if self.has_failed {
+            return None
+        }
+
+        match self.samples.next() {
+            Some(Ok(sample)) => match S::from_i32(sample) {
+                Some(s) => Some(Ok(s)),
+                None => {
+                    self.has_failed = true;
+                    Some(Err(Error::SampleTooWide {
+                        needed_bits: self.bits_per_sample,
+                        requested_type: S::type_name(),
+                    }))
+                }
+            },
+            Some(Err(error)) => Some(Err(error)),
+            None => None,
+        }
+    }
+}
+
+/// An iterator that yields samples converted to a different bit depth.
+///
+/// See `FlacReader::samples_at_bit_depth()` for more details.
+pub struct BitDepthSamples<R: ReadBytes> {
+    samples: FlacSamples<R>,
+    shift_left: u32,
+    shift_right: u32,
+    target_bits: u32,
+
+    /// The ditherer to use when narrowing, if any.
+    ditherer: Option<sample::Ditherer>,
+}
+
+impl<R: ReadBytes> BitDepthSamples<R> {
+    /// Returns whether the stream ended before the number of samples in the streaminfo.
+    ///
+    /// See `FlacSamples::is_truncated()`.
+    pub fn is_truncated(&self) -> bool {
+        self.samples.is_truncated()
+    }
+
+    /// Returns whether the stream contains more samples than the streaminfo declares.
+    ///
+    /// See `FlacSamples::has_extra_samples()`.
+    pub fn has_extra_samples(&self) -> bool {
+        self.samples.has_extra_samples()
+    }
+
+    /// Returns the number of inter-channel padding samples yielded so far.
+    ///
+    /// See `FlacSamples::padding_samples()`.
+    pub fn padding_samples(&self) -> u64 {
+        self.samples.padding_samples()
+    }
+}
+
+impl<R: ReadBytes> Iterator for BitDepthSamples<R> {
+    type Item = Result<i32>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<i32>> {
+        let shift_left = self.shift_left;
+        let shift_right = self.shift_right;
+        let target_bits = self.target_bits;
+        match self.ditherer {
+            Some(ref mut ditherer) => {
+                // The ditherer is only set when narrowing, so there is no left shift.
+                let from_bits = target_bits + shift_right;
+                self.samples.next().map(|r| r.map(|s| ditherer.convert(s, from_bits, target_bits)))
+            }
+            None => self.samples.next().map(|r| r.map(|s| (s << shift_left) >> shift_right)),
+        }
+    }
+}
+
+/// An iterator that yields samples together with their channel and index.
+///
+/// See `FlacReader::indexed_samples()` for more details.
+pub struct IndexedSamples<R: ReadBytes> {
+    samples: FlacSamples<R>,
+
+    /// The index of the first inter-channel sample of the current block.
+    block_start: u64,
+
+    /// The index of the first inter-channel sample of the next block.
+    next_block_start: u64,
+}
+
+impl<R: ReadBytes> IndexedSamples<R> {
+    /// Returns whether the stream ended before the number of samples in the streaminfo.
+    ///
+    /// See `FlacSamples::is_truncated()`.
+    pub fn is_truncated(&self) -> bool {
+        self.samples.is_truncated()
+    }
+
+    /// Returns whether the stream contains more samples than the streaminfo declares.
+    ///
+    /// See `FlacSamples::has_extra_samples()`.
+    pub fn has_extra_samples(&self) -> bool {
+        self.samples.has_extra_samples()
+    }
+
+    /// Returns the number of inter-channel padding samples yielded so far.
+    ///
+    /// See `FlacSamples::padding_samples()`.
+    pub fn padding_samples(&self) -> u64 {
+        self.samples.padding_samples()
+    }
+}
+
+impl<R: ReadBytes> Iterator for IndexedSamples<R> {
+    type Item = Result<(usize, u64, i32)>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<(usize, u64, i32)>> {
+        let value = match self.samples.next() {
+            Some(Ok(value)) => value,
+            Some(Err(error)) => return Some(Err(error)),
+            None => return None,
+        };
+
+        // The first sample of a block was just yielded, so the index moves
+        // past the previous block.
+        let channel = self.samples.channel;
+        let sample = self.samples.sample;
+        if channel == 0 && sample == 0 {
+            self.block_start = self.next_block_start;
+            self.next_block_start += self.samples.block.duration() as u64;
+        }
+
+        Some(Ok((channel as usize, self.block_start + sample as u64, value)))
+    }
+}
+
+/// An iterator that yields windows of samples of a single channel.
+///
+/// See `FlacReader::windows()` for more details.
+pub struct ChannelWindows<R: ReadBytes> {
+    frame_reader: FrameReader<R>,
+    streaminfo: StreamInfo,
+    channel: u32,
+    window: usize,
+    hop: usize,
+    pad_last: bool,
+
+    /// Decoded samples, starting at the start of the next window.
+    pending: Vec<i32>,
+
+    /// The number of samples to drop before the next window, if `hop` exceeds `window`.
+    skip: usize,
+
+    /// The buffer of the previous block, to be reused for the next one.
+    buffer: Vec<i32>,
+
+    /// Set after the stream ended or reading failed, to end iteration.
+    is_done: bool,
+}
+
+impl<R: ReadBytes> Iterator for ChannelWindows<R> {
+    type Item = Result<Vec<i32>>;
+
+    fn next(&mut self) -> Option<Result<Vec<i32>>> {
+        loop {
+            if self.pending.len() >= self.window {
+                let window = self.pending[..self.window].to_vec();
+                if self.hop <= self.pending.len() {
+                    self.pending.drain(..self.hop);
+                } else {
+                    self.skip = self.hop - self.pending.len();
+                    self.pending.clear();
+                }
+                return Some(Ok(window));
+            }
+
+            if self.is_done {
+                if self.pad_last && !self.pending.is_empty() {
+                    let mut window = mem::replace(&mut self.pending, Vec::new());
+                    window.resize(self.window, 0);
+                    return Some(Ok(window));
+                }
+                return None;
+            }
+
+            let buffer = mem::replace(&mut self.buffer, Vec::new());
+            let result = self.frame_reader.read_next_or_eof(buffer).and_then(|block| {
+                if let Some(ref b) = block {
+                    try!(check_block_channels(b, &self.streaminfo));
+                }
+                Ok(block)
+            });
+            match result {
+                Ok(Some(block)) => {
+                    {
+                        let samples = block.channel(self.channel);
+                        let skip = cmp::min(self.skip, samples.len());
+                        self.pending.extend_from_slice(&samples[skip..]);
+                        self.skip -= skip;
+                    }
+                    self.buffer = block.into_buffer();
+                }
+                Ok(None) => self.is_done = true,
+                Err(error) => {
+                    self.is_done = true;
+                    self.pending.clear();
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// An iterator that yields an array with the samples of all channels at a time.
+///
+/// See `FlacReader::sample_arrays()` for more details.
+pub struct SampleArrays<R: ReadBytes, F: SampleArray> {
+    frame_reader: FrameReader<R>,
+    streaminfo: StreamInfo,
+    block: Block,
+
+    /// The index of the next inter-channel sample to yield from `block`.
+    sample: u32,
+
+    /// If reading ever failed, this flag is set, so that the iterator knows not
+    /// to return any new values.
+    has_failed: bool,
+
+    array_type: PhantomData<F>,
+}
+
+impl<R: ReadBytes, F: SampleArray> Iterator for SampleArrays<R, F> {
+    type Item = Result<F>;
+
+    fn next(&mut self) -> Option<Result<F>> {
+        if self.has_failed {
+            return None
+        }
+
+        while self.sample >= self.block.duration() {
+            let block = mem::replace(&mut self.block, Block::empty());
+            let result = self.frame_reader.read_next_or_eof(block.into_buffer()).and_then(|block| {
+                if let Some(ref b) = block {
+                    try!(check_block_channels(b, &self.streaminfo));
+                }
+                Ok(block)
+            });
+            match result {
+                Ok(Some(next_block)) => self.block = next_block,
+                Ok(None) => return None,
+                Err(error) => {
+                    self.has_failed = true;
+                    return Some(Err(error));
+                }
+            }
+            self.sample = 0;
+        }
+
+        // FLAC streams have at most eight channels.
+        let mut samples = [0; 8];
+        let n_channels = self.block.channels();
+        for ch in 0..n_channels {
+            samples[ch as usize] = self.block.sample(ch, self.sample);
+        }
+        self.sample += 1;
+        Some(Ok(F::from_slice(&samples[..n_channels as usize])))
+    }
+}
+
 impl<R: ReadBytes> Iterator for FlacSamples<R> {
     type Item = Result<i32>;
 
@@ -468,16 +5041,64 @@ impl<R: ReadBytes> Iterator for FlacSamples<R> {
                 // Replace the current block with an empty one so that we may
                 // reuse the current buffer to decode again.
                 let current_block = mem::replace(&mut self.block, Block::empty());
+                let end = current_block.time() + current_block.duration() as u64 -
+                          self.block_padding;
+
+                let mut buffer = current_block.into_buffer();
+                if buffer.capacity() == 0 {
+                    if let Some(ref pool) = self.pool {
+                        buffer = pool.take();
+                    }
+                }
 
-                match self.frame_reader.read_next_or_eof(current_block.into_buffer()) {
+                let padding_before = self.frame_reader.padding_samples();
+                match self.frame_reader.read_next_or_eof(buffer) {
                     Ok(Some(next_block)) => {
+                        // Padding is not part of the stream, so it does not
+                        // count towards the number of samples.
+                        self.block_padding = self.frame_reader.padding_samples() - padding_before;
+                        // If a number of samples was specified in the
+                        // streaminfo metadata block, verify that we did not
+                        // read more samples. Samples are counted from the first
+                        // block, because a captured stream need not start at
+                        // sample zero.
+                        let start = match self.first_time {
+                            Some(t) => t,
+                            None => next_block.time(),
+                        };
+                        self.first_time = Some(start);
+                        let next_end = next_block.time() + next_block.duration() as u64 -
+                                       self.block_padding;
+                        match self.expected_samples {
+                            Some(n) if next_end.saturating_sub(start) > n => {
+                                self.has_extra_samples = true;
+                                if self.strict {
+                                    self.has_failed = true;
+                                    let error = Error::FormatError("stream contains more samples \
+                                                                    than the streaminfo");
+                                    return Some(Err(self.frame_reader.label_error(error)));
+                                }
+                            }
+                            _ => {}
+                        }
                         self.block = next_block;
                     }
                     Ok(None) => {
-                        // The stream ended with EOF.
-                        // TODO: If a number of samples was specified in the
-                        // streaminfo metadata block, verify that we did not
-                        // read more or less samples.
+                        // The stream ended with EOF. If a number of samples was
+                        // specified in the streaminfo metadata block, verify
+                        // that we did not read less samples.
+                        match self.expected_samples {
+                            Some(n) if end < n => {
+                                self.is_truncated = true;
+                                if self.strict && !self.allow_truncation {
+                                    self.has_failed = true;
+                                    let error = Error::FormatError("stream ended before the number \
+                                                                    of samples in the streaminfo");
+                                    return Some(Err(self.frame_reader.label_error(error)));
+                                }
+                            }
+                            _ => {}
+                        }
                         return None;
                     }
                     Err(error) => {
@@ -0,0 +1,176 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2017 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `md5` module implements the MD5 hash used for the audio signature.
+//!
+//! The streaminfo block contains an MD5 signature of the unencoded audio data.
+//! MD5 is not suitable for cryptographic purposes, but it is what the FLAC
+//! format prescribes, and it is good enough to detect corruption.
+
+/// The per-round shift amounts.
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// The integer part of the sines of integers (in radians) times 2^32.
+const SINES: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Computes an MD5 hash incrementally.
+#[derive(Clone)]
+pub struct Md5 {
+    state: [u32; 4],
+    buffer: [u8; 64],
+    /// The total number of bytes hashed so far.
+    len: u64,
+}
+
+impl Md5 {
+    /// Creates a hasher that has not consumed any data yet.
+    pub fn new() -> Md5 {
+        Md5 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: [0; 64],
+            len: 0,
+        }
+    }
+
+    /// Feeds the bytes into the hash.
+    pub fn update(&mut self, mut data: &[u8]) {
+        let mut buffered = (self.len % 64) as usize;
+        self.len += data.len() as u64;
+
+        // Complete a partially filled buffer first.
+        if buffered > 0 {
+            let n = ::std::cmp::min(64 - buffered, data.len());
+            self.buffer[buffered..buffered + n].copy_from_slice(&data[..n]);
+            buffered += n;
+            data = &data[n..];
+
+            if buffered < 64 {
+                return
+            }
+
+            let block = self.buffer;
+            self.process_block(&block);
+        }
+
+        // Then process full blocks directly from the input.
+        while data.len() >= 64 {
+            self.process_block(&data[..64]);
+            data = &data[64..];
+        }
+
+        self.buffer[..data.len()].copy_from_slice(data);
+    }
+
+    /// Applies the padding and returns the hash of all data fed so far.
+    pub fn finalize(mut self) -> [u8; 16] {
+        let bit_len = self.len.wrapping_mul(8);
+
+        // Pad with a single one bit and zeros, up to 8 bytes before the end of
+        // a block. Then append the length in bits, as little-endian integer.
+        let n_zeros = (119 - (self.len % 64) as usize) % 64;
+        let mut padding = [0u8; 72];
+        padding[0] = 0x80;
+        for i in 0..8 {
+            padding[1 + n_zeros + i] = (bit_len >> (8 * i)) as u8;
+        }
+        self.update(&padding[..1 + n_zeros + 8]);
+        debug_assert_eq!(self.len % 64, 0);
+
+        let mut digest = [0u8; 16];
+        for (i, word) in self.state.iter().enumerate() {
+            for j in 0..4 {
+                digest[i * 4 + j] = (word >> (8 * j)) as u8;
+            }
+        }
+        digest
+    }
+
+    fn process_block(&mut self, block: &[u8]) {
+        debug_assert_eq!(block.len(), 64);
+
+        let mut m = [0u32; 16];
+        for (i, word) in m.iter_mut().enumerate() {
+            *word = (block[i * 4] as u32) |
+                    (block[i * 4 + 1] as u32) << 8 |
+                    (block[i * 4 + 2] as u32) << 16 |
+                    (block[i * 4 + 3] as u32) << 24;
+        }
+
+        let mut a = self.state[0];
+        let mut b = self.state[1];
+        let mut c = self.state[2];
+        let mut d = self.state[3];
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f)
+                           .wrapping_add(SINES[i])
+                           .wrapping_add(m[g])
+                           .rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        self.state[0] = self.state[0].wrapping_add(a);
+        self.state[1] = self.state[1].wrapping_add(b);
+        self.state[2] = self.state[2].wrapping_add(c);
+        self.state[3] = self.state[3].wrapping_add(d);
+    }
+}
+
+#[cfg(test)]
+fn md5_hex(data: &[u8]) -> String {
+    let mut hasher = Md5::new();
+    hasher.update(data);
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn verify_md5_reference_vectors() {
+    // Test vectors from RFC 1321.
+    assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(md5_hex(b"a"), "0cc175b9c0f1b6a831c399e269772661");
+    assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(md5_hex(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+    assert_eq!(md5_hex(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"),
+               "57edf4a22be3c955ac49da2e2107b67a");
+}
+
+#[test]
+fn verify_md5_incremental_update() {
+    let data: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+    let mut whole = Md5::new();
+    whole.update(&data);
+
+    // Feed the same data in uneven pieces, which straddle block boundaries.
+    let mut pieces = Md5::new();
+    for chunk in data.chunks(13) {
+        pieces.update(chunk);
+    }
+
+    assert_eq!(whole.finalize(), pieces.finalize());
+}
@@ -8,19 +8,64 @@
 //! The `metadata` module deals with metadata at the beginning of a FLAC stream.
 
 use error::{Error, Result, fmt_err};
+#[cfg(feature = "image")]
+use image;
 use input::ReadBytes;
+use sample::{self, Sample};
+use std::io;
+use std::mem;
 use std::str;
 use std::slice;
+use std::time::Duration;
+
+/// The metadata block type of a streaminfo block.
+pub const BLOCK_TYPE_STREAMINFO: u8 = 0;
+/// The metadata block type of a padding block.
+pub const BLOCK_TYPE_PADDING: u8 = 1;
+/// The metadata block type of an application block.
+pub const BLOCK_TYPE_APPLICATION: u8 = 2;
+/// The metadata block type of a seek table block.
+pub const BLOCK_TYPE_SEEKTABLE: u8 = 3;
+/// The metadata block type of a Vorbis comment block.
+pub const BLOCK_TYPE_VORBIS_COMMENT: u8 = 4;
+/// The metadata block type of a CUE sheet block.
+pub const BLOCK_TYPE_CUESHEET: u8 = 5;
+/// The metadata block type of a picture block.
+pub const BLOCK_TYPE_PICTURE: u8 = 6;
+/// The first reserved metadata block type; types up to and including 126 are reserved.
+pub const BLOCK_TYPE_FIRST_RESERVED: u8 = 7;
+/// The invalid metadata block type, which could be confused with a frame sync code.
+pub const BLOCK_TYPE_INVALID: u8 = 127;
+
+#[test]
+fn verify_block_type_constants() {
+    // These values are prescribed by the specification.
+    assert_eq!(BLOCK_TYPE_STREAMINFO, 0);
+    assert_eq!(BLOCK_TYPE_PADDING, 1);
+    assert_eq!(BLOCK_TYPE_APPLICATION, 2);
+    assert_eq!(BLOCK_TYPE_SEEKTABLE, 3);
+    assert_eq!(BLOCK_TYPE_VORBIS_COMMENT, 4);
+    assert_eq!(BLOCK_TYPE_CUESHEET, 5);
+    assert_eq!(BLOCK_TYPE_PICTURE, 6);
+    assert_eq!(BLOCK_TYPE_FIRST_RESERVED, 7);
+    assert_eq!(BLOCK_TYPE_INVALID, 127);
+}
 
-#[derive(Clone, Copy)]
-struct MetadataBlockHeader {
-    is_last: bool,
-    block_type: u8,
-    length: u32,
+/// The header of a metadata block, which precedes the block contents.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MetadataBlockHeader {
+    /// Whether this is the last metadata block before the audio frames.
+    pub is_last: bool,
+    /// The block type, one of the `BLOCK_TYPE_*` constants.
+    ///
+    /// Values from `BLOCK_TYPE_FIRST_RESERVED` up to 126 are reserved.
+    pub block_type: u8,
+    /// The length of the block contents in bytes, excluding the header.
+    pub length: u32,
 }
 
 /// The streaminfo metadata block, with important information about the stream.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct StreamInfo {
     // TODO: "size" would better be called "duration" for clarity.
     /// The minimum block size (in inter-channel samples) used in the stream.
@@ -53,6 +98,112 @@ pub struct StreamInfo {
     pub md5sum: [u8; 16],
 }
 
+impl StreamInfo {
+    /// Returns whether all blocks in the stream have the same size.
+    ///
+    /// The final block may still be shorter. For such a stream, the block that
+    /// contains a given sample follows from dividing by the block size. A block
+    /// size of zero means that the encoder did not record the sizes, so the
+    /// stream is not considered fixed-blocksize in that case.
+    pub fn is_fixed_blocksize(&self) -> bool {
+        self.min_block_size == self.max_block_size && self.min_block_size != 0
+    }
+
+    /// Returns whether the streaminfo specifies the number of samples in the stream.
+    ///
+    /// A streaming encoder may not know the length when it writes the
+    /// streaminfo, and it then stores a total of zero. That is why `samples`
+    /// is `None` rather than `Some(0)` in that case: a total of zero always
+    /// means an unknown length, also for a stream without audio. Methods that
+    /// depend on the length, such as `duration()`, then return `None` too.
+    pub fn has_known_length(&self) -> bool {
+        self.samples.is_some()
+    }
+
+    /// Returns the duration of the stream, if the number of samples is known.
+    ///
+    /// The sample count is a 36-bit field, so the duration can exceed the
+    /// 2<sup>32</sup> samples (27 hours at 44.1 kHz) that fit in a `u32`. It is
+    /// rounded down to whole nanoseconds.
+    pub fn duration(&self) -> Option<Duration> {
+        match self.samples {
+            Some(n) if self.sample_rate > 0 => {
+                let rate = self.sample_rate as u64;
+                // The remainder is less than 2^20, so this does not overflow.
+                let nanos = (n % rate) * 1_000_000_000 / rate;
+                Some(Duration::new(n / rate, nanos as u32))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether every sample of the stream fits in the sample type `S` without loss.
+    ///
+    /// This compares the bits per sample of the stream against the range of the
+    /// type, so `i16` fits streams of up to 16 bits. If it returns true,
+    /// `FlacReader::samples_as::<S>()` never fails with `SampleTooWide` on
+    /// valid input.
+    pub fn fits_in<S: Sample>(&self) -> bool {
+        // The extremes of the bit depth are the extremes of `i32` narrowed.
+        let bits = self.bits_per_sample;
+        let max = sample::convert_bit_depth(<i32 as Sample>::max(), 32, bits);
+        let min = sample::convert_bit_depth(<i32 as Sample>::min(), 32, bits);
+        S::from_i32(max).is_some() && S::from_i32(min).is_some()
+    }
+}
+
+#[test]
+fn verify_stream_info_fits_in() {
+    let mut stream_info = StreamInfo {
+        min_block_size: 4096,
+        max_block_size: 4096,
+        min_frame_size: None,
+        max_frame_size: None,
+        sample_rate: 44_100,
+        channels: 2,
+        bits_per_sample: 16,
+        samples: None,
+        md5sum: [0u8; 16],
+    };
+    assert!(stream_info.fits_in::<i16>());
+    assert!(stream_info.fits_in::<i32>());
+    assert!(!stream_info.fits_in::<i8>());
+
+    stream_info.bits_per_sample = 24;
+    assert!(!stream_info.fits_in::<i8>());
+    assert!(!stream_info.fits_in::<i16>());
+    assert!(stream_info.fits_in::<i32>());
+
+    let expected = [(4, true, true), (8, true, true), (9, false, true), (17, false, false)];
+    for &(bits, fits_i8, fits_i16) in &expected {
+        stream_info.bits_per_sample = bits;
+        assert_eq!(stream_info.fits_in::<i8>(), fits_i8);
+        assert_eq!(stream_info.fits_in::<i16>(), fits_i16);
+    }
+    stream_info.bits_per_sample = 32;
+    assert!(stream_info.fits_in::<i32>());
+}
+
+#[test]
+fn verify_stream_info_with_more_than_32_bits_of_samples() {
+    use std::io;
+
+    // A streaminfo block for 44.1 kHz stereo 16-bit audio, with a sample count
+    // of 2^35 + 22050, which uses all four of the high bits.
+    let mut data = vec![0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0];
+    data.extend_from_slice(&[0x0a, 0xc4, 0x42, 0xf8, 0x00, 0x00, 0x56, 0x22]);
+    data.extend_from_slice(&[0u8; 16]);
+    let streaminfo = read_streaminfo_block(&mut io::Cursor::new(data)).unwrap();
+    assert_eq!(streaminfo.sample_rate, 44100);
+    assert_eq!(streaminfo.channels, 2);
+    assert_eq!(streaminfo.bits_per_sample, 16);
+    assert_eq!(streaminfo.samples, Some((1 << 35) + 22050));
+    assert_eq!(streaminfo.duration(), Some(Duration::new(779_132, 889_297_052)));
+
+    let unknown = StreamInfo { samples: None, ..streaminfo };
+    assert_eq!(unknown.duration(), None);
+}
+
 /// A seek point in the seek table.
 #[derive(Clone, Copy)]
 pub struct SeekPoint {
@@ -68,10 +219,16 @@ pub struct SeekPoint {
 /// A seek table to aid seeking in the stream.
 pub struct SeekTable {
     /// The seek points, sorted in ascending order by sample number.
-    #[allow(dead_code)] // TODO: Implement seeking.
     seekpoints: Vec<SeekPoint>,
 }
 
+impl SeekTable {
+    /// Returns the seek points, including placeholders.
+    pub fn seekpoints(&self) -> &[SeekPoint] {
+        &self.seekpoints
+    }
+}
+
 /// Vorbis comments, also known as FLAC tags (e.g. artist, title, etc.).
 pub struct VorbisComment {
     /// The “vendor string”, chosen by the encoder vendor.
@@ -100,6 +257,508 @@ pub struct VorbisComment {
     pub comments: Vec<(String, usize)>,
 }
 
+/// A CUE sheet, which describes the track layout of the stream (e.g. of a CD).
+pub struct CueSheet {
+    /// The media catalog number, or an empty string if there is none.
+    ///
+    /// For a CD this is the 13-digit UPC/EAN code of the disc.
+    pub catalog_number: String,
+    /// The number of lead-in samples. Only meaningful for a CD.
+    pub lead_in: u64,
+    /// Whether the CUE sheet corresponds to a compact disc.
+    pub is_cd: bool,
+    /// The tracks, including the lead-out track, which is the last one.
+    pub tracks: Vec<CueSheetTrack>,
+}
+
+impl CueSheet {
+    /// Returns the offset in samples of the lead-out track, the end of the audio.
+    ///
+    /// The lead-out track is the last track. For a valid stream, its offset
+    /// equals the number of samples in the streaminfo. Returns `None` if the
+    /// CUE sheet has no tracks.
+    pub fn lead_out(&self) -> Option<u64> {
+        self.tracks.last().map(|track| track.offset)
+    }
+}
+
+/// A track in a CUE sheet.
+pub struct CueSheetTrack {
+    /// The offset in samples of the first index point of the track, relative
+    /// to the start of the stream.
+    pub offset: u64,
+    /// The track number. The lead-out track is number 170 for a CD, or 255 otherwise.
+    pub number: u8,
+    /// The International Standard Recording Code, or an empty string if there is none.
+    pub isrc: String,
+    /// Whether the track contains audio, as opposed to data.
+    pub is_audio: bool,
+    /// Whether the audio was recorded with pre-emphasis.
+    ///
+    /// Pre-emphasis boosts high frequencies, and the decoded audio must be
+    /// de-emphasized to sound right. Claxon does not do this.
+    pub pre_emphasis: bool,
+    /// The index points of the track. The lead-out track has none.
+    pub indices: Vec<CueSheetIndex>,
+}
+
+/// An index point in a CUE sheet track.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CueSheetIndex {
+    /// The offset in samples relative to the offset of the track.
+    pub offset: u64,
+    /// The index point number.
+    pub number: u8,
+}
+
+/// A picture, such as the cover art of an album.
+///
+/// The picture data is stored as it appears in the file, in the format that
+/// the MIME type describes, commonly `image/jpeg` or `image/png`. The
+/// dimensions and color depth are those declared in the picture block, which
+/// Claxon does not check against the data.
+pub struct Picture {
+    /// The kind of picture, as in the ID3v2 APIC frame.
+    ///
+    /// For example, 3 is the front cover, and 4 is the back cover. See
+    /// <https://xiph.org/flac/format.html#metadata_block_picture> for all
+    /// values.
+    pub picture_type: u32,
+    /// The MIME type of the data, such as `image/png`.
+    ///
+    /// A MIME type of `-->` means that the data is a URL of the picture,
+    /// rather than the picture itself.
+    pub mime_type: String,
+    /// A description of the picture, which may be empty.
+    pub description: String,
+    /// The width of the picture in pixels.
+    pub width: u32,
+    /// The height of the picture in pixels.
+    pub height: u32,
+    /// The color depth of the picture in bits per pixel.
+    pub depth: u32,
+    /// The number of colors of an indexed-color picture, or 0 otherwise.
+    pub colors: u32,
+    /// The picture data.
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    /// Decodes the picture data into pixels with the `image` crate.
+    ///
+    /// The MIME type is used to select the image format when it names one
+    /// that the `image` crate knows. Otherwise the format is guessed from the
+    /// data. Data that is not a valid image results in a format error, and
+    /// an image format that the `image` crate was built without, or a picture
+    /// that is a URL, results in an `Unsupported` error.
+    ///
+    /// This method is only available with the `image` feature enabled.
+    #[cfg(feature = "image")]
+    pub fn decode_image(&self) -> Result<image::DynamicImage> {
+        if self.mime_type == "-->" {
+            return Err(Error::Unsupported("picture is a URL, not image data"))
+        }
+        let result = match image::ImageFormat::from_mime_type(&self.mime_type) {
+            Some(format) => image::load_from_memory_with_format(&self.data, format),
+            None => image::load_from_memory(&self.data),
+        };
+        result.map_err(|err| match err {
+            image::ImageError::Unsupported(..) => {
+                Error::Unsupported("picture has an image format that is not supported")
+            }
+            image::ImageError::IoError(err) => Error::IoError(err),
+            _ => Error::FormatError("picture data is not a valid image"),
+        })
+    }
+}
+
+/// Identifying metadata of a stream, for matching and deduplication.
+///
+/// The identifiers are taken from the Vorbis comments with the conventional
+/// names, and from the streaminfo. If a tag occurs more than once, the first
+/// occurrence is used. See `FlacReader::identifiers()`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Identifiers<'a> {
+    /// The International Standard Recording Code, from the `ISRC` tag.
+    pub isrc: Option<&'a str>,
+    /// The MusicBrainz recording ID, from the `MUSICBRAINZ_TRACKID` tag.
+    pub musicbrainz_track_id: Option<&'a str>,
+    /// The MusicBrainz track ID, from the `MUSICBRAINZ_RELEASETRACKID` tag.
+    pub musicbrainz_release_track_id: Option<&'a str>,
+    /// The MusicBrainz release ID, from the `MUSICBRAINZ_ALBUMID` tag.
+    pub musicbrainz_album_id: Option<&'a str>,
+    /// The MusicBrainz artist ID, from the `MUSICBRAINZ_ARTISTID` tag.
+    pub musicbrainz_artist_id: Option<&'a str>,
+    /// The MusicBrainz release group ID, from the `MUSICBRAINZ_RELEASEGROUPID` tag.
+    pub musicbrainz_release_group_id: Option<&'a str>,
+    /// The MD5 signature of the audio data from the streaminfo.
+    ///
+    /// This is `None` if the signature is all zeros, which indicates that the
+    /// encoder did not compute it.
+    pub md5sum: Option<[u8; 16]>,
+}
+
+/// Loudness normalization gains of a stream, see `FlacReader::gain()`.
+///
+/// The classic ReplayGain tags store a gain in dB relative to the ReplayGain
+/// reference level, and a peak as a fraction of full scale. The EBU R128
+/// tags store a gain in dB relative to -23 LUFS. If a tag occurs more than
+/// once, the first occurrence is used, and a tag that cannot be parsed is
+/// treated as missing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gain {
+    /// The track gain in dB, from the `REPLAYGAIN_TRACK_GAIN` tag.
+    pub track_gain: Option<f32>,
+    /// The track peak, from the `REPLAYGAIN_TRACK_PEAK` tag.
+    pub track_peak: Option<f32>,
+    /// The album gain in dB, from the `REPLAYGAIN_ALBUM_GAIN` tag.
+    pub album_gain: Option<f32>,
+    /// The album peak, from the `REPLAYGAIN_ALBUM_PEAK` tag.
+    pub album_peak: Option<f32>,
+    /// The track gain in dB relative to -23 LUFS, from the `R128_TRACK_GAIN` tag.
+    pub r128_track_gain: Option<f32>,
+    /// The album gain in dB relative to -23 LUFS, from the `R128_ALBUM_GAIN` tag.
+    pub r128_album_gain: Option<f32>,
+}
+
+/// Parses the value of a ReplayGain gain or peak tag, such as `-7.03 dB` or `0.988`.
+///
+/// The unit `dB` after the number is optional, and its casing is ignored.
+pub fn parse_replay_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let unit = value.bytes().rev().take(2).map(|b| b | 0x20).collect::<Vec<u8>>();
+    let number = if unit == b"bd" { &value[..value.len() - 2] } else { value };
+    // Accept a leading plus sign, which the Rust parser does not.
+    let number = number.trim();
+    let number = if number.starts_with('+') { &number[1..] } else { number };
+    number.parse::<f32>().ok().and_then(|x| if x.is_finite() { Some(x) } else { None })
+}
+
+/// Parses the value of an EBU R128 gain tag, such as `-2048`, into dB.
+///
+/// The value is a 16-bit signed integer in Q7.8 fixed point, the number of
+/// 1/256 dB, so `-2048` is -8 dB.
+pub fn parse_r128_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = if value.starts_with('+') { &value[1..] } else { value };
+    value.parse::<i16>().ok().map(|q| q as f32 / 256.0)
+}
+
+#[test]
+fn verify_parse_gain() {
+    assert_eq!(parse_replay_gain("-7.03 dB"), Some(-7.03));
+    assert_eq!(parse_replay_gain("+1.50 dB"), Some(1.5));
+    assert_eq!(parse_replay_gain("2 DB"), Some(2.0));
+    assert_eq!(parse_replay_gain("0.988"), Some(0.988));
+    assert_eq!(parse_replay_gain("-3.5dB"), Some(-3.5));
+    assert_eq!(parse_replay_gain("dB"), None);
+    assert_eq!(parse_replay_gain("loud"), None);
+    assert_eq!(parse_replay_gain("inf dB"), None);
+
+    assert_eq!(parse_r128_gain("-2048"), Some(-8.0));
+    assert_eq!(parse_r128_gain("+384"), Some(1.5));
+    assert_eq!(parse_r128_gain("0"), Some(0.0));
+    assert_eq!(parse_r128_gain("-32768"), Some(-128.0));
+    assert_eq!(parse_r128_gain("32768"), None);
+    assert_eq!(parse_r128_gain("-8.0"), None);
+}
+
+/// Parses the value of a position tag, such as `3/12` for track 3 of 12, or `03`.
+///
+/// This is the format of the `TRACKNUMBER` and `DISCNUMBER` tags. The total
+/// after the slash is optional. Whitespace around the numbers and leading
+/// zeros are ignored. If either number is not a non-negative integer, this
+/// returns `None`.
+pub fn parse_position(value: &str) -> Option<(u32, Option<u32>)> {
+    fn parse_number(number: &str) -> Option<u32> {
+        let number = number.trim();
+        // The Rust parser accepts a leading plus sign, which is not a digit.
+        if number.is_empty() || !number.bytes().all(|b| b >= b'0' && b <= b'9') {
+            return None
+        }
+        number.parse().ok()
+    }
+
+    let mut parts = value.splitn(2, '/');
+    let number = match parts.next().and_then(parse_number) {
+        Some(n) => n,
+        None => return None,
+    };
+    match parts.next() {
+        None => Some((number, None)),
+        Some(total) if total.trim().is_empty() => Some((number, None)),
+        Some(total) => parse_number(total).map(|t| (number, Some(t))),
+    }
+}
+
+#[test]
+fn verify_parse_position() {
+    assert_eq!(parse_position("3/12"), Some((3, Some(12))));
+    assert_eq!(parse_position("03"), Some((3, None)));
+    assert_eq!(parse_position(" 01 / 02 "), Some((1, Some(2))));
+    assert_eq!(parse_position("7/"), Some((7, None)));
+    assert_eq!(parse_position("0"), Some((0, None)));
+    assert_eq!(parse_position("three"), None);
+    assert_eq!(parse_position("3/twelve"), None);
+    assert_eq!(parse_position("/12"), None);
+    assert_eq!(parse_position("+3"), None);
+    assert_eq!(parse_position("-3"), None);
+    assert_eq!(parse_position("3.5"), None);
+    assert_eq!(parse_position(""), None);
+}
+
+/// Clues about how a stream was encoded, see `FlacReader::encoder_hints()`.
+///
+/// These are useful to re-encode a stream with matching settings. Most
+/// encoders record only their name and version in the vendor string, so
+/// expect the settings to be missing for many streams.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EncoderHints<'a> {
+    /// The version of libFLAC, such as `1.3.2`, taken from the vendor string.
+    ///
+    /// This is `None` if the stream was not encoded with libFLAC.
+    pub libflac_version: Option<&'a str>,
+    /// The encoding application, from the `ENCODER` tag.
+    pub encoder: Option<&'a str>,
+    /// The encoder settings, from the `ENCODER_SETTINGS` tag, or if there is
+    /// none, from the `ENCODER_OPTIONS` tag.
+    pub settings: Option<&'a str>,
+}
+
+/// Extracts the libFLAC version from a vendor string.
+///
+/// The reference encoder writes a vendor string such as
+/// `reference libFLAC 1.3.2 20170101`, from which this returns `1.3.2`.
+pub fn parse_libflac_version(vendor: &str) -> Option<&str> {
+    let prefix = "libFLAC ";
+    let start = match vendor.find(prefix) {
+        Some(i) => i + prefix.len(),
+        None => return None,
+    };
+    let rest = &vendor[start..];
+    let len = rest.find(|c: char| !(c.is_digit(10) || c == '.')).unwrap_or(rest.len());
+    let version = rest[..len].trim_right_matches('.');
+    if version.is_empty() { None } else { Some(version) }
+}
+
+#[test]
+fn verify_parse_libflac_version() {
+    assert_eq!(parse_libflac_version("reference libFLAC 1.3.2 20170101"), Some("1.3.2"));
+    assert_eq!(parse_libflac_version("reference libFLAC 1.2.1 20070917"), Some("1.2.1"));
+    assert_eq!(parse_libflac_version("libFLAC 1.4.3"), Some("1.4.3"));
+    assert_eq!(parse_libflac_version("libFLAC 1.3."), Some("1.3"));
+    assert_eq!(parse_libflac_version("libFLAC git-abc"), None);
+    assert_eq!(parse_libflac_version("Lavf57.25.100"), None);
+    assert_eq!(parse_libflac_version(""), None);
+}
+
+/// A set of speaker positions, in the bit layout of WAVEFORMATEXTENSIBLE.
+///
+/// Channels are stored in the order of the bits, from least significant to
+/// most significant. For example, 5.1 is front left, front right, front
+/// center, low frequency, back left, and back right: 0x3f.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChannelMask(pub u32);
+
+/// The front left speaker.
+pub const SPEAKER_FRONT_LEFT: u32 = 0x1;
+/// The front right speaker.
+pub const SPEAKER_FRONT_RIGHT: u32 = 0x2;
+/// The front center speaker.
+pub const SPEAKER_FRONT_CENTER: u32 = 0x4;
+/// The low frequency effects speaker.
+pub const SPEAKER_LOW_FREQUENCY: u32 = 0x8;
+/// The back left speaker.
+pub const SPEAKER_BACK_LEFT: u32 = 0x10;
+/// The back right speaker.
+pub const SPEAKER_BACK_RIGHT: u32 = 0x20;
+/// The back center speaker.
+pub const SPEAKER_BACK_CENTER: u32 = 0x100;
+/// The side left speaker.
+pub const SPEAKER_SIDE_LEFT: u32 = 0x200;
+/// The side right speaker.
+pub const SPEAKER_SIDE_RIGHT: u32 = 0x400;
+
+impl ChannelMask {
+    /// Returns the channel layout that the FLAC format prescribes for `channels` channels.
+    ///
+    /// Returns `None` if the number of channels is not between 1 and 8.
+    pub fn default_for_channels(channels: u32) -> Option<ChannelMask> {
+        let mask = match channels {
+            1 => SPEAKER_FRONT_CENTER,
+            2 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT,
+            3 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER,
+            4 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT,
+            5 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER |
+                 SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT,
+            6 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER |
+                 SPEAKER_LOW_FREQUENCY | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT,
+            7 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER |
+                 SPEAKER_LOW_FREQUENCY | SPEAKER_BACK_CENTER | SPEAKER_SIDE_LEFT |
+                 SPEAKER_SIDE_RIGHT,
+            8 => SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT | SPEAKER_FRONT_CENTER |
+                 SPEAKER_LOW_FREQUENCY | SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT |
+                 SPEAKER_SIDE_LEFT | SPEAKER_SIDE_RIGHT,
+            _ => return None,
+        };
+        Some(ChannelMask(mask))
+    }
+
+    /// Parses the value of a `WAVEFORMATEXTENSIBLE_CHANNEL_MASK` tag.
+    ///
+    /// The value is a hexadecimal number with a `0x` prefix, such as
+    /// `0x0000003f`. Returns `None` if the value is not of that form.
+    pub fn from_tag_value(value: &str) -> Option<ChannelMask> {
+        let digits = if value.starts_with("0x") || value.starts_with("0X") {
+            &value[2..]
+        } else {
+            return None
+        };
+        // Unlike `from_str_radix`, do not accept a sign.
+        if digits.is_empty() || !digits.bytes().all(|b| (b as char).is_digit(16)) {
+            return None
+        }
+        u32::from_str_radix(digits, 16).ok().map(ChannelMask)
+    }
+
+    /// Returns the number of speaker positions in the mask.
+    pub fn channels(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Returns whether the mask includes `speaker`, one of the `SPEAKER_*` constants.
+    pub fn contains(&self, speaker: u32) -> bool {
+        self.0 & speaker == speaker
+    }
+}
+
+#[test]
+fn verify_channel_mask_from_tag_value() {
+    let mask = ChannelMask::from_tag_value("0x3f").unwrap();
+    assert_eq!(mask, ChannelMask::default_for_channels(6).unwrap());
+    assert_eq!(mask.channels(), 6);
+    assert!(mask.contains(SPEAKER_LOW_FREQUENCY));
+    assert!(!mask.contains(SPEAKER_SIDE_LEFT));
+
+    assert_eq!(ChannelMask::from_tag_value("0x00000033"), Some(ChannelMask(0x33)));
+    assert_eq!(ChannelMask::from_tag_value("0X633"), Some(ChannelMask(0x633)));
+    assert_eq!(ChannelMask::from_tag_value("63"), None);
+    assert_eq!(ChannelMask::from_tag_value("0x"), None);
+    assert_eq!(ChannelMask::from_tag_value("0x+3f"), None);
+    assert_eq!(ChannelMask::from_tag_value("0x100000000"), None);
+
+    for channels in 1..9 {
+        assert_eq!(ChannelMask::default_for_channels(channels).unwrap().channels(), channels);
+    }
+    assert_eq!(ChannelMask::default_for_channels(9), None);
+}
+
+/// Lyrics stored in a Vorbis comment, see `FlacReader::lyrics()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Lyrics<'a> {
+    /// Synced lyrics in LRC format, as lines with the time at which they start.
+    ///
+    /// The lines are ordered by time, and their text has surrounding
+    /// whitespace removed.
+    Synced(Vec<(Duration, String)>),
+    /// Lyrics without timing information, as the tag value is stored.
+    Unsynced(&'a str),
+}
+
+impl<'a> Lyrics<'a> {
+    /// Interprets the value of a lyrics tag.
+    ///
+    /// If any line starts with an LRC time tag such as `[00:12.34]`, the value
+    /// is parsed as LRC. A line may start with several time tags when it is
+    /// sung more than once, and then it occurs once for every time tag. Lines
+    /// without a time tag, such as LRC ID tags like `[ar:Artist]`, are skipped.
+    /// A value without time tags is returned as unsynced lyrics.
+    pub fn from_tag_value(value: &'a str) -> Lyrics<'a> {
+        let mut lines = Vec::new();
+        for line in value.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+            while rest.starts_with('[') {
+                let time = match rest.find(']') {
+                    Some(end) => parse_lrc_time(&rest[1..end]).map(|t| (t, end)),
+                    None => None,
+                };
+                match time {
+                    Some((t, end)) => {
+                        times.push(t);
+                        rest = &rest[end + 1..];
+                    }
+                    None => break,
+                }
+            }
+            for t in times {
+                lines.push((t, rest.trim().to_string()));
+            }
+        }
+
+        if lines.is_empty() {
+            Lyrics::Unsynced(value)
+        } else {
+            // The sort is stable, so lines with the same time keep their order.
+            lines.sort_by_key(|line| line.0);
+            Lyrics::Synced(lines)
+        }
+    }
+}
+
+/// Parses the contents of an LRC time tag, such as `01:23.45`.
+///
+/// The minutes can have any number of digits, the seconds must have two, and
+/// the fraction of a second is optional, with up to three digits.
+fn parse_lrc_time(tag: &str) -> Option<Duration> {
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b >= b'0' && b <= b'9');
+
+    let (minutes, rest) = match tag.find(':') {
+        Some(i) => (&tag[..i], &tag[i + 1..]),
+        None => return None,
+    };
+    let (seconds, fraction) = match rest.find('.') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, "0"),
+    };
+    if !is_digits(minutes) || seconds.len() != 2 || !is_digits(seconds) ||
+       fraction.len() > 3 || !is_digits(fraction) {
+        return None
+    }
+
+    let seconds: u64 = seconds.parse().unwrap();
+    if seconds >= 60 {
+        return None
+    }
+    let scale = [100_000_000, 10_000_000, 1_000_000][fraction.len() - 1];
+    let nanos = fraction.parse::<u32>().unwrap() * scale;
+    minutes.parse::<u64>()
+        .ok()
+        .and_then(|m| m.checked_mul(60))
+        .map(|s| Duration::new(s + seconds, nanos))
+}
+
+#[test]
+fn verify_lyrics_from_tag_value() {
+    let lrc = "[ar:Claxon]\r\n[00:12.34]First line\r\n\r\n[01:05]Second line\r\n\
+               [00:30.5][02:00.123] Chorus \r\n";
+    let expected = vec![
+        (Duration::new(12, 340_000_000), "First line".to_string()),
+        (Duration::new(30, 500_000_000), "Chorus".to_string()),
+        (Duration::new(65, 0), "Second line".to_string()),
+        (Duration::new(120, 123_000_000), "Chorus".to_string()),
+    ];
+    assert_eq!(Lyrics::from_tag_value(lrc), Lyrics::Synced(expected));
+
+    let plain = "First line\nSecond line [with brackets]\n[not a time]";
+    assert_eq!(Lyrics::from_tag_value(plain), Lyrics::Unsynced(plain));
+
+    for tag in &["00:60.00", "0:1.00", ":12.00", "00:12.3456", "00:12.", "+1:00"] {
+        assert_eq!(parse_lrc_time(tag), None);
+    }
+    assert_eq!(parse_lrc_time("123:45"), Some(Duration::new(123 * 60 + 45, 0)));
+}
+
 /// A metadata about the flac stream.
 pub enum MetadataBlock {
     /// A stream info block.
@@ -121,9 +780,9 @@ pub enum MetadataBlock {
     /// A Vorbis comment block, also known as FLAC tags.
     VorbisComment(VorbisComment),
     /// A CUE sheet block.
-    CueSheet, // TODO
+    CueSheet(CueSheet),
     /// A picture block.
-    Picture, // TODO
+    Picture(Picture),
     /// A block with a reserved block type, not supported by this library.
     Reserved,
 }
@@ -205,6 +864,184 @@ impl<'a> Iterator for GetTag<'a> {
     }
 }
 
+/// A borrowed view of a Vorbis comment block, that does not copy the tags.
+///
+/// `VorbisComment` allocates a string for the vendor string and for every
+/// comment. When scanning the tags of many files, it is cheaper to read the
+/// raw block into a buffer that is reused (see `read_vorbis_comment_into()`),
+/// and to look up the tags in place. The block is validated once, by
+/// `parse()`, with the same checks as when reading a `VorbisComment`. After
+/// that, lookups return `&str` slices into the buffer.
+pub struct VorbisCommentRef<'a> {
+    /// The vendor string.
+    vendor: &'a str,
+    /// The comments, every one prefixed with its 32-bit little-endian length.
+    comments: &'a [u8],
+}
+
+/// Reads a 32-bit little-endian integer from `bytes` at `pos`.
+#[inline]
+fn read_le_u32_at(bytes: &[u8], pos: usize) -> u32 {
+    (bytes[pos] as u32) | (bytes[pos + 1] as u32) << 8 |
+    (bytes[pos + 2] as u32) << 16 | (bytes[pos + 3] as u32) << 24
+}
+
+/// Interprets the bytes as UTF-8, with the same error as `VorbisComment`.
+#[inline]
+fn vorbis_comment_str(bytes: &[u8]) -> Result<&str> {
+    str::from_utf8(bytes).or_else(|_| fmt_err("Vorbis comment or vendor string is not valid UTF-8"))
+}
+
+impl<'a> VorbisCommentRef<'a> {
+    /// Validates the raw contents of a Vorbis comment block.
+    ///
+    /// The bytes are the block without its metadata block header. Invalid
+    /// blocks are rejected with the same errors as by `read_metadata_block()`.
+    pub fn parse(bytes: &'a [u8]) -> Result<VorbisCommentRef<'a>> {
+        if bytes.len() < 8 {
+            return fmt_err("Vorbis comment block is too short")
+        }
+        if bytes.len() > 10 * 1024 * 1024 {
+            let msg = "Vorbis comment blocks larger than 10 MiB are not supported";
+            return Err(Error::Unsupported(msg))
+        }
+
+        let vendor_len = read_le_u32_at(bytes, 0) as usize;
+        if vendor_len > bytes.len() - 8 { return fmt_err("vendor string too long") }
+        let vendor = try!(vorbis_comment_str(&bytes[4..4 + vendor_len]));
+
+        let comments_len = read_le_u32_at(bytes, 4 + vendor_len) as usize;
+        let comments = &bytes[8 + vendor_len..];
+        if comments_len > comments.len() / 4 {
+            return fmt_err("too many entries for Vorbis comment block")
+        }
+
+        // Walk the comments in the same way as `read_vorbis_comment_block()`,
+        // so trailing bytes too short for a length prefix are ignored too.
+        let mut pos = 0;
+        let mut n_comments = 0;
+        while comments.len() - pos >= 4 {
+            let comment_len = read_le_u32_at(comments, pos) as usize;
+            pos += 4;
+
+            if comment_len > comments.len() - pos {
+                return fmt_err("Vorbis comment too long for Vorbis comment block")
+            }
+            let comment = &comments[pos..pos + comment_len];
+            pos += comment_len;
+
+            if let Some(sep_index) = comment.iter().position(|&x| x == b'=') {
+                // The name must consist of ascii bytes 0x20 through 0x7d, so
+                // only the value can contain invalid UTF-8.
+                if comment[..sep_index].iter().any(|&x| x < 0x20 || x > 0x7d) {
+                    return fmt_err("Vorbis comment field name contains invalid byte")
+                }
+                try!(vorbis_comment_str(&comment[sep_index + 1..]));
+            } else {
+                return fmt_err("Vorbis comment does not contain '='")
+            }
+            n_comments += 1;
+        }
+
+        if n_comments != comments_len {
+            return fmt_err("Vorbis comment block contains wrong number of entries")
+        }
+
+        let vorbis_comment = VorbisCommentRef {
+            vendor: vendor,
+            comments: comments,
+        };
+
+        Ok(vorbis_comment)
+    }
+
+    /// Returns the vendor string, see `VorbisComment::vendor`.
+    #[inline]
+    pub fn vendor(&self) -> &'a str {
+        self.vendor
+    }
+
+    /// Returns name-value pairs of Vorbis comments, like `FlacReader::tags()`.
+    #[inline]
+    pub fn tags(&self) -> TagsRef<'a> {
+        TagsRef {
+            comments: self.comments,
+            pos: 0,
+        }
+    }
+
+    /// Look up a Vorbis comment, like `FlacReader::get_tag()`.
+    ///
+    /// The tag name is compared case-insensitively.
+    #[inline]
+    pub fn get_tag(&self, tag_name: &'a str) -> GetTagRef<'a> {
+        GetTagRef {
+            tags: self.tags(),
+            needle: tag_name,
+        }
+    }
+}
+
+/// Iterates over the Vorbis comments in a `VorbisCommentRef`.
+///
+/// See `VorbisCommentRef::tags()` for more details.
+pub struct TagsRef<'a> {
+    /// The comments, every one prefixed with its length.
+    comments: &'a [u8],
+    /// The offset of the length prefix of the next comment.
+    pos: usize,
+}
+
+impl<'a> Iterator for TagsRef<'a> {
+    type Item = (&'a str, &'a str);
+
+    #[inline]
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        if self.comments.len() - self.pos < 4 {
+            return None
+        }
+        let comment_len = read_le_u32_at(self.comments, self.pos) as usize;
+        let comment = &self.comments[self.pos + 4..self.pos + 4 + comment_len];
+        self.pos += 4 + comment_len;
+
+        // `VorbisCommentRef::parse()` verified that every comment contains a
+        // separator, that the name is ascii, and that the value is valid UTF-8.
+        let sep_index = comment.iter().position(|&x| x == b'=').unwrap();
+        let name = str::from_utf8(&comment[..sep_index]).expect("comment name was validated");
+        let value = str::from_utf8(&comment[sep_index + 1..]).expect("comment value was validated");
+        Some((name, value))
+    }
+}
+
+/// Iterates over the values of a specific Vorbis comment in a `VorbisCommentRef`.
+///
+/// See `VorbisCommentRef::get_tag()` for more details.
+pub struct GetTagRef<'a> {
+    /// The Vorbis comments to search through.
+    tags: TagsRef<'a>,
+    /// The tag to look for.
+    needle: &'a str,
+}
+
+impl<'a> Iterator for GetTagRef<'a> {
+    type Item = &'a str;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a str> {
+        // This import is actually required on Rust 1.13.
+        #[allow(unused_imports)]
+        use std::ascii::AsciiExt;
+
+        for (name, value) in &mut self.tags {
+            if name.eq_ignore_ascii_case(self.needle) {
+                return Some(value)
+            }
+        }
+
+        None
+    }
+}
+
 #[inline]
 fn read_metadata_block_header<R: ReadBytes>(input: &mut R) -> Result<MetadataBlockHeader> {
     let byte = try!(input.read_u8());
@@ -258,7 +1095,7 @@ pub fn read_metadata_block<R: ReadBytes>(input: &mut R,
                                          length: u32)
                                          -> Result<MetadataBlock> {
     match block_type {
-        0 => {
+        BLOCK_TYPE_STREAMINFO => {
             // The streaminfo block has a fixed size of 34 bytes.
             if length == 34 {
                 let streaminfo = try!(read_streaminfo_block(input));
@@ -267,37 +1104,34 @@ pub fn read_metadata_block<R: ReadBytes>(input: &mut R,
                 fmt_err("invalid streaminfo metadata block length")
             }
         }
-        1 => {
+        BLOCK_TYPE_PADDING => {
             try!(read_padding_block(input, length));
             Ok(MetadataBlock::Padding { length: length })
         }
-        2 => {
+        BLOCK_TYPE_APPLICATION => {
             let (id, data) = try!(read_application_block(input, length));
             Ok(MetadataBlock::Application {
                 id: id,
                 data: data,
             })
         }
-        3 => {
-            // TODO: implement seektable reading. For now, pretend it is padding.
-            try!(input.skip(length));
-            Ok(MetadataBlock::Padding { length: length })
+        BLOCK_TYPE_SEEKTABLE => {
+            let seektable = try!(read_seektable_block(input, length));
+            Ok(MetadataBlock::SeekTable(seektable))
         }
-        4 => {
+        BLOCK_TYPE_VORBIS_COMMENT => {
             let vorbis_comment = try!(read_vorbis_comment_block(input, length));
             Ok(MetadataBlock::VorbisComment(vorbis_comment))
         }
-        5 => {
-            // TODO: implement CUE sheet reading. For now, pretend it is padding.
-            try!(input.skip(length));
-            Ok(MetadataBlock::Padding { length: length })
+        BLOCK_TYPE_CUESHEET => {
+            let cuesheet = try!(read_cuesheet_block(input, length));
+            Ok(MetadataBlock::CueSheet(cuesheet))
         }
-        6 => {
-            // TODO: implement picture reading. For now, pretend it is padding.
-            try!(input.skip(length));
-            Ok(MetadataBlock::Padding { length: length })
+        BLOCK_TYPE_PICTURE => {
+            let picture = try!(read_picture_block(input, length));
+            Ok(MetadataBlock::Picture(picture))
         }
-        127 => {
+        BLOCK_TYPE_INVALID => {
             // This code is invalid to avoid confusion with a frame sync code.
             fmt_err("invalid metadata block type")
         }
@@ -435,16 +1269,16 @@ fn read_vorbis_comment_block<R: ReadBytes>(input: &mut R, length: u32) -> Result
 
     // Next up is the number of comments. Because every comment is at least 4
     // bytes to indicate its length, there cannot be more comments than the
-    // length of the block divided by 4. This is only an upper bound to ensure
-    // that we don't allocate a big vector, to protect against DoS attacks.
+    // remaining length of the block divided by 4. This is only an upper bound
+    // to ensure that we don't allocate a big vector, to protect against DoS
+    // attacks.
     let comments_len = try!(input.read_le_u32());
-    if comments_len >= length / 4 {
+    let mut bytes_left = length - 8 - vendor_len;
+    if comments_len > bytes_left / 4 {
         return fmt_err("too many entries for Vorbis comment block")
     }
     let mut comments = Vec::with_capacity(comments_len as usize);
 
-    let mut bytes_left = length - 8 - vendor_len;
-
     // For every comment, there is a length-prefixed string of the form
     // "NAME=value".
     while bytes_left >= 4 {
@@ -494,6 +1328,195 @@ fn read_vorbis_comment_block<R: ReadBytes>(input: &mut R, length: u32) -> Result
     Ok(vorbis_comment)
 }
 
+#[test]
+fn verify_read_vorbis_comment_block_rejects_bogus_comment_count() {
+    use std::io;
+
+    // A 40-byte block with an empty vendor string, that claims 10^9 comments.
+    let mut data = vec![0u8, 0, 0, 0, 0x00, 0xca, 0x9a, 0x3b];
+    data.extend_from_slice(&[0u8; 32]);
+    assert_eq!(read_vorbis_comment_block(&mut io::Cursor::new(&data[..]), 40).err(),
+               Some(Error::FormatError("too many entries for Vorbis comment block")));
+
+    // The vendor string leaves room for one comment length, not two.
+    let mut data = vec![28u8, 0, 0, 0];
+    data.extend_from_slice(&[b'x'; 28]);
+    data.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(read_vorbis_comment_block(&mut io::Cursor::new(&data[..]), 40).err(),
+               Some(Error::FormatError("too many entries for Vorbis comment block")));
+
+    // With one comment, the same block is valid, though the comment is empty.
+    data[32] = 1;
+    assert_eq!(read_vorbis_comment_block(&mut io::Cursor::new(&data[..]), 40).err(),
+               Some(Error::FormatError("Vorbis comment does not contain '='")));
+}
+
+/// Reads the raw contents of the Vorbis comment block into a buffer.
+///
+/// It is assumed that the next byte that the reader will read, is the first
+/// byte of a metadata block header, as for `MetadataBlockReader`. Other blocks
+/// are skipped. Returns whether a Vorbis comment block was found before the
+/// last metadata block; if not, the buffer is left empty. The buffer can be
+/// reused for many streams, so it does not need to be reallocated once it is
+/// large enough. Use `VorbisCommentRef::parse()` to look up the tags.
+pub fn read_vorbis_comment_block_into<R: ReadBytes>(input: &mut R,
+                                                    buffer: &mut Vec<u8>)
+                                                    -> Result<bool> {
+    buffer.clear();
+    loop {
+        let header = try!(read_metadata_block_header(input));
+        if header.block_type == BLOCK_TYPE_VORBIS_COMMENT {
+            // The same limit applies as in `read_vorbis_comment_block()`.
+            if header.length > 10 * 1024 * 1024 {
+                let msg = "Vorbis comment blocks larger than 10 MiB are not supported";
+                return Err(Error::Unsupported(msg))
+            }
+            buffer.resize(header.length as usize, 0);
+            try!(input.read_into(buffer));
+            return Ok(true)
+        }
+        try!(input.skip(header.length));
+        if header.is_last {
+            return Ok(false)
+        }
+    }
+}
+
+#[test]
+fn verify_vorbis_comment_ref_matches_vorbis_comment() {
+    use std::io;
+
+    let mut data = vec![3u8, 0, 0, 0, b'a', b'b', b'c', 3, 0, 0, 0];
+    for comment in &["TITLE=Fl\u{e4}che", "Artist=A", "artist=B=C"] {
+        data.extend_from_slice(&[comment.len() as u8, 0, 0, 0]);
+        data.extend_from_slice(comment.as_bytes());
+    }
+    let owned = read_vorbis_comment_block(&mut io::Cursor::new(&data[..]), data.len() as u32).unwrap();
+    let borrowed = VorbisCommentRef::parse(&data).unwrap();
+
+    assert_eq!(borrowed.vendor(), &owned.vendor[..]);
+    assert_eq!(borrowed.tags().collect::<Vec<_>>(),
+               Tags::new(&owned.comments).collect::<Vec<_>>());
+    assert_eq!(borrowed.get_tag("ARTIST").collect::<Vec<_>>(), vec!["A", "B=C"]);
+    assert_eq!(borrowed.get_tag("title").next(), Some("Fl\u{e4}che"));
+    assert_eq!(borrowed.get_tag("album").next(), None);
+}
+
+#[test]
+fn verify_vorbis_comment_ref_rejects_invalid_blocks() {
+    use std::io;
+
+    let blocks: Vec<Vec<u8>> = vec![
+        vec![0, 0, 0, 0, 0, 0, 0],
+        vec![1, 0, 0, 0, 0, 0, 0, 0],
+        vec![1, 0, 0, 0, 0xff, 0, 0, 0, 0],
+        vec![0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0],
+        vec![0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, b'a'],
+        vec![0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, b'a'],
+        vec![0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 0x7e, b'='],
+        vec![0, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, b'a', b'=', 0xc3],
+        vec![0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, b'a', b'='],
+    ];
+    for data in &blocks {
+        let expected = read_vorbis_comment_block(&mut io::Cursor::new(&data[..]), data.len() as u32).err();
+        assert!(expected.is_some());
+        assert_eq!(VorbisCommentRef::parse(data).err(), expected);
+    }
+}
+
+/// Reads a fixed-size, NUL-padded ASCII string, as used in the CUE sheet.
+fn read_padded_ascii<R: ReadBytes>(input: &mut R, buffer: &mut [u8]) -> Result<String> {
+    try!(input.read_into(buffer));
+    let len = buffer.iter().position(|&x| x == 0).unwrap_or(buffer.len());
+    if buffer[..len].iter().any(|&x| x < 0x20 || x > 0x7e) {
+        return fmt_err("CUE sheet string contains invalid byte")
+    }
+    // All bytes are printable ASCII, so the conversion cannot fail.
+    Ok(String::from_utf8(buffer[..len].to_vec()).unwrap())
+}
+
+fn read_cuesheet_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<CueSheet> {
+    // The fixed part consists of a 128-byte catalog number, the 64-bit number
+    // of lead-in samples, 259 bytes of flags and reserved bits, and the 8-bit
+    // number of tracks.
+    if length < 396 {
+        return fmt_err("CUE sheet block is too short")
+    }
+
+    let mut catalog_bytes = [0u8; 128];
+    let catalog_number = try!(read_padded_ascii(input, &mut catalog_bytes));
+    let lead_in = try!(input.read_be_u64());
+    let is_cd = (try!(input.read_u8()) >> 7) == 1;
+    try!(input.skip(258));
+    let n_tracks = try!(input.read_u8());
+
+    // Every track takes at least 36 bytes, so the track count is bounded by the
+    // block length; it is at most 255 anyway, so allocating is fine.
+    let mut bytes_left = length - 396;
+    let mut tracks = Vec::with_capacity(n_tracks as usize);
+
+    for _ in 0..n_tracks {
+        if bytes_left < 36 {
+            return fmt_err("CUE sheet track does not fit in CUE sheet block")
+        }
+        bytes_left -= 36;
+
+        let offset = try!(input.read_be_u64());
+        let number = try!(input.read_u8());
+        let mut isrc_bytes = [0u8; 12];
+        let isrc = try!(read_padded_ascii(input, &mut isrc_bytes));
+
+        // The first bit is the track type (0 for audio), the second bit
+        // indicates pre-emphasis. Then follow 6 + 13 * 8 reserved bits.
+        let flags = try!(input.read_u8());
+        try!(input.skip(13));
+        let n_indices = try!(input.read_u8());
+
+        if number == 0 {
+            return fmt_err("invalid CUE sheet track number 0")
+        }
+
+        if n_indices as u32 * 12 > bytes_left {
+            return fmt_err("CUE sheet index points do not fit in CUE sheet block")
+        }
+        bytes_left -= n_indices as u32 * 12;
+
+        let mut indices = Vec::with_capacity(n_indices as usize);
+        for _ in 0..n_indices {
+            let index_offset = try!(input.read_be_u64());
+            let index_number = try!(input.read_u8());
+            // Three reserved bytes follow.
+            try!(input.skip(3));
+            indices.push(CueSheetIndex {
+                offset: index_offset,
+                number: index_number,
+            });
+        }
+
+        tracks.push(CueSheetTrack {
+            offset: offset,
+            number: number,
+            isrc: isrc,
+            is_audio: (flags >> 7) == 0,
+            pre_emphasis: (flags >> 6) & 1 == 1,
+            indices: indices,
+        });
+    }
+
+    if bytes_left != 0 {
+        return fmt_err("CUE sheet block length does not match its contents")
+    }
+
+    let cuesheet = CueSheet {
+        catalog_number: catalog_number,
+        lead_in: lead_in,
+        is_cd: is_cd,
+        tracks: tracks,
+    };
+
+    Ok(cuesheet)
+}
+
 fn read_padding_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<()> {
     // The specification dictates that all bits of the padding block must be 0.
     // However, the reference implementation does not issue an error when this
@@ -503,6 +1526,243 @@ fn read_padding_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<()> {
     Ok(try!(input.skip(length)))
 }
 
+fn read_seektable_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<SeekTable> {
+    // The block consists of nothing but 18-byte seek points, so the number of
+    // points follows from the length. The length is a 24-bit number, so the
+    // allocation is bounded, and proportional to the size of the block.
+    if length % 18 != 0 {
+        return fmt_err("seek table block length is not a multiple of the seek point size")
+    }
+
+    let n_points = length / 18;
+    let mut seekpoints = Vec::with_capacity(n_points as usize);
+    for _ in 0..n_points {
+        let sample = try!(input.read_be_u64());
+        let offset = try!(input.read_be_u64());
+        let samples = try!(input.read_be_u16());
+        seekpoints.push(SeekPoint {
+            sample: sample,
+            offset: offset,
+            samples: samples,
+        });
+    }
+
+    Ok(SeekTable { seekpoints: seekpoints })
+}
+
+#[test]
+fn verify_read_seektable_block() {
+    use std::io;
+
+    // A length that is not a multiple of 18 is rejected before reading points.
+    let data = vec![0u8; 40];
+    match read_metadata_block(&mut io::Cursor::new(&data), BLOCK_TYPE_SEEKTABLE, 20) {
+        Err(Error::FormatError(..)) => {}
+        _ => panic!("expected format error for seek table length 20"),
+    }
+
+    // A large but consistent seek table, with a placeholder at the end.
+    let n_points = 50_000;
+    let mut data = Vec::with_capacity(n_points * 18);
+    for i in 0..n_points as u64 {
+        let sample = if i + 1 == n_points as u64 { 0xffff_ffff_ffff_ffff } else { i * 4096 };
+        for k in 0..8 {
+            data.push((sample >> (56 - 8 * k)) as u8);
+        }
+        for k in 0..8 {
+            data.push(((i * 100) >> (56 - 8 * k)) as u8);
+        }
+        data.push(0x10);
+        data.push(0x00);
+    }
+    let length = data.len() as u32;
+    match read_metadata_block(&mut io::Cursor::new(&data), BLOCK_TYPE_SEEKTABLE, length) {
+        Ok(MetadataBlock::SeekTable(table)) => {
+            let points = table.seekpoints();
+            assert_eq!(points.len(), n_points);
+            assert_eq!(points[3].sample, 3 * 4096);
+            assert_eq!(points[3].offset, 300);
+            assert_eq!(points[3].samples, 4096);
+            assert_eq!(points[n_points - 1].sample, 0xffff_ffff_ffff_ffff);
+        }
+        _ => panic!("expected seek table"),
+    }
+
+    // A consistent length, but the data ends early.
+    assert!(read_metadata_block(&mut io::Cursor::new(&data[..30]), BLOCK_TYPE_SEEKTABLE, length).is_err());
+}
+
+#[test]
+fn verify_read_picture_block() {
+    use std::io;
+
+    let mut data = vec![0, 0, 0, 3, 0, 0, 0, 9];
+    data.extend_from_slice(b"image/png");
+    data.extend_from_slice(&[0, 0, 0, 5]);
+    data.extend_from_slice(b"Front");
+    data.extend_from_slice(&[0, 0, 1, 0, 0, 0, 0, 200, 0, 0, 0, 24, 0, 0, 0, 0]);
+    data.extend_from_slice(&[0, 0, 0, 3, 0xaa, 0xbb, 0xcc]);
+    let length = data.len() as u32;
+    match read_metadata_block(&mut io::Cursor::new(&data), BLOCK_TYPE_PICTURE, length) {
+        Ok(MetadataBlock::Picture(picture)) => {
+            assert_eq!(picture.picture_type, 3);
+            assert_eq!(picture.mime_type, "image/png");
+            assert_eq!(picture.description, "Front");
+            assert_eq!((picture.width, picture.height), (256, 200));
+            assert_eq!((picture.depth, picture.colors), (24, 0));
+            assert_eq!(picture.data, [0xaa, 0xbb, 0xcc]);
+        }
+        _ => panic!("expected picture"),
+    }
+
+    // The data does not fit in the declared block length.
+    match read_metadata_block(&mut io::Cursor::new(&data), BLOCK_TYPE_PICTURE, length - 1) {
+        Err(Error::FormatError(..)) => {}
+        _ => panic!("expected format error for truncated picture"),
+    }
+
+    // The block is longer than its fields.
+    data.push(0);
+    match read_metadata_block(&mut io::Cursor::new(&data), BLOCK_TYPE_PICTURE, length + 1) {
+        Err(Error::FormatError(..)) => {}
+        _ => panic!("expected format error for trailing bytes"),
+    }
+
+    // A description that is not UTF-8.
+    let mut invalid = data.clone();
+    invalid[21] = 0xff;
+    match read_metadata_block(&mut io::Cursor::new(&invalid), BLOCK_TYPE_PICTURE, length + 1) {
+        Err(Error::FormatError(msg)) => assert_eq!(msg, "picture description is not valid UTF-8"),
+        _ => panic!("expected format error for invalid description"),
+    }
+
+    // A MIME type length past the end of the block.
+    data[7] = 200;
+    match read_metadata_block(&mut io::Cursor::new(&data), BLOCK_TYPE_PICTURE, length) {
+        Err(Error::FormatError(..)) => {}
+        _ => panic!("expected format error for long MIME type"),
+    }
+}
+
+#[test]
+fn verify_read_zero_length_blocks() {
+    use std::io;
+
+    // Trailing bytes must not be consumed by an empty block.
+    let data = [0xaa, 0xbb];
+    let read = |block_type: u8| {
+        let mut input = io::Cursor::new(&data[..]);
+        let result = read_metadata_block(&mut input, block_type, 0);
+        assert!(result.is_err() || input.position() == 0);
+        result
+    };
+
+    match read(BLOCK_TYPE_PADDING) {
+        Ok(MetadataBlock::Padding { length: 0 }) => {}
+        _ => panic!("expected empty padding"),
+    }
+    match read(BLOCK_TYPE_SEEKTABLE) {
+        Ok(MetadataBlock::SeekTable(table)) => assert!(table.seekpoints().is_empty()),
+        _ => panic!("expected empty seek table"),
+    }
+    match read(BLOCK_TYPE_FIRST_RESERVED) {
+        Ok(MetadataBlock::Reserved) => {}
+        _ => panic!("expected reserved block"),
+    }
+
+    // These blocks have mandatory fields, so an empty one is malformed.
+    for &block_type in &[BLOCK_TYPE_STREAMINFO,
+                         BLOCK_TYPE_APPLICATION,
+                         BLOCK_TYPE_VORBIS_COMMENT,
+                         BLOCK_TYPE_CUESHEET,
+                         BLOCK_TYPE_PICTURE] {
+        match read(block_type) {
+            Err(Error::FormatError(..)) => {}
+            _ => panic!("expected format error for empty block of type {}", block_type),
+        }
+    }
+
+    // An empty vendor string and no comments is the smallest valid Vorbis comment block.
+    let data = [0u8; 8];
+    match read_metadata_block(&mut io::Cursor::new(&data[..]), BLOCK_TYPE_VORBIS_COMMENT, 8) {
+        Ok(MetadataBlock::VorbisComment(vc)) => {
+            assert_eq!(vc.vendor, "");
+            assert!(vc.comments.is_empty());
+        }
+        _ => panic!("expected empty Vorbis comment"),
+    }
+
+    // An application block may consist of only its ID.
+    let data = [0x61, 0x62, 0x63, 0x64];
+    match read_metadata_block(&mut io::Cursor::new(&data[..]), BLOCK_TYPE_APPLICATION, 4) {
+        Ok(MetadataBlock::Application { id: 0x6162_6364, data }) => assert!(data.is_empty()),
+        _ => panic!("expected empty application block"),
+    }
+}
+
+/// Reads a length-prefixed field of a picture block, of which `bytes_left` remain.
+fn read_picture_field<R: ReadBytes>(input: &mut R, bytes_left: &mut u32) -> Result<Vec<u8>> {
+    if *bytes_left < 4 {
+        return fmt_err("picture block is too short")
+    }
+    let len = try!(input.read_be_u32());
+    *bytes_left -= 4;
+    // The length is bounded by the block length, so allocating is fine.
+    if len > *bytes_left {
+        return fmt_err("picture field does not fit in picture block")
+    }
+    *bytes_left -= len;
+    let mut bytes = vec![0; len as usize];
+    try!(input.read_into(&mut bytes));
+    Ok(bytes)
+}
+
+fn read_picture_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<Picture> {
+    // The fixed part consists of the 32-bit picture type, six 32-bit lengths
+    // and dimensions, and the 32-bit length of the data.
+    if length < 32 {
+        return fmt_err("picture block is too short")
+    }
+
+    let picture_type = try!(input.read_be_u32());
+    let mut bytes_left = length - 4;
+
+    let mime_bytes = try!(read_picture_field(input, &mut bytes_left));
+    if mime_bytes.iter().any(|&x| x < 0x20 || x > 0x7e) {
+        return fmt_err("picture MIME type contains invalid byte")
+    }
+    // All bytes are printable ASCII, so the conversion cannot fail.
+    let mime_type = String::from_utf8(mime_bytes).unwrap();
+    let description_bytes = try!(read_picture_field(input, &mut bytes_left));
+    let description = try!(String::from_utf8(description_bytes)
+        .or_else(|_| fmt_err("picture description is not valid UTF-8")));
+
+    if bytes_left < 16 {
+        return fmt_err("picture block is too short")
+    }
+    bytes_left -= 16;
+    let width = try!(input.read_be_u32());
+    let height = try!(input.read_be_u32());
+    let depth = try!(input.read_be_u32());
+    let colors = try!(input.read_be_u32());
+    let data = try!(read_picture_field(input, &mut bytes_left));
+
+    if bytes_left != 0 {
+        return fmt_err("picture block has trailing bytes")
+    }
+
+    Ok(Picture {
+        picture_type: picture_type,
+        mime_type: mime_type,
+        description: description,
+        width: width,
+        height: height,
+        depth: depth,
+        colors: colors,
+        data: data,
+    })
+}
+
 fn read_application_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<(u32, Vec<u8>)> {
     if length < 4 {
         return fmt_err("application block length must be at least 4 bytes")
@@ -530,6 +1790,320 @@ fn read_application_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<(u
     Ok((id, data))
 }
 
+/// The application ID under which WAV chunks are stored, "riff".
+pub const APPLICATION_ID_RIFF: u32 = 0x72_69_66_66;
+
+/// The application ID under which AIFF chunks are stored, "aiff".
+pub const APPLICATION_ID_AIFF: u32 = 0x61_69_66_66;
+
+/// The chunks of a WAV or AIFF file that the FLAC stream was encoded from.
+///
+/// When encoding with `flac --keep-foreign-metadata`, every chunk of the
+/// source file is stored in an application block, in the order of the file.
+/// For the chunk that contains the audio (`data` for WAV, `SSND` for AIFF),
+/// only the chunk header is stored. The original file is therefore the header
+/// returned by `header()`, followed by the audio, followed by the trailer
+/// returned by `trailer()`.
+pub struct ForeignMetadata {
+    application_id: u32,
+    bytes: Vec<u8>,
+}
+
+impl ForeignMetadata {
+    /// Creates foreign metadata from the first application block with the chunks.
+    pub fn new(application_id: u32, data: Vec<u8>) -> ForeignMetadata {
+        ForeignMetadata {
+            application_id: application_id,
+            bytes: data,
+        }
+    }
+
+    /// Appends the chunks of a subsequent application block.
+    pub fn push(&mut self, data: &[u8]) {
+        self.bytes.extend_from_slice(data);
+    }
+
+    /// Returns the ID of the application blocks, `APPLICATION_ID_RIFF` or `APPLICATION_ID_AIFF`.
+    pub fn application_id(&self) -> u32 {
+        self.application_id
+    }
+
+    /// Returns the stored chunk bytes, concatenated.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Reads a 32-bit integer at `pos`, in the byte order of the container.
+    fn read_u32(&self, pos: usize) -> u32 {
+        let b = &self.bytes[pos..pos + 4];
+        if self.application_id == APPLICATION_ID_AIFF {
+            (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32
+        } else {
+            (b[3] as u32) << 24 | (b[2] as u32) << 16 | (b[1] as u32) << 8 | b[0] as u32
+        }
+    }
+
+    /// Returns whether the chunks start with a valid WAV or AIFF header.
+    fn has_valid_header(&self) -> bool {
+        let bytes = &self.bytes[..];
+        if bytes.len() < 12 {
+            return false
+        }
+        if self.application_id == APPLICATION_ID_AIFF {
+            &bytes[0..4] == b"FORM" && (&bytes[8..12] == b"AIFF" || &bytes[8..12] == b"AIFC")
+        } else {
+            &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE"
+        }
+    }
+
+    /// Returns the length of the header, and the size of the audio chunk.
+    ///
+    /// Returns `None` if the chunks do not form a valid file, if there is no
+    /// audio chunk, or for an AIFF file where the audio does not directly
+    /// follow the SSND chunk header.
+    fn locate_audio(&self) -> Option<(usize, u32)> {
+        let is_aiff = self.application_id == APPLICATION_ID_AIFF;
+        let bytes = &self.bytes[..];
+
+        if !self.has_valid_header() {
+            return None
+        }
+
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let id = &bytes[pos..pos + 4];
+            let size = self.read_u32(pos + 4);
+            if !is_aiff && id == b"data" {
+                return Some((pos + 8, size))
+            }
+            if is_aiff && id == b"SSND" {
+                // The chunk starts with an offset and block size, and the
+                // offset is the number of bytes to skip to the audio.
+                if pos + 16 > bytes.len() || self.read_u32(pos + 8) != 0 {
+                    return None
+                }
+                return Some((pos + 16, size))
+            }
+            // Chunks are padded to an even length.
+            let next = pos as u64 + 8 + size as u64 + (size & 1) as u64;
+            if next > bytes.len() as u64 {
+                return None
+            }
+            pos = next as usize;
+        }
+
+        None
+    }
+
+    /// Returns the bytes of the original file that precede the audio.
+    ///
+    /// Returns `None` if the chunks cannot be reassembled, see `ForeignMetadata`.
+    pub fn header(&self) -> Option<&[u8]> {
+        self.locate_audio().map(|(len, _)| &self.bytes[..len])
+    }
+
+    /// Returns the bytes of the original file that follow the audio.
+    ///
+    /// If the audio chunk has an odd size, this starts with its padding byte.
+    /// Returns `None` if the chunks cannot be reassembled, see `ForeignMetadata`.
+    pub fn trailer(&self) -> Option<Vec<u8>> {
+        self.locate_audio().map(|(len, size)| {
+            let mut trailer = Vec::with_capacity(self.bytes.len() - len + 1);
+            if size & 1 == 1 {
+                trailer.push(0);
+            }
+            trailer.extend_from_slice(&self.bytes[len..]);
+            trailer
+        })
+    }
+
+    /// Returns the contents of the first chunk with the given ID, such as `b"bext"`.
+    ///
+    /// Only the header of the audio chunk is stored, so the contents of the
+    /// `data` or `SSND` chunk cannot be returned, but chunks that follow it
+    /// can. Returns `None` if there is no such chunk, or if the chunks do not
+    /// form a valid file up to it.
+    pub fn chunk(&self, id: &[u8]) -> Option<&[u8]> {
+        let is_aiff = self.application_id == APPLICATION_ID_AIFF;
+        if !self.has_valid_header() {
+            return None
+        }
+
+        let mut pos = 12;
+        while pos + 8 <= self.bytes.len() {
+            let chunk_id = &self.bytes[pos..pos + 4];
+            if (!is_aiff && chunk_id == b"data") || (is_aiff && chunk_id == b"SSND") {
+                // The audio and its padding are not stored. For AIFF, the
+                // offset and block size that precede the audio are.
+                pos += if is_aiff { 16 } else { 8 };
+                continue
+            }
+            let size = self.read_u32(pos + 4);
+            let end = pos as u64 + 8 + size as u64;
+            if end > self.bytes.len() as u64 {
+                return None
+            }
+            if chunk_id == id {
+                return Some(&self.bytes[pos + 8..end as usize])
+            }
+            // Chunks are padded to an even length.
+            pos = end as usize + (size & 1) as usize;
+        }
+
+        None
+    }
+}
+
+#[test]
+fn verify_foreign_metadata_header_and_trailer() {
+    let mut wav = ForeignMetadata::new(APPLICATION_ID_RIFF, b"RIFF\x2a\0\0\0WAVE".to_vec());
+    wav.push(b"fmt \x02\0\0\0ab");
+    wav.push(b"odd \x01\0\0\0c\0");
+    wav.push(b"data\x03\0\0\0");
+    wav.push(b"tail\0\0\0\0");
+    assert_eq!(wav.header(), Some(&wav.bytes()[..40]));
+    assert_eq!(wav.trailer(), Some(b"\0tail\0\0\0\0".to_vec()));
+
+    let mut aiff = ForeignMetadata::new(APPLICATION_ID_AIFF, b"FORM\0\0\0\x2aAIFF".to_vec());
+    aiff.push(b"COMM\0\0\0\x02ab");
+    aiff.push(b"SSND\0\0\0\x0c\0\0\0\0\0\0\0\0");
+    assert_eq!(aiff.header(), Some(aiff.bytes()));
+    assert_eq!(aiff.trailer(), Some(Vec::new()));
+
+    // Without an audio chunk, or with a chunk that extends past the end, the
+    // file cannot be reassembled.
+    let truncated = ForeignMetadata::new(APPLICATION_ID_RIFF, b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0ab".to_vec());
+    assert_eq!(truncated.header(), None);
+    let no_data = ForeignMetadata::new(APPLICATION_ID_RIFF, b"RIFF\0\0\0\0WAVEfmt \x02\0\0\0ab".to_vec());
+    assert_eq!(no_data.trailer(), None);
+    let offset = ForeignMetadata::new(APPLICATION_ID_AIFF, b"FORM\0\0\0\0AIFFSSND\0\0\0\x0c\0\0\0\x04\0\0\0\0".to_vec());
+    assert_eq!(offset.header(), None);
+
+    // Chunks can be found on both sides of the audio chunk.
+    assert_eq!(wav.chunk(b"fmt "), Some(&b"ab"[..]));
+    assert_eq!(wav.chunk(b"odd "), Some(&b"c"[..]));
+    assert_eq!(wav.chunk(b"tail"), Some(&b""[..]));
+    assert_eq!(wav.chunk(b"data"), None);
+    assert_eq!(wav.chunk(b"bext"), None);
+    aiff.push(b"ANNO\0\0\0\x01x\0");
+    assert_eq!(aiff.chunk(b"COMM"), Some(&b"ab"[..]));
+    assert_eq!(aiff.chunk(b"ANNO"), Some(&b"x"[..]));
+    assert_eq!(truncated.chunk(b"fmt "), None);
+}
+
+/// The origination info of a Broadcast Wave file, stored in its `bext` chunk.
+///
+/// See `FlacReader::broadcast_info()`. The text fields are stored with a fixed
+/// size, and padded with NUL bytes, which are removed. The fields are
+/// specified in EBU Tech 3285.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BroadcastInfo {
+    /// A free description of the sound sequence, at most 256 characters.
+    pub description: String,
+    /// The name of the originator or producer, at most 32 characters.
+    pub originator: String,
+    /// A reference assigned by the originator, at most 32 characters.
+    pub originator_reference: String,
+    /// The date of creation, in the format `yyyy-mm-dd`.
+    pub origination_date: String,
+    /// The time of creation, in the format `hh:mm:ss`.
+    pub origination_time: String,
+    /// The number of samples since midnight of the first sample.
+    pub time_reference: u64,
+    /// The version of the `bext` chunk, which determines the fields that are set.
+    pub version: u16,
+    /// The 64-byte SMPTE UMID of the recording, from version 1 onwards.
+    pub umid: Option<Vec<u8>>,
+    /// The integrated loudness in LUFS, from version 2 onwards.
+    pub loudness_value: Option<f32>,
+    /// The loudness range in LU, from version 2 onwards.
+    pub loudness_range: Option<f32>,
+    /// The maximum true peak level in dBTP, from version 2 onwards.
+    pub max_true_peak_level: Option<f32>,
+    /// The highest momentary loudness in LUFS, from version 2 onwards.
+    pub max_momentary_loudness: Option<f32>,
+    /// The highest short-term loudness in LUFS, from version 2 onwards.
+    pub max_short_term_loudness: Option<f32>,
+    /// The coding history, lines that describe the processing of the audio.
+    pub coding_history: String,
+}
+
+/// Parses the contents of a `bext` chunk, see `BroadcastInfo`.
+///
+/// Returns `None` if the chunk is too short to hold the fixed-size fields.
+/// Text that is not valid UTF-8 is converted lossily.
+pub fn parse_bext_chunk(bytes: &[u8]) -> Option<BroadcastInfo> {
+    // The fixed-size fields take 602 bytes, the coding history follows.
+    if bytes.len() < 602 {
+        return None
+    }
+
+    let text = |from: usize, to: usize| {
+        let field = &bytes[from..to];
+        let len = field.iter().position(|&x| x == 0).unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..len]).into_owned()
+    };
+    let read_le_u16_at = |pos: usize| (bytes[pos] as u16) | (bytes[pos + 1] as u16) << 8;
+
+    // The loudness values are stored as signed integers, in units of 0.01.
+    let version = read_le_u16_at(346);
+    let loudness = |pos: usize| {
+        if version >= 2 {
+            Some(read_le_u16_at(pos) as i16 as f32 / 100.0)
+        } else {
+            None
+        }
+    };
+    let umid = if version >= 1 { Some(bytes[348..412].to_vec()) } else { None };
+
+    let info = BroadcastInfo {
+        description: text(0, 256),
+        originator: text(256, 288),
+        originator_reference: text(288, 320),
+        origination_date: text(320, 330),
+        origination_time: text(330, 338),
+        time_reference: read_le_u32_at(bytes, 338) as u64 | (read_le_u32_at(bytes, 342) as u64) << 32,
+        version: version,
+        umid: umid,
+        loudness_value: loudness(412),
+        loudness_range: loudness(414),
+        max_true_peak_level: loudness(416),
+        max_momentary_loudness: loudness(418),
+        max_short_term_loudness: loudness(420),
+        coding_history: text(602, bytes.len()),
+    };
+    Some(info)
+}
+
+#[test]
+fn verify_parse_bext_chunk() {
+    let mut chunk = vec![0u8; 602];
+    chunk[..4].copy_from_slice(b"desc");
+    chunk[338] = 0x10;
+    chunk[345] = 0x01;
+    chunk[348] = 0xaa;
+    chunk[412] = 0x01;
+    chunk[413] = 0x80;
+    chunk.extend_from_slice(b"history\0\0");
+
+    let info = parse_bext_chunk(&chunk).unwrap();
+    assert_eq!(info.description, "desc");
+    assert_eq!(info.originator, "");
+    assert_eq!(info.time_reference, 0x0100_0000_0000_0010);
+    assert_eq!(info.version, 0);
+    assert_eq!(info.umid, None);
+    assert_eq!(info.loudness_value, None);
+    assert_eq!(info.coding_history, "history");
+
+    chunk[346] = 2;
+    let info = parse_bext_chunk(&chunk).unwrap();
+    assert_eq!(info.umid.map(|u| u[0]), Some(0xaa));
+    assert_eq!(info.loudness_value, Some(-327.67));
+    assert_eq!(info.loudness_range, Some(0.0));
+
+    assert_eq!(parse_bext_chunk(&chunk[..601]), None);
+}
+
 /// Reads metadata blocks from a stream and exposes them as an iterator.
 ///
 /// It is assumed that the next byte that the reader will read, is the first
@@ -539,6 +2113,19 @@ fn read_application_block<R: ReadBytes>(input: &mut R, length: u32) -> Result<(u
 pub struct MetadataBlockReader<R: ReadBytes> {
     input: R,
     done: bool,
+    last_header: Option<MetadataBlockHeader>,
+
+    /// The contents of the last streaminfo block read.
+    streaminfo_bytes: Option<[u8; 34]>,
+
+    /// Whether to skip optional blocks with invalid contents.
+    skip_damaged: bool,
+
+    /// Blocks that were skipped since the last call to `take_skipped_blocks()`.
+    skipped: Vec<(MetadataBlockHeader, Error)>,
+
+    /// Whether to decide from the next byte whether a block was the last one.
+    infer_last: bool,
 }
 
 /// Either a `MetadataBlock` or an `Error`.
@@ -550,18 +2137,132 @@ impl<R: ReadBytes> MetadataBlockReader<R> {
         MetadataBlockReader {
             input: input,
             done: false,
+            last_header: None,
+            streaminfo_bytes: None,
+            skip_damaged: false,
+            skipped: Vec::new(),
+            infer_last: false,
+        }
+    }
+
+    /// Sets whether to skip optional blocks with invalid contents, rather than failing.
+    ///
+    /// This applies to application blocks, seek tables, CUE sheets, and
+    /// pictures. None of these are needed to decode the audio. If such a block
+    /// contains an inconsistency, it is skipped by its length, and reading
+    /// continues with the next block. The skipped blocks and the errors they
+    /// caused can be retrieved with `take_skipped_blocks()`. Errors from the
+    /// underlying reader are never skipped. Neither are errors in other
+    /// blocks. Skipping requires the reader to report its position, like
+    /// `BufferedReader` does. When it does not, nothing is skipped. Defaults
+    /// to false.
+    pub fn set_skip_damaged_blocks(&mut self, skip_damaged: bool) {
+        self.skip_damaged = skip_damaged;
+    }
+
+    /// Sets whether to ignore the last-block flag, and look at what follows a block instead.
+    ///
+    /// Some encoders set the flag on the wrong block. When this is set, the
+    /// metadata ends after a block if the next byte can start a frame header
+    /// (0xff, which cannot start a metadata block header, because block type
+    /// 127 is invalid), or if the stream ends there. Otherwise another block
+    /// follows if the next byte has a known block type, and the metadata ends
+    /// if it does not. Defaults to false.
+    pub fn set_infer_last_block(&mut self, infer_last: bool) {
+        self.infer_last = infer_last;
+    }
+
+    /// Returns whether the block with the given header is the last metadata block.
+    fn is_last_block(&mut self, header: &MetadataBlockHeader) -> Result<bool> {
+        if !self.infer_last {
+            return Ok(header.is_last)
+        }
+        match try!(self.input.peek_u8()) {
+            None | Some(0xff) => Ok(true),
+            Some(byte) => Ok(byte & 0x7f >= BLOCK_TYPE_FIRST_RESERVED),
         }
     }
 
+    /// Returns the blocks that were skipped, with the errors they caused, and forgets them.
+    ///
+    /// A block that is skipped is not yielded, so this can be called after
+    /// every iteration to learn which blocks preceded the block yielded.
+    pub fn take_skipped_blocks(&mut self) -> Vec<(MetadataBlockHeader, Error)> {
+        mem::replace(&mut self.skipped, Vec::new())
+    }
+
+    /// Returns the header of the block that was read last, if any.
+    ///
+    /// If reading the block contents failed, this is still the header of the
+    /// failing block.
+    pub fn last_header(&self) -> Option<MetadataBlockHeader> {
+        self.last_header
+    }
+
+    /// Returns the contents of the last streaminfo block read, exactly as stored.
+    ///
+    /// This is the 34-byte body of the block, without the block header.
+    pub fn streaminfo_bytes(&self) -> Option<[u8; 34]> {
+        self.streaminfo_bytes
+    }
+
+    /// Reads the next block, or returns `None` if the remaining blocks were all skipped.
     #[inline]
-    fn read_next(&mut self) -> MetadataBlockResult {
-        let header = try!(read_metadata_block_header(&mut self.input));
-        let block = try!(read_metadata_block(&mut self.input, header.block_type, header.length));
-        self.done = header.is_last;
-        Ok(block)
+    fn read_next(&mut self) -> Result<Option<MetadataBlock>> {
+        loop {
+            let header = try!(read_metadata_block_header(&mut self.input));
+            self.last_header = Some(header);
+            let start = self.input.byte_position();
+            let result = if header.block_type == BLOCK_TYPE_STREAMINFO && header.length == 34 {
+                // Retain the streaminfo as stored, and parse it from there.
+                let mut bytes = [0u8; 34];
+                try!(self.input.read_into(&mut bytes));
+                self.streaminfo_bytes = Some(bytes);
+                read_metadata_block(&mut io::Cursor::new(&bytes[..]), header.block_type, header.length)
+            } else {
+                read_metadata_block(&mut self.input, header.block_type, header.length)
+            };
+            match result {
+                Ok(block) => {
+                    self.done = try!(self.is_last_block(&header));
+                    return Ok(Some(block))
+                }
+                Err(err) => {
+                    if !self.skip_damaged || !is_optional_block_error(header.block_type, &err) {
+                        return Err(err)
+                    }
+                    // Skip whatever remains of the block, if we know where we are.
+                    let consumed = match (start, self.input.byte_position()) {
+                        (Some(from), Some(to)) if to - from <= header.length as u64 => to - from,
+                        _ => return Err(err),
+                    };
+                    if self.input.skip(header.length - consumed as u32).is_err() {
+                        return Err(err)
+                    }
+                    self.skipped.push((header, err));
+                    if try!(self.is_last_block(&header)) {
+                        self.done = true;
+                        return Ok(None)
+                    }
+                }
+            }
+        }
     }
 }
 
+/// Returns whether a block of the given type can be skipped after it failed to parse with `err`.
+fn is_optional_block_error(block_type: u8, err: &Error) -> bool {
+    let is_optional = match block_type {
+        BLOCK_TYPE_APPLICATION | BLOCK_TYPE_SEEKTABLE | BLOCK_TYPE_CUESHEET | BLOCK_TYPE_PICTURE => true,
+        _ => false,
+    };
+    let is_content_error = match *err {
+        Error::FormatError(..) | Error::Unsupported(..) => true,
+        _ => false,
+    };
+    is_optional && is_content_error
+}
+
 impl<R: ReadBytes> Iterator for MetadataBlockReader<R> {
     type Item = MetadataBlockResult;
 
@@ -570,22 +2271,29 @@ impl<R: ReadBytes> Iterator for MetadataBlockReader<R> {
         if self.done {
             None
         } else {
-            let block = self.read_next();
-
-            // After a failure, no more attempts to read will be made,
-            // because we don't know where we are in the stream.
-            if !block.is_ok() {
-                self.done = true;
+            match self.read_next() {
+                Ok(Some(block)) => Some(Ok(block)),
+                Ok(None) => None,
+                Err(err) => {
+                    // After a failure, no more attempts to read will be made,
+                    // because we don't know where we are in the stream.
+                    self.done = true;
+                    Some(Err(err))
+                }
             }
-
-            Some(block)
         }
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        // When done, there will be no more blocks,
-        // when not done, there will be at least one more.
-        if self.done { (0, Some(0)) } else { (1, None) }
+        // When done, there will be no more blocks, when not done, there will
+        // be at least one more, unless all remaining blocks are skipped.
+        if self.done {
+            (0, Some(0))
+        } else if self.skip_damaged {
+            (0, None)
+        } else {
+            (1, None)
+        }
     }
 }
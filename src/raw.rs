@@ -0,0 +1,238 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2017 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `raw` module serializes decoded audio as raw PCM bytes.
+
+use std::cmp;
+use std::io;
+use std::mem;
+
+use error::Error;
+use frame::Block;
+use sample;
+use FlacReader;
+
+/// The sample format of raw PCM output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawFormat {
+    /// The number of bits per sample: 8, 16, 24, or 32.
+    pub bits: u32,
+
+    /// Whether samples are signed (two's complement) or unsigned.
+    ///
+    /// Unsigned samples are offset by half the range, so silence is at
+    /// 2<sup>bits - 1</sup>. This is the convention that WAV uses for 8-bit
+    /// audio, where silence is 128.
+    pub signed: bool,
+
+    /// Whether the bytes of a sample are in little-endian order.
+    ///
+    /// This has no effect for 8-bit samples.
+    pub little_endian: bool,
+}
+
+/// Serializes `value`, a sample of `bits_per_sample` bits, in the given format.
+///
+/// The sample is scaled to the bits of the format by shifting, so the full
+/// range of the stream maps onto the full range of the output, as with
+/// `FlacReader::samples_at_bit_depth()`.
+fn push_sample(out: &mut Vec<u8>, value: i32, bits_per_sample: u32, format: RawFormat) {
+    let value = sample::convert_bit_depth(value, bits_per_sample, format.bits);
+
+    // Adding half the range to a two's complement number is the same as
+    // flipping its most significant bit.
+    let mut x = value as u32;
+    if !format.signed {
+        x ^= 1 << (format.bits - 1);
+    }
+
+    let n_bytes = format.bits / 8;
+    for i in 0..n_bytes {
+        let shift = if format.little_endian { 8 * i } else { 8 * (n_bytes - 1 - i) };
+        out.push((x >> shift) as u8);
+    }
+}
+
+#[test]
+fn verify_push_sample() {
+    let s16le = RawFormat { bits: 16, signed: true, little_endian: true };
+    let s16be = RawFormat { bits: 16, signed: true, little_endian: false };
+    let u8_ = RawFormat { bits: 8, signed: false, little_endian: true };
+    let s24le = RawFormat { bits: 24, signed: true, little_endian: true };
+    let u16le = RawFormat { bits: 16, signed: false, little_endian: true };
+
+    let mut out = Vec::new();
+    for &x in &[0, 1, -2, 0x1234, 0x7fff, -0x8000] {
+        push_sample(&mut out, x, 16, s16le);
+    }
+    assert_eq!(out, [0x00, 0x00, 0x01, 0x00, 0xfe, 0xff, 0x34, 0x12, 0xff, 0x7f, 0x00, 0x80]);
+
+    out.clear();
+    push_sample(&mut out, 0x1234, 16, s16be);
+    assert_eq!(out, [0x12, 0x34]);
+
+    // From 16 bits, the low byte is dropped, and 128 is added.
+    out.clear();
+    for &x in &[0, 0x0100, -2, -0x100, 0x7fff, -0x8000] {
+        push_sample(&mut out, x, 16, u8_);
+    }
+    assert_eq!(out, [0x80, 0x81, 0x7f, 0x7f, 0xff, 0x00]);
+
+    // From 8 bits, samples are widened.
+    out.clear();
+    for &x in &[-1, 127, -128] {
+        push_sample(&mut out, x, 8, s16le);
+    }
+    assert_eq!(out, [0x00, 0xff, 0x00, 0x7f, 0x00, 0x80]);
+
+    out.clear();
+    push_sample(&mut out, -0x1234, 20, s24le);
+    assert_eq!(out, [0xc0, 0xdc, 0xfe]);
+
+    out.clear();
+    push_sample(&mut out, 0, 16, u16le);
+    push_sample(&mut out, -1, 16, u16le);
+    assert_eq!(out, [0x00, 0x80, 0xff, 0x7f]);
+}
+
+/// Panics if the number of bits of `format` is not 8, 16, 24, or 32.
+fn assert_valid_format(format: RawFormat) {
+    assert!(format.bits % 8 == 0 && format.bits >= 8 && format.bits <= 32,
+            "raw sample size must be 8, 16, 24, or 32 bits");
+}
+
+/// Appends the samples of `block`, of `bits_per_sample` bits, to `out`, interleaved, in the given format.
+fn push_block(out: &mut Vec<u8>, block: &Block, bits_per_sample: u32, format: RawFormat) {
+    out.reserve((block.duration() * block.channels() * format.bits / 8) as usize);
+    for i in 0..block.duration() {
+        for ch in 0..block.channels() {
+            push_sample(out, block.sample(ch, i), bits_per_sample, format);
+        }
+    }
+}
+
+impl Block {
+    /// Returns the samples in this block as raw PCM bytes in `format`.
+    ///
+    /// The samples are interleaved, and scaled from the bits per sample of the
+    /// block to the bits of the format, as `RawReader` does for a full
+    /// stream. For a bit depth that is not a whole number of bytes, pick the
+    /// next larger format, such as 24 bits for 20-bit audio; the samples are
+    /// then left-justified, like `write_canonical_bytes_be()` stores them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of bits of `format` is not 8, 16, 24, or 32.
+    pub fn to_pcm_bytes(&self, format: RawFormat) -> Vec<u8> {
+        assert_valid_format(format);
+        let mut out = Vec::new();
+        push_block(&mut out, self, self.bits_per_sample(), format);
+        out
+    }
+}
+
+/// A reader that yields the decoded samples of a stream as raw PCM bytes.
+///
+/// Samples are interleaved, like `FlacReader::samples()` yields them. A
+/// decoding error is returned from `read()` as an `io::Error` of kind
+/// `InvalidData`, or as the original error if reading the input failed, after
+/// which the reader ends.
+///
+/// This reader is produced by `FlacReader::into_raw_reader()`.
+pub struct RawReader<R: io::Read> {
+    reader: FlacReader<R>,
+    format: RawFormat,
+
+    /// Serialized samples of the current block.
+    bytes: Vec<u8>,
+    /// The number of bytes in `bytes` that have been read already.
+    pos: usize,
+
+    /// The buffer of the previous block, to be reused for the next one.
+    buffer: Vec<i32>,
+
+    /// Set at the end of the stream or after an error.
+    is_done: bool,
+}
+
+impl<R: io::Read> RawReader<R> {
+    /// Creates a reader that serializes the samples of `reader` in `format`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of bits of `format` is not 8, 16, 24, or 32.
+    pub fn new(reader: FlacReader<R>, format: RawFormat) -> RawReader<R> {
+        assert_valid_format(format);
+        RawReader {
+            reader: reader,
+            format: format,
+            bytes: Vec::new(),
+            pos: 0,
+            buffer: Vec::new(),
+            is_done: false,
+        }
+    }
+
+    /// Destroys the raw reader and returns the underlying `FlacReader`.
+    pub fn into_inner(self) -> FlacReader<R> {
+        self.reader
+    }
+
+    /// Decodes the next block into `bytes`, or sets `is_done` at the end of the stream.
+    fn decode_next_block(&mut self) -> io::Result<()> {
+        let bits_per_sample = self.reader.streaminfo().bits_per_sample;
+        let buffer = mem::replace(&mut self.buffer, Vec::new());
+        let block = match self.reader.blocks().read_next_or_eof(buffer) {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                self.is_done = true;
+                return Ok(())
+            }
+            Err(err) => {
+                self.is_done = true;
+                return Err(into_io_error(err))
+            }
+        };
+
+        self.bytes.clear();
+        self.pos = 0;
+        push_block(&mut self.bytes, &block, bits_per_sample, self.format);
+        self.buffer = block.into_buffer();
+        Ok(())
+    }
+}
+
+/// Converts a decoding error into an `io::Error`, preserving input errors.
+fn into_io_error(err: Error) -> io::Error {
+    match err {
+        Error::IoError(io_err) => io_err,
+        other => {
+            // An input error with a label keeps its kind.
+            let kind = match *other.unlabeled() {
+                Error::IoError(ref io_err) => io_err.kind(),
+                _ => io::ErrorKind::InvalidData,
+            };
+            io::Error::new(kind, other)
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for RawReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.bytes.len() {
+            if self.is_done {
+                return Ok(0)
+            }
+            try!(self.decode_next_block());
+        }
+
+        let n = cmp::min(buf.len(), self.bytes.len() - self.pos);
+        buf[..n].copy_from_slice(&self.bytes[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
@@ -0,0 +1,228 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2017 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `resample` module converts decoded audio to a different sample rate.
+//!
+//! This module is only available with the `resample` feature enabled.
+
+use std::io;
+use std::mem;
+
+use error::Result;
+use frame::Block;
+use check_block_channels;
+use FlacReader;
+
+/// An iterator that yields samples converted to a different sample rate.
+///
+/// Samples are yielded interleaved, like `FlacReader::samples()` does. The
+/// conversion interpolates linearly between neighbouring source samples. This
+/// is cheap and introduces no delay, but it does not filter, so content above
+/// the Nyquist frequency of the target rate aliases when downsampling.
+///
+/// This iterator is produced by `FlacReader::resampled()`.
+pub struct Resampled<R: io::Read> {
+    reader: FlacReader<R>,
+    source_rate: u64,
+    target_rate: u64,
+
+    /// The block that source samples are taken from.
+    block: Block,
+    /// The index of the next inter-channel sample to take from `block`.
+    block_pos: u32,
+
+    /// The source sample at index `current_index`, for every channel.
+    current: Vec<i32>,
+    /// The source sample at index `current_index + 1`, if the stream has one.
+    next: Option<Vec<i32>>,
+    /// The index of `current` in the source, or `None` before the first one.
+    current_index: Option<u64>,
+
+    /// The output sample currently being yielded, for every channel.
+    output: Vec<i32>,
+    /// The index of the next output sample to compute.
+    output_index: u64,
+    /// The next channel of `output` to yield, equal to the number of channels
+    /// if a new output sample must be computed.
+    channel: usize,
+
+    /// Set at the end of the stream or after an error, after which the
+    /// iterator yields no more samples.
+    is_done: bool,
+}
+
+impl<R: io::Read> Resampled<R> {
+    /// Creates an iterator that converts the samples of `reader` to `target_rate` Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target_rate` is zero.
+    pub fn new(reader: FlacReader<R>, target_rate: u32) -> Resampled<R> {
+        assert!(target_rate > 0, "target sample rate must be nonzero");
+        let n_channels = reader.streaminfo().channels as usize;
+        Resampled {
+            source_rate: reader.streaminfo().sample_rate as u64,
+            target_rate: target_rate as u64,
+            reader: reader,
+            block: Block::empty(),
+            block_pos: 0,
+            current: vec![0; n_channels],
+            next: None,
+            current_index: None,
+            output: vec![0; n_channels],
+            output_index: 0,
+            channel: n_channels,
+            is_done: false,
+        }
+    }
+
+    /// Returns the number of inter-channel samples that the conversion produces, if known.
+    ///
+    /// For a stream of `n` inter-channel samples, this is `n` times the target
+    /// rate divided by the source rate, rounded up.
+    pub fn expected_samples(&self) -> Option<u64> {
+        let source_rate = self.source_rate;
+        let target_rate = self.target_rate;
+        // A `FlacReader` never has a sample rate of zero, but do not divide by
+        // it regardless.
+        if source_rate == 0 {
+            return None
+        }
+        self.reader.streaminfo().samples.map(|n| {
+            (n * target_rate + source_rate - 1) / source_rate
+        })
+    }
+
+    /// Destroys the iterator and returns the underlying `FlacReader`.
+    pub fn into_inner(self) -> FlacReader<R> {
+        self.reader
+    }
+
+    /// Reads the next source sample into `out`, returning false at the end of the stream.
+    ///
+    /// Blocks can have any size, so a new block is decoded whenever the
+    /// current one is exhausted. A block must have as many channels as the
+    /// streaminfo, because `out` has one sample per channel of the streaminfo.
+    fn read_source_sample(&mut self, out: &mut [i32]) -> Result<bool> {
+        while self.block_pos >= self.block.duration() {
+            let block = mem::replace(&mut self.block, Block::empty());
+            let streaminfo = self.reader.streaminfo();
+            match try!(self.reader.blocks().read_next_or_eof(block.into_buffer())) {
+                Some(next_block) => {
+                    try!(check_block_channels(&next_block, &streaminfo));
+                    self.block = next_block;
+                }
+                None => return Ok(false),
+            }
+            self.block_pos = 0;
+        }
+
+        for (ch, sample) in out.iter_mut().enumerate() {
+            *sample = self.block.sample(ch as u32, self.block_pos);
+        }
+        self.block_pos += 1;
+        Ok(true)
+    }
+
+    /// Advances the source until `current` holds the source sample at `index`.
+    ///
+    /// Returns false if the stream ends before that sample.
+    fn advance_to(&mut self, index: u64) -> Result<bool> {
+        if self.current_index.is_none() {
+            let mut first = mem::replace(&mut self.current, Vec::new());
+            let has_first = try!(self.read_source_sample(&mut first));
+            self.current = first;
+            if !has_first {
+                return Ok(false)
+            }
+            try!(self.read_next());
+            self.current_index = Some(0);
+        }
+
+        while self.current_index.unwrap() < index {
+            match self.next.take() {
+                Some(next) => {
+                    let old = mem::replace(&mut self.current, next);
+                    self.next = Some(old);
+                    try!(self.read_next());
+                    self.current_index = self.current_index.map(|i| i + 1);
+                }
+                None => return Ok(false),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Reads the sample after `current` into `next`, reusing the buffer in `next` if there is one.
+    fn read_next(&mut self) -> Result<()> {
+        let n_channels = self.current.len();
+        let mut next = self.next.take().unwrap_or_else(|| vec![0; n_channels]);
+        if try!(self.read_source_sample(&mut next)) {
+            self.next = Some(next);
+        }
+        Ok(())
+    }
+
+    /// Computes the output sample at `output_index` into `output`.
+    ///
+    /// Returns false if there are no more output samples.
+    fn compute_output(&mut self) -> Result<bool> {
+        // The output sample lies at source position `num / target_rate`,
+        // between the source samples at `index` and `index + 1`.
+        let num = self.output_index * self.source_rate;
+        let index = num / self.target_rate;
+        let frac = num % self.target_rate;
+
+        if !try!(self.advance_to(index)) {
+            return Ok(false)
+        }
+
+        // At the end of the stream there is no next sample to interpolate
+        // with, so the last sample is held instead.
+        let w1 = frac as f64 / self.target_rate as f64;
+        let w0 = 1.0 - w1;
+        for (ch, out) in self.output.iter_mut().enumerate() {
+            let a = self.current[ch];
+            *out = match self.next {
+                Some(ref next) => (a as f64 * w0 + next[ch] as f64 * w1).round() as i32,
+                None => a,
+            };
+        }
+
+        self.output_index += 1;
+        Ok(true)
+    }
+}
+
+impl<R: io::Read> Iterator for Resampled<R> {
+    type Item = Result<i32>;
+
+    fn next(&mut self) -> Option<Result<i32>> {
+        if self.is_done {
+            return None
+        }
+
+        if self.channel == self.output.len() {
+            match self.compute_output() {
+                Ok(true) => self.channel = 0,
+                Ok(false) => {
+                    self.is_done = true;
+                    return None
+                }
+                Err(err) => {
+                    self.is_done = true;
+                    return Some(Err(err))
+                }
+            }
+        }
+
+        let sample = self.output[self.channel];
+        self.channel += 1;
+        Some(Ok(sample))
+    }
+}
@@ -0,0 +1,339 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2017 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `sample` module provides the `Sample` trait for integer sample types,
+//! the `SampleArray` trait for the samples of all channels at one time, and
+//! conversions between bit depths, with or without dither.
+
+use std::cmp;
+use std::i16;
+use std::i32;
+use std::i8;
+
+/// A signed integer type that decoded samples can be converted into.
+///
+/// Claxon decodes samples as `i32`, which can hold samples of every bit
+/// depth. When the bit depth of a stream is known to be small, a narrower
+/// type can save memory. See `FlacReader::samples_as()`.
+pub trait Sample: Copy {
+    /// Converts a decoded sample, returning `None` if it does not fit.
+    fn from_i32(sample: i32) -> Option<Self>;
+
+    /// Returns the largest value of the type.
+    fn max() -> Self;
+
+    /// Returns the smallest value of the type.
+    fn min() -> Self;
+
+    /// Returns the name of the type, such as `"i16"`, for use in messages.
+    fn type_name() -> &'static str;
+}
+
+impl Sample for i8 {
+    #[inline(always)]
+    fn from_i32(sample: i32) -> Option<i8> {
+        if sample < i8::MIN as i32 || sample > i8::MAX as i32 {
+            None
+        } else {
+            Some(sample as i8)
+        }
+    }
+
+    fn max() -> i8 { i8::MAX }
+    fn min() -> i8 { i8::MIN }
+    fn type_name() -> &'static str { "i8" }
+}
+
+impl Sample for i16 {
+    #[inline(always)]
+    fn from_i32(sample: i32) -> Option<i16> {
+        if sample < i16::MIN as i32 || sample > i16::MAX as i32 {
+            None
+        } else {
+            Some(sample as i16)
+        }
+    }
+
+    fn max() -> i16 { i16::MAX }
+    fn min() -> i16 { i16::MIN }
+    fn type_name() -> &'static str { "i16" }
+}
+
+impl Sample for i32 {
+    #[inline(always)]
+    fn from_i32(sample: i32) -> Option<i32> {
+        Some(sample)
+    }
+
+    fn max() -> i32 { i32::MAX }
+    fn min() -> i32 { i32::MIN }
+    fn type_name() -> &'static str { "i32" }
+}
+
+#[test]
+fn verify_sample_from_i32() {
+    assert_eq!(<i8 as Sample>::from_i32(-128), Some(-128));
+    assert_eq!(<i8 as Sample>::from_i32(127), Some(127));
+    assert_eq!(<i8 as Sample>::from_i32(-129), None);
+    assert_eq!(<i8 as Sample>::from_i32(128), None);
+    assert_eq!(<i16 as Sample>::from_i32(-32768), Some(-32768));
+    assert_eq!(<i16 as Sample>::from_i32(32767), Some(32767));
+    assert_eq!(<i16 as Sample>::from_i32(-32769), None);
+    assert_eq!(<i16 as Sample>::from_i32(32768), None);
+    assert_eq!(<i32 as Sample>::from_i32(i32::MIN), Some(i32::MIN));
+    assert_eq!(<i32 as Sample>::from_i32(i32::MAX), Some(i32::MAX));
+}
+
+/// Converts a sample of `from_bits` bits per sample to `to_bits` bits per sample.
+///
+/// When the target is wider, the sample is shifted left, which is lossless.
+/// When the target is narrower, the sample is shifted right, which discards
+/// the least significant bits (rounding towards negative infinity). Because
+/// the range of a two's complement number is asymmetric, the most negative
+/// value always maps to the most negative value of the target, but the most
+/// positive value maps to the most positive value only when narrowing. For
+/// example, 16-bit 32767 becomes 8388352 at 24 bits, not 8388607.
+///
+/// Both bit depths must be between 1 and 32 inclusive.
+#[inline(always)]
+pub fn convert_bit_depth(sample: i32, from_bits: u32, to_bits: u32) -> i32 {
+    debug_assert!(1 <= from_bits && from_bits <= 32);
+    debug_assert!(1 <= to_bits && to_bits <= 32);
+    if to_bits >= from_bits {
+        sample << (to_bits - from_bits)
+    } else {
+        sample >> (from_bits - to_bits)
+    }
+}
+
+/// Converts a sample of `bits_per_sample` bits to a float in [-1.0, 1.0).
+///
+/// The sample is divided by 2 to the power of `bits_per_sample` minus one, so
+/// the most negative value maps to exactly -1.0. An `f32` represents integers
+/// of up to 24 bits exactly, so wider samples are first narrowed to 24 bits
+/// with `convert_bit_depth()`. Without that, the most positive values would
+/// round up to 1.0, outside of the range.
+///
+/// The bit depth must be between 1 and 32 inclusive.
+#[inline(always)]
+pub fn to_f32(sample: i32, bits_per_sample: u32) -> f32 {
+    let bits = cmp::min(bits_per_sample, 24);
+    let narrowed = convert_bit_depth(sample, bits_per_sample, bits);
+    narrowed as f32 / (1u32 << (bits - 1)) as f32
+}
+
+/// Returns the smallest and largest value of a sample of `bits` bits.
+#[cfg(test)]
+fn sample_range(bits: u32) -> (i32, i32) {
+    let max = ((1u64 << (bits - 1)) - 1) as i32;
+    (-max - 1, max)
+}
+
+#[test]
+fn verify_convert_bit_depth_at_extremes() {
+    for from_bits in 1..33 {
+        let (min, max) = sample_range(from_bits);
+        for to_bits in 1..33 {
+            let (to_min, to_max) = sample_range(to_bits);
+            assert_eq!(convert_bit_depth(min, from_bits, to_bits), to_min);
+            if to_bits <= from_bits {
+                assert_eq!(convert_bit_depth(max, from_bits, to_bits), to_max);
+            } else {
+                let widened = convert_bit_depth(max, from_bits, to_bits);
+                let low_bits = (1i64 << (to_bits - from_bits)) - 1;
+                assert_eq!(widened as i64, to_max as i64 - low_bits);
+                // Widening is lossless, so narrowing back restores the sample.
+                assert_eq!(convert_bit_depth(widened, to_bits, from_bits), max);
+            }
+        }
+    }
+
+    assert_eq!(convert_bit_depth(-32768, 16, 24), -8388608);
+    assert_eq!(convert_bit_depth(32767, 16, 24), 8388352);
+    assert_eq!(convert_bit_depth(-8388608, 24, 16), -32768);
+    assert_eq!(convert_bit_depth(8388607, 24, 16), 32767);
+    assert_eq!(convert_bit_depth(-1, 24, 16), -1);
+    assert_eq!(convert_bit_depth(i32::MIN, 32, 8), -128);
+    assert_eq!(convert_bit_depth(i32::MAX, 32, 8), 127);
+}
+
+#[test]
+fn verify_to_f32_at_extremes() {
+    for bits in 1..33 {
+        let (min, max) = sample_range(bits);
+        assert_eq!(to_f32(min, bits), -1.0);
+        assert!(to_f32(max, bits) < 1.0);
+        assert_eq!(to_f32(0, bits), 0.0);
+        if bits <= 24 {
+            // Up to 24 bits the result is exact.
+            assert_eq!(to_f32(max, bits), 1.0 - 1.0 / (1u32 << (bits - 1)) as f32);
+        } else {
+            assert_eq!(to_f32(max, bits), 1.0 - 1.0 / (1u32 << 23) as f32);
+        }
+    }
+
+    assert_eq!(to_f32(-32768, 16), -1.0);
+    assert_eq!(to_f32(16384, 16), 0.5);
+    assert_eq!(to_f32(-128, 8), -1.0);
+    assert_eq!(to_f32(-8388608, 24), -1.0);
+    assert_eq!(to_f32(i32::MIN, 32), -1.0);
+}
+
+/// The kind of noise to add when reducing the bit depth, see `Ditherer`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Dither {
+    /// No noise, the discarded bits are truncated as by `convert_bit_depth()`.
+    None,
+    /// Noise with a rectangular distribution of one least significant bit wide.
+    ///
+    /// This makes the error of the conversion unbiased, but its noise still
+    /// depends on the signal.
+    Rectangular,
+    /// Noise with a triangular distribution of two least significant bits wide.
+    ///
+    /// This is the sum of two rectangular noises. It makes the noise
+    /// independent of the signal, which avoids audible distortion of quiet
+    /// passages, and is the usual choice to produce 16-bit audio.
+    Triangular,
+}
+
+/// Converts samples to a lower bit depth, adding dither noise before truncation.
+///
+/// The noise comes from a pseudorandom number generator with a fixed seed, so
+/// the same input and seed always produce the same output. The noise is
+/// scaled to the least significant bit of the target bit depth, and results
+/// are clamped to its range. See `FlacReader::samples_at_bit_depth_dithered()`.
+#[derive(Clone, Debug)]
+pub struct Ditherer {
+    dither: Dither,
+    state: u64,
+}
+
+impl Ditherer {
+    /// Creates a ditherer that adds the given noise, with the generator seeded from `seed`.
+    pub fn new(dither: Dither, seed: u64) -> Ditherer {
+        Ditherer {
+            dither: dither,
+            // The xorshift state must not be zero.
+            state: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed },
+        }
+    }
+
+    /// Returns 32 pseudorandom bits, using the xorshift64* generator.
+    fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32
+    }
+
+    /// Converts a sample of `from_bits` bits per sample to `to_bits` bits per sample.
+    ///
+    /// When the target is not narrower, or when the dither is `Dither::None`,
+    /// this is `convert_bit_depth()`. Both bit depths must be between 1 and 32
+    /// inclusive.
+    pub fn convert(&mut self, sample: i32, from_bits: u32, to_bits: u32) -> i32 {
+        if to_bits >= from_bits || self.dither == Dither::None {
+            return convert_bit_depth(sample, from_bits, to_bits)
+        }
+
+        // The discarded bits form the fraction of the least significant bit
+        // of the target. Truncation rounds down, so rectangular noise in
+        // [0, 1) LSB rounds without bias, and triangular noise in (-1, 1) LSB
+        // is offset by half an LSB to round to nearest.
+        let shift = from_bits - to_bits;
+        let mask = (1u64 << shift) - 1;
+        let noise = match self.dither {
+            Dither::None => 0,
+            Dither::Rectangular => (self.next_u32() as u64 & mask) as i64,
+            Dither::Triangular => {
+                let a = (self.next_u32() as u64 & mask) as i64;
+                let b = (self.next_u32() as u64 & mask) as i64;
+                a + b - (mask as i64 + 1) / 2
+            }
+        };
+        let max = (1i64 << (to_bits - 1)) - 1;
+        let result = (sample as i64 + noise) >> shift;
+        cmp::max(-max - 1, cmp::min(max, result)) as i32
+    }
+}
+
+#[test]
+fn verify_ditherer() {
+    // Without dither, and when widening, this is plain conversion.
+    let mut none = Ditherer::new(Dither::None, 1);
+    let mut triangular = Ditherer::new(Dither::Triangular, 1);
+    for &x in &[-8388608, -1, 0, 1, 0x1234, 8388607] {
+        assert_eq!(none.convert(x, 24, 16), convert_bit_depth(x, 24, 16));
+        assert_eq!(triangular.convert(x >> 8, 16, 24), convert_bit_depth(x >> 8, 16, 24));
+    }
+
+    // The extremes stay in range.
+    for _ in 0..1000 {
+        let x = triangular.convert(8388607, 24, 16);
+        assert!(x == 32767 || x == 32766);
+        let x = triangular.convert(-8388608, 24, 16);
+        assert!(x >= -32768 && x <= -32767);
+    }
+
+    // A sample halfway between two 16-bit values is rounded down and up
+    // about equally often, and the same seed gives the same sequence.
+    for &dither in &[Dither::Rectangular, Dither::Triangular] {
+        let mut a = Ditherer::new(dither, 42);
+        let mut b = Ditherer::new(dither, 42);
+        let mut sum = 0;
+        for _ in 0..10_000 {
+            let x = a.convert(0x1280, 24, 16);
+            assert_eq!(x, b.convert(0x1280, 24, 16));
+            assert!(x >= 0x11 && x <= 0x13);
+            sum += x - 0x12;
+        }
+        assert!(sum > 4_500 && sum < 5_500, "sum is {}", sum);
+    }
+}
+
+/// An array with one sample for every channel, such as `[i32; 2]` for stereo.
+///
+/// This is the frame type of sample-based DSP libraries such as dasp, which
+/// represents the samples of all channels at one point in time as an array.
+/// See `FlacReader::sample_arrays()`. It is implemented for arrays of one up
+/// to eight `i32` samples, the channel counts that FLAC supports.
+pub trait SampleArray: Copy {
+    /// Returns the number of channels, the length of the array.
+    fn channels() -> u32;
+
+    /// Creates an array from a slice of exactly `channels()` samples.
+    fn from_slice(samples: &[i32]) -> Self;
+}
+
+macro_rules! impl_sample_array {
+    ($($n:expr),*) => {
+        $(
+            impl SampleArray for [i32; $n] {
+                #[inline(always)]
+                fn channels() -> u32 { $n }
+
+                #[inline(always)]
+                fn from_slice(samples: &[i32]) -> [i32; $n] {
+                    let mut array = [0; $n];
+                    array.copy_from_slice(samples);
+                    array
+                }
+            }
+        )*
+    }
+}
+
+impl_sample_array!(1, 2, 3, 4, 5, 6, 7, 8);
+
+#[test]
+fn verify_sample_array_from_slice() {
+    assert_eq!(<[i32; 1] as SampleArray>::channels(), 1);
+    assert_eq!(<[i32; 8] as SampleArray>::channels(), 8);
+    assert_eq!(<[i32; 2] as SampleArray>::from_slice(&[-3, 7]), [-3, 7]);
+    assert_eq!(<[i32; 6] as SampleArray>::from_slice(&[1, 2, 3, 4, 5, 6]), [1, 2, 3, 4, 5, 6]);
+}
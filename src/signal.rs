@@ -0,0 +1,102 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2017 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `signal` module adapts decoded audio to the dasp sample framework.
+//!
+//! This module is only available with the `dasp` feature enabled.
+
+use dasp;
+
+use error::{Error, Result};
+use input::ReadBytes;
+use sample::SampleArray;
+use SampleArrays;
+
+/// A `dasp::Signal` that yields the decoded samples of all channels at a time.
+///
+/// Frames are arrays such as `[i32; 2]` for stereo, the dasp frame types for
+/// the channel counts that FLAC supports. A signal cannot fail, so decoding
+/// stops at the first error, after which the signal is exhausted and yields
+/// only `EQUILIBRIUM`. The error can be retrieved with `error()`. Check it
+/// once the signal is exhausted, because otherwise a stream that failed to
+/// decode cannot be told apart from a short one.
+///
+/// The signal decodes one frame ahead, so that `is_exhausted()` is accurate
+/// before the last frame is taken, as `dasp::Signal::until_exhausted()`
+/// expects.
+///
+/// This signal is produced by `FlacReader::signal()`.
+pub struct FlacSignal<R: ReadBytes, F: SampleArray> {
+    arrays: SampleArrays<R, F>,
+    /// The frame that `next()` yields, or `None` once the signal is exhausted.
+    next_frame: Option<F>,
+    error: Option<Error>,
+}
+
+impl<R: ReadBytes, F: SampleArray> FlacSignal<R, F> {
+    /// Creates a signal that yields the arrays of `arrays`.
+    ///
+    /// This decodes the first block, to know whether the signal is exhausted.
+    pub fn new(arrays: SampleArrays<R, F>) -> FlacSignal<R, F> {
+        let mut signal = FlacSignal {
+            arrays: arrays,
+            next_frame: None,
+            error: None,
+        };
+        signal.advance();
+        signal
+    }
+
+    /// Returns the error that ended decoding, if any.
+    ///
+    /// This is `None` while frames are being yielded, and after the stream
+    /// ended normally.
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    /// Destroys the signal and returns the error that ended decoding, if any.
+    pub fn into_result(self) -> Result<()> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Decodes the frame that the next call to `next()` yields.
+    fn advance(&mut self) {
+        self.next_frame = match self.arrays.next() {
+            Some(Ok(frame)) => Some(frame),
+            Some(Err(error)) => {
+                self.error = Some(error);
+                None
+            }
+            None => None,
+        };
+    }
+}
+
+impl<R, F> dasp::Signal for FlacSignal<R, F>
+    where R: ReadBytes,
+          F: SampleArray + dasp::Frame
+{
+    type Frame = F;
+
+    fn next(&mut self) -> F {
+        match self.next_frame.take() {
+            Some(frame) => {
+                self.advance();
+                frame
+            }
+            None => F::EQUILIBRIUM,
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.next_frame.is_none()
+    }
+}
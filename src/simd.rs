@@ -0,0 +1,135 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2017 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `simd` module contains vectorized versions of the stereo decorrelation.
+//!
+//! With the `simd` feature enabled on x86-64, these use AVX2 if the CPU
+//! supports it, which is detected at runtime. Every function returns whether
+//! it converted the buffer; if it returns false, the caller must fall back to
+//! the scalar version. The buffers have the same layout as for the scalar
+//! versions in the `frame` module: the first channel followed by the second.
+//! The arithmetic wraps like the scalar versions do, so the results are
+//! bit-identical, also for invalid input.
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    /// Converts left ++ side in-place to left ++ right.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn decode_left_side(buffer: &mut [i32]) {
+        let block_size = buffer.len() / 2;
+        let (fst, snd) = buffer.split_at_mut(block_size);
+        let n_vectors = block_size / 8;
+        for i in 0..n_vectors {
+            let pf = fst.as_ptr().add(i * 8) as *const __m256i;
+            let ps = snd.as_mut_ptr().add(i * 8) as *mut __m256i;
+            let left = _mm256_loadu_si256(pf);
+            let side = _mm256_loadu_si256(ps);
+            _mm256_storeu_si256(ps, _mm256_sub_epi32(left, side));
+        }
+        for i in n_vectors * 8..block_size {
+            snd[i] = fst[i].wrapping_sub(snd[i]);
+        }
+    }
+
+    /// Converts side ++ right in-place to left ++ right.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn decode_right_side(buffer: &mut [i32]) {
+        let block_size = buffer.len() / 2;
+        let (fst, snd) = buffer.split_at_mut(block_size);
+        let n_vectors = block_size / 8;
+        for i in 0..n_vectors {
+            let pf = fst.as_mut_ptr().add(i * 8) as *mut __m256i;
+            let ps = snd.as_ptr().add(i * 8) as *const __m256i;
+            let side = _mm256_loadu_si256(pf);
+            let right = _mm256_loadu_si256(ps);
+            _mm256_storeu_si256(pf, _mm256_add_epi32(side, right));
+        }
+        for i in n_vectors * 8..block_size {
+            fst[i] = fst[i].wrapping_add(snd[i]);
+        }
+    }
+
+    /// Converts mid ++ side in-place to left ++ right.
+    ///
+    /// The doubled mid has the parity of side, so both the sum and the
+    /// difference are even, and the division by two of the scalar version is
+    /// an arithmetic shift.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn decode_mid_side(buffer: &mut [i32]) {
+        let block_size = buffer.len() / 2;
+        let (fst, snd) = buffer.split_at_mut(block_size);
+        let n_vectors = block_size / 8;
+        let one = _mm256_set1_epi32(1);
+        for i in 0..n_vectors {
+            let pf = fst.as_mut_ptr().add(i * 8) as *mut __m256i;
+            let ps = snd.as_mut_ptr().add(i * 8) as *mut __m256i;
+            let mid = _mm256_loadu_si256(pf);
+            let side = _mm256_loadu_si256(ps);
+            let mid = _mm256_or_si256(_mm256_slli_epi32(mid, 1), _mm256_and_si256(side, one));
+            let left = _mm256_srai_epi32(_mm256_add_epi32(mid, side), 1);
+            let right = _mm256_srai_epi32(_mm256_sub_epi32(mid, side), 1);
+            _mm256_storeu_si256(pf, left);
+            _mm256_storeu_si256(ps, right);
+        }
+        for i in n_vectors * 8..block_size {
+            let side = snd[i];
+            let mid = fst[i].wrapping_mul(2) | (side & 1);
+            fst[i] = mid.wrapping_add(side) / 2;
+            snd[i] = mid.wrapping_sub(side) / 2;
+        }
+    }
+}
+
+/// Converts a buffer with left samples and a side channel in-place to left ++ right.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+pub fn decode_left_side(buffer: &mut [i32]) -> bool {
+    if !is_x86_feature_detected!("avx2") {
+        return false
+    }
+    unsafe { avx2::decode_left_side(buffer) };
+    true
+}
+
+/// Converts a buffer with right samples and a side channel in-place to left ++ right.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+pub fn decode_right_side(buffer: &mut [i32]) -> bool {
+    if !is_x86_feature_detected!("avx2") {
+        return false
+    }
+    unsafe { avx2::decode_right_side(buffer) };
+    true
+}
+
+/// Converts a buffer with mid samples and a side channel in-place to left ++ right.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+pub fn decode_mid_side(buffer: &mut [i32]) -> bool {
+    if !is_x86_feature_detected!("avx2") {
+        return false
+    }
+    unsafe { avx2::decode_mid_side(buffer) };
+    true
+}
+
+/// Without SIMD support, the scalar version must be used.
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+pub fn decode_left_side(_buffer: &mut [i32]) -> bool {
+    false
+}
+
+/// Without SIMD support, the scalar version must be used.
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+pub fn decode_right_side(_buffer: &mut [i32]) -> bool {
+    false
+}
+
+/// Without SIMD support, the scalar version must be used.
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+pub fn decode_mid_side(_buffer: &mut [i32]) -> bool {
+    false
+}
@@ -0,0 +1,156 @@
+// Claxon -- A FLAC decoding library in Rust
+// Copyright 2017 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+
+//! The `source` module adapts decoded audio to a rodio `Source` for playback.
+//!
+//! This module is only available with the `rodio` feature enabled.
+
+use std::io;
+use std::mem;
+use std::time::Duration;
+
+use rodio;
+
+use error::{Error, Result};
+use frame::Block;
+use sample;
+use check_block_channels;
+use FlacReader;
+
+/// A rodio `Source` that yields the decoded samples for playback.
+///
+/// Samples are yielded interleaved, like `FlacReader::samples()` does, and
+/// converted to `f32` in the range -1.0 to 1.0 with `sample::to_f32()`, the
+/// sample type of rodio. The number of channels and the sample rate are those
+/// of the streaminfo, and the total duration is known if the streaminfo
+/// records the number of samples.
+///
+/// A source cannot fail, so playback stops at the first error, after which
+/// the source yields no more samples. The error can be retrieved with
+/// `error()`.
+///
+/// This source is produced by `FlacReader::into_source()`.
+pub struct FlacSource<R: io::Read> {
+    reader: FlacReader<R>,
+
+    /// The block that samples are taken from, empty at the end of the stream.
+    block: Block,
+    /// The index of the next inter-channel sample to take from `block`.
+    sample: u32,
+    /// The channel of the next sample to take from `block`.
+    channel: u32,
+
+    error: Option<Error>,
+}
+
+impl<R: io::Read> FlacSource<R> {
+    /// Creates a source that plays the samples of `reader`.
+    ///
+    /// This decodes the first block, so that `current_span_len()` is accurate
+    /// from the start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `FlacReader` was constructed with `metadata_only` set.
+    pub fn new(reader: FlacReader<R>) -> FlacSource<R> {
+        let mut source = FlacSource {
+            reader: reader,
+            block: Block::empty(),
+            sample: 0,
+            channel: 0,
+            error: None,
+        };
+        source.read_next_block();
+        source
+    }
+
+    /// Returns the error that ended playback, if any.
+    ///
+    /// This is `None` while samples are being yielded, and after the stream
+    /// ended normally.
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    /// Destroys the source and returns the underlying `FlacReader`.
+    pub fn into_inner(self) -> FlacReader<R> {
+        self.reader
+    }
+
+    /// Decodes the next block, or leaves an empty one at the end of the stream or on error.
+    ///
+    /// A block is decoded as soon as the previous one is exhausted, so that a
+    /// span never ends before the stream does.
+    fn read_next_block(&mut self) {
+        let block = mem::replace(&mut self.block, Block::empty());
+        let streaminfo = self.reader.streaminfo();
+        let result: Result<Option<Block>> = self.reader.blocks()
+            .read_next_or_eof(block.into_buffer())
+            .and_then(|block| {
+                if let Some(ref b) = block {
+                    try!(check_block_channels(b, &streaminfo));
+                }
+                Ok(block)
+            });
+        match result {
+            Ok(Some(next_block)) => self.block = next_block,
+            Ok(None) => {}
+            Err(error) => self.error = Some(error),
+        }
+        self.sample = 0;
+        self.channel = 0;
+    }
+}
+
+impl<R: io::Read> Iterator for FlacSource<R> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample >= self.block.duration() {
+            return None
+        }
+
+        let value = self.block.sample(self.channel, self.sample);
+        let bits_per_sample = self.reader.streaminfo().bits_per_sample;
+
+        self.channel += 1;
+        if self.channel >= self.block.channels() {
+            self.channel = 0;
+            self.sample += 1;
+            if self.sample >= self.block.duration() {
+                self.read_next_block();
+            }
+        }
+
+        Some(sample::to_f32(value, bits_per_sample))
+    }
+}
+
+impl<R: io::Read> rodio::Source for FlacSource<R> {
+    fn current_span_len(&self) -> Option<usize> {
+        // A span lasts until the end of the current block. At the end of the
+        // stream the block is empty, and the span length is zero.
+        let remaining = (self.block.duration() - self.sample) * self.block.channels() - self.channel;
+        Some(remaining as usize)
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.reader.streaminfo().channels as rodio::ChannelCount
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.reader.streaminfo().sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let sample_rate = self.reader.streaminfo().sample_rate as u64;
+        self.reader.streaminfo().samples.map(|n| {
+            let nanos = (n % sample_rate) * 1_000_000_000 / sample_rate;
+            Duration::new(n / sample_rate, nanos as u32)
+        })
+    }
+}
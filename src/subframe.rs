@@ -8,15 +8,21 @@
 //! The `subframe` module deals with subframes that make up a frame of the FLAC stream.
 
 use std::cmp;
+use std::io;
 use std::num;
 use error::{Error, Result, fmt_err};
 use input::{Bitstream, ReadBytes};
 
-#[derive(Clone, Copy, Debug)]
-enum SubframeType {
+/// The type of a subframe, as specified in its header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SubframeType {
+    /// All samples in the subframe have the same value.
     Constant,
+    /// The samples are stored without any prediction or encoding.
     Verbatim,
+    /// A fixed polynomial predictor of the given order (at most 4).
     Fixed(u8),
+    /// A linear predictor of the given order (at most 32).
     Lpc(u8),
 }
 
@@ -90,6 +96,38 @@ fn read_subframe_header<R: ReadBytes>(input: &mut Bitstream<R>) -> Result<Subfra
     Ok(subframe_header)
 }
 
+#[test]
+fn verify_read_subframe_header_rejects_reserved_types() {
+    use input::BufferedReader;
+
+    let read_type = |code: u8| {
+        // A zero padding bit, the type, and no wasted bits.
+        let data = io::Cursor::new(vec![code << 1]);
+        let mut input = Bitstream::new(BufferedReader::new(data));
+        read_subframe_header(&mut input).map(|header| header.sf_type)
+    };
+
+    // A fixed subframe with order 5, a code between the fixed and LPC ranges,
+    // and a code between verbatim and fixed.
+    for &code in &[0b001_101, 0b010_000, 0b000_010] {
+        match read_type(code) {
+            Err(Error::FormatError(..)) => {}
+            _ => panic!("expected format error for reserved subframe type {:06b}", code),
+        }
+    }
+
+    for code in 0..64 {
+        let expected = match code {
+            0 => Some(SubframeType::Constant),
+            1 => Some(SubframeType::Verbatim),
+            n if 8 <= n && n <= 12 => Some(SubframeType::Fixed(n - 8)),
+            n if n >= 32 => Some(SubframeType::Lpc(n - 31)),
+            _ => None,
+        };
+        assert_eq!(read_type(code).ok(), expected, "subframe type {:06b}", code);
+    }
+}
+
 /// Given a signed two's complement integer in the `bits` least significant
 /// bits of `val`, extends the sign bit to a valid 16-bit signed integer.
 #[inline(always)]
@@ -180,11 +218,12 @@ fn verify_rice_to_signed() {
 
 /// Decodes a subframe into the provided block-size buffer.
 ///
-/// It is assumed that the length of the buffer is the block size.
+/// It is assumed that the length of the buffer is the block size. Returns the
+/// type of the subframe that was decoded.
 pub fn decode<R: ReadBytes>(input: &mut Bitstream<R>,
                             bps: u32,
                             buffer: &mut [i32])
-                            -> Result<()> {
+                            -> Result<SubframeType> {
     // The sample type i32 should be wide enough to accomodate for all bits of
     // the stream, but this can be verified at a higher level than here. Still,
     // it is a good idea to make the assumption explicit. FLAC supports up to
@@ -193,6 +232,13 @@ pub fn decode<R: ReadBytes>(input: &mut Bitstream<R>,
     // 24 bits per sample at most, so restricting ourselves to i32 is fine.
     debug_assert!(32 >= bps);
 
+    // A frame header cannot encode an empty block, but this function is
+    // public. The residual decoding assumes at least one sample, and the block
+    // size must fit in 16 bits.
+    if buffer.is_empty() || buffer.len() > 0xffff {
+        return fmt_err("invalid block size, must be between 1 and 65535");
+    }
+
     let header = try!(read_subframe_header(input));
 
     if header.wasted_bits_per_sample >= bps {
@@ -224,7 +270,21 @@ pub fn decode<R: ReadBytes>(input: &mut Bitstream<R>,
         }
     }
 
-    Ok(())
+    Ok(header.sf_type)
+}
+
+#[test]
+fn verify_decode_rejects_empty_block() {
+    use input::BufferedReader;
+    use std::io;
+
+    // Constant, verbatim, fixed and LPC subframe headers, with some data.
+    for &header in &[0b0000_0000u8, 0b0000_0010, 0b0001_0000, 0b0100_0000] {
+        let data = io::Cursor::new(vec![header, 0xff, 0xff, 0xff, 0xff]);
+        let mut input = Bitstream::new(BufferedReader::new(data));
+        let mut buffer: [i32; 0] = [];
+        assert!(decode(&mut input, 16, &mut buffer).is_err());
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -249,7 +309,7 @@ fn decode_residual<R: ReadBytes>(input: &mut Bitstream<R>,
     let order = try!(input.read_leq_u8(4));
 
     // There are 2^order partitions. Note: the specification states a 4-bit
-    // partition order, so the order is at most 31, so there could be 2^31
+    // partition order, so the order is at most 15, so there could be 2^15
     // partitions, but the block size is a 16-bit number, so there are at
     // most 2^16 - 1 samples in the block. No values have been marked as
     // invalid by the specification though.
@@ -257,6 +317,14 @@ fn decode_residual<R: ReadBytes>(input: &mut Bitstream<R>,
     let n_samples = block_size >> order;
     let n_warm_up = block_size - buffer.len() as u16;
 
+    // The partitions must cover the block exactly; the block size must be a
+    // multiple of the number of partitions. If it is not, the partitions would
+    // not cover the end of the buffer, and those samples would never be
+    // written.
+    if block_size & (n_partitions as u16 - 1) != 0 {
+        return fmt_err("invalid residual, partition order does not divide block size");
+    }
+
     // The partition size must be at least as big as the number of warm-up
     // samples, otherwise the size of the first partition is negative.
     if n_warm_up > n_samples {
@@ -290,6 +358,31 @@ fn decode_residual<R: ReadBytes>(input: &mut Bitstream<R>,
     Ok(())
 }
 
+#[test]
+fn verify_decode_residual_rejects_partition_order_not_dividing_block_size() {
+    use std::io;
+    use error::Error;
+
+    // Rice coding method (00), partition order 1 (0001), and then a zero
+    // parameter for every partition. A block of 5 samples cannot be split into
+    // two partitions.
+    let data = io::Cursor::new(vec![0b0000_0100, 0b0000_0000, 0b0000_0000]);
+    let mut input = Bitstream::new(data);
+    let mut buffer = [0_i32; 5];
+    match decode_residual(&mut input, 5, &mut buffer) {
+        Err(Error::FormatError(msg)) => assert!(msg.contains("partition order")),
+        other => panic!("expected format error, got {:?}", other),
+    }
+
+    // Six samples can be split into two partitions. With a zero Rice
+    // parameter, every zero residual is a single 1 bit.
+    let data = io::Cursor::new(vec![0b0000_0100, 0b0011_1000, 0b0111_0000, 0b0000_0000]);
+    let mut input = Bitstream::new(data);
+    let mut buffer = [1_i32; 6];
+    decode_residual(&mut input, 6, &mut buffer).unwrap();
+    assert_eq!(buffer, [0; 6]);
+}
+
 // Performance note: all Rice partitions in real-world FLAC files are Rice
 // partitions, not Rice2 partitions. Therefore it makes sense to inline this
 // function into decode_residual.
@@ -380,6 +473,90 @@ fn decode_constant<R: ReadBytes>(input: &mut Bitstream<R>,
     Ok(())
 }
 
+#[test]
+fn verify_decode_constant_sign_extends_at_all_bit_depths() {
+    use input::BufferedReader;
+
+    // Packs (value, width) fields into bytes, most significant bit first.
+    fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut acc = 0u64;
+        let mut n = 0;
+        for &(value, width) in fields {
+            acc = acc << width | (value as u64 & ((1 << width) - 1));
+            n += width;
+            while n >= 8 {
+                bytes.push((acc >> (n - 8)) as u8);
+                n -= 8;
+            }
+        }
+        if n > 0 {
+            bytes.push((acc << (8 - n)) as u8);
+        }
+        bytes
+    }
+
+    // Every width, including odd ones such as 12 and 20 bits, sign extends
+    // negative values rather than zero extending them.
+    for bps in 4..33 {
+        let min = -(1i64 << (bps - 1));
+        for &value in &[-1, -5, min, min + 1, 3] {
+            let value = value as i32;
+            // A constant subframe header without wasted bits, then the value.
+            let data = pack(&[(0, 8), (value as u32, bps)]);
+            let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(data)));
+            let mut buffer = [0; 3];
+            assert_eq!(decode(&mut input, bps, &mut buffer).unwrap(), SubframeType::Constant);
+            assert_eq!(buffer, [value; 3], "bps = {}", bps);
+        }
+    }
+
+    // With two wasted bits, a 20-bit value is stored in 18 bits.
+    let data = pack(&[(0b0000_0001, 8), (0b01, 2), (-5i32 as u32, 18)]);
+    let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(data)));
+    let mut buffer = [0; 2];
+    decode(&mut input, 20, &mut buffer).unwrap();
+    assert_eq!(buffer, [-20; 2]);
+}
+
+#[test]
+fn verify_decode_with_maximal_wasted_bits() {
+    use input::BufferedReader;
+
+    // With bps - 1 wasted bits, samples are coded in a single bit, so they are
+    // 0 or -1, and restoring the wasted bits yields 0 or the most negative value
+    // of the bit depth, which for 32 bits is exactly `i32::MIN`.
+    for &bps in &[2, 8, 16, 24, 31, 32] {
+        let min = (-(1i64 << (bps - 1))) as i32;
+
+        let mut bits = Vec::new();
+        let mut push = |value: u32, width: u32| {
+            for i in (0..width).rev() {
+                bits.push((value >> i) & 1 == 1);
+            }
+        };
+        // A constant subframe with value -1, then a verbatim subframe. Each
+        // header is a zero bit, the type, the wasted bits flag, and bps - 2
+        // zero bits and a one bit for the unary count of bps - 1 wasted bits.
+        push(0b0000_0001, 8);
+        push(1, bps - 1);
+        push(1, 1);
+        push(0b0000_0011, 8);
+        push(1, bps - 1);
+        push(0b1011, 4);
+        let bytes: Vec<u8> = bits.chunks(8)
+                                 .map(|c| c.iter().enumerate().fold(0, |b, (i, &x)| b | (x as u8) << (7 - i)))
+                                 .collect();
+
+        let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(bytes)));
+        let mut buffer = [0; 4];
+        assert_eq!(decode(&mut input, bps, &mut buffer).unwrap(), SubframeType::Constant);
+        assert_eq!(buffer, [min; 4], "bps = {}", bps);
+        assert_eq!(decode(&mut input, bps, &mut buffer).unwrap(), SubframeType::Verbatim);
+        assert_eq!(buffer, [min, 0, min, min], "bps = {}", bps);
+    }
+}
+
 #[cold]
 fn decode_verbatim<R: ReadBytes>(input: &mut Bitstream<R>,
                                  bps: u32,
@@ -393,6 +570,15 @@ fn decode_verbatim<R: ReadBytes>(input: &mut Bitstream<R>,
     // FLAC, and the reference decoder does not support it either.
     debug_assert!(bps <= 32);
 
+    // The size of the data follows from the block size and bits per sample.
+    // If the input is known to end before that, fail before reading anything,
+    // rather than working through up to 256 KiB of samples first.
+    let bits = buffer.len() as u64 * bps as u64;
+    if !try!(input.may_have_bits(bits)) {
+        return Err(Error::IoError(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                 "verbatim subframe extends beyond the end of the input")));
+    }
+
     // A verbatim block stores samples without encoding whatsoever.
     for s in buffer {
         *s = extend_sign_u32(try!(input.read_leq_u32(bps)), bps);
@@ -401,6 +587,30 @@ fn decode_verbatim<R: ReadBytes>(input: &mut Bitstream<R>,
     Ok(())
 }
 
+#[test]
+fn verify_decode_verbatim_fails_early_on_truncated_input() {
+    use input::BufferedReader;
+
+    // A verbatim subframe header, followed by only 3 of the 8 KiB of samples.
+    let data = io::Cursor::new(vec![0b0000_0010u8, 0x12, 0x34, 0x56]);
+    let mut input = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = vec![7; 4096];
+    match decode(&mut input, 16, &mut buffer) {
+        Err(Error::IoError(ref err)) => assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof),
+        _ => panic!("expected an unexpected end of input"),
+    }
+
+    // Nothing was decoded into the buffer before the error.
+    assert!(buffer.iter().all(|&s| s == 7));
+
+    // When all data is present, the samples are decoded.
+    let data = io::Cursor::new(vec![0b0000_0010u8, 0x12, 0x34, 0xff, 0xfe]);
+    let mut input = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = [0; 2];
+    decode(&mut input, 16, &mut buffer).unwrap();
+    assert_eq!(buffer, [0x1234, -2]);
+}
+
 fn predict_fixed(order: u32, buffer: &mut [i32]) -> Result<()> {
     // When this is called during decoding, the order as read from the subframe
     // header has already been verified, so it is safe to assume that
@@ -607,10 +817,35 @@ fn decode_lpc<R: ReadBytes>(input: &mut Bitstream<R>,
     // There are order * bits per sample unencoded warm-up sample bits.
     try!(decode_verbatim(input, bps, &mut buffer[..order as usize]));
 
+    let (coefficients, qlp_shift) = try!(read_lpc_parameters(input, order));
+
+    // Next up is the residual. We decode it into the buffer directly, the
+    // predictor contributions will be added in a second pass. The first
+    // `order` samples have been decoded already, so continue after that.
+    try!(decode_residual(input,
+                         buffer.len() as u16,
+                         &mut buffer[order as usize..]));
+
+    try!(predict_lpc(&coefficients[..order as usize], qlp_shift, buffer));
+
+    Ok(())
+}
+
+/// Reads the precision, shift, and coefficients of an LPC subframe.
+///
+/// The coefficients are stored in reverse, the one for the oldest sample
+/// first, because that is how they are used in prediction. Only the first
+/// `order` coefficients are set.
+fn read_lpc_parameters<R: ReadBytes>(input: &mut Bitstream<R>,
+                                     order: u32)
+                                     -> Result<([i16; 32], i16)> {
     // Next are four bits quantised linear predictor coefficient precision - 1.
     let qlp_precision = try!(input.read_leq_u8(4)) as u32 + 1;
 
-    // The bit pattern 1111 is invalid.
+    // The bit pattern 1111 is invalid. This check must happen before reading
+    // the coefficients, as a precision of 16 bits would not fit in the 16-bit
+    // coefficients that they are read into. A precision of zero cannot occur,
+    // because the field stores the precision minus one.
     if qlp_precision - 1 == 0b1111 {
         return fmt_err("invalid subframe, qlp precision value invalid");
     }
@@ -641,14 +876,270 @@ fn decode_lpc<R: ReadBytes>(input: &mut Bitstream<R>,
         *coef = extend_sign_u16(coef_unsig, qlp_precision);
     }
 
-    // Next up is the residual. We decode it into the buffer directly, the
-    // predictor contributions will be added in a second pass. The first
-    // `order` samples have been decoded already, so continue after that.
-    try!(decode_residual(input,
-                         buffer.len() as u16,
-                         &mut buffer[order as usize..]));
+    Ok((coefficients, qlp_shift))
+}
 
-    try!(predict_lpc(&coefficients[..order as usize], qlp_shift, buffer));
+#[test]
+fn verify_decode_lpc_rejects_invalid_precision() {
+    use input::BufferedReader;
+
+    // An LPC subframe header of order 1, a 16-bit warm-up sample, then the
+    // precision 1111, followed by a shift of 0 and a 16-bit coefficient.
+    let data = io::Cursor::new(vec![0b0100_0000u8, 0x00, 0x05, 0xf0, 0x00, 0x00, 0x00, 0x00]);
+    let mut input = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = [0; 4];
+    assert_eq!(decode(&mut input, 16, &mut buffer).err().unwrap(),
+               Error::FormatError("invalid subframe, qlp precision value invalid"));
+
+    // The largest valid precision, 15 bits, is accepted. The coefficient
+    // 0x2000 with shift 13 repeats the previous sample, and the residual is a
+    // single Rice partition with parameter 0 and all residuals 0.
+    let data = io::Cursor::new(vec![0b0100_0000u8, 0x00, 0x05, 0xe6, 0xa0, 0x00, 0x00, 0x38]);
+    let mut input = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = [0; 4];
+    decode(&mut input, 16, &mut buffer).unwrap();
+    assert_eq!(buffer, [5, 5, 5, 5]);
+}
 
-    Ok(())
+#[test]
+fn verify_decode_rejects_order_larger_than_block_size() {
+    use input::BufferedReader;
+
+    // An LPC subframe header of order 32, in a block of 16 samples. The order
+    // must be checked before the warm-up samples are read, so they can be
+    // absent, and no samples beyond the block are written.
+    let data = io::Cursor::new(vec![0b0111_1110u8, 0xff, 0xff, 0xff, 0xff]);
+    let mut input = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = [0; 16];
+    assert_eq!(decode(&mut input, 16, &mut buffer).err().unwrap(),
+               Error::FormatError("invalid LPC subframe, lpc order is larger than block size"));
+    assert_eq!(buffer, [0; 16]);
+
+    // The same for a fixed subframe of order 4, in a block of 3 samples.
+    let data = io::Cursor::new(vec![0b0001_1000u8, 0xff, 0xff, 0xff, 0xff]);
+    let mut input = Bitstream::new(BufferedReader::new(data));
+    let mut buffer = [0; 3];
+    assert_eq!(decode(&mut input, 16, &mut buffer).err().unwrap(),
+               Error::FormatError("invalid fixed subframe, order is larger than block size"));
+    assert_eq!(buffer, [0; 3]);
+}
+
+/// The coefficients of the fixed predictors, the one for the oldest sample first.
+///
+/// These are the coefficients that `predict_fixed()` uses, for orders 0 to 4.
+const FIXED_COEFFICIENTS: [&'static [i16]; 5] = [&[], &[1], &[-1, 2], &[1, -3, 3], &[-1, 4, -6, 4]];
+
+/// The coded contents of a subframe, before the prediction is undone.
+///
+/// This is returned by `decode_residuals()`, and exposes the inner workings
+/// of the decoder for experiments with prediction. All samples are as stored
+/// in the subframe, so they must still be shifted left by the wasted bits.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Residuals {
+    /// The type of the subframe.
+    pub sf_type: SubframeType,
+
+    /// The number of wasted bits per sample.
+    pub wasted_bits_per_sample: u32,
+
+    /// The order of the predictor, the number of elements of `coefficients`.
+    ///
+    /// Constant and verbatim subframes have no predictor, and order zero.
+    pub order: u32,
+
+    /// The predictor coefficients, the one for the oldest sample first.
+    ///
+    /// For a fixed predictor, these are the coefficients of its polynomial,
+    /// with a shift of zero. Constant and verbatim subframes have none.
+    pub coefficients: Vec<i16>,
+
+    /// The number of bits that the prediction is shifted right by.
+    pub shift: i16,
+
+    /// The warm-up samples, which are stored without prediction.
+    ///
+    /// For a constant or verbatim subframe, this holds all of the samples.
+    pub warmup: Vec<i32>,
+
+    /// The differences between the samples after the warm-up and their prediction.
+    ///
+    /// Constant and verbatim subframes have none.
+    pub residuals: Vec<i32>,
+}
+
+/// Reads a subframe of `block_size` samples without undoing its prediction.
+///
+/// This reads the same data as `decode()`, but returns the predictor and the
+/// residuals rather than the samples. Pass the result to
+/// `reconstruct_from_residuals()` to obtain the samples.
+pub fn decode_residuals<R: ReadBytes>(input: &mut Bitstream<R>,
+                                      bps: u32,
+                                      block_size: u16)
+                                      -> Result<Residuals> {
+    if block_size == 0 {
+        return fmt_err("invalid block size, must be between 1 and 65535");
+    }
+
+    let header = try!(read_subframe_header(input));
+
+    if header.wasted_bits_per_sample >= bps {
+        return fmt_err("subframe has no non-wasted bits");
+    }
+
+    let sf_bps = bps - header.wasted_bits_per_sample;
+    let mut buffer = vec![0; block_size as usize];
+
+    let (warmup_len, coefficients, shift) = match header.sf_type {
+        SubframeType::Constant => {
+            try!(decode_constant(input, sf_bps, &mut buffer));
+            (buffer.len(), Vec::new(), 0)
+        }
+        SubframeType::Verbatim => {
+            try!(decode_verbatim(input, sf_bps, &mut buffer));
+            (buffer.len(), Vec::new(), 0)
+        }
+        SubframeType::Fixed(ord) => {
+            let order = ord as usize;
+            if buffer.len() < order {
+                return fmt_err("invalid fixed subframe, order is larger than block size")
+            }
+            try!(decode_verbatim(input, sf_bps, &mut buffer[..order]));
+            try!(decode_residual(input, block_size, &mut buffer[order..]));
+            (order, FIXED_COEFFICIENTS[order].to_vec(), 0)
+        }
+        SubframeType::Lpc(ord) => {
+            let order = ord as usize;
+            if buffer.len() < order {
+                return fmt_err("invalid LPC subframe, lpc order is larger than block size")
+            }
+            try!(decode_verbatim(input, sf_bps, &mut buffer[..order]));
+            let (coefficients, qlp_shift) = try!(read_lpc_parameters(input, ord as u32));
+            try!(decode_residual(input, block_size, &mut buffer[order..]));
+            (order, coefficients[..order].to_vec(), qlp_shift)
+        }
+    };
+
+    let residuals = buffer.split_off(warmup_len);
+    Ok(Residuals {
+        sf_type: header.sf_type,
+        wasted_bits_per_sample: header.wasted_bits_per_sample,
+        order: coefficients.len() as u32,
+        coefficients: coefficients,
+        shift: shift,
+        warmup: buffer,
+        residuals: residuals,
+    })
+}
+
+/// Undoes linear prediction, the way the decoder restores fixed and LPC subframes.
+///
+/// The predictor of the given `order` has `coefficients`, the one for the
+/// oldest sample first, and the prediction is shifted right by `shift`. The
+/// result holds the `warmup` samples, followed by one sample for every
+/// residual. A fixed predictor is restored exactly by passing its polynomial
+/// coefficients with a shift of zero, as `decode_residuals()` returns them.
+///
+/// A predictor of order zero predicts zero, so then the result is a copy of
+/// the warm-up samples and the residuals, and `warmup` may have any length.
+/// This restores constant and verbatim subframes, for which
+/// `decode_residuals()` returns all samples as warm-up.
+///
+/// This returns a `FormatError` if `coefficients` does not have `order`
+/// elements, if `warmup` does not have `order` elements for a nonzero order,
+/// or if `shift` is larger than 15, which a subframe cannot encode. Like the
+/// decoder, it returns `Error::Unsupported` for an order larger than 12 or a
+/// negative shift.
+pub fn reconstruct_from_residuals(order: u32,
+                                  coefficients: &[i16],
+                                  shift: i16,
+                                  warmup: &[i32],
+                                  residuals: &[i32])
+                                  -> Result<Vec<i32>> {
+    if coefficients.len() != order as usize {
+        return fmt_err("invalid predictor, expected one coefficient per order");
+    }
+    if order > 0 && warmup.len() != order as usize {
+        return fmt_err("invalid predictor, expected one warm-up sample per order");
+    }
+    if shift > 15 {
+        return fmt_err("invalid predictor, shift must be at most 15");
+    }
+
+    if shift < 0 {
+        let msg = "a negative quantized linear predictor coefficient shift is \
+                   not supported, please file a bug.";
+        return Err(Error::Unsupported(msg))
+    }
+
+    let mut buffer = Vec::with_capacity(warmup.len() + residuals.len());
+    buffer.extend_from_slice(warmup);
+    buffer.extend_from_slice(residuals);
+    if order > 0 {
+        try!(predict_lpc(coefficients, shift, &mut buffer));
+    }
+    Ok(buffer)
+}
+
+#[test]
+fn verify_reconstruct_from_residuals_matches_decode() {
+    use input::BufferedReader;
+
+    // A fixed subframe of order 2 with 6 samples, an LPC subframe of order 2
+    // with 8 samples and 2 wasted bits, a constant subframe with 4 samples, and
+    // a verbatim subframe with 3 samples, all for 16 bits per sample.
+    let fixed = vec![0x14, 0x00, 0x64, 0x00, 0x6e, 0x00, 0x56, 0x50];
+    let lpc = vec![0x43, 0x7f, 0xce, 0xff, 0x62, 0xc6, 0x01, 0x9f,
+                   0xf8, 0x01, 0x37, 0x86, 0x17, 0x00];
+    let constant = vec![0x00, 0x12, 0x34];
+    let verbatim = vec![0x02, 0x00, 0x01, 0xff, 0xfe, 0x12, 0x34];
+
+    for &(ref data, block_size) in &[(fixed, 6), (lpc, 8), (constant, 4), (verbatim, 3)] {
+        let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(data.clone())));
+        let mut expected = vec![0; block_size];
+        decode(&mut input, 16, &mut expected).unwrap();
+
+        let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(data.clone())));
+        let r = decode_residuals(&mut input, 16, block_size as u16).unwrap();
+        assert_eq!(r.warmup.len() + r.residuals.len(), block_size);
+
+        let samples = reconstruct_from_residuals(r.order,
+                                                 &r.coefficients,
+                                                 r.shift,
+                                                 &r.warmup,
+                                                 &r.residuals).unwrap();
+        let shifted: Vec<i32> = samples.iter().map(|&s| s << r.wasted_bits_per_sample).collect();
+        assert_eq!(shifted, expected);
+    }
+
+    let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(vec![0x14, 0x00, 0x64,
+                                                                            0x00, 0x6e, 0x00,
+                                                                            0x56, 0x50])));
+    let r = decode_residuals(&mut input, 16, 6).unwrap();
+    assert_eq!(r.sf_type, SubframeType::Fixed(2));
+    assert_eq!(r.order, 2);
+    assert_eq!(r.coefficients, [-1, 2]);
+    assert_eq!(r.warmup, [100, 110]);
+    assert_eq!(r.residuals, [1, -1, 2, 0]);
+
+    // A modified residual changes the sample it belongs to, and through the
+    // prediction, the samples after it.
+    let samples = reconstruct_from_residuals(2, &r.coefficients, 0, &r.warmup, &[1, 0, 2, 0]).unwrap();
+    assert_eq!(samples, [100, 110, 121, 132, 145, 158]);
+
+    // A constant subframe has all of its samples as warm-up, and no predictor.
+    let mut input = Bitstream::new(BufferedReader::new(io::Cursor::new(vec![0x00, 0x12, 0x34])));
+    let r = decode_residuals(&mut input, 16, 4).unwrap();
+    assert_eq!(r.sf_type, SubframeType::Constant);
+    assert_eq!(r.order, 0);
+    assert_eq!(r.coefficients, []);
+    assert_eq!(r.warmup, [0x1234; 4]);
+    assert_eq!(r.residuals, []);
+
+    // Arguments that do not describe a predictor are an error, not a panic.
+    assert_eq!(reconstruct_from_residuals(2, &[1], 0, &[1, 2], &[]).err().unwrap(),
+               Error::FormatError("invalid predictor, expected one coefficient per order"));
+    assert_eq!(reconstruct_from_residuals(1, &[1], 0, &[1, 2], &[]).err().unwrap(),
+               Error::FormatError("invalid predictor, expected one warm-up sample per order"));
+    assert_eq!(reconstruct_from_residuals(1, &[1], 16, &[1], &[]).err().unwrap(),
+               Error::FormatError("invalid predictor, shift must be at most 15"));
 }
@@ -9,6 +9,13 @@ extern crate claxon;
 extern crate hound;
 extern crate walkdir;
 
+#[cfg(feature = "dasp")]
+extern crate dasp;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "rodio")]
+extern crate rodio;
+
 use std::fs;
 use std::io;
 use std::path::Path;
@@ -239,6 +246,20 @@ fn verify_streaminfo_p4() {
     compare_metaflac_streaminfo("testsamples/p4.flac");
 }
 
+/// Returns the contents of the file, with the number of channels in the streaminfo replaced.
+///
+/// The frames keep their own number of channels, so they contradict the
+/// streaminfo, as in a damaged or maliciously crafted file.
+fn read_with_streaminfo_channels(fname: &str, channels: u8) -> Vec<u8> {
+    use std::io::Read;
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    // The streaminfo block starts at byte 8. Bits 3 through 1 of its byte 12
+    // hold the number of channels minus one.
+    data[20] = (data[20] & 0xf1) | ((channels - 1) << 1);
+    data
+}
+
 #[test]
 fn verify_streaminfo_pop() {
     compare_metaflac_streaminfo("testsamples/pop.flac");
@@ -393,6 +414,7 @@ fn metadata_only_still_reads_vorbis_comment_block() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: true,
+        ..Default::default()
     };
     let reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     assert_eq!(reader.vendor(), Some("reference libFLAC 1.3.2 20170101"));
@@ -403,6 +425,7 @@ fn no_read_vorbis_comment_block_does_not_contain_vendor_string() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: false,
+        ..Default::default()
     };
     let reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     assert_eq!(reader.vendor(), None);
@@ -414,6 +437,7 @@ fn samples_panics_when_metadata_only_is_set() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: true,
+        ..Default::default()
     };
     let mut reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     let _samples = reader.samples();
@@ -425,11 +449,3594 @@ fn blocks_panics_when_metadata_only_is_set() {
     let opts = claxon::FlacReaderOptions {
         metadata_only: true,
         read_vorbis_comment: true,
+        ..Default::default()
     };
     let mut reader = claxon::FlacReader::open_ext("testsamples/short.flac", opts).unwrap();
     let _blocks = reader.blocks();
 }
 
+#[test]
+fn verify_constant_channels_in_silent_sections() {
+    // This file contains five blocks of 1024 samples: a tone, two blocks of
+    // digital silence, a tone in the left channel with a constant -3 in the
+    // right channel, and finally a mid/side block where both channels are -5.
+    let mut reader = claxon::FlacReader::open("testsamples/silent_sections.flac").unwrap();
+    let expected = [
+        (None, None),
+        (Some(0), Some(0)),
+        (Some(0), Some(0)),
+        (None, Some(-3)),
+        (Some(-5), Some(-5)),
+    ];
+
+    let mut blocks = reader.blocks();
+    let mut buffer = Vec::new();
+    for &(left, right) in expected.iter() {
+        let block = blocks.read_next_or_eof(buffer).unwrap().unwrap();
+        assert_eq!(block.is_channel_constant(0), left);
+        assert_eq!(block.is_channel_constant(1), right);
+
+        // The constant value must match the samples that were decoded.
+        for ch in 0..2 {
+            if let Some(x) = block.is_channel_constant(ch) {
+                assert!(block.channel(ch).iter().all(|&s| s == x));
+            }
+        }
+        buffer = block.into_buffer();
+    }
+    assert!(blocks.read_next_or_eof(buffer).unwrap().is_none());
+}
+
+#[test]
+fn verify_channel_samples_rejects_channel_mismatch() {
+    // The streaminfo claims six channels, but the frames have two.
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 6);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.streaminfo().channels, 6);
+    assert_eq!(reader.channel_samples(5).err(),
+               Some(claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+fn verify_decode_channel_range_rejects_channel_mismatch() {
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 6);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.decode_channel_range(2..6).err(),
+               Some(claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+fn verify_waveform_overview_rejects_channel_mismatch() {
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 6);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.waveform_overview(10, Some(5)).err(),
+               Some(claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+fn verify_windows_rejects_channel_mismatch() {
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 6);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let windows: Vec<_> = reader.windows(5, 256, 256, true).collect();
+    assert_eq!(windows.len(), 1);
+    assert_eq!(windows[0].as_ref().err(),
+               Some(&claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+fn verify_sample_arrays_rejects_channel_mismatch() {
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 1);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let arrays: Vec<_> = reader.sample_arrays::<[i32; 1]>().collect();
+    assert_eq!(arrays.len(), 1);
+    assert_eq!(arrays[0].as_ref().err(),
+               Some(&claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+fn verify_pictures() {
+    let opts = claxon::FlacReaderOptions {
+        read_picture: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let reader = claxon::FlacReader::open_ext("testsamples/cover_art.flac", opts).unwrap();
+    let pictures = reader.pictures();
+    assert_eq!(pictures.len(), 2);
+    assert_eq!(pictures[0].picture_type, 3);
+    assert_eq!(pictures[0].mime_type, "image/png");
+    assert_eq!(pictures[0].description, "Front");
+    assert_eq!((pictures[0].width, pictures[0].height, pictures[0].depth), (5, 9, 32));
+    assert_eq!(&pictures[0].data[..4], b"\x89PNG");
+    assert_eq!(pictures[1].picture_type, 4);
+    assert_eq!(pictures[1].mime_type, "image/jpeg");
+    assert_eq!((pictures[1].width, pictures[1].height, pictures[1].depth), (12, 7, 24));
+
+    // Pictures are not kept unless they are desired.
+    let reader = claxon::FlacReader::open("testsamples/cover_art.flac").unwrap();
+    assert!(reader.pictures().is_empty());
+    assert_eq!(reader.metadata_blocks().iter().filter(|h| h.block_type == 6).count(), 2);
+}
+
+#[test]
+#[cfg(feature = "image")]
+fn verify_picture_decode_image() {
+    use image::GenericImageView;
+
+    let opts = claxon::FlacReaderOptions {
+        read_picture: true,
+        ..claxon::FlacReaderOptions::default()
+    };
+    let reader = claxon::FlacReader::open_ext("testsamples/cover_art.flac", opts).unwrap();
+    for picture in reader.pictures() {
+        let image = picture.decode_image().unwrap();
+        assert_eq!(image.dimensions(), (picture.width, picture.height));
+    }
+
+    // Data that is not an image of the declared type is an error.
+    let mut picture = claxon::metadata::Picture {
+        picture_type: 3,
+        mime_type: "image/png".to_string(),
+        description: String::new(),
+        width: 5,
+        height: 9,
+        depth: 32,
+        colors: 0,
+        data: reader.pictures()[1].data.clone(),
+    };
+    assert_eq!(picture.decode_image().err(),
+               Some(claxon::Error::FormatError("picture data is not a valid image")));
+    picture.mime_type = "-->".to_string();
+    assert_eq!(picture.decode_image().err(),
+               Some(claxon::Error::Unsupported("picture is a URL, not image data")));
+}
+
+#[test]
+fn verify_waveform_overview_covers_stream() {
+    let fname = "testsamples/silent_sections.flac";
+
+    // Compute the true per-channel extremes with a full decode.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    for (i, sample) in reader.samples().enumerate() {
+        if i % 2 == 0 { left.push(sample.unwrap()) } else { right.push(sample.unwrap()) }
+    }
+
+    for &(ch, samples) in [(0, &left), (1, &right)].iter() {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let overview = reader.waveform_overview(37, Some(ch)).unwrap();
+        assert_eq!(overview.len(), 37);
+        let min = overview.iter().map(|&(lo, _)| lo).min().unwrap();
+        let max = overview.iter().map(|&(_, hi)| hi).max().unwrap();
+        assert_eq!(min, *samples.iter().min().unwrap());
+        assert_eq!(max, *samples.iter().max().unwrap());
+    }
+
+    // The mono mix averages the channels.
+    let mono: Vec<i32> = left.iter().zip(right.iter()).map(|(&l, &r)| (l + r) / 2).collect();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let overview = reader.waveform_overview(10, None).unwrap();
+    assert_eq!(overview.len(), 10);
+    assert_eq!(overview.iter().map(|&(lo, _)| lo).min(), mono.iter().cloned().min());
+    assert_eq!(overview.iter().map(|&(_, hi)| hi).max(), mono.iter().cloned().max());
+
+    // The fourth of five blocks is a constant -5 in both channels, and it
+    // spans exactly the final two buckets.
+    assert_eq!(overview[8], (-5, -5));
+    assert_eq!(overview[9], (-5, -5));
+}
+
+#[test]
+fn verify_blocks_with_position_variable_block_size() {
+    let mut reader = claxon::FlacReader::open("testsamples/variable_blocksize.flac").unwrap();
+    let total_samples = reader.streaminfo().samples.unwrap();
+
+    let mut expected_position = 0;
+    let mut durations = Vec::new();
+    for item in reader.blocks_with_position() {
+        let (position, block) = item.unwrap();
+        assert_eq!(position, expected_position);
+        expected_position += block.duration() as u64;
+        durations.push(block.duration());
+    }
+
+    assert_eq!(durations, [1152, 4096, 576, 300, 2000, 17]);
+    assert_eq!(expected_position, total_samples);
+}
+
+#[test]
+fn verify_blocks_with_position_fixed_block_size() {
+    // Fixed block size with a short final block.
+    let mut reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    let total_samples = reader.streaminfo().samples.unwrap();
+
+    let mut end = 0;
+    for item in reader.blocks_with_position() {
+        let (position, block) = item.unwrap();
+        assert_eq!(position, end);
+        end = position + block.duration() as u64;
+    }
+
+    assert_eq!(end, total_samples);
+}
+
+#[test]
+fn verify_frames_reproduce_audio_section() {
+    use std::io::Read;
+
+    for fname in &["testsamples/pop.flac", "testsamples/variable_blocksize.flac"] {
+        let mut original = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut original).unwrap();
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut spliced = Vec::new();
+        let mut n_samples = 0;
+        for item in reader.frames() {
+            let (range, block) = item.unwrap();
+
+            // The first frame starts right after the metadata.
+            if spliced.is_empty() {
+                spliced.extend_from_slice(&original[..range.offset as usize]);
+            }
+
+            assert_eq!(range.offset, spliced.len() as u64);
+            let end = (range.offset + range.len) as usize;
+            spliced.extend_from_slice(&original[range.offset as usize..end]);
+            n_samples += block.duration() as u64;
+        }
+
+        assert_eq!(n_samples, reader.streaminfo().samples.unwrap());
+        assert!(spliced == original);
+    }
+}
+
+#[test]
+fn verify_read_interleaved_f32_in_small_chunks() {
+    let fname = "testsamples/pop.flac";
+
+    // Decode to planar floats in one go.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let n_channels = reader.streaminfo().channels as usize;
+    let scale = 1.0 / (1 << (reader.streaminfo().bits_per_sample - 1)) as f32;
+    let mut planar = vec![Vec::new(); n_channels];
+    let mut blocks = reader.blocks();
+    let mut buffer = Vec::new();
+    while let Some(block) = blocks.read_next_or_eof(buffer).unwrap() {
+        for ch in 0..n_channels {
+            planar[ch].extend(block.channel(ch as u32).iter().map(|&s| s as f32 * scale));
+        }
+        buffer = block.into_buffer();
+    }
+
+    // Then decode in chunks that do not align with blocks or channels.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut interleaved = Vec::new();
+    let mut chunk = [0.0_f32; 37];
+    loop {
+        let n = reader.read_interleaved_f32(&mut chunk).unwrap();
+        interleaved.extend_from_slice(&chunk[..n]);
+        if n < chunk.len() {
+            break
+        }
+    }
+
+    assert_eq!(interleaved.len(), planar[0].len() * n_channels);
+    for (i, &sample) in interleaved.iter().enumerate() {
+        assert_eq!(sample, planar[i % n_channels][i / n_channels]);
+        assert!(sample >= -1.0 && sample < 1.0);
+    }
+
+    // Once the end is reached, no more samples are returned.
+    assert_eq!(reader.read_interleaved_f32(&mut chunk).unwrap(), 0);
+}
+
+#[test]
+fn verify_md5_of_samples() {
+    for fname in &["testsamples/pop.flac", "testsamples/variable_blocksize.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        assert!(reader.verify_md5().unwrap());
+    }
+}
+
+#[test]
+fn verify_compute_digest_pipelined_matches_serial() {
+    for fname in &["testsamples/stereo_28bit.flac", "testsamples/variable_blocksize.flac",
+                   "testsamples/short_final_block.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let serial = reader.compute_digest().unwrap();
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let pipelined = reader.compute_digest_pipelined().unwrap();
+        assert_eq!(pipelined, serial);
+        assert_eq!(pipelined.md5sum, reader.streaminfo().md5sum);
+        assert_eq!(Some(pipelined.samples), reader.streaminfo().samples);
+    }
+
+    // A decode error is reported after the hashing thread has finished.
+    use std::io::Read;
+    let mut data = Vec::new();
+    fs::File::open("testsamples/stereo_28bit.flac").unwrap().read_to_end(&mut data).unwrap();
+    let mid = data.len() / 2;
+    data[mid] ^= 0x55;
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    assert!(reader.compute_digest_pipelined().is_err());
+}
+
+#[test]
+fn verify_repair_streaminfo_restores_samples_and_md5() {
+    use std::io::Read;
+
+    let mut original = Vec::new();
+    fs::File::open("testsamples/pop.flac").unwrap().read_to_end(&mut original).unwrap();
+
+    // Zero the 36-bit sample count and the MD5 signature.
+    let mut damaged = original.clone();
+    damaged[21] &= 0xf0;
+    for byte in &mut damaged[22..42] {
+        *byte = 0;
+    }
+    {
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&damaged)).unwrap();
+        assert_eq!(reader.streaminfo().samples, None);
+        assert!(!reader.verify_md5().unwrap());
+    }
+
+    let mut repaired = io::Cursor::new(Vec::new());
+    let digest = claxon::repair_streaminfo(io::Cursor::new(&damaged), &mut repaired).unwrap();
+    let repaired = repaired.into_inner();
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&repaired)).unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(digest.samples));
+    assert_eq!(reader.streaminfo().md5sum, digest.md5sum);
+    assert!(reader.verify_md5().unwrap());
+    assert!(repaired == original);
+}
+
+#[test]
+fn verify_decode_range_in_short_final_block() {
+    for fname in &["testsamples/short_final_block.flac", "testsamples/variable_blocksize.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let n_channels = reader.streaminfo().channels as usize;
+        let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        let total = (all.len() / n_channels) as u64;
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut last_block_start = 0;
+        for item in reader.blocks_with_position() {
+            let (position, block) = item.unwrap();
+            last_block_start = position;
+            assert!(position + (block.duration() as u64) <= total);
+        }
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let targets = [total - 1, total - 5, last_block_start, last_block_start + 1, 0, 1000];
+        for &target in &targets {
+            let range = reader.decode_range(target, 8).unwrap();
+            let start = target as usize * n_channels;
+            let end = std::cmp::min(all.len(), start + 8 * n_channels);
+            assert_eq!(&range[..], &all[start..end]);
+        }
+
+        // Ranges beyond the end are empty.
+        assert_eq!(reader.decode_range(total, 8).unwrap().len(), 0);
+        assert_eq!(reader.decode_range(total + 100, 8).unwrap().len(), 0);
+    }
+}
+
+#[test]
+fn verify_seek_to_sample_fixed_and_variable_blocksize() {
+    let files = [("testsamples/silent_sections.flac", true),
+                 ("testsamples/stereo_24bit.flac", true),
+                 ("testsamples/surround_5_1.flac", true),
+                 ("testsamples/variable_blocksize.flac", false)];
+    for &(fname, is_fixed) in &files {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        assert_eq!(reader.streaminfo().is_fixed_blocksize(), is_fixed);
+        let n_channels = reader.streaminfo().channels as usize;
+        let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        let total = all.len() / n_channels;
+
+        // Seek around in both directions, including to every block boundary.
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut targets: Vec<usize> = (0..total).filter(|t| t % 1024 < 2 || t % 1152 == 0).collect();
+        targets.extend(targets.clone().into_iter().rev());
+        targets.push(total - 1);
+        for &target in &targets {
+            let range = reader.decode_range(target as u64, 3).unwrap();
+            let start = target * n_channels;
+            let end = std::cmp::min(all.len(), start + 3 * n_channels);
+            assert_eq!(&range[..], &all[start..end]);
+        }
+    }
+}
+
+#[test]
+fn verify_seek_discard_count() {
+    for fname in &["testsamples/short_final_block.flac", "testsamples/variable_blocksize.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let n_channels = reader.streaminfo().channels as usize;
+        let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        let total = (all.len() / n_channels) as u64;
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        for &target in &[1500, 0, 1024, 1023, total - 1, 777] {
+            let discard = reader.seek_discard_count(target).unwrap();
+            let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+            assert_eq!(block.time() + discard, target);
+            for ch in 0..n_channels {
+                assert_eq!(block.sample(ch as u32, discard as u32), all[target as usize * n_channels + ch]);
+            }
+
+            // Discarding from the samples iterator works the same way.
+            let discard = reader.seek_discard_count(target).unwrap();
+            let first = reader.samples().skip(discard as usize * n_channels).next().unwrap().unwrap();
+            assert_eq!(first, all[target as usize * n_channels]);
+        }
+
+        assert_eq!(reader.seek_discard_count(total).unwrap(), 0);
+        assert!(reader.blocks().read_next_or_eof(Vec::new()).unwrap().is_none());
+    }
+}
+
+#[test]
+fn verify_blocks_reverse() {
+    for fname in &["testsamples/short_final_block.flac", "testsamples/stereo_20bit.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut forward = Vec::new();
+        {
+            let mut blocks = reader.blocks();
+            while let Some(block) = blocks.read_next_or_eof(Vec::new()).unwrap() {
+                forward.push(block);
+            }
+        }
+        assert!(forward.len() > 1);
+
+        // Start somewhere in the middle, which should not matter.
+        reader.seek_to_sample(forward[1].time() as u64).unwrap();
+        let mut reverse: Vec<claxon::Block> = reader.blocks_reverse().unwrap()
+                                                    .map(|b| b.unwrap())
+                                                    .collect();
+        reverse.reverse();
+
+        assert_eq!(reverse.len(), forward.len());
+        for (b, f) in reverse.iter().zip(forward.iter()) {
+            assert_eq!(b.time(), f.time());
+            assert_eq!(b.duration(), f.duration());
+            for ch in 0..f.channels() {
+                assert_eq!(b.channel(ch), f.channel(ch));
+            }
+        }
+
+        // The reader is left after the first frame.
+        let second = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+        assert_eq!(second.time(), forward[1].time());
+    }
+}
+
+#[test]
+fn verify_resume_at_byte() {
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let ranges: Vec<_> = reader.frames().map(|f| f.unwrap().0).collect();
+    let mut expected = Vec::new();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    reader.decode_all_into(&mut expected).unwrap();
+
+    // From the middle of the second frame, decoding resumes at the third.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert_eq!(reader.resume_at_byte(ranges[1].offset + 10).unwrap(), 2048);
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(&samples[..], &expected[2 * 2048..]);
+
+    // The start of a frame resumes at that frame, also after reading further.
+    assert_eq!(reader.resume_at_byte(ranges[1].offset).unwrap(), 1024);
+    let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    for i in 0..1024 {
+        assert_eq!(block.sample(0, i), expected[2 * (1024 + i as usize)]);
+        assert_eq!(block.sample(1, i), expected[2 * (1024 + i as usize) + 1]);
+    }
+
+    // An offset inside the metadata resumes at the first frame.
+    assert_eq!(reader.resume_at_byte(0).unwrap(), 0);
+    assert_eq!(reader.samples().count(), expected.len());
+
+    // Past the start of the last frame, there is nothing to resume at.
+    let last = ranges.last().unwrap().offset;
+    assert!(reader.resume_at_byte(last + 1).is_err());
+}
+
+#[test]
+fn verify_indexed_samples_cover_all_indices() {
+    for fname in &["testsamples/variable_blocksize.flac",
+                   "testsamples/short_final_block.flac",
+                   "testsamples/surround_5_1.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let n_channels = reader.streaminfo().channels as usize;
+        let total = reader.streaminfo().samples.unwrap();
+        let mut next_index = vec![0u64; n_channels];
+        let mut n = 0;
+        for (i, item) in reader.indexed_samples().enumerate() {
+            let (channel, index, value) = item.unwrap();
+            assert_eq!(channel, i % n_channels);
+            assert_eq!(index, next_index[channel]);
+            assert_eq!(value, all[i]);
+            next_index[channel] += 1;
+            n += 1;
+        }
+
+        assert_eq!(n, all.len());
+        assert!(next_index.iter().all(|&i| i == total));
+    }
+}
+
+#[test]
+fn verify_decode_statistics_match_manual_scan() {
+    // All channels of this file are coded independently, so a channel is
+    // constant exactly when its subframe is.
+    let fname = "testsamples/surround_5_1.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut frames = 0;
+    let mut bytes = 0;
+    let mut subframes = 0;
+    let mut constant = 0;
+    for item in reader.frames() {
+        let (range, block) = item.unwrap();
+        frames += 1;
+        bytes += range.len;
+        subframes += block.channels() as u64;
+        constant += (0..block.channels()).filter(|&ch| block.is_channel_constant(ch).is_some()).count() as u64;
+    }
+    assert!(reader.statistics().is_none());
+
+    let opts = claxon::FlacReaderOptions {
+        collect_stats: true,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::open_ext(fname, opts).unwrap();
+    assert_eq!(reader.statistics(), Some(Default::default()));
+    for sample in reader.samples() {
+        sample.unwrap();
+    }
+
+    let stats = reader.statistics().unwrap();
+    assert_eq!(stats.frames, frames);
+    assert_eq!(stats.bytes_read, bytes);
+    assert_eq!(stats.subframe_type_counts.total(), subframes);
+    assert_eq!(stats.subframe_type_counts.constant, constant);
+    assert!(stats.total_decode_nanos > 0);
+}
+
+#[test]
+fn verify_decode_into_producer_matches_full_decode() {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    // A bounded queue, smaller than a block, so the decoder has to wait.
+    struct Producer {
+        queue: Arc<Mutex<VecDeque<i32>>>,
+        capacity: usize,
+    }
+
+    impl claxon::SampleProducer for Producer {
+        fn slots(&self) -> usize {
+            self.capacity - self.queue.lock().unwrap().len()
+        }
+
+        fn push(&mut self, sample: i32) {
+            self.queue.lock().unwrap().push_back(sample);
+        }
+    }
+
+    let fname = "testsamples/stereo_24bit.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let queue = Arc::new(Mutex::new(VecDeque::new()));
+    let done = Arc::new(AtomicBool::new(false));
+    let consumer = {
+        let queue = queue.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut drained = Vec::new();
+            loop {
+                let is_done = done.load(Ordering::SeqCst);
+                match queue.lock().unwrap().pop_front() {
+                    Some(sample) => drained.push(sample),
+                    None if is_done => return drained,
+                    None => thread::yield_now(),
+                }
+            }
+        })
+    };
+
+    let mut producer = Producer { queue: queue, capacity: 1000 };
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    reader.decode_into_producer(&mut producer).unwrap();
+    done.store(true, Ordering::SeqCst);
+
+    assert_eq!(consumer.join().unwrap(), expected);
+}
+
+#[test]
+fn verify_decode_frame_matches_sequential_decode() {
+    for &(fname, index) in &[("testsamples/silent_sections.flac", 3),
+                             ("testsamples/variable_blocksize.flac", 5),
+                             ("testsamples/short_final_block.flac", 3)] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut expected = Vec::new();
+        let mut n_frames = 0;
+        {
+            let mut blocks = reader.blocks();
+            while let Some(block) = blocks.read_next_or_eof(Vec::new()).unwrap() {
+                if n_frames == index {
+                    expected = block.into_buffer();
+                }
+                n_frames += 1;
+            }
+        }
+
+        // Decode a later frame first, so the reader is not at the start.
+        assert!(reader.decode_frame(index + 1).unwrap().is_some() || index + 1 == n_frames);
+        let block = reader.decode_frame(index).unwrap().unwrap();
+        assert_eq!(block.into_buffer(), expected);
+        assert!(reader.decode_frame(n_frames).unwrap().is_none());
+    }
+}
+
+#[test]
+fn verify_block_size_info() {
+    let reader = claxon::FlacReader::open("testsamples/cuesheet.flac").unwrap();
+    let info = reader.block_size_info();
+    assert!(info.is_uniform);
+    assert_eq!(info.min_block_size, 4096);
+    assert_eq!(info.max_block_size, 4096);
+    assert_eq!(info.recommended_chunk_size, 4096);
+
+    let reader = claxon::FlacReader::open("testsamples/variable_blocksize.flac").unwrap();
+    let info = reader.block_size_info();
+    assert!(!info.is_uniform);
+    assert_eq!(info.min_block_size, 17);
+    assert_eq!(info.max_block_size, 4096);
+    assert_eq!(info.recommended_chunk_size, 4096);
+}
+
+#[test]
+fn verify_channel_mask_defaults_without_tag() {
+    use claxon::metadata::{ChannelMask, SPEAKER_FRONT_CENTER};
+
+    let reader = claxon::FlacReader::open("testsamples/surround_5_1.flac").unwrap();
+    assert_eq!(reader.get_tag("WAVEFORMATEXTENSIBLE_CHANNEL_MASK").next(), None);
+    assert_eq!(reader.channel_mask(), Some(ChannelMask(0x3f)));
+
+    let reader = claxon::FlacReader::open("testsamples/identifiers.flac").unwrap();
+    assert_eq!(reader.channel_mask(), Some(ChannelMask(SPEAKER_FRONT_CENTER)));
+}
+
+#[test]
+fn verify_damaged_optional_metadata_is_skipped_in_lenient_mode() {
+    use std::io::{Cursor, Read};
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    // Insert a seek table after the streaminfo, with a length that is not a
+    // multiple of the seek point size. If the streaminfo was the last block,
+    // the seek table becomes the last block.
+    let mut damaged = data[..42].to_vec();
+    let mut block_type = claxon::metadata::BLOCK_TYPE_SEEKTABLE;
+    if damaged[4] & 0x80 != 0 {
+        damaged[4] &= 0x7f;
+        block_type |= 0x80;
+    }
+    damaged.extend_from_slice(&[block_type, 0, 0, 20]);
+    damaged.extend_from_slice(&[0xaa; 20]);
+    damaged.extend_from_slice(&data[42..]);
+
+    let strict = claxon::FlacReaderOptions {
+        strict: true,
+        ..Default::default()
+    };
+    assert!(claxon::FlacReader::new_ext(Cursor::new(damaged.clone()), strict).is_err());
+
+    let opts = claxon::FlacReaderOptions::default();
+    let mut reader = claxon::FlacReader::new_ext(Cursor::new(damaged), opts).unwrap();
+    assert_eq!(reader.skipped_metadata_blocks().len(), 1);
+    let (header, _) = reader.skipped_metadata_blocks()[0];
+    assert_eq!(header.block_type, claxon::metadata::BLOCK_TYPE_SEEKTABLE);
+    assert_eq!(header.length, 20);
+    assert_eq!(reader.metadata_blocks()[1], header);
+
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    let reader = claxon::FlacReader::new_ext(Cursor::new(data), opts).unwrap();
+    assert!(reader.skipped_metadata_blocks().is_empty());
+}
+
+#[test]
+fn verify_block_decorrelation_and_subframe_count() {
+    use claxon::frame::Decorrelation;
+
+    // The last frame of this file is coded as mid/side.
+    let mut reader = claxon::FlacReader::open("testsamples/silent_sections.flac").unwrap();
+    let mut blocks = reader.blocks();
+    let mut decorrelations = Vec::new();
+    while let Some(block) = blocks.read_next_or_eof(Vec::new()).unwrap() {
+        assert_eq!(block.channels(), 2);
+        assert_eq!(block.subframe_count(), 2);
+        decorrelations.push(block.decorrelation());
+    }
+    assert_eq!(decorrelations.len(), 5);
+    assert_eq!(decorrelations[4], Decorrelation::MidSide);
+
+    let mut reader = claxon::FlacReader::open("testsamples/surround_5_1.flac").unwrap();
+    let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    assert_eq!(block.subframe_count(), 6);
+    assert_eq!(block.decorrelation(), Decorrelation::Independent);
+}
+
+#[test]
+fn verify_decode_stereo_decorrelation_at_extremes() {
+    use claxon::frame::Decorrelation;
+
+    // Every frame of this file uses a different stereo decorrelation, and
+    // starts with samples at the extremes of the 24-bit range, for which the
+    // side channel needs all of its 25 bits. In strict mode, this also checks
+    // that the reconstructed samples pass the range check.
+    let opts = claxon::FlacReaderOptions { strict: true, ..Default::default() };
+    let mut reader = claxon::FlacReader::open_ext("testsamples/stereo_24bit_decorrelated.flac", opts).unwrap();
+    assert_eq!(reader.streaminfo().bits_per_sample, 24);
+    let mut blocks = reader.blocks();
+    let mut decorrelations = Vec::new();
+    while let Some(block) = blocks.read_next_or_eof(Vec::new()).unwrap() {
+        assert_eq!(&block.channel(0)[..4], &[-0x80_0000, 0x7f_ffff, -0x80_0000, 0x7f_ffff]);
+        assert_eq!(&block.channel(1)[..4], &[0x7f_ffff, -0x80_0000, -0x80_0000, 0x7f_ffff]);
+        decorrelations.push(block.decorrelation());
+    }
+    assert_eq!(decorrelations, vec![Decorrelation::MidSide,
+                                    Decorrelation::LeftSide,
+                                    Decorrelation::RightSide]);
+}
+
+#[test]
+fn verify_windows_reconstruct_channel() {
+    // The file has 3172 samples per channel in blocks of 1024, so windows
+    // straddle block boundaries, and the last one is partial.
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let expected = reader.channel_samples(1).unwrap();
+    assert_eq!(expected.len(), 3172);
+
+    // Without overlap, the windows concatenate to the channel, plus padding.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut joined = Vec::new();
+    for window in reader.windows(1, 300, 300, true) {
+        let window = window.unwrap();
+        assert_eq!(window.len(), 300);
+        joined.extend_from_slice(&window);
+    }
+    assert_eq!(joined.len(), 3300);
+    assert_eq!(&joined[..3172], &expected[..]);
+    assert!(joined[3172..].iter().all(|&s| s == 0));
+
+    // When the partial window is dropped, only full windows remain.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let count = reader.windows(1, 300, 300, false).map(|w| w.unwrap()).count();
+    assert_eq!(count, 10);
+
+    // Overlapping and sparse windows start every hop samples.
+    for &(window, hop) in &[(1000, 250), (100, 700)] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut n = 0;
+        for w in reader.windows(1, window, hop, false) {
+            let start = n * hop;
+            assert_eq!(&w.unwrap()[..], &expected[start..start + window]);
+            n += 1;
+        }
+        assert_eq!(n, (expected.len() - window) / hop + 1);
+    }
+}
+
+#[test]
+fn verify_stream_with_more_than_32_bits_of_samples() {
+    use std::time::Duration;
+
+    // This file declares (2^21 + 2) * 4096 samples, but only contains the last
+    // two frames, numbered 2^21 and 2^21 + 1. Their first samples lie beyond
+    // 2^33, so seeking into them requires 64-bit sample numbers throughout.
+    let fname = "testsamples/long_stream.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let total = ((1 << 21) + 2) * 4096;
+    assert!(total > 0xffff_ffff);
+    assert_eq!(reader.streaminfo().samples, Some(total));
+    assert_eq!(reader.streaminfo().duration(), Some(Duration::new(194_783, 283_083_900)));
+
+    let mut expected = Vec::new();
+    let mut blocks = reader.blocks();
+    while let Some(block) = blocks.read_next_or_eof(Vec::new()).unwrap() {
+        assert_eq!(block.time(), (1 << 33) + expected.len() as u64);
+        expected.extend_from_slice(block.channel(0));
+    }
+    assert_eq!(expected.len(), 8192);
+
+    let start = (1 << 33) + 5000;
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert_eq!(reader.seek_discard_count(start).unwrap(), 904);
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert_eq!(reader.decode_range(start, 100).unwrap(), &expected[5000..5100]);
+    assert_eq!(reader.decode_frame((1 << 21) + 1).unwrap().unwrap().channel(0), &expected[4096..]);
+}
+
+#[test]
+fn verify_raw_reader_formats() {
+    use claxon::raw::RawFormat;
+    use std::io::Read;
+
+    // Arbitrary sizes of reads must produce the same bytes.
+    fn read_all<R: Read>(mut reader: R) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut buf = [0u8; 1000];
+        loop {
+            match reader.read(&mut buf[..333]).unwrap() {
+                0 => return bytes,
+                n => bytes.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(&samples[..6], &[0, 6967, 817, 6365, 1627, 5061]);
+
+    let s16le = RawFormat { bits: 16, signed: true, little_endian: true };
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let bytes = read_all(reader.into_raw_reader(s16le));
+    assert_eq!(bytes.len(), samples.len() * 2);
+    assert_eq!(&bytes[..12], &[0x00, 0x00, 0x37, 0x1b, 0x31, 0x03, 0xdd, 0x18, 0x5b, 0x06, 0xc5, 0x13]);
+    for (i, &s) in samples.iter().enumerate() {
+        assert_eq!((bytes[i * 2] as u16 | (bytes[i * 2 + 1] as u16) << 8) as i16 as i32, s);
+    }
+
+    // For unsigned 8-bit output, the top byte is kept, and offset by 128.
+    let u8_ = RawFormat { bits: 8, signed: false, little_endian: true };
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let bytes = read_all(reader.into_raw_reader(u8_));
+    assert_eq!(bytes.len(), samples.len());
+    assert_eq!(&bytes[..6], &[128, 155, 131, 152, 134, 147]);
+    for (&b, &s) in bytes.iter().zip(samples.iter()) {
+        assert_eq!(b as i32 - 128, s >> 8);
+    }
+}
+
+#[test]
+fn verify_block_to_pcm_bytes() {
+    use claxon::raw::RawFormat;
+    use std::io::Read;
+
+    let s16le = RawFormat { bits: 16, signed: true, little_endian: true };
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    let bytes = block.to_pcm_bytes(s16le);
+    assert_eq!(bytes.len(), 1024 * 2 * 2);
+    assert_eq!(&bytes[..12], &[0x00, 0x00, 0x37, 0x1b, 0x31, 0x03, 0xdd, 0x18, 0x5b, 0x06, 0xc5, 0x13]);
+
+    // The bytes of all blocks together are what the raw reader produces.
+    let mut all_bytes = bytes;
+    let mut buffer = block.into_buffer();
+    while let Some(block) = reader.blocks().read_next_or_eof(buffer).unwrap() {
+        all_bytes.extend_from_slice(&block.to_pcm_bytes(s16le));
+        buffer = block.into_buffer();
+    }
+    let reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let mut raw_bytes = Vec::new();
+    reader.into_raw_reader(s16le).read_to_end(&mut raw_bytes).unwrap();
+    assert_eq!(all_bytes, raw_bytes);
+
+    // 20-bit samples take three bytes, left-justified.
+    let s24be = RawFormat { bits: 24, signed: true, little_endian: false };
+    let mut reader = claxon::FlacReader::open("testsamples/stereo_20bit.flac").unwrap();
+    let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+    assert_eq!(block.bits_per_sample(), 20);
+    let bytes = block.to_pcm_bytes(s24be);
+    assert_eq!(bytes.len() as u32, block.duration() * 2 * 3);
+    for i in 0..block.duration() {
+        for ch in 0..2 {
+            let k = ((i * 2 + ch) * 3) as usize;
+            let x = (bytes[k] as i32) << 24 | (bytes[k + 1] as i32) << 16 | (bytes[k + 2] as i32) << 8;
+            assert_eq!(x & 0xf00, 0);
+            assert_eq!(x >> 12, block.sample(ch, i));
+        }
+    }
+}
+
+#[test]
+fn verify_compression_profile() {
+    // Noise is stored verbatim, and it takes slightly more space than PCM.
+    let mut reader = claxon::FlacReader::open("testsamples/noise.flac").unwrap();
+    let profile = reader.compression_profile().unwrap();
+    assert_eq!(profile.subframe_type_counts.verbatim, 4);
+    assert_eq!(profile.subframe_type_counts.total(), 4);
+    assert_eq!(profile.verbatim_or_constant_fraction(), Some(1.0));
+    assert_eq!(profile.decoded_bytes, 2 * 2048 * 2 * 2);
+    assert!(profile.compression_ratio().unwrap() > 1.0);
+
+    // A tone is predicted well, so it compresses well.
+    let mut reader = claxon::FlacReader::open("testsamples/tone_lpc.flac").unwrap();
+    let profile = reader.compression_profile().unwrap();
+    assert_eq!(profile.subframe_type_counts.lpc, 6);
+    assert_eq!(profile.verbatim_or_constant_fraction(), Some(0.0));
+    assert!(profile.compression_ratio().unwrap() < 0.25);
+
+    // The frame bytes are those of the stream minus the metadata.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let audio_start = reader.frames().next().unwrap().unwrap().0.offset;
+    let file_len = fs::metadata("testsamples/short_final_block.flac").unwrap().len();
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let profile = reader.compression_profile().unwrap();
+    assert_eq!(profile.frame_bytes, file_len - audio_start);
+
+    // Nothing is left to decode afterwards.
+    assert_eq!(reader.compression_profile().unwrap().compression_ratio(), None);
+}
+
+#[test]
+fn verify_samples_decodes_lazily() {
+    let fname = "testsamples/short_final_block.flac";
+    let mut expected = Vec::new();
+    claxon::FlacReader::open(fname).unwrap().decode_all_into(&mut expected).unwrap();
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // Taking a few samples decodes only the first frame.
+    let opts = claxon::FlacReaderOptions {
+        collect_stats: true,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::open_ext(fname, opts).unwrap();
+    let first: Vec<i32> = reader.samples().take(10).map(|s| s.unwrap()).collect();
+    assert_eq!(&first[..], &expected[..10]);
+    assert_eq!(reader.statistics().unwrap().frames, 1);
+}
+
+#[test]
+fn verify_boundary_discontinuities() {
+    use claxon::BoundaryDiscontinuity;
+
+    // In a clean tone, the jumps at boundaries are like those within frames.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let maxima = reader.boundary_discontinuities().unwrap();
+    assert_eq!(maxima.len(), 2);
+    for max in &maxima {
+        let max = max.unwrap();
+        assert!(max.magnitude < 6000);
+        assert!([1024, 2048, 3072].contains(&max.position));
+    }
+
+    // The left channel steps up by 12000 at the start of the third frame.
+    let mut reader = claxon::FlacReader::open("testsamples/boundary_step.flac").unwrap();
+    let maxima = reader.boundary_discontinuities().unwrap();
+    let left = maxima[0].unwrap();
+    assert_eq!(left.position, 2048);
+    assert!(left.magnitude > 11000);
+    assert!(maxima[1].unwrap().magnitude < 3000);
+
+    // A stream of a single frame has no boundaries.
+    let mut reader = claxon::FlacReader::open("testsamples/gain.flac").unwrap();
+    let maxima: Vec<Option<BoundaryDiscontinuity>> = reader.boundary_discontinuities().unwrap();
+    assert_eq!(maxima, vec![None]);
+}
+
+#[test]
+fn verify_is_silent() {
+    let opts = claxon::FlacReaderOptions {
+        collect_stats: true,
+        ..Default::default()
+    };
+
+    // All frames of this file are silent. One of them is coded verbatim, for
+    // which the decoded samples must be checked.
+    let mut reader = claxon::FlacReader::open_ext("testsamples/silent.flac", opts).unwrap();
+    assert!(reader.is_silent().unwrap());
+    let stats = reader.statistics().unwrap();
+    assert_eq!(stats.frames, 4);
+    assert_eq!(stats.subframe_type_counts.verbatim, 2);
+
+    // Decoding stops after the first frame that contains sound.
+    for fname in &["testsamples/short_final_block.flac", "testsamples/silent_sections.flac"] {
+        let mut reader = claxon::FlacReader::open_ext(fname, opts).unwrap();
+        assert!(!reader.is_silent().unwrap());
+        assert_eq!(reader.statistics().unwrap().frames, 1);
+    }
+
+    // Only the remainder of the stream is considered. The second and third
+    // frames of this file are silent, the fourth is not.
+    let mut reader = claxon::FlacReader::open_ext("testsamples/silent_sections.flac", opts).unwrap();
+    reader.blocks().read_next_or_eof(Vec::new()).unwrap();
+    assert!(!reader.is_silent().unwrap());
+    assert_eq!(reader.statistics().unwrap().frames, 4);
+}
+
+#[test]
+fn verify_original_container_reconstructs_wav() {
+    use claxon::raw::RawFormat;
+    use std::io::Read;
+
+    // This file stores the chunks of foreign_metadata.wav in application
+    // blocks, like `flac --keep-foreign-metadata` does.
+    let mut original = Vec::new();
+    fs::File::open("testsamples/foreign_metadata.wav").unwrap().read_to_end(&mut original).unwrap();
+
+    let reader = claxon::FlacReader::open("testsamples/foreign_metadata.flac").unwrap();
+    assert_eq!(reader.foreign_metadata().unwrap().application_id(), claxon::metadata::APPLICATION_ID_RIFF);
+    let mut wav = reader.original_container_header().unwrap();
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[wav.len() - 8..wav.len() - 4], b"data");
+    let trailer = reader.original_container_trailer().unwrap();
+
+    let format = RawFormat { bits: 16, signed: true, little_endian: true };
+    reader.into_raw_reader(format).read_to_end(&mut wav).unwrap();
+    wav.extend_from_slice(&trailer);
+    assert!(wav == original);
+
+    // Without foreign metadata, there is nothing to reconstruct.
+    let reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    assert!(reader.foreign_metadata().is_none());
+    assert_eq!(reader.original_container_header(), None);
+}
+
+#[test]
+fn verify_extra_frames_beyond_streaminfo() {
+    // The streaminfo of this file declares 2048 samples, but it contains a
+    // third frame of 1024 samples.
+    let fname = "testsamples/extra_frame.flac";
+    let strict = claxon::FlacReaderOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::open_ext(fname, strict).unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(2048));
+    {
+        let mut samples = reader.samples();
+        for _ in 0..2048 * 2 {
+            samples.next().unwrap().unwrap();
+        }
+        assert!(!samples.has_extra_samples());
+        match samples.next() {
+            Some(Err(claxon::Error::FormatError(..))) => {}
+            other => panic!("expected format error, got {:?}", other.map(|r| r.is_ok())),
+        }
+        assert!(samples.has_extra_samples());
+        assert!(samples.next().is_none());
+    }
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut samples = reader.samples();
+    let mut count = 0;
+    while let Some(sample) = samples.next() {
+        sample.unwrap();
+        count += 1;
+    }
+    assert_eq!(count, 3072 * 2);
+    assert!(samples.has_extra_samples());
+    assert!(!samples.is_truncated());
+
+    // A stream with the declared number of samples has no extra samples.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let mut samples = reader.samples();
+    while let Some(sample) = samples.next() {
+        sample.unwrap();
+    }
+    assert!(!samples.has_extra_samples());
+}
+
+#[test]
+fn verify_decode_split_stream() {
+    use claxon::input::ChainReader;
+    use std::io::Read;
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    // Split the file inside the metadata, and inside a frame.
+    let (a, b) = (20, data.len() / 2 + 7);
+    let parts = vec![io::Cursor::new(data[..a].to_vec()),
+                     io::Cursor::new(data[a..b].to_vec()),
+                     io::Cursor::new(data[b..].to_vec())];
+    let mut reader = claxon::FlacReader::new(ChainReader::new(parts)).unwrap();
+    assert_eq!(reader.streaminfo().samples, Some(3172));
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert!(samples == expected);
+}
+
+#[test]
+fn verify_streaminfo_bytes_reparse_to_streaminfo() {
+    use claxon::metadata::{self, MetadataBlock};
+    use std::io::Read;
+
+    for fname in &["testsamples/short_final_block.flac",
+                   "testsamples/variable_blocksize.flac",
+                   "testsamples/long_stream.flac"] {
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let bytes = reader.streaminfo_bytes().unwrap();
+
+        // The streaminfo block is the first block, after the stream header
+        // and the block header.
+        let mut data = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(&bytes[..], &data[8..42]);
+
+        let mut cursor = io::Cursor::new(&bytes[..]);
+        match metadata::read_metadata_block(&mut cursor, metadata::BLOCK_TYPE_STREAMINFO, 34).unwrap() {
+            MetadataBlock::StreamInfo(info) => assert_eq!(info, reader.streaminfo()),
+            _ => panic!("expected streaminfo block"),
+        }
+    }
+}
+
+#[test]
+fn verify_sniff_requires_a_decodable_frame() {
+    use std::io::Read;
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let (first, _) = reader.frames().next().unwrap().unwrap();
+    let audio_start = first.offset as usize;
+
+    let info = claxon::FlacReader::sniff(io::Cursor::new(&data[..])).unwrap();
+    assert_eq!(info, reader.streaminfo());
+
+    // Only the first frame needs to be present.
+    let end = audio_start + first.len as usize;
+    assert!(claxon::FlacReader::sniff(io::Cursor::new(&data[..end])).is_ok());
+
+    // Without a complete first frame, the stream is rejected.
+    assert!(claxon::FlacReader::sniff(io::Cursor::new(&data[..audio_start])).is_err());
+    assert!(claxon::FlacReader::sniff(io::Cursor::new(&data[..end - 1])).is_err());
+    let mut damaged = data.clone();
+    damaged[audio_start + 20] ^= 0x10;
+    assert!(claxon::FlacReader::sniff(io::Cursor::new(&damaged[..])).is_err());
+}
+
+#[test]
+fn verify_sample_arrays_match_samples() {
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let samples: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let arrays: Vec<[i32; 2]> = reader.sample_arrays().map(|r| r.unwrap()).collect();
+    assert_eq!(arrays.len() * 2, samples.len());
+    for (array, chunk) in arrays.iter().zip(samples.chunks(2)) {
+        assert_eq!(&array[..], chunk);
+    }
+
+    let mut reader = claxon::FlacReader::open("testsamples/surround_5_1.flac").unwrap();
+    let samples: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open("testsamples/surround_5_1.flac").unwrap();
+    let arrays: Vec<[i32; 6]> = reader.sample_arrays().map(|r| r.unwrap()).collect();
+    assert_eq!(arrays.len(), 2500);
+    for (array, chunk) in arrays.iter().zip(samples.chunks(6)) {
+        assert_eq!(&array[..], chunk);
+    }
+}
+
+#[test]
+#[should_panic]
+fn verify_sample_arrays_panics_on_channel_mismatch() {
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let _ = reader.sample_arrays::<[i32; 1]>();
+}
+
+#[test]
+#[cfg(feature = "dasp")]
+fn verify_signal_matches_samples() {
+    use dasp::Signal;
+
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let samples: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let mut signal = reader.signal::<[i32; 2]>();
+    let frames: Vec<[i32; 2]> = signal.by_ref().until_exhausted().collect();
+    assert_eq!(frames.len() * 2, samples.len());
+    for (frame, chunk) in frames.iter().zip(samples.chunks(2)) {
+        assert_eq!(&frame[..], chunk);
+    }
+    assert!(signal.is_exhausted());
+    assert_eq!(signal.next(), [0, 0]);
+    assert_eq!(signal.into_result(), Ok(()));
+
+    // A decode error ends the signal, and can be retrieved afterwards.
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 1);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let signal = reader.signal::<[i32; 1]>();
+    assert!(signal.is_exhausted());
+    assert_eq!(signal.into_result(),
+               Err(claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+#[cfg(feature = "rodio")]
+fn verify_source_matches_samples() {
+    use rodio::Source;
+
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let bits = reader.streaminfo().bits_per_sample;
+    let samples: Vec<f32> = reader.samples().map(|r| claxon::sample::to_f32(r.unwrap(), bits)).collect();
+
+    let reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let n = reader.streaminfo().samples.unwrap();
+    let mut source = reader.into_source();
+    assert_eq!(source.channels(), 2);
+    assert_eq!(source.sample_rate(), 44100);
+    let nanos = (n % 44100) * 1_000_000_000 / 44100;
+    assert_eq!(source.total_duration(), Some(::std::time::Duration::new(n / 44100, nanos as u32)));
+
+    // Pull the samples span by span, as rodio does.
+    let mut played = Vec::new();
+    loop {
+        let span_len = source.current_span_len().unwrap();
+        if span_len == 0 {
+            break
+        }
+        played.extend(source.by_ref().take(span_len));
+    }
+    assert_eq!(played, samples);
+    assert_eq!(source.next(), None);
+    assert!(source.error().is_none());
+
+    // A decode error ends the source, and can be retrieved afterwards.
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 6);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let mut source = reader.into_source();
+    assert_eq!(source.current_span_len(), Some(0));
+    assert_eq!(source.next(), None);
+    assert_eq!(source.error(),
+               Some(&claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+fn verify_deadline_in_the_past_aborts_after_one_frame() {
+    use std::time::Instant;
+    let opts = claxon::FlacReaderOptions {
+        deadline: Some(Instant::now()),
+        .. claxon::FlacReaderOptions::default()
+    };
+    let exceeded = || claxon::Error::DeadlineExceeded { decoded_samples: 1024 };
+
+    let mut reader = claxon::FlacReader::open_ext("testsamples/short_final_block.flac", opts).unwrap();
+    {
+        let mut blocks = reader.blocks();
+        assert_eq!(blocks.read_next_or_eof(Vec::new()).unwrap().unwrap().duration(), 1024);
+        assert_eq!(blocks.read_next_or_eof(Vec::new()).err(), Some(exceeded()));
+    }
+
+    let mut reader = claxon::FlacReader::open_ext("testsamples/short_final_block.flac", opts).unwrap();
+    let results: Vec<_> = reader.samples().collect();
+    assert_eq!(results.len(), 2 * 1024 + 1);
+    assert!(results[..2 * 1024].iter().all(|r| r.is_ok()));
+    assert_eq!(results[2 * 1024].as_ref().err(), Some(&exceeded()));
+
+    let mut reader = claxon::FlacReader::open_ext("testsamples/short_final_block.flac", opts).unwrap();
+    let results: Vec<_> = reader.frames().collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().err(), Some(&exceeded()));
+
+    // Without a deadline, the stream decodes fully.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    assert_eq!(reader.samples().filter(|r| r.is_ok()).count(), 2 * (3 * 1024 + 100));
+}
+
+#[test]
+fn verify_max_input_bytes_decodes_complete_frames() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/stereo_28bit.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    let mut ranges = Vec::new();
+    let mut all_samples = Vec::new();
+    {
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        for frame in reader.frames() {
+            let (range, block) = frame.unwrap();
+            ranges.push((range, all_samples.len()));
+            for i in 0..block.duration() {
+                for ch in 0..block.channels() {
+                    all_samples.push(block.sample(ch, i));
+                }
+            }
+        }
+    }
+
+    // A limit in the middle of a frame, and one exactly after a frame, both
+    // keep the frames that end before the limit.
+    let limit = 50 * 1024;
+    let (cut, cut_start) = *ranges.iter().find(|&&(r, _)| r.offset + r.len > limit).unwrap();
+    assert!(cut.offset < limit);
+    for &limit in &[limit, cut.offset] {
+        let opts = claxon::FlacReaderOptions {
+            max_input_bytes: Some(limit),
+            .. claxon::FlacReaderOptions::default()
+        };
+        let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data), opts).unwrap();
+        let mut samples = reader.samples();
+        let decoded: Vec<i32> = samples.by_ref().map(|s| s.unwrap()).collect();
+        assert_eq!(&decoded[..], &all_samples[..cut_start]);
+        assert!(samples.is_truncated());
+    }
+
+    // Without the limit, the same prefix is an error.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..limit as usize])).unwrap();
+    assert!(reader.samples().any(|s| s.is_err()));
+}
+
+#[test]
+fn verify_max_memory_bounds_frame_buffers() {
+    let opts = |max_memory| claxon::FlacReaderOptions {
+        max_memory: Some(max_memory),
+        .. claxon::FlacReaderOptions::default()
+    };
+
+    // Blocks of 4096 stereo samples take 32 KiB, on top of the read buffer.
+    let mut reader = claxon::FlacReader::open_ext("testsamples/cuesheet.flac", opts(16 * 1024)).unwrap();
+    let result = reader.blocks().read_next_or_eof(Vec::new());
+    assert_eq!(result.err(), Some(claxon::Error::MemoryBudgetExceeded {
+        needed_bytes: 2048 + 4096 * 2 * 4,
+        budget_bytes: 16 * 1024,
+    }));
+
+    // Blocks of 1024 stereo samples fit in the same budget.
+    let mut reader = claxon::FlacReader::open_ext("testsamples/dc_offset.flac", opts(16 * 1024)).unwrap();
+    assert_eq!(reader.samples().filter(|r| r.is_ok()).count(), 2 * 3000);
+
+    // The budget is inclusive, and it covers the padding of the final block.
+    let exact = claxon::FlacReaderOptions {
+        pad_final_block: true,
+        .. opts(2048 + 1024 * 2 * 4)
+    };
+    let mut reader = claxon::FlacReader::open_ext("testsamples/short_final_block.flac", exact).unwrap();
+    assert_eq!(reader.samples().filter(|r| r.is_ok()).count(), 2 * 4 * 1024);
+    let mut reader = claxon::FlacReader::open_ext("testsamples/short_final_block.flac",
+                                                  opts(2048 + 1024 * 2 * 4 - 1)).unwrap();
+    assert!(reader.samples().next().unwrap().is_err());
+
+    // A budget smaller than the read buffer is rejected up front.
+    match claxon::FlacReader::open_ext("testsamples/dc_offset.flac", opts(1024)) {
+        Err(claxon::Error::MemoryBudgetExceeded { needed_bytes: 2048, budget_bytes: 1024 }) => {}
+        _ => panic!("expected the memory budget to be exceeded"),
+    }
+}
+
+#[test]
+fn verify_flac_equal_and_flac_difference() {
+    use claxon::Difference;
+    let open = |name: &str| fs::File::open(Path::new("testsamples").join(name)).unwrap();
+
+    assert!(claxon::flac_equal(open("short_final_block.flac"), open("short_final_block.flac")).unwrap());
+
+    // The same audio in differently sized blocks is equal.
+    assert!(claxon::flac_equal(open("short_final_block.flac"),
+                               open("short_final_block_reblocked.flac")).unwrap());
+
+    // One sample of the right channel has been incremented.
+    assert!(!claxon::flac_equal(open("short_final_block.flac"),
+                                open("short_final_block_altered.flac")).unwrap());
+    assert_eq!(claxon::flac_difference(open("short_final_block.flac"),
+                                       open("short_final_block_altered.flac")).unwrap(),
+               Some(Difference::Sample { index: 2000, channel: 1 }));
+
+    assert_eq!(claxon::flac_difference(open("short_final_block.flac"),
+                                       open("surround_5_1.flac")).unwrap(),
+               Some(Difference::Format));
+
+    // Cut the stream after two frames, and fix up the streaminfo.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let range = reader.frames().nth(1).unwrap().unwrap().0;
+    let end = (range.offset + range.len) as usize;
+    let mut data = Vec::new();
+    io::Read::read_to_end(&mut open("short_final_block.flac"), &mut data).unwrap();
+    let mut shortened = io::Cursor::new(Vec::new());
+    claxon::repair_streaminfo(&data[..end], &mut shortened).unwrap();
+    assert_eq!(claxon::flac_difference(open("short_final_block.flac"), &shortened.get_ref()[..]).unwrap(),
+               Some(Difference::Length { index: 2048 }));
+}
+
+#[test]
+fn verify_decode_odd_bit_depths() {
+    use claxon::raw::RawFormat;
+    use std::io::Read;
+
+    for &bps in &[12, 20, 28] {
+        let fname = format!("testsamples/stereo_{}bit.flac", bps);
+        let lo = -(1 << (bps - 1));
+        let hi = (1 << (bps - 1)) - 1;
+
+        // Every frame starts with the extremes of the range, and uses a
+        // different stereo decorrelation.
+        let mut reader = claxon::FlacReader::open(&fname).unwrap();
+        assert_eq!(reader.streaminfo().bits_per_sample, bps);
+        let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples.len(), 2 * 2548);
+        for &start in &[0, 1024, 2048] {
+            assert_eq!(&samples[2 * start..2 * start + 8], &[lo, hi, hi, lo, lo, lo, hi, hi]);
+        }
+
+        let mut reader = claxon::FlacReader::open(&fname).unwrap();
+        assert!(reader.full_check().unwrap().is_ok());
+
+        // Converted to 32 bits, the samples span the full range.
+        let mut reader = claxon::FlacReader::open(&fname).unwrap();
+        let wide: Vec<i32> = reader.samples_at_bit_depth(32).map(|s| s.unwrap()).collect();
+        assert_eq!(wide[0], i32::min_value());
+        assert_eq!(wide[1], hi << (32 - bps));
+        for (&w, &s) in wide.iter().zip(samples.iter()) {
+            assert_eq!(w >> (32 - bps), s);
+        }
+
+        // Raw output rounds the container up to whole bytes, as WAV does, and
+        // shifts the samples up to fill it.
+        let container = (bps + 7) / 8 * 8;
+        let format = RawFormat { bits: container, signed: true, little_endian: true };
+        let mut bytes = Vec::new();
+        let reader = claxon::FlacReader::open(&fname).unwrap();
+        reader.into_raw_reader(format).read_to_end(&mut bytes).unwrap();
+        let n_bytes = container as usize / 8;
+        assert_eq!(bytes.len(), samples.len() * n_bytes);
+        for (chunk, &s) in bytes.chunks(n_bytes).zip(samples.iter()) {
+            let mut x = 0i32;
+            for (k, &b) in chunk.iter().enumerate() {
+                x |= (b as i32) << (8 * k + 32 - container as usize);
+            }
+            assert_eq!(x >> (32 - bps), s);
+        }
+    }
+}
+
+#[test]
+fn verify_build_index_matches_frame_positions() {
+    for &fname in &["testsamples/short_final_block.flac",
+                    "testsamples/variable_blocksize.flac",
+                    "testsamples/large_sample_number.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let index = reader.build_index().unwrap();
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let frames: Vec<_> = reader.frames().map(|f| f.unwrap()).collect();
+        assert_eq!(index.len(), frames.len());
+        for (&(sample, offset), &(ref range, ref block)) in index.iter().zip(frames.iter()) {
+            assert_eq!(offset, range.offset);
+            assert_eq!(sample, block.time());
+        }
+
+        // Seeking to the first sample of a frame lands on that frame.
+        let first = index[0].0;
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        for (&(sample, _), &(_, ref block)) in index.iter().zip(frames.iter()).rev() {
+            assert_eq!(reader.seek_discard_count(sample - first).unwrap(), 0);
+            let decoded = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+            assert_eq!(decoded.time(), sample);
+            assert_eq!(decoded.channel(0), block.channel(0));
+        }
+    }
+}
+
+#[test]
+fn verify_write_with_seek_table() {
+    use std::io::Read;
+
+    let fname = "testsamples/variable_blocksize.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    assert!(reader.seek_table().is_none());
+    let audio_len = data.len() - reader.metadata_blocks().iter().fold(4, |n, h| n + 4 + h.length as usize);
+
+    let mut with_table = Vec::new();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    reader.write_with_seek_table(&mut with_table, 2000).unwrap();
+    assert!(claxon::flac_equal(&data[..], &with_table[..]).unwrap());
+
+    // There is no padding, so the metadata grows by the seek table. Blocks
+    // start at samples 0, 1152, 5248, 5824, 6124, and 8124.
+    assert_eq!(with_table.len(), data.len() + 4 + 4 * 18);
+    assert_eq!(&with_table[with_table.len() - audio_len..], &data[data.len() - audio_len..]);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&with_table)).unwrap();
+    let index = reader.build_index().unwrap();
+    let first_frame = index[0].1;
+    let reader = claxon::FlacReader::new(io::Cursor::new(&with_table)).unwrap();
+    let points: Vec<(u64, u64, u16)> = reader.seek_table().unwrap().seekpoints().iter()
+        .map(|p| (p.sample, p.offset, p.samples)).collect();
+    assert_eq!(points, vec![(0, index[0].1 - first_frame, 1152),
+                            (1152, index[1].1 - first_frame, 4096),
+                            (5824, index[3].1 - first_frame, 300),
+                            (6124, index[4].1 - first_frame, 2000)]);
+
+    // Seeking into the last frame decodes the frames from the last seek point
+    // onwards only. Without a seek table, the frame is found by bisection.
+    let opts = claxon::FlacReaderOptions { collect_stats: true, .. claxon::FlacReaderOptions::default() };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data), opts).unwrap();
+    reader.seek_to_sample(8130).unwrap();
+    assert_eq!(reader.statistics().unwrap().frames, 1);
+    let expected = reader.decode_range(8130, 5).unwrap();
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&with_table), opts).unwrap();
+    reader.seek_to_sample(8130).unwrap();
+    assert_eq!(reader.statistics().unwrap().frames, 2);
+    assert_eq!(reader.decode_range(8130, 5).unwrap(), expected);
+
+    // Trimming replaces the seek table by padding of the same size, which a
+    // new seek table then reuses, so the size stays the same.
+    let mut trimmed = Vec::new();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&with_table)).unwrap();
+    reader.trim_frames(0, 100, &mut trimmed).unwrap();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&trimmed)).unwrap();
+    assert!(reader.seek_table().is_none());
+    assert_eq!(reader.total_padding(), 4 * 18);
+    let mut again = Vec::new();
+    reader.write_with_seek_table(&mut again, 2000).unwrap();
+    assert_eq!(again.len(), trimmed.len());
+    let reader = claxon::FlacReader::new(io::Cursor::new(&again)).unwrap();
+    assert_eq!(reader.seek_table().unwrap().seekpoints().len(), 4);
+    assert_eq!(reader.total_padding(), 0);
+    assert!(claxon::flac_equal(&data[..], &again[..]).unwrap());
+}
+
+#[test]
+fn verify_rewrite_tags() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/identifiers.flac").unwrap().read_to_end(&mut data).unwrap();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let audio_len = data.len() - reader.metadata_blocks().iter().fold(4, |n, h| n + 4 + h.length as usize);
+    assert_eq!(reader.total_padding(), 0);
+
+    let tags = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+        pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    };
+    let tags_of = |reader: &claxon::FlacReader<io::Cursor<&Vec<u8>>>| -> Vec<(String, String)> {
+        reader.tags().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    };
+
+    // The new comments are smaller, and there is no padding, so the space
+    // that they free becomes a padding block.
+    let new_tags = tags(&[("TITLE", "Rewritten"), ("ARTIST", "Claxon")]);
+    let mut rewritten = Vec::new();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    reader.rewrite_tags(&new_tags, &mut rewritten).unwrap();
+    assert_eq!(rewritten.len(), data.len());
+    assert_eq!(&rewritten[rewritten.len() - audio_len..], &data[data.len() - audio_len..]);
+    assert!(claxon::flac_equal(&data[..], &rewritten[..]).unwrap());
+    let reader = claxon::FlacReader::new(io::Cursor::new(&rewritten)).unwrap();
+    assert_eq!(tags_of(&reader), new_tags);
+    assert_eq!(reader.vendor(), Some("claxon test fixture"));
+    let padding = reader.total_padding();
+    assert!(padding > 0);
+
+    // Growing the comments again consumes the padding.
+    let new_tags = tags(&[("TITLE", "Rewritten again"), ("ARTIST", "Claxon"), ("GENRE", "Test")]);
+    let mut again = Vec::new();
+    reader.rewrite_tags(&new_tags, &mut again).unwrap();
+    assert_eq!(again.len(), data.len());
+    let reader = claxon::FlacReader::new(io::Cursor::new(&again)).unwrap();
+    assert_eq!(tags_of(&reader), new_tags);
+    // The title grows by 6 bytes, the genre takes 4 bytes of length and 10 bytes.
+    assert_eq!(reader.total_padding(), padding - 6 - (4 + 10));
+    assert!(claxon::flac_equal(&data[..], &again[..]).unwrap());
+
+    // Without a Vorbis comment block, one is added after the streaminfo.
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+    let new_tags = tags(&[("TITLE", "Added")]);
+    let mut added = Vec::new();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    reader.rewrite_tags(&new_tags, &mut added).unwrap();
+    // A block header, vendor length, comment count, comment length, comment.
+    assert_eq!(added.len(), data.len() + 4 + 4 + 4 + 4 + 11);
+    let reader = claxon::FlacReader::new(io::Cursor::new(&added)).unwrap();
+    assert_eq!(reader.metadata_blocks()[1].block_type, 4);
+    assert_eq!(tags_of(&reader), new_tags);
+    assert_eq!(reader.vendor(), Some(""));
+    assert!(claxon::flac_equal(&data[..], &added[..]).unwrap());
+}
+
+#[test]
+fn verify_seek_with_wrong_seek_table_offsets() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/variable_blocksize.flac").unwrap().read_to_end(&mut data).unwrap();
+    let mut with_table = Vec::new();
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    reader.write_with_seek_table(&mut with_table, 2000).unwrap();
+
+    // The seek table follows the streaminfo. Its points are at samples 0,
+    // 1152, 5824, and 6124. Make the offset of the point at 1152 that of the
+    // frame at 6124, which lies beyond targets before 5824. Make the point at
+    // 5824 point into the middle of the first frame, so the next frame is at
+    // 1152. Make the point at 6124 that of the frame at 5824, a valid frame
+    // with a different sample number than the point claims.
+    let offset = |table: &[u8], i: usize| -> Vec<u8> { table[46 + 18 * i + 8..46 + 18 * i + 16].to_vec() };
+    let mut wrong = with_table.clone();
+    let (off2, off3) = (offset(&with_table, 2), offset(&with_table, 3));
+    wrong[46 + 18 + 8..46 + 18 + 16].copy_from_slice(&off3);
+    wrong[46 + 36 + 8..46 + 36 + 16].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 5]);
+    wrong[46 + 54 + 8..46 + 54 + 16].copy_from_slice(&off2);
+
+    for &target in &[0, 1200, 2000, 5830, 6000, 6130, 8130] {
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        let expected = reader.decode_range(target, 5).unwrap();
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&wrong)).unwrap();
+        assert_eq!(reader.decode_range(target, 5).unwrap(), expected, "target {}", target);
+    }
+}
+
+#[test]
+fn verify_block_subframe_type() {
+    use claxon::subframe::SubframeType;
+
+    // The fixture has a frame for every kind of subframe.
+    let mut reader = claxon::FlacReader::open("testsamples/predictor_orders.flac").unwrap();
+    let types: Vec<SubframeType> = reader.blocks().map(|b| b.unwrap().subframe_type(0)).collect();
+    assert_eq!(types, vec![SubframeType::Constant,
+                           SubframeType::Verbatim,
+                           SubframeType::Fixed(0),
+                           SubframeType::Fixed(1),
+                           SubframeType::Fixed(2),
+                           SubframeType::Fixed(3),
+                           SubframeType::Fixed(4),
+                           SubframeType::Lpc(1),
+                           SubframeType::Lpc(8),
+                           SubframeType::Lpc(12)]);
+
+    // The distribution over all subframes matches the statistics.
+    for &fname in &["testsamples/surround_5_1.flac", "testsamples/silent_sections.flac"] {
+        let opts = claxon::FlacReaderOptions { collect_stats: true, .. claxon::FlacReaderOptions::default() };
+        let mut reader = claxon::FlacReader::open_ext(fname, opts).unwrap();
+        let mut counts = claxon::frame::SubframeTypeCounts::default();
+        for block in reader.blocks() {
+            let block = block.unwrap();
+            for i in 0..block.channels() {
+                match block.subframe_type(i) {
+                    SubframeType::Constant => counts.constant += 1,
+                    SubframeType::Verbatim => counts.verbatim += 1,
+                    SubframeType::Fixed(order) => {
+                        assert!(order <= 4);
+                        counts.fixed += 1;
+                    }
+                    SubframeType::Lpc(order) => {
+                        assert!(order >= 1 && order <= 32);
+                        counts.lpc += 1;
+                    }
+                }
+            }
+        }
+        assert_eq!(counts, reader.statistics().unwrap().subframe_type_counts);
+        assert!(counts.total() > 0);
+    }
+}
+
+#[test]
+fn verify_open_matches_manual_buffered_reader() {
+    // FlacReader::open() reads from the file directly, it buffers internally.
+    // This must decode the same as wrapping the file in a BufReader manually.
+    let fname = "testsamples/short_final_block.flac";
+    let mut opened = claxon::FlacReader::open(fname).unwrap();
+    let file = fs::File::open(fname).unwrap();
+    let mut manual = claxon::FlacReader::new(io::BufReader::new(file)).unwrap();
+
+    assert_eq!(opened.streaminfo(), manual.streaminfo());
+    let a: Vec<i32> = opened.samples().map(|s| s.unwrap()).collect();
+    let b: Vec<i32> = manual.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(a.len(), 2 * (3 * 1024 + 100));
+    assert_eq!(a, b);
+}
+
+#[test]
+fn verify_lyrics() {
+    use claxon::metadata::Lyrics;
+    use std::time::Duration;
+
+    // The LYRICS tag takes precedence over UNSYNCEDLYRICS, with any casing.
+    let reader = claxon::FlacReader::open("testsamples/lyrics.flac").unwrap();
+    let expected = vec![
+        (Duration::new(1, 500_000_000), "La la la".to_string()),
+        (Duration::new(3, 250_000_000), "La la".to_string()),
+    ];
+    assert_eq!(reader.lyrics(), Some(Lyrics::Synced(expected)));
+    assert_eq!(reader.get_tag("UNSYNCEDLYRICS").next(), Some("La la la\r\nLa la"));
+
+    let reader = claxon::FlacReader::open("testsamples/identifiers.flac").unwrap();
+    assert_eq!(reader.lyrics(), None);
+}
+
+#[test]
+fn verify_open_any_detects_container() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    // A native stream.
+    let mut reader = claxon::FlacReader::open_any(io::Cursor::new(&data)).unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // An ID3v2.4 tag of 200 bytes with a footer, then the stream. The size
+    // 200 is stored as the syncsafe bytes 00 00 01 48.
+    let mut tagged = b"ID3\x04\x00\x10\x00\x00\x01\x48".to_vec();
+    tagged.extend(vec![0u8; 200]);
+    tagged.extend(b"3DI\x04\x00\x10\x00\x00\x01\x48".iter().cloned());
+    tagged.extend(data.iter().cloned());
+    assert!(claxon::FlacReader::new(io::Cursor::new(&tagged)).is_err());
+    let mut reader = claxon::FlacReader::open_any(io::Cursor::new(&tagged)).unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // Seeking works relative to the end of the tag.
+    let mut reader = claxon::FlacReader::open_any(io::Cursor::new(&tagged)).unwrap();
+    reader.seek_to_sample(2000).unwrap();
+    let mut buffer = [0.0f32; 2];
+    assert_eq!(reader.read_interleaved_f32(&mut buffer).unwrap(), 2);
+    assert_eq!(buffer[0], expected[4000] as f32 / 32768.0);
+
+    // An Ogg FLAC stream is recognized, but not supported.
+    let ogg = b"OggS\x00\x02\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    match claxon::FlacReader::open_any(io::Cursor::new(&ogg)) {
+        Err(claxon::Error::Unsupported(msg)) => assert_eq!(msg, "Ogg FLAC is not supported"),
+        _ => panic!("expected Ogg FLAC to be unsupported"),
+    }
+}
+
+#[test]
+fn verify_max_metadata_blocks() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // Insert `n` empty padding blocks after the streaminfo, the only block.
+    let with_padding = |n: usize| {
+        let mut out = data[..42].to_vec();
+        out[4] &= 0x7f;
+        for i in 0..n {
+            let is_last = if i + 1 == n { 0x80 } else { 0 };
+            out.extend_from_slice(&[0x01 | is_last, 0, 0, 0]);
+        }
+        out.extend_from_slice(&data[42..]);
+        out
+    };
+
+    // With the default cap of 1024, a thousand blocks are fine, and the
+    // streaminfo counts towards the cap.
+    let stream = with_padding(1023);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&stream)).unwrap();
+    assert_eq!(reader.metadata_blocks().len(), 1024);
+    assert_eq!(reader.samples().count(), 2 * 3172);
+
+    let stream = with_padding(5000);
+    assert_eq!(claxon::FlacReader::new(io::Cursor::new(&stream)).err(),
+               Some(claxon::Error::TooManyMetadataBlocks));
+
+    let opts = claxon::FlacReaderOptions {
+        max_metadata_blocks: 10,
+        ..Default::default()
+    };
+    assert!(claxon::FlacReader::new_ext(io::Cursor::new(with_padding(9)), opts).is_ok());
+    assert_eq!(claxon::FlacReader::new_ext(io::Cursor::new(with_padding(10)), opts).err(),
+               Some(claxon::Error::TooManyMetadataBlocks));
+}
+
+#[test]
+fn verify_samples_at_bit_depth_dithered() {
+    use claxon::sample::Dither;
+
+    let fname = "testsamples/stereo_24bit.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let truncated: Vec<i32> = reader.samples_at_bit_depth(16).map(|s| s.unwrap()).collect();
+
+    // Without dither, the result is pure truncation.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let none: Vec<i32> = reader.samples_at_bit_depth_dithered(16, Dither::None, 1)
+                               .map(|s| s.unwrap())
+                               .collect();
+    assert_eq!(none, truncated);
+
+    // With triangular dither, samples differ, but stay close to truncation,
+    // and the same seed reproduces the output.
+    let dithered = |seed| {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        reader.samples_at_bit_depth_dithered(16, Dither::Triangular, seed)
+              .map(|s| s.unwrap())
+              .collect::<Vec<i32>>()
+    };
+    let triangular = dithered(7);
+    assert_eq!(triangular, dithered(7));
+    assert!(triangular != dithered(8));
+    assert_eq!(triangular.len(), truncated.len());
+    assert!(triangular != truncated);
+    let mut sum = 0i64;
+    for (&d, &t) in triangular.iter().zip(truncated.iter()) {
+        assert!(d >= -32768 && d <= 32767);
+        assert!(d - t >= -1 && d - t <= 2, "dithered {} truncated {}", d, t);
+        sum += (d - t) as i64;
+    }
+    let mean = sum as f64 / triangular.len() as f64;
+    assert!(mean > -1.0 && mean < 1.0, "mean difference is {}", mean);
+
+    // Widening is unaffected by dither.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let wide: Vec<i32> = reader.samples_at_bit_depth(32).map(|s| s.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let wide_dithered: Vec<i32> = reader.samples_at_bit_depth_dithered(32, Dither::Triangular, 7)
+                                        .map(|s| s.unwrap())
+                                        .collect();
+    assert_eq!(wide, wide_dithered);
+}
+
+#[test]
+fn verify_gain() {
+    let reader = claxon::FlacReader::open("testsamples/gain.flac").unwrap();
+    let gain = reader.gain();
+    assert_eq!(gain.track_gain, Some(-7.03));
+    assert_eq!(gain.track_peak, Some(0.98765432));
+    assert_eq!(gain.album_gain, Some(1.5));
+    assert_eq!(gain.album_peak, None);
+    assert_eq!(gain.r128_track_gain, Some(-8.0));
+    assert_eq!(gain.r128_album_gain, Some(1.0));
+
+    let reader = claxon::FlacReader::open("testsamples/identifiers.flac").unwrap();
+    let gain = reader.gain();
+    assert_eq!(gain.track_gain, None);
+    assert_eq!(gain.r128_track_gain, None);
+}
+
+#[test]
+fn verify_track_and_disc_number() {
+    let reader = claxon::FlacReader::open("testsamples/track_number.flac").unwrap();
+    assert_eq!(reader.track_number(), Some((3, Some(12))));
+    assert_eq!(reader.disc_number(), None);
+
+    let reader = claxon::FlacReader::open("testsamples/gain.flac").unwrap();
+    assert_eq!(reader.track_number(), None);
+    assert_eq!(reader.disc_number(), None);
+}
+
+#[test]
+fn verify_labeled_reader_errors() {
+    use std::io::{Cursor, Read};
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/tone_lpc.flac").unwrap().read_to_end(&mut data).unwrap();
+    let len = data.len();
+    data.truncate(len - 100);
+
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    assert_eq!(reader.label(), None);
+    reader.set_label("track03.flac".to_string());
+    assert_eq!(reader.label(), Some("track03.flac"));
+    let err = reader.samples().filter_map(|s| s.err()).next().unwrap();
+    match *err.unlabeled() {
+        claxon::Error::IoError(ref io_err) => assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof),
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    let msg = format!("{}", err);
+    assert!(msg.starts_with("error in 'track03.flac': "), "{}", msg);
+    assert!(msg.ends_with(&format!("{}", err.unlabeled())));
+
+    // Attaching a label again keeps the first one, and errors that do not
+    // come from a labeled reader can be labeled too.
+    assert_eq!(err.with_label("other.flac").to_string(), msg);
+    let err = claxon::FlacReader::new(Cursor::new(&b"RIFF"[..])).err().unwrap();
+    assert_eq!(err.with_label("a.wav").to_string(),
+               "error in 'a.wav': Ill-formed FLAC stream: invalid stream header");
+}
+
+#[test]
+fn verify_progress() {
+    let mut reader = claxon::FlacReader::open("testsamples/tone_lpc.flac").unwrap();
+    let start = reader.progress_with_length().unwrap();
+    let total = reader.streaminfo().samples.unwrap();
+    let file_len = fs::metadata("testsamples/tone_lpc.flac").unwrap().len();
+    assert_eq!(start.samples_done, 0);
+    assert_eq!(start.samples_total, Some(total));
+    assert_eq!(start.bytes_read, reader.audio_start_offset());
+    assert_eq!(start.bytes_total, Some(file_len));
+    assert_eq!(reader.progress().bytes_total, None);
+
+    // Decode about half of the stream, one block at a time.
+    let mut buffer = Vec::new();
+    while reader.progress().samples_done < total / 2 {
+        let block = reader.blocks().read_next_or_eof(buffer).unwrap().unwrap();
+        buffer = block.into_buffer();
+    }
+    let half = reader.progress_with_length().unwrap();
+    let block_size = reader.streaminfo().max_block_size as u64;
+    assert!(half.samples_done >= total / 2 && half.samples_done < total / 2 + block_size);
+    assert_eq!(half.bytes_read, reader.byte_position());
+    assert!(half.bytes_read > start.bytes_read && half.bytes_read < file_len);
+    assert_eq!(half.bytes_total, Some(file_len));
+
+    for sample in reader.samples() {
+        sample.unwrap();
+    }
+    let end = reader.progress_with_length().unwrap();
+    assert_eq!(end.samples_done, total);
+    assert_eq!(end.bytes_read, file_len);
+}
+
+#[test]
+fn verify_broadcast_info() {
+    let reader = claxon::FlacReader::open("testsamples/broadcast_info.flac").unwrap();
+    let info = reader.broadcast_info().unwrap();
+    assert_eq!(info.description, "Interview, take 2");
+    assert_eq!(info.originator, "Claxon Radio");
+    assert_eq!(info.originator_reference, "CLX0001");
+    assert_eq!(info.origination_date, "2019-08-04");
+    assert_eq!(info.origination_time, "13:37:00");
+    assert_eq!(info.time_reference, 0x1_2000_0000);
+    assert_eq!(info.version, 2);
+    assert_eq!(info.umid, Some((0..64).collect()));
+    assert_eq!(info.loudness_value, Some(-23.0));
+    assert_eq!(info.loudness_range, Some(4.5));
+    assert_eq!(info.max_true_peak_level, Some(-1.0));
+    assert_eq!(info.max_momentary_loudness, Some(-18.0));
+    assert_eq!(info.max_short_term_loudness, Some(-20.0));
+    assert_eq!(info.coding_history, "A=PCM,F=48000,W=16,M=stereo\r\n");
+
+    // A WAV file without a bext chunk has no broadcast info.
+    let reader = claxon::FlacReader::open("testsamples/foreign_metadata.flac").unwrap();
+    assert!(reader.foreign_metadata().is_some());
+    assert_eq!(reader.broadcast_info(), None);
+}
+
+#[test]
+fn verify_vorbis_comment_ref_matches_tags() {
+    let mut buffer = Vec::new();
+    for fname in &["testsamples/encoder_hints.flac", "testsamples/gain.flac",
+                   "testsamples/repeated_vorbis_comment.flac"] {
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let file = fs::File::open(fname).unwrap();
+        assert!(claxon::read_vorbis_comment_into(file, &mut buffer).unwrap());
+        let comment = claxon::metadata::VorbisCommentRef::parse(&buffer).unwrap();
+
+        assert_eq!(Some(comment.vendor()), reader.vendor());
+        assert_eq!(comment.tags().collect::<Vec<_>>(), reader.tags().collect::<Vec<_>>());
+        for (name, _) in reader.tags() {
+            assert_eq!(comment.get_tag(name).collect::<Vec<_>>(),
+                       reader.get_tag(name).collect::<Vec<_>>());
+        }
+    }
+
+    // A stream without a Vorbis comment block leaves the buffer empty.
+    let file = fs::File::open("testsamples/short.flac").unwrap();
+    let has_comment = claxon::read_vorbis_comment_into(file, &mut buffer).unwrap();
+    let reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    assert_eq!(has_comment, reader.vendor().is_some());
+    assert_eq!(has_comment, !buffer.is_empty());
+}
+
+#[test]
+fn verify_encoder_hints() {
+    let reader = claxon::FlacReader::open("testsamples/encoder_hints.flac").unwrap();
+    let hints = reader.encoder_hints();
+    assert_eq!(hints.libflac_version, Some("1.3.2"));
+    assert_eq!(hints.encoder, Some("fre:ac v1.1.2"));
+    // Settings take precedence over options, regardless of the tag order.
+    assert_eq!(hints.settings, Some("-8 -p"));
+
+    let reader = claxon::FlacReader::open("testsamples/gain.flac").unwrap();
+    let hints = reader.encoder_hints();
+    assert_eq!(hints.libflac_version, None);
+    assert_eq!(hints.encoder, None);
+    assert_eq!(hints.settings, None);
+}
+
+#[test]
+fn verify_decode_normalized() {
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let peak = *reader.peak().unwrap().iter().max().unwrap() as f64;
+    let mut expected = Vec::new();
+    claxon::FlacReader::open(fname).unwrap().decode_all_into(&mut expected).unwrap();
+
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let (streaminfo, samples) = reader.decode_normalized().unwrap();
+    assert_eq!(streaminfo.bits_per_sample, 16);
+    assert_eq!(samples.len(), expected.len());
+    assert_eq!(samples.iter().map(|s| s.abs()).max(), Some(32767));
+    for (&s, &x) in samples.iter().zip(expected.iter()) {
+        assert_eq!(s, (x as f64 * 32767.0 / peak).round() as i32);
+    }
+
+    let reader = claxon::FlacReader::open("testsamples/silent.flac").unwrap();
+    let (_, samples) = reader.decode_normalized().unwrap();
+    assert!(!samples.is_empty());
+    assert!(samples.iter().all(|&s| s == 0));
+}
+
+#[test]
+fn verify_into_arc_samples() {
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut expected = Vec::new();
+    reader.decode_all_into(&mut expected).unwrap();
+
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let (streaminfo, samples) = reader.into_arc_samples().unwrap();
+    assert_eq!(streaminfo.channels, 2);
+    assert_eq!(&samples[..], &expected[..]);
+
+    // A clone shares the samples rather than copying them.
+    let shared = samples.clone();
+    assert_eq!(shared.as_ptr(), samples.as_ptr());
+    assert_eq!(std::sync::Arc::strong_count(&samples), 2);
+}
+
+#[test]
+fn verify_decode_channel_range() {
+    let fname = "testsamples/surround_5_1.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let full = |ch: usize| -> Vec<i32> {
+        all.iter().enumerate().filter(|&(i, _)| i % 6 == ch).map(|(_, &s)| s).collect()
+    };
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let front = reader.decode_channel_range(0..2).unwrap();
+    assert_eq!(front, vec![full(0), full(1)]);
+    assert_eq!(front[0].len(), 2500);
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let back = reader.decode_channel_range(4..6).unwrap();
+    assert_eq!(back, vec![full(4), full(5)]);
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert!(reader.decode_channel_range(3..3).unwrap().is_empty());
+}
+
+#[test]
+#[should_panic]
+fn verify_decode_channel_range_rejects_out_of_range() {
+    let mut reader = claxon::FlacReader::open("testsamples/surround_5_1.flac").unwrap();
+    let _ = reader.decode_channel_range(4..7);
+}
+
+#[test]
+fn verify_peak() {
+    for fname in &["testsamples/full_scale.flac",
+                   "testsamples/silent_sections.flac",
+                   "testsamples/stereo_24bit.flac",
+                   "testsamples/surround_5_1.flac",
+                   "testsamples/silent.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let n_channels = reader.streaminfo().channels as usize;
+        let mut expected = vec![0u32; n_channels];
+        for (i, sample) in reader.samples().enumerate() {
+            let magnitude = (sample.unwrap() as i64).abs() as u32;
+            expected[i % n_channels] = std::cmp::max(expected[i % n_channels], magnitude);
+        }
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        assert_eq!(reader.peak().unwrap(), expected, "{}", fname);
+    }
+
+    let mut reader = claxon::FlacReader::open("testsamples/full_scale.flac").unwrap();
+    assert_eq!(reader.peak().unwrap(), vec![32767, 32768]);
+    let mut reader = claxon::FlacReader::open("testsamples/silent.flac").unwrap();
+    assert_eq!(reader.peak().unwrap(), vec![0, 0]);
+}
+
+#[test]
+fn verify_pad_final_block() {
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let channels = reader.streaminfo().channels as usize;
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let opts = claxon::FlacReaderOptions {
+        pad_final_block: true,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::open_ext(fname, opts).unwrap();
+    {
+        let mut blocks = reader.blocks();
+        let mut n = 0;
+        while let Some(block) = blocks.read_next_or_eof(Vec::new()).unwrap() {
+            assert_eq!(block.duration(), 1024);
+            n += 1;
+        }
+        assert_eq!(n, 4);
+        assert_eq!(blocks.padding_samples(), 924);
+    }
+
+    let mut reader = claxon::FlacReader::open_ext(fname, opts).unwrap();
+    let mut samples = reader.samples();
+    let padded: Vec<i32> = samples.by_ref().map(|s| s.unwrap()).collect();
+    assert_eq!(samples.padding_samples(), 924);
+    assert!(!samples.has_extra_samples());
+    assert_eq!(padded.len(), expected.len() + 924 * channels);
+    assert_eq!(&padded[..expected.len()], &expected[..]);
+    assert!(padded[expected.len()..].iter().all(|&s| s == 0));
+
+    // Without the option, nothing is padded.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut samples = reader.samples();
+    assert_eq!(samples.by_ref().count(), expected.len());
+    assert_eq!(samples.padding_samples(), 0);
+}
+
+#[test]
+fn verify_new_with_pool() {
+    use std::io::{Cursor, Read};
+
+    let pool = claxon::BufferPool::new();
+    for fname in &["testsamples/short_final_block.flac",
+                   "testsamples/stereo_24bit.flac",
+                   "testsamples/surround_5_1.flac",
+                   "testsamples/short_final_block.flac"] {
+        let mut data = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+        let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+        let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+        let cursor = Cursor::new(&data[..]);
+        let mut reader = claxon::FlacReader::new_with_pool(cursor, pool.clone()).unwrap();
+        let pooled: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(pooled, expected);
+
+        // The buffer of the finished iterator went back to the pool, and it
+        // is taken again by the next iterator, so the pool does not grow.
+        assert_eq!(pool.len(), 1);
+    }
+}
+
+#[test]
+fn verify_block_first_sample_number() {
+    // A variable-blocksize stream codes sample numbers, a fixed-blocksize
+    // stream codes frame numbers.
+    for &(fname, ref sizes, start) in &[
+        ("testsamples/variable_blocksize.flac", vec![1152, 4096, 576, 300, 2000, 17], 0),
+        ("testsamples/short_final_block.flac", vec![1024, 1024, 1024, 100], 0),
+        ("testsamples/large_sample_number.flac", vec![256, 256], (1 << 33) + 5),
+    ] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut blocks = reader.blocks();
+        let mut expected = start;
+        for &size in sizes {
+            let block = blocks.read_next_or_eof(Vec::new()).unwrap().unwrap();
+            assert_eq!(block.first_sample_number(), expected);
+            assert_eq!(block.first_sample_number(), block.time());
+            assert_eq!(block.duration(), size);
+            expected += size as u64;
+        }
+        assert!(blocks.read_next_or_eof(Vec::new()).unwrap().is_none());
+    }
+}
+
+#[test]
+fn verify_cuesheet_lead_out_matches_samples() {
+    use std::io::{Cursor, Read};
+
+    let fname = "testsamples/cuesheet.flac";
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    assert_eq!(reader.cuesheet().unwrap().lead_out(), Some(11760));
+    assert_eq!(reader.streaminfo().samples, Some(11760));
+    assert_eq!(reader.check_cuesheet_lead_out(), Ok(()));
+
+    // Move the lead-out track, which has number 170, to offset 11761.
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    let pattern = [0, 0, 0, 0, 0, 0, 0x2d, 0xf0, 0xaa];
+    let pos = data.windows(pattern.len()).position(|w| w == &pattern[..]).unwrap();
+    data[pos + 7] = 0xf1;
+
+    let opts = claxon::FlacReaderOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mismatch = claxon::Error::CueSheetLeadOutMismatch { lead_out: 11761, samples: 11760 };
+    assert_eq!(claxon::FlacReader::new_ext(Cursor::new(&data[..]), opts).err(), Some(mismatch));
+
+    // By default, the reader can be constructed, and the mismatch is reported
+    // by the check and by a full check.
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    assert_eq!(reader.cuesheet().unwrap().lead_out(), Some(11761));
+    assert_eq!(reader.check_cuesheet_lead_out(),
+               Err(claxon::Error::CueSheetLeadOutMismatch { lead_out: 11761, samples: 11760 }));
+    let report = reader.full_check().unwrap();
+    assert_eq!(report.warnings, vec!["CUE sheet lead-out does not match the number of samples"]);
+
+    // Without a CUE sheet there is nothing to check.
+    let reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    assert_eq!(reader.check_cuesheet_lead_out(), Ok(()));
+}
+
+#[test]
+fn verify_byte_position() {
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let ranges: Vec<claxon::frame::FrameRange> = reader.frames().map(|f| f.unwrap().0).collect();
+    assert_eq!(ranges.len(), 4);
+
+    // After the metadata, the position is at the first frame, and after
+    // every frame, it is at the end of that frame.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert_eq!(reader.byte_position(), ranges[0].offset);
+    let mut end = ranges[0].offset;
+    for range in &ranges {
+        reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+        end += range.len;
+        assert_eq!(reader.byte_position(), end);
+    }
+    assert_eq!(end, fs::metadata(fname).unwrap().len());
+}
+
+#[test]
+fn verify_validate_collect() {
+    use std::io::{Cursor, Read};
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let ranges: Vec<claxon::frame::FrameRange> = reader.frames().map(|f| f.unwrap().0).collect();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert!(reader.validate_collect().unwrap().is_empty());
+
+    // Damage the second and the last frame in the middle.
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    for &i in &[1, 3] {
+        let mid = (ranges[i].offset + ranges[i].len / 2) as usize;
+        data[mid] ^= 0x55;
+    }
+
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    let errors = reader.validate_collect().unwrap();
+    let positions: Vec<u64> = errors.iter().map(|&(pos, _)| pos).collect();
+    assert_eq!(positions, vec![ranges[1].offset, ranges[3].offset]);
+    for &(_, ref err) in &errors {
+        match *err {
+            claxon::Error::FormatError(..) => {}
+            ref other => panic!("expected a format error, got {:?}", other),
+        }
+    }
+
+    // Stopping at the first error, a full check sees only one of them.
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    let report = reader.full_check().unwrap();
+    assert_eq!(report.frames, 1);
+    assert!(report.error.is_some());
+}
+
+#[test]
+fn verify_bits_per_sample_from_streaminfo() {
+    use std::io::Read;
+
+    // All frames but the second refer to the streaminfo for their bit depth.
+    let fname = "testsamples/bps_from_streaminfo.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut n = 0;
+    for block in reader.blocks() {
+        assert_eq!(block.unwrap().bits_per_sample(), 16);
+        n += 1;
+    }
+    assert_eq!(n, 3);
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert!(reader.verify_md5().unwrap());
+
+    // The 28-bit stream has no frame header code for its bit depth at all.
+    let mut reader = claxon::FlacReader::open("testsamples/stereo_28bit.flac").unwrap();
+    let block = reader.blocks().next().unwrap().unwrap();
+    assert_eq!(block.bits_per_sample(), 28);
+
+    // Without a streaminfo, the bit depth cannot be resolved.
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    data.drain(4..42);
+    let opts = claxon::FlacReaderOptions {
+        allow_missing_streaminfo: true,
+        ..Default::default()
+    };
+    match claxon::FlacReader::new_ext(io::Cursor::new(&data), opts) {
+        Err(claxon::Error::FormatError(msg)) => assert!(msg.contains("refers to")),
+        _ => panic!("expected a format error"),
+    }
+    let mut frames = claxon::frame::FrameReader::new(io::Cursor::new(&data[4..]));
+    match frames.read_next_or_eof(Vec::new()) {
+        Err(claxon::Error::Unsupported(..)) => {}
+        _ => panic!("expected an unsupported error"),
+    }
+}
+
+#[test]
+fn verify_audio_start_offset() {
+    use std::io::Read;
+
+    for fname in &["testsamples/encoder_hints.flac", "testsamples/short_final_block.flac",
+                   "testsamples/surround_5_1.flac"] {
+        let mut data = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+
+        // Walk the metadata block headers, to the one with the last-block flag.
+        let mut expected = 4;
+        loop {
+            let header = &data[expected..expected + 4];
+            let length = (header[1] as usize) << 16 | (header[2] as usize) << 8 | header[3] as usize;
+            expected += 4 + length;
+            if header[0] & 0x80 != 0 {
+                break
+            }
+        }
+
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        assert_eq!(reader.audio_start_offset(), expected as u64);
+        let first = reader.frames().next().unwrap().unwrap().0;
+        assert_eq!(first.offset, expected as u64);
+
+        // Decoding does not move it.
+        while let Some(..) = reader.blocks().read_next_or_eof(Vec::new()).unwrap() {}
+        assert_eq!(reader.audio_start_offset(), expected as u64);
+    }
+}
+
+#[test]
+fn verify_has_known_length() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+    {
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        assert!(reader.streaminfo().has_known_length());
+        assert!(reader.streaminfo().duration().is_some());
+        let mut samples = reader.samples();
+        assert!(samples.by_ref().all(|s| s.is_ok()));
+        assert!(!samples.is_truncated());
+    }
+
+    // Zero the 36-bit sample count, as a streaming encoder would write it.
+    data[21] &= 0xf0;
+    for byte in &mut data[22..26] {
+        *byte = 0;
+    }
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    assert!(!reader.streaminfo().has_known_length());
+    assert_eq!(reader.streaminfo().samples, None);
+    assert_eq!(reader.streaminfo().duration(), None);
+
+    // The stream still decodes, and it is not considered truncated.
+    let mut samples = reader.samples();
+    assert!(samples.by_ref().all(|s| s.is_ok()));
+    assert!(!samples.is_truncated());
+}
+
+#[test]
+fn verify_zero_sample_rate_is_rejected() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // The sample rate is the first 20 bits at offset 10 into the streaminfo,
+    // which follows the stream header and the metadata block header.
+    data[18] = 0;
+    data[19] = 0;
+    data[20] &= 0x0f;
+    match claxon::FlacReader::new(io::Cursor::new(&data)) {
+        Err(claxon::Error::FormatError(msg)) => assert_eq!(msg, "invalid sample rate"),
+        _ => panic!("expected a format error"),
+    }
+}
+
+#[test]
+fn verify_decode_with_md5() {
+    for fname in &["testsamples/short_final_block.flac", "testsamples/stereo_20bit.flac"] {
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let (streaminfo, samples, md5sum) = reader.decode_with_md5().unwrap();
+        assert_eq!(md5sum, streaminfo.md5sum);
+
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, expected);
+    }
+}
+
+#[test]
+fn verify_decode_with() {
+    use std::cmp;
+
+    let fname = "testsamples/full_scale.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let mut expected = Vec::new();
+    reader.decode_all_into(&mut expected).unwrap();
+
+    // A gain of +6 dB doubles the samples, saturating at the 16-bit range.
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let doubled = reader.decode_with(|block| {
+        for ch in 0..block.channels() {
+            for s in block.channel_mut(ch) {
+                *s = cmp::max(-0x8000, cmp::min(0x7fff, *s * 2));
+            }
+        }
+    }).unwrap();
+
+    assert_eq!(doubled.len(), expected.len());
+    for (&d, &e) in doubled.iter().zip(expected.iter()) {
+        assert_eq!(d, cmp::max(-0x8000, cmp::min(0x7fff, e * 2)));
+    }
+    assert!(doubled.iter().filter(|&&d| d == 0x7fff).count() >= 2);
+    assert!(doubled.iter().any(|&d| d == -0x8000));
+}
+
+#[test]
+fn verify_truncated_at_final_frame_crc() {
+    use std::io::Read;
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+
+    // Remove both bytes of the CRC-16 of the final frame, or only the last one.
+    for &missing in &[2, 1] {
+        let truncated = &data[..data.len() - missing];
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(truncated)).unwrap();
+        let mut blocks = reader.blocks();
+        for _ in 0..3 {
+            blocks.read_next_or_eof(Vec::new()).unwrap().unwrap();
+        }
+        match blocks.read_next_or_eof(Vec::new()) {
+            Err(claxon::Error::IoError(ref err)) => {
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof)
+            }
+            Err(err) => panic!("expected an unexpected end of stream, got {:?}", err),
+            Ok(..) => panic!("expected an unexpected end of stream"),
+        }
+
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(truncated)).unwrap();
+        let result: claxon::Result<Vec<i32>> = reader.samples().collect();
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn verify_seek_variable_blocksize_without_seek_table() {
+    use std::io::{Cursor, Read};
+
+    let fname = "testsamples/variable_blocksize.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+
+    // Record where every frame starts, and the samples of a clean decode.
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    assert!(!reader.streaminfo().is_fixed_blocksize());
+    assert!(reader.seek_table().is_none());
+    let mut frame_starts = vec![reader.byte_position()];
+    let mut all = Vec::new();
+    while let Some(block) = reader.blocks().read_next_or_eof(Vec::new()).unwrap() {
+        frame_starts.push(reader.byte_position());
+        for i in 0..block.duration() {
+            for ch in 0..block.channels() {
+                all.push(block.sample(ch, i));
+            }
+        }
+    }
+
+    // Damage the audio data of the first frame. Decoding from the start of
+    // the stream would fail there, but seeking only reads frame headers
+    // until it finds the frame that contains the target.
+    let mid = (frame_starts[0] + frame_starts[1]) / 2;
+    data[mid as usize] ^= 0xff;
+
+    // The fourth frame starts at sample 1152 + 4096 + 576 = 5824.
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    for &target in &[5824, 6000, 7999, 5825, 8123] {
+        let discard = reader.seek_discard_count(target).unwrap();
+        let block = reader.blocks().read_next_or_eof(Vec::new()).unwrap().unwrap();
+        assert_eq!(block.first_sample_number() + discard, target);
+        assert_eq!(block.sample(0, discard as u32), all[target as usize * 2]);
+
+        let range = reader.decode_range(target, 4).unwrap();
+        let start = target as usize * 2;
+        assert_eq!(&range[..], &all[start..start + 8]);
+    }
+}
+
+#[test]
+fn verify_sample_histogram() {
+    for fname in &["testsamples/short_final_block.flac",
+                   "testsamples/surround_5_1.flac",
+                   "testsamples/full_scale.flac",
+                   "testsamples/silent_sections.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let n = reader.streaminfo().samples.unwrap() * reader.streaminfo().channels as u64;
+        for &bins in &[1, 2, 7, 256, 1 << 16, 100_000] {
+            let mut reader = claxon::FlacReader::open(fname).unwrap();
+            let histogram = reader.sample_histogram(bins).unwrap();
+            assert_eq!(histogram.len(), bins);
+            assert_eq!(histogram.iter().sum::<u64>(), n);
+        }
+
+        // With one bin per sample value, counts can be checked exactly.
+        let mut expected = vec![0u64; 1 << 16];
+        for s in reader.samples() {
+            expected[(s.unwrap() + 0x8000) as usize] += 1;
+        }
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        assert_eq!(reader.sample_histogram(1 << 16).unwrap(), expected);
+    }
+
+    // A quiet tone with a DC offset of 26624 lies entirely in the last
+    // quarter of the range, in the middle of the second-to-last sixteenth.
+    let mut reader = claxon::FlacReader::open("testsamples/dc_offset.flac").unwrap();
+    assert_eq!(reader.sample_histogram(4).unwrap(), [0, 0, 0, 6000]);
+    let mut reader = claxon::FlacReader::open("testsamples/dc_offset.flac").unwrap();
+    let histogram = reader.sample_histogram(16).unwrap();
+    assert_eq!(histogram[14], 6000);
+
+    // Zero is the lowest value of the upper half.
+    let mut reader = claxon::FlacReader::open("testsamples/silent.flac").unwrap();
+    let n = reader.streaminfo().samples.unwrap() * reader.streaminfo().channels as u64;
+    assert_eq!(reader.sample_histogram(2).unwrap(), [0, n]);
+
+    let mut reader = claxon::FlacReader::open("testsamples/dc_offset.flac").unwrap();
+    assert!(reader.sample_histogram(0).unwrap().is_empty());
+}
+
+#[test]
+fn verify_misset_last_metadata_block_flag() {
+    use std::io::{Cursor, Read};
+
+    let fname = "testsamples/gain.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let mut header_pos = vec![4];
+    for header in reader.metadata_blocks() {
+        let next = header_pos[header_pos.len() - 1] + 4 + header.length as usize;
+        header_pos.push(next);
+    }
+    let n = header_pos.len() - 1;
+    assert!(n >= 2);
+
+    let lenient = claxon::FlacReaderOptions { strict: false, ..Default::default() };
+
+    // First the flag is missing from the last block, then it is set on the
+    // streaminfo block, which is followed by the Vorbis comment block.
+    let mut no_last = data.clone();
+    no_last[header_pos[n - 1]] &= 0x7f;
+    let mut early_last = data.clone();
+    early_last[header_pos[0]] |= 0x80;
+
+    for damaged in &[no_last, early_last] {
+        let mut reader = claxon::FlacReader::new_ext(Cursor::new(&damaged[..]), lenient).unwrap();
+        assert_eq!(reader.metadata_blocks().len(), n);
+        assert_eq!(reader.get_tag("TITLE").next(), Some("Gain"));
+        let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, expected);
+
+        // In strict mode the flag is honored, so either a frame header is read
+        // as a metadata block header, or a metadata block as a frame.
+        let strict = claxon::FlacReaderOptions { strict: true, ..Default::default() };
+        let result = claxon::FlacReader::new_ext(Cursor::new(&damaged[..]), strict)
+            .and_then(|mut reader| reader.samples().collect::<claxon::Result<Vec<i32>>>());
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn verify_decode_to_wav_file() {
+    let path = std::env::temp_dir().join("claxon_verify_decode_to_wav_file.wav");
+    // Hound does not read 20 bits, so the file to check with it comes last.
+    for &fname in &["testsamples/stereo_20bit.flac", "testsamples/short_final_block.flac"] {
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        reader.decode_to_wav_file(&path).unwrap();
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let expected = reader.to_wav_bytes().unwrap();
+        let mut wav = Vec::new();
+        io::Read::read_to_end(&mut fs::File::open(&path).unwrap(), &mut wav).unwrap();
+        assert_eq!(wav, expected);
+    }
+
+    let wav_reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(wav_reader.duration(), 3 * 1024 + 100);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn verify_to_wav_bytes() {
+    for &fname in &["testsamples/short_final_block.flac",
+                    "testsamples/stereo_12bit.flac",
+                    "testsamples/stereo_20bit.flac",
+                    "testsamples/stereo_24bit.flac",
+                    "testsamples/surround_5_1.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let streaminfo = reader.streaminfo();
+        let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let wav = reader.to_wav_bytes().unwrap();
+        let le_u16 = |pos: usize| (wav[pos] as u32) | (wav[pos + 1] as u32) << 8;
+        let le_u32 = |pos: usize| le_u16(pos) | le_u16(pos + 2) << 16;
+        assert_eq!(le_u32(4) as usize, wav.len() - 8);
+
+        let bits = streaminfo.bits_per_sample;
+        if bits % 8 == 0 {
+            let mut wav_reader = hound::WavReader::new(io::Cursor::new(&wav[..])).unwrap();
+            let spec = wav_reader.spec();
+            assert_eq!(spec.channels as u32, streaminfo.channels);
+            assert_eq!(spec.sample_rate, streaminfo.sample_rate);
+            assert_eq!(spec.bits_per_sample as u32, bits);
+            assert_eq!(wav_reader.duration() as u64, streaminfo.samples.unwrap());
+            let samples: Vec<i32> = wav_reader.samples::<i32>().map(|s| s.unwrap()).collect();
+            assert_eq!(samples, expected);
+        } else {
+            // Hound does not read odd bit depths, so check the extensible fmt
+            // chunk, and undo the left-justification of the samples by hand.
+            assert_eq!(le_u16(20), 0xfffe);
+            assert_eq!(le_u16(38), bits);
+            assert_eq!(&wav[60..64], b"data");
+            let bytes_per_sample = (bits as usize + 7) / 8;
+            assert_eq!(le_u32(64) as usize, expected.len() * bytes_per_sample);
+            let samples: Vec<i32> = wav[68..].chunks(bytes_per_sample).map(|bytes| {
+                let sample = bytes.iter().rev().fold(0i32, |acc, &b| acc << 8 | b as i32);
+                let shift = 32 - 8 * bytes_per_sample as u32;
+                (sample << shift) >> (shift + 8 * bytes_per_sample as u32 - bits)
+            }).collect();
+            assert_eq!(samples, expected);
+        }
+    }
+}
+
+#[test]
+fn verify_to_wav_f32_bytes() {
+    let path = std::env::temp_dir().join("claxon_verify_to_wav_f32_bytes.wav");
+    for &fname in &["testsamples/short_final_block.flac",
+                    "testsamples/stereo_20bit.flac",
+                    "testsamples/stereo_28bit.flac",
+                    "testsamples/surround_5_1.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let streaminfo = reader.streaminfo();
+        let bits = streaminfo.bits_per_sample;
+        let expected: Vec<f32> = reader.samples()
+                                       .map(|s| claxon::sample::to_f32(s.unwrap(), bits))
+                                       .collect();
+
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let wav = reader.to_wav_f32_bytes().unwrap();
+        let format_tag = wav[20] as u16 | (wav[21] as u16) << 8;
+        assert_eq!(format_tag, if streaminfo.channels > 2 { 0xfffe } else { 3 });
+
+        let mut wav_reader = hound::WavReader::new(io::Cursor::new(&wav[..])).unwrap();
+        let spec = wav_reader.spec();
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        assert_eq!(spec.bits_per_sample, 32);
+        assert_eq!(spec.channels as u32, streaminfo.channels);
+        assert_eq!(spec.sample_rate, streaminfo.sample_rate);
+        let samples: Vec<f32> = wav_reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, expected);
+        assert!(samples.iter().all(|&x| x >= -1.0 && x < 1.0));
+
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        reader.decode_to_wav_f32_file(&path).unwrap();
+        let mut file_wav = Vec::new();
+        io::Read::read_to_end(&mut fs::File::open(&path).unwrap(), &mut file_wav).unwrap();
+        assert_eq!(file_wav, wav);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn verify_subframe_bits() {
+    for fname in &["testsamples/silent_sections.flac",
+                   "testsamples/stereo_24bit_decorrelated.flac",
+                   "testsamples/surround_5_1.flac",
+                   "testsamples/variable_blocksize.flac",
+                   "testsamples/wasted_bits.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut frame_start = reader.byte_position();
+        while let Some(block) = reader.blocks().read_next_or_eof(Vec::new()).unwrap() {
+            let frame_bits = (reader.byte_position() - frame_start) * 8;
+            frame_start = reader.byte_position();
+
+            let subframe_bits: u64 = (0..block.channels()).map(|i| block.subframe_bits(i)).sum();
+            assert!((0..block.channels()).all(|i| block.subframe_bits(i) > 0));
+
+            // What remains is the 6 to 16 byte frame header, up to 7 bits of
+            // padding, and the CRC-16.
+            let rest = frame_bits - subframe_bits - 16;
+            assert!(rest >= 6 * 8 && rest <= 16 * 8 + 7, "{}: {} bits of overhead", fname, rest);
+        }
+    }
+}
+
+#[test]
+fn verify_zero_length_metadata_blocks() {
+    use std::io::{Cursor, Read};
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+    let mut reader = claxon::FlacReader::new(Cursor::new(&data[..])).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let n_blocks = reader.metadata_blocks().len();
+    let padding = reader.total_padding();
+
+    // Insert empty blocks after the streaminfo, and move its last-block flag
+    // to the final inserted block.
+    let with_empty_blocks = |types: &[u8]| {
+        let mut out = data[..42].to_vec();
+        let is_last = out[4] & 0x80;
+        out[4] &= 0x7f;
+        for (i, &block_type) in types.iter().enumerate() {
+            let flag = if i + 1 == types.len() { is_last } else { 0 };
+            out.extend_from_slice(&[flag | block_type, 0, 0, 0]);
+        }
+        out.extend_from_slice(&data[42..]);
+        out
+    };
+
+    let padded = with_empty_blocks(&[claxon::metadata::BLOCK_TYPE_PADDING,
+                                     claxon::metadata::BLOCK_TYPE_SEEKTABLE]);
+    let mut reader = claxon::FlacReader::new(Cursor::new(&padded[..])).unwrap();
+    assert_eq!(reader.metadata_blocks().len(), n_blocks + 2);
+    assert_eq!(reader.total_padding(), padding);
+    assert!(reader.seek_table().unwrap().seekpoints().is_empty());
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // An application block must at least contain its ID. Outside of strict
+    // mode, the block is skipped.
+    let app = with_empty_blocks(&[claxon::metadata::BLOCK_TYPE_APPLICATION]);
+    let strict = claxon::FlacReaderOptions { strict: true, ..Default::default() };
+    match claxon::FlacReader::new_ext(Cursor::new(&app[..]), strict) {
+        Err(claxon::Error::FormatError(..)) => {}
+        _ => panic!("expected format error for an empty application block"),
+    }
+    let mut reader = claxon::FlacReader::new(Cursor::new(&app[..])).unwrap();
+    assert_eq!(reader.skipped_metadata_blocks().len(), 1);
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn verify_split_by_cuesheet() {
+    let fname = "testsamples/cuesheet.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let offsets: Vec<u64> = reader.cuesheet().unwrap().tracks.iter().map(|t| t.offset).collect();
+    assert_eq!(offsets, [0, 5880, 11760]);
+
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let tracks = reader.split_by_cuesheet().unwrap();
+    let numbers: Vec<u8> = tracks.iter().map(|&(ref track, _)| track.number).collect();
+    assert_eq!(numbers, [1, 2]);
+    assert_eq!(tracks[1].0.isrc, "NLA1Z1700002");
+
+    // The tracks are the consecutive parts of the stream, and the last one
+    // runs up to the lead-out.
+    let mut pos = 0;
+    for (i, &(_, ref samples)) in tracks.iter().enumerate() {
+        assert_eq!(samples.len() as u64, (offsets[i + 1] - offsets[i]) * 2);
+        assert_eq!(&samples[..], &all[pos..pos + samples.len()]);
+        pos += samples.len();
+    }
+    assert_eq!(pos, all.len());
+
+    let reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    match reader.split_by_cuesheet() {
+        Err(claxon::Error::Unsupported(..)) => {}
+        _ => panic!("expected an error for a stream without CUE sheet"),
+    }
+}
+
+#[test]
+fn verify_split_by_cuesheet_indices() {
+    let fname = "testsamples/cuesheet.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    // Track 2 has a pregap: index point 0 at its start, and 1 after 588 samples.
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let segments = reader.split_by_cuesheet_indices().unwrap();
+    let labels: Vec<(u8, u8)> = segments.iter().map(|&(t, i, _)| (t, i)).collect();
+    assert_eq!(labels, [(1, 1), (2, 0), (2, 1)]);
+
+    let bounds = [0, 5880, 5880 + 588, 11760];
+    let mut pos = 0;
+    for (i, &(_, _, ref samples)) in segments.iter().enumerate() {
+        assert_eq!(samples.len() as u64, (bounds[i + 1] - bounds[i]) * 2);
+        assert_eq!(&samples[..], &all[pos..pos + samples.len()]);
+        pos += samples.len();
+    }
+    assert_eq!(pos, all.len());
+
+    // The segments of a track together are the track.
+    let reader = claxon::FlacReader::open(fname).unwrap();
+    let tracks = reader.split_by_cuesheet().unwrap();
+    let mut track_2 = segments[1].2.clone();
+    track_2.extend_from_slice(&segments[2].2);
+    assert_eq!(track_2, tracks[1].1);
+
+    let reader = claxon::FlacReader::open("testsamples/short.flac").unwrap();
+    match reader.split_by_cuesheet_indices() {
+        Err(claxon::Error::Unsupported(..)) => {}
+        _ => panic!("expected an error for a stream without CUE sheet"),
+    }
+}
+
+#[test]
+fn verify_channel_samples_matches_full_decode() {
+    let fname = "testsamples/surround_5_1.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let expected: Vec<i32> = all.iter().enumerate()
+        .filter(|&(i, _)| i % 6 == 3).map(|(_, &s)| s).collect();
+
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    assert_eq!(reader.streaminfo().channels, 6);
+    let channel_3 = reader.channel_samples(3).unwrap();
+    assert_eq!(channel_3.len() as u64, reader.streaminfo().samples.unwrap());
+    assert_eq!(channel_3, expected);
+
+    // Stereo files with decorrelation still reconstruct the requested channel.
+    let fname = "testsamples/silent_sections.flac";
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let all: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open(fname).unwrap();
+    let right = reader.channel_samples(1).unwrap();
+    let expected: Vec<i32> = all.iter().enumerate()
+        .filter(|&(i, _)| i % 2 == 1).map(|(_, &s)| s).collect();
+    assert_eq!(right, expected);
+}
+
+#[test]
+fn verify_truncation_at_frame_boundary_is_detected() {
+    use std::io::Read;
+
+    let fname = "testsamples/short_final_block.flac";
+    let mut data = Vec::new();
+    fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+
+    // A complete file is not truncated.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    {
+        let mut samples = reader.samples();
+        assert!(samples.by_ref().all(|s| s.is_ok()));
+        assert!(!samples.is_truncated());
+    }
+
+    // Cut the file right before the last frame.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let (last, _) = reader.frames().last().unwrap().unwrap();
+    data.truncate(last.offset as usize);
+
+    let strict = claxon::FlacReaderOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data), strict).unwrap();
+    let results: Vec<_> = reader.samples().collect();
+    let n_ok = results.iter().take_while(|s| s.is_ok()).count();
+    assert_eq!(n_ok, 3 * 1024 * 2);
+    assert_eq!(results.len(), n_ok + 1);
+    match results[n_ok] {
+        Err(claxon::Error::FormatError(_)) => {}
+        ref other => panic!("expected format error, got {:?}", other),
+    }
+
+    // In lenient mode, the default, the iterator ends normally but reports
+    // the shortfall.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let mut samples = reader.samples();
+    assert_eq!(samples.by_ref().map(|s| s.unwrap()).count(), 3 * 1024 * 2);
+    assert!(samples.is_truncated());
+}
+
+#[test]
+fn verify_short_final_block_time() {
+    // In a fixed-blocksize stream, the time of the short final block is based
+    // on the size of the preceding blocks.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let mut blocks = reader.blocks();
+    let mut times = Vec::new();
+    let mut buffer = Vec::new();
+    while let Some(block) = blocks.read_next_or_eof(buffer).unwrap() {
+        times.push((block.time(), block.duration()));
+        buffer = block.into_buffer();
+    }
+    assert_eq!(times, [(0, 1024), (1024, 1024), (2048, 1024), (3072, 100)]);
+}
+
+#[test]
+fn verify_large_sample_number_in_frame_header() {
+    // The frames of this variable-blocksize stream start at sample 2^33 + 5,
+    // which requires the 7-byte form of the variable-length sample number.
+    let mut reader = claxon::FlacReader::open("testsamples/large_sample_number.flac").unwrap();
+    let mut blocks = reader.blocks();
+    let first = blocks.read_next_or_eof(Vec::new()).unwrap().unwrap();
+    let second = blocks.read_next_or_eof(Vec::new()).unwrap().unwrap();
+    assert_eq!(first.time(), (1 << 33) + 5);
+    assert_eq!(second.time(), (1 << 33) + 5 + 256);
+    assert!(blocks.read_next_or_eof(Vec::new()).unwrap().is_none());
+}
+
+#[test]
+fn verify_block_size_above_maximum_is_rejected() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // The frames have block size 1024, a lower ceiling rejects them.
+    let opts = claxon::FlacReaderOptions {
+        max_block_size: 512,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data), opts).unwrap();
+    match reader.blocks().read_next_or_eof(Vec::new()) {
+        Err(claxon::Error::FormatError(_)) => {}
+        other => panic!("expected format error, got {:?}", other.map(|_| ())),
+    }
+
+    // Claim a minimum and maximum block size of 512 in the streaminfo.
+    for i in 0..2 {
+        data[8 + 2 * i] = 0x02;
+        data[9 + 2 * i] = 0x00;
+    }
+    let opts = claxon::FlacReaderOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data), opts).unwrap();
+    assert_eq!(reader.streaminfo().max_block_size, 512);
+    assert!(reader.samples().next().unwrap().is_err());
+
+    // In lenient mode, the default, only the configured ceiling applies.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 3172 * 2);
+}
+
+#[test]
+fn verify_identifiers() {
+    let reader = claxon::FlacReader::open("testsamples/identifiers.flac").unwrap();
+    let ids = reader.identifiers();
+
+    // Tag names are matched case-insensitively.
+    assert_eq!(ids.isrc, Some("NLA1Z1700001"));
+    assert_eq!(ids.musicbrainz_track_id, Some("1c81a6bc-6a5f-4e2d-b7a8-8e5d1ddc9a01"));
+    assert_eq!(ids.musicbrainz_release_track_id, Some("0c3f1c8f-2f1e-4a7e-9c55-7d3b7b4c2c02"));
+    assert_eq!(ids.musicbrainz_album_id, Some("7a0b0a47-0a41-4a57-a7b4-3f4a5d1b8f03"));
+    assert_eq!(ids.musicbrainz_artist_id, Some("5b11f4ce-a62d-471e-81fc-a69a8278c7da"));
+    assert_eq!(ids.musicbrainz_release_group_id, Some("3e2a6b1c-93d4-4a5e-8f61-2a3c1b9d7e05"));
+    assert_eq!(ids.md5sum, Some(reader.streaminfo().md5sum));
+
+    // A file without tags has only the MD5 signature.
+    let reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let ids = reader.identifiers();
+    assert_eq!(ids.isrc, None);
+    assert_eq!(ids.musicbrainz_track_id, None);
+    assert!(ids.md5sum.is_some());
+}
+
+#[test]
+fn verify_decode_without_streaminfo() {
+    use std::io::Read;
+
+    for &(fname, has_tags) in &[("testsamples/short_final_block.flac", false),
+                                ("testsamples/identifiers.flac", true)] {
+        let mut data = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        let streaminfo = reader.streaminfo();
+
+        // Remove the streaminfo block, 4 bytes of header and 34 bytes of data.
+        // What follows is either a frame, or the Vorbis comment block.
+        data.drain(4..42);
+
+        assert!(claxon::FlacReader::new(io::Cursor::new(&data)).is_err());
+
+        let opts = claxon::FlacReaderOptions {
+            allow_missing_streaminfo: true,
+            ..Default::default()
+        };
+        let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data), opts).unwrap();
+        assert_eq!(reader.streaminfo().sample_rate, streaminfo.sample_rate);
+        assert_eq!(reader.streaminfo().channels, streaminfo.channels);
+        assert_eq!(reader.streaminfo().bits_per_sample, streaminfo.bits_per_sample);
+        assert_eq!(reader.streaminfo().samples, None);
+        assert_eq!(reader.get_tag("TITLE").next().is_some(), has_tags);
+
+        let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, expected);
+    }
+}
+
+#[test]
+fn verify_decode_all_into_matches_samples() {
+    for fname in &["testsamples/surround_5_1.flac", "testsamples/silent_sections.flac",
+                   "testsamples/variable_blocksize.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+        // The output is appended to the existing contents.
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let mut samples = vec![42, 43];
+        reader.decode_all_into(&mut samples).unwrap();
+        assert_eq!(&samples[..2], &[42, 43]);
+        assert!(&samples[2..] == &expected[..]);
+    }
+}
+
+#[test]
+fn verify_cuesheet_with_pre_emphasis_track() {
+    let reader = claxon::FlacReader::open("testsamples/cuesheet.flac").unwrap();
+    assert!(reader.has_pre_emphasis());
+
+    let cuesheet = reader.cuesheet().unwrap();
+    assert_eq!(&cuesheet.catalog_number[..], "1234567890123");
+    assert_eq!(cuesheet.lead_in, 88200);
+    assert!(cuesheet.is_cd);
+    assert_eq!(cuesheet.tracks.len(), 3);
+
+    let numbers: Vec<u8> = cuesheet.tracks.iter().map(|t| t.number).collect();
+    let offsets: Vec<u64> = cuesheet.tracks.iter().map(|t| t.offset).collect();
+    let pre_emphasis: Vec<bool> = cuesheet.tracks.iter().map(|t| t.pre_emphasis).collect();
+    assert_eq!(numbers, vec![1, 2, 170]);
+    assert_eq!(offsets, vec![0, 5880, 11760]);
+    assert_eq!(pre_emphasis, vec![false, true, false]);
+    assert!(cuesheet.tracks.iter().all(|t| t.is_audio));
+
+    let track = &cuesheet.tracks[1];
+    assert_eq!(&track.isrc[..], "NLA1Z1700002");
+    assert_eq!(track.indices.len(), 2);
+    assert_eq!((track.indices[1].offset, track.indices[1].number), (588, 1));
+    assert_eq!(cuesheet.tracks[0].isrc.len(), 0);
+    assert_eq!(cuesheet.tracks[2].indices.len(), 0);
+
+    // Files without CUE sheet or emphasis tag do not need de-emphasis.
+    let reader = claxon::FlacReader::open("testsamples/identifiers.flac").unwrap();
+    assert!(reader.cuesheet().is_none());
+    assert!(!reader.has_pre_emphasis());
+}
+
+#[test]
+fn verify_full_check_reports_damage() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // A complete, intact file is all green.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let report = reader.full_check().unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.metadata_blocks.len(), 1);
+    assert_eq!(report.metadata_blocks[0].block_type, 0);
+    assert_eq!(report.metadata_blocks[0].length, 34);
+    assert_eq!(report.frames, 4);
+    assert_eq!(report.samples, 3172);
+    assert_eq!(report.md5_matches, Some(true));
+    assert!(report.error.is_none());
+    assert!(report.warnings.is_empty());
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let ranges: Vec<_> = reader.frames().map(|f| f.unwrap().0).collect();
+
+    // A corrupted byte in the second frame stops decoding there.
+    let mut corrupted = data.clone();
+    corrupted[(ranges[1].offset + ranges[1].len / 2) as usize] ^= 0x10;
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&corrupted)).unwrap();
+    let report = reader.full_check().unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.frames, 1);
+    assert_eq!(report.samples, 1024);
+    assert_eq!(report.md5_matches, None);
+    match report.error {
+        Some(claxon::Error::FormatError(_)) => {}
+        ref other => panic!("expected format error, got {:?}", other),
+    }
+
+    // Cutting the file in the middle of the last frame.
+    let mut truncated = data.clone();
+    truncated.truncate((ranges[3].offset + 4) as usize);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&truncated)).unwrap();
+    let report = reader.full_check().unwrap();
+    assert_eq!(report.frames, 3);
+    match report.error {
+        Some(claxon::Error::IoError(ref err)) => {
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        ref other => panic!("expected unexpected EOF, got {:?}", other),
+    }
+
+    // Cutting the file at a frame boundary leaves no decode error.
+    let mut truncated = data.clone();
+    truncated.truncate(ranges[3].offset as usize);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&truncated)).unwrap();
+    let report = reader.full_check().unwrap();
+    assert!(report.error.is_none());
+    assert_eq!(report.warnings, vec!["stream contains fewer samples than the streaminfo"]);
+    assert_eq!(report.md5_matches, Some(false));
+
+    // A wrong signature in the streaminfo.
+    let mut wrong_md5 = data.clone();
+    wrong_md5[30] ^= 0xff;
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&wrong_md5)).unwrap();
+    let report = reader.full_check().unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.frames, 4);
+    assert_eq!(report.md5_matches, Some(false));
+}
+
+#[test]
+fn verify_full_check_counts_full_scale_samples() {
+    use claxon::FullScaleSamples;
+
+    let mut reader = claxon::FlacReader::open("testsamples/full_scale.flac").unwrap();
+    let report = reader.full_check().unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.full_scale, vec![
+        FullScaleSamples { count: 2, first: Some(1500) },
+        FullScaleSamples { count: 1, first: Some(2100) },
+    ]);
+
+    // The extremes depend on the bit depth of the stream.
+    let mut reader = claxon::FlacReader::open("testsamples/stereo_24bit.flac").unwrap();
+    let report = reader.full_check().unwrap();
+    assert_eq!(report.full_scale, vec![
+        FullScaleSamples { count: 2, first: Some(0) },
+        FullScaleSamples { count: 2, first: Some(0) },
+    ]);
+
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let report = reader.full_check().unwrap();
+    assert_eq!(report.full_scale, vec![FullScaleSamples::default(); 2]);
+}
+
+#[test]
+fn verify_total_padding() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    assert_eq!(reader.total_padding(), 0);
+
+    // Insert an 8 KiB padding block as last block after the streaminfo.
+    data[4] &= 0x7f;
+    let mut padded = data[..42].to_vec();
+    padded.extend_from_slice(&[0x81, 0x00, 0x20, 0x00]);
+    padded.extend(std::iter::repeat(0).take(8192));
+    padded.extend_from_slice(&data[42..]);
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&padded)).unwrap();
+    assert_eq!(reader.total_padding(), 8192);
+    assert_eq!(reader.metadata_blocks()[1].block_type, 1);
+    assert_eq!(reader.metadata_blocks()[1].length, 8192);
+    assert!(reader.metadata_blocks()[1].is_last);
+    assert_eq!(reader.samples().count(), 3172 * 2);
+}
+
+#[test]
+fn verify_samples_at_bit_depth() {
+    // Upscaling 16 to 24 bits is a pure shift.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let upscaled: Vec<i32> = reader.samples_at_bit_depth(24).map(|s| s.unwrap()).collect();
+    assert_eq!(upscaled.len(), samples.len());
+    assert!(samples.iter().zip(upscaled.iter()).all(|(&s, &u)| u == s << 8));
+
+    // Downscaling 24 to 16 bits discards the 8 least significant bits.
+    let mut reader = claxon::FlacReader::open("testsamples/stereo_24bit.flac").unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open("testsamples/stereo_24bit.flac").unwrap();
+    let downscaled: Vec<i32> = reader.samples_at_bit_depth(16).map(|s| s.unwrap()).collect();
+    assert_eq!(downscaled.len(), samples.len());
+    assert_eq!(&samples[..8], &[-8388608, 8388607, 8388607, -8388608, -1, 255, 1, -257]);
+    assert_eq!(&downscaled[..8], &[-32768, 32767, 32767, -32768, -1, 0, 0, -2]);
+    assert!(samples.iter().zip(downscaled.iter()).all(|(&s, &d)| d == s >> 8));
+
+    // Converting to the native bit depth is the identity.
+    let mut reader = claxon::FlacReader::open("testsamples/stereo_24bit.flac").unwrap();
+    assert!(reader.samples_at_bit_depth(24).map(|s| s.unwrap()).eq(samples.iter().cloned()));
+}
+
+#[test]
+fn verify_blocks_end_with_none_or_unexpected_eof() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // A complete file ends cleanly.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    {
+        let mut blocks = reader.blocks();
+        assert_eq!(blocks.by_ref().map(|b| b.unwrap().duration()).sum::<u32>(), 3172);
+        assert!(blocks.next().is_none());
+    }
+
+    // Truncating the file in the middle of a frame, and one byte into the next
+    // frame header, must both be reported.
+    let len = data.len();
+    for &cut in &[len - 20, len - 1] {
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data[..cut])).unwrap();
+        let mut last = None;
+        for result in reader.blocks() {
+            last = Some(result);
+        }
+        match last {
+            Some(Err(claxon::Error::IoError(ref err))) => {
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+            }
+            other => panic!("expected unexpected EOF, got {:?}", other.map(|r| r.map(|b| b.duration()))),
+        }
+    }
+
+    let mut extra = data.clone();
+    extra.push(0xff);
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&extra)).unwrap();
+    let results: Vec<_> = reader.blocks().collect();
+    assert_eq!(results.len(), 5);
+    assert!(results[..4].iter().all(|r| r.is_ok()));
+    assert!(results[4].is_err());
+}
+
+#[test]
+fn verify_chained_streams() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+    fs::File::open("testsamples/identifiers.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    assert_eq!(reader.streaminfo().channels, 2);
+    assert_eq!(reader.get_tag("TITLE").next(), None);
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 3172 * 2);
+
+    let mut reader = reader.next_stream().unwrap().unwrap();
+    assert_eq!(reader.streaminfo().channels, 1);
+    assert_eq!(reader.streaminfo().samples, Some(1000));
+    assert_eq!(reader.get_tag("TITLE").next(), Some("Identifiers"));
+    assert!(reader.verify_md5().unwrap());
+    assert!(reader.next_stream().unwrap().is_none());
+
+    // Skipping a stream without decoding it first works too.
+    let reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    assert!(reader.next_stream().unwrap().is_none());
+}
+
+#[test]
+fn verify_samples_as_narrower_type() {
+    // A 16-bit stream fits in i16.
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let mut reader = claxon::FlacReader::open("testsamples/short_final_block.flac").unwrap();
+    let narrow: Vec<i16> = reader.samples_as::<i16>().map(|s| s.unwrap()).collect();
+    assert!(samples.iter().zip(narrow.iter()).all(|(&s, &n)| s == n as i32));
+    assert_eq!(narrow.len(), samples.len());
+
+    // A 24-bit stream does not, and the error names the bit depth.
+    let mut reader = claxon::FlacReader::open("testsamples/stereo_24bit.flac").unwrap();
+    let results: Vec<_> = reader.samples_as::<i16>().collect();
+    assert_eq!(results.len(), 1);
+    let err = results.into_iter().next().unwrap().err().unwrap();
+    assert_eq!(err, claxon::Error::SampleTooWide { needed_bits: 24, requested_type: "i16" });
+    assert!(format!("{}", err).contains("24 bits"));
+}
+
+#[test]
+fn verify_trailing_vorbis_comment_block() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // Append a Vorbis comment block after the last frame.
+    let comment = b"TITLE=Trailing";
+    let mut body = vec![1, 0, 0, 0, b'x', 1, 0, 0, 0, comment.len() as u8, 0, 0, 0];
+    body.extend_from_slice(comment);
+    data.extend_from_slice(&[0x84, 0, 0, body.len() as u8]);
+    data.extend_from_slice(&body);
+
+    // By default, this is an error at the end of the audio.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    assert!(reader.samples().any(|s| s.is_err()));
+
+    let opts = claxon::FlacReaderOptions {
+        scan_all_metadata: true,
+        ..Default::default()
+    };
+    let mut reader = claxon::FlacReader::new_ext(io::Cursor::new(&data), opts).unwrap();
+    assert_eq!(reader.get_tag("TITLE").next(), None);
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 3172 * 2);
+    reader.read_trailing_metadata().unwrap();
+    assert_eq!(reader.get_tag("TITLE").next(), Some("Trailing"));
+    assert_eq!(reader.vendor(), Some("x"));
+    assert_eq!(reader.metadata_blocks().len(), 2);
+    assert_eq!(reader.metadata_blocks()[1].block_type, 4);
+
+    // Without trailing blocks, nothing changes.
+    let mut reader = claxon::FlacReader::open_ext("testsamples/identifiers.flac", opts).unwrap();
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 1000);
+    reader.read_trailing_metadata().unwrap();
+    assert_eq!(reader.get_tag("TITLE").next(), Some("Identifiers"));
+    assert_eq!(reader.metadata_blocks().len(), 2);
+}
+
+#[test]
+fn verify_into_inner_after_decode() {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut data).unwrap();
+
+    // After a full decode, the reader is at the end of the stream.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    assert_eq!(reader.samples().map(|s| s.unwrap()).count(), 3172 * 2);
+    let cursor = reader.into_inner();
+    assert_eq!(cursor.position(), data.len() as u64);
+
+    // After a partial decode, the buffer and the reader hold the remainder.
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let (first, _) = reader.frames().next().unwrap().unwrap();
+    let (mut cursor, buffered) = reader.into_inner_with_buffer();
+    let mut remainder = buffered;
+    cursor.read_to_end(&mut remainder).unwrap();
+    let end_of_first = (first.offset + first.len) as usize;
+    assert!(&remainder[..] == &data[end_of_first..]);
+}
+
+#[test]
+fn verify_flac_magic_and_block_types() {
+    use std::io::Read;
+
+    assert_eq!(&claxon::FLAC_MAGIC, b"fLaC");
+
+    let mut data = Vec::new();
+    fs::File::open("testsamples/cuesheet.flac").unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(&data[..4], &claxon::FLAC_MAGIC);
+
+    let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+    let types: Vec<u8> = reader.metadata_blocks().iter().map(|h| h.block_type).collect();
+    assert_eq!(types, vec![claxon::metadata::BLOCK_TYPE_STREAMINFO,
+                           claxon::metadata::BLOCK_TYPE_CUESHEET]);
+}
+
+#[test]
+fn verify_write_aiff_round_trip() {
+    use std::io::Read;
+
+    fn be_u32(bytes: &[u8]) -> u32 {
+        (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+    }
+
+    for &fname in &["testsamples/stereo_24bit.flac",
+                    "testsamples/short_final_block.flac",
+                    "testsamples/stereo_12bit.flac",
+                    "testsamples/stereo_20bit.flac",
+                    "testsamples/stereo_28bit.flac"] {
+        let mut data = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        let streaminfo = reader.streaminfo();
+        let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+        let mut aiff = Vec::new();
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        reader.write_aiff(&mut aiff).unwrap();
+
+        assert_eq!(&aiff[0..4], b"FORM");
+        assert_eq!(be_u32(&aiff[4..8]) as usize, aiff.len() - 8);
+        assert_eq!(&aiff[8..12], b"AIFF");
+
+        // Walk the chunks, and collect the COMM and SSND contents.
+        let mut comm = None;
+        let mut ssnd = None;
+        let mut pos = 12;
+        while pos < aiff.len() {
+            let len = be_u32(&aiff[pos + 4..pos + 8]) as usize;
+            let chunk = &aiff[pos + 8..pos + 8 + len];
+            match &aiff[pos..pos + 4] {
+                b"COMM" => comm = Some(chunk),
+                b"SSND" => ssnd = Some(&chunk[8..]),
+                _ => panic!("unexpected chunk"),
+            }
+            pos += 8 + len + (len & 1);
+        }
+
+        let comm = comm.unwrap();
+        assert_eq!(comm[1] as u32, streaminfo.channels);
+        assert_eq!(be_u32(&comm[2..6]) as u64, streaminfo.samples.unwrap());
+        assert_eq!(comm[7] as u32, streaminfo.bits_per_sample);
+
+        let bytes_per_sample = (streaminfo.bits_per_sample as usize + 7) / 8;
+        let samples: Vec<i32> = ssnd.unwrap().chunks(bytes_per_sample).map(|bytes| {
+            let mut sample = 0i32;
+            for &b in bytes {
+                sample = (sample << 8) | b as i32;
+            }
+            // Sign-extend from the most significant byte, and drop the zero
+            // bits that left-justify samples of odd bit depths.
+            let shift = 32 - 8 * bytes_per_sample;
+            let padding = 8 * bytes_per_sample as u32 - streaminfo.bits_per_sample;
+            (sample << shift) >> shift >> padding
+        }).collect();
+        assert_eq!(samples, expected);
+    }
+}
+
+#[test]
+fn verify_progress_callback() {
+    use std::sync::{Arc, Mutex};
+
+    for fname in &["testsamples/short_final_block.flac", "testsamples/variable_blocksize.flac"] {
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let n_frames = reader.blocks().count();
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_cb = reports.clone();
+        let mut reader = claxon::FlacReader::open(fname).unwrap();
+        let total = reader.streaminfo().samples;
+        reader.set_progress_callback(move |decoded, total| {
+            reports_cb.lock().unwrap().push((decoded, total));
+        });
+        for sample in reader.samples() {
+            sample.unwrap();
+        }
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), n_frames);
+        assert!(reports.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(reports.iter().all(|&(_, t)| t == total));
+        assert_eq!(Some(reports[reports.len() - 1].0), total);
+    }
+}
+
+#[test]
+#[cfg(feature = "resample")]
+fn verify_resampled_length() {
+    for fname in &["testsamples/short_final_block.flac", "testsamples/variable_blocksize.flac"] {
+        let reader = claxon::FlacReader::open(fname).unwrap();
+        let n = reader.streaminfo().samples.unwrap();
+        let n_channels = reader.streaminfo().channels as u64;
+        assert_eq!(reader.streaminfo().sample_rate, 44100);
+
+        let resampled = reader.resampled(48000);
+        let expected = (n * 48000 + 44099) / 44100;
+        assert_eq!(resampled.expected_samples(), Some(expected));
+        let samples: Vec<i32> = resampled.map(|s| s.unwrap()).collect();
+        assert_eq!(samples.len() as u64, expected * n_channels);
+    }
+
+    // Converting to the source rate is the identity.
+    let mut reader = claxon::FlacReader::open("testsamples/variable_blocksize.flac").unwrap();
+    let original: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+    let reader = claxon::FlacReader::open("testsamples/variable_blocksize.flac").unwrap();
+    let same: Vec<i32> = reader.resampled(44100).map(|s| s.unwrap()).collect();
+    assert_eq!(same, original);
+
+    // Halving the rate takes every other sample.
+    let reader = claxon::FlacReader::open("testsamples/variable_blocksize.flac").unwrap();
+    let half: Vec<i32> = reader.resampled(22050).map(|s| s.unwrap()).collect();
+    let every_other: Vec<i32> = original.chunks(4).flat_map(|c| c[..2].to_vec()).collect();
+    assert_eq!(half, every_other);
+}
+
+#[test]
+#[cfg(feature = "resample")]
+fn verify_resampled_rejects_channel_mismatch() {
+    let data = read_with_streaminfo_channels("testsamples/stereo_24bit.flac", 6);
+    let reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    let samples: Vec<_> = reader.resampled(48000).collect();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].as_ref().err(),
+               Some(&claxon::Error::FormatError("frame has a different number of channels than the streaminfo")));
+}
+
+#[test]
+fn verify_chained_streams_report_tag_changes() {
+    use std::io::Read;
+
+    let mut untagged = Vec::new();
+    fs::File::open("testsamples/short_final_block.flac").unwrap().read_to_end(&mut untagged).unwrap();
+    let mut tagged = Vec::new();
+    fs::File::open("testsamples/identifiers.flac").unwrap().read_to_end(&mut tagged).unwrap();
+
+    // A chain of songs: untagged, tagged, the same tags again, then untagged.
+    let mut data = Vec::new();
+    data.extend_from_slice(&untagged);
+    data.extend_from_slice(&tagged);
+    data.extend_from_slice(&tagged);
+    data.extend_from_slice(&untagged);
+
+    let mut changes = Vec::new();
+    let mut reader = claxon::FlacReader::new(io::Cursor::new(data)).unwrap();
+    changes.push(reader.tags_changed());
+    while let Some(next) = reader.next_stream().unwrap() {
+        reader = next;
+        changes.push(reader.tags_changed());
+    }
+    assert_eq!(changes, vec![false, true, false, true]);
+}
+
+#[test]
+fn verify_trim_frames() {
+    use std::io::Read;
+
+    let cases = [("testsamples/short_final_block.flac", 1, 2),
+                 ("testsamples/short_final_block.flac", 2, 100),
+                 ("testsamples/variable_blocksize.flac", 3, 5),
+                 ("testsamples/cuesheet.flac", 0, 0)];
+    for &(fname, first, last) in &cases {
+        let mut data = Vec::new();
+        fs::File::open(fname).unwrap().read_to_end(&mut data).unwrap();
+
+        // Collect the samples that the kept frames decode to.
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        let mut expected = Vec::new();
+        let mut durations = Vec::new();
+        for (i, item) in reader.frames().enumerate() {
+            let (_, block) = item.unwrap();
+            if i >= first && i <= last {
+                durations.push(block.duration());
+                for t in 0..block.duration() {
+                    for ch in 0..block.channels() {
+                        expected.push(block.sample(ch, t));
+                    }
+                }
+            }
+        }
+
+        let reader = claxon::FlacReader::new(io::Cursor::new(&data)).unwrap();
+        let mut trimmed = Vec::new();
+        reader.trim_frames(first as u64, last as u64, &mut trimmed).unwrap();
+
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&trimmed)).unwrap();
+        assert!(reader.cuesheet().is_none());
+        let streaminfo = reader.streaminfo();
+        assert_eq!(streaminfo.samples, Some(durations.iter().map(|&d| d as u64).sum()));
+        assert_eq!(streaminfo.max_block_size as u32, *durations.iter().max().unwrap());
+
+        // The frames are numbered from zero again, and the MD5 signature matches.
+        let mut time = 0;
+        for item in reader.frames() {
+            let (range, block) = item.unwrap();
+            assert_eq!(block.time(), time);
+            assert!(range.len as u32 >= streaminfo.min_frame_size.unwrap());
+            assert!(range.len as u32 <= streaminfo.max_frame_size.unwrap());
+            time += block.duration() as u64;
+        }
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&trimmed)).unwrap();
+        let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, expected);
+        let mut reader = claxon::FlacReader::new(io::Cursor::new(&trimmed)).unwrap();
+        assert!(reader.verify_md5().unwrap());
+    }
+}
+
 #[test]
 fn verify_extra_samples() {
     use std::ffi::OsStr;